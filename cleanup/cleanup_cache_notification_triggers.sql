@@ -2,4 +2,7 @@
 -- Description: Removes all artifacts created by sql/cache_notification_triggers.sql
 
 -- Drop the notification function (CASCADE will also drop any triggers using it)
-DROP FUNCTION IF EXISTS notify_cache_change() CASCADE;
\ No newline at end of file
+DROP FUNCTION IF EXISTS notify_cache_change() CASCADE;
+
+-- Drop the table recording the installed script version
+DROP TABLE IF EXISTS postgres_index_cache_meta;
@@ -0,0 +1,69 @@
+//! Compares staging 10k items one at a time with staging them in one batch
+//!
+//! Run with `cargo run --release --example batch_staging`.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::RwLock;
+use postgres_index_cache::{
+    HasPrimaryKey, IdxModelCache, IndexKeys, Indexable, TransactionAware, TransactionAwareIdxModelCache,
+};
+use smallvec::smallvec;
+use uuid::Uuid;
+
+const ITEMS: i64 = 10_000;
+const ROUNDS: u32 = 20;
+
+#[derive(Debug, Clone)]
+struct Row {
+    id: Uuid,
+    value: i64,
+}
+
+impl HasPrimaryKey for Row {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for Row {
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        smallvec![(Cow::Borrowed("value"), Some(self.value))]
+    }
+}
+
+fn rows() -> Vec<Row> {
+    (0..ITEMS).map(|value| Row { id: Uuid::new_v4(), value }).collect()
+}
+
+/// Average time to stage and commit `ITEMS` rows with `stage`
+async fn measure(stage: impl Fn(&TransactionAwareIdxModelCache<Row>, Vec<Row>)) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(Vec::new()).unwrap()));
+        let tx_cache = TransactionAwareIdxModelCache::new(shared_cache);
+        let rows = rows();
+
+        let started = Instant::now();
+        stage(&tx_cache, rows);
+        tx_cache.on_commit().await.unwrap();
+        total += started.elapsed();
+    }
+    total / ROUNDS
+}
+
+#[tokio::main]
+async fn main() {
+    let one_by_one = measure(|tx_cache, rows| {
+        for row in rows {
+            tx_cache.add(row);
+        }
+    })
+    .await;
+    let batched = measure(|tx_cache, rows| tx_cache.add_all(rows)).await;
+
+    println!("staging and committing {ITEMS} rows, averaged over {ROUNDS} rounds:");
+    println!("  add in a loop: {one_by_one:?}");
+    println!("  add_all:       {batched:?}");
+}
@@ -0,0 +1,96 @@
+//! Measures how long readers of the shared cache wait while a commit of N
+//! staged items applies, against applying the same items under one write
+//! lock the way commits did before staged changes were drained first
+//!
+//! Run with `cargo run --release --example commit_lock_hold`.
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::RwLock;
+use postgres_index_cache::{
+    HasPrimaryKey, IdxModelCache, IndexKeys, Indexable, TransactionAware, TransactionAwareIdxModelCache,
+};
+use smallvec::smallvec;
+use uuid::Uuid;
+
+const ROUNDS: u32 = 10;
+
+#[derive(Debug, Clone)]
+struct Row {
+    id: Uuid,
+    value: i64,
+    /// Only read through Debug; makes each clone allocate like a real row
+    #[allow(dead_code)]
+    name: String,
+}
+
+impl HasPrimaryKey for Row {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for Row {
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        smallvec![(Cow::Borrowed("value"), Some(self.value))]
+    }
+}
+
+fn rows(n: usize) -> Vec<Row> {
+    (0..n as i64).map(|value| Row { id: Uuid::new_v4(), value, name: format!("row {value}") }).collect()
+}
+
+/// The longest a reader waited for the read lock while `apply` ran
+fn max_reader_wait(shared_cache: &Arc<RwLock<IdxModelCache<Row>>>, apply: impl FnOnce()) -> Duration {
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = std::thread::spawn({
+        let shared_cache = shared_cache.clone();
+        let done = done.clone();
+        move || {
+            let mut max_wait = Duration::ZERO;
+            while !done.load(Ordering::Relaxed) {
+                let started = Instant::now();
+                let cache = shared_cache.read();
+                max_wait = max_wait.max(started.elapsed());
+                drop(cache);
+                std::hint::spin_loop();
+            }
+            max_wait
+        }
+    });
+    apply();
+    done.store(true, Ordering::Relaxed);
+    reader.join().unwrap()
+}
+
+/// Clones every item into the cache under the write lock
+fn apply_under_write_lock(shared_cache: &RwLock<IdxModelCache<Row>>, staged: &[Row]) {
+    let mut cache = shared_cache.write();
+    for row in staged {
+        cache.add(row.clone());
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    println!("longest reader wait while applying N items, worst of {ROUNDS} rounds:");
+    for n in [100, 1_000, 10_000] {
+        let (mut cloned, mut drained) = (Duration::ZERO, Duration::ZERO);
+        for _ in 0..ROUNDS {
+            let staged = rows(n);
+            let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(Vec::new()).unwrap()));
+            cloned = cloned.max(max_reader_wait(&shared_cache, || apply_under_write_lock(&shared_cache, &staged)));
+
+            let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(Vec::new()).unwrap()));
+            let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+            tx_cache.add_all(staged);
+            let handle = tokio::runtime::Handle::current();
+            drained = drained.max(max_reader_wait(&shared_cache, || {
+                tokio::task::block_in_place(|| handle.block_on(tx_cache.on_commit())).unwrap();
+            }));
+        }
+        println!("  N = {n:>6}: cloned under the write lock {cloned:?}, on_commit {drained:?}");
+    }
+}
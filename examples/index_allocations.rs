@@ -0,0 +1,145 @@
+//! Counts the allocations of `Vec` against `SmallVec` posting lists, and of
+//! `HashMap` against `IndexKeys` key extraction
+//!
+//! Run with `cargo run --release --example index_allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use postgres_index_cache::{HasPrimaryKey, IdxModelCache, IndexKeys, Indexable};
+use smallvec::{smallvec, SmallVec};
+use uuid::Uuid;
+
+const ITEMS: i64 = 10_000;
+
+/// The system allocator, counting allocations
+struct Counting;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations and time taken by `f`
+fn measure<R>(f: impl FnOnce() -> R) -> (u64, Duration) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    black_box(f());
+    (ALLOCATIONS.load(Ordering::Relaxed) - allocations, started.elapsed())
+}
+
+fn report(label: &str, (allocations, elapsed): (u64, Duration)) {
+    println!("  {label:<28} {allocations:>8} allocations  {elapsed:?}");
+}
+
+/// Implements the deprecated map-returning methods
+#[derive(Debug, Clone)]
+struct LegacyRow {
+    id: Uuid,
+    value: i64,
+    owner: Uuid,
+}
+
+impl HasPrimaryKey for LegacyRow {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+#[allow(deprecated)]
+impl Indexable for LegacyRow {
+    fn i64_keys(&self) -> HashMap<String, Option<i64>> {
+        HashMap::from([("value".to_string(), Some(self.value))])
+    }
+
+    fn uuid_keys(&self) -> HashMap<String, Option<Uuid>> {
+        HashMap::from([("owner".to_string(), Some(self.owner))])
+    }
+}
+
+/// Implements the `*_index_keys` methods
+#[derive(Debug, Clone)]
+struct Row {
+    id: Uuid,
+    value: i64,
+    owner: Uuid,
+}
+
+impl HasPrimaryKey for Row {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for Row {
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        smallvec![(Cow::Borrowed("value"), Some(self.value))]
+    }
+
+    fn uuid_index_keys(&self) -> IndexKeys<Uuid> {
+        smallvec![(Cow::Borrowed("owner"), Some(self.owner))]
+    }
+}
+
+fn main() {
+    let ids: Vec<Uuid> = (0..ITEMS).map(|_| Uuid::new_v4()).collect();
+    let rows: Vec<Row> = ids.iter().zip(0..).map(|(id, value)| Row { id: *id, value, owner: Uuid::new_v4() }).collect();
+    let legacy_rows: Vec<LegacyRow> =
+        rows.iter().map(|row| LegacyRow { id: row.id, value: row.value, owner: row.owner }).collect();
+
+    // One item per index value, the common case
+    println!("posting lists of {ITEMS} distinct values:");
+    report(
+        "Vec<Uuid>",
+        measure(|| {
+            let mut postings: HashMap<i64, Vec<Uuid>> = HashMap::with_capacity(ids.len());
+            for (value, id) in (0..).zip(&ids) {
+                postings.entry(value).or_default().push(*id);
+            }
+            postings
+        }),
+    );
+    report(
+        "SmallVec<[Uuid; 1]>",
+        measure(|| {
+            let mut postings: HashMap<i64, SmallVec<[Uuid; 1]>> = HashMap::with_capacity(ids.len());
+            for (value, id) in (0..).zip(&ids) {
+                postings.entry(value).or_default().push(*id);
+            }
+            postings
+        }),
+    );
+
+    println!("extracting the keys of {ITEMS} items:");
+    report(
+        "HashMap (deprecated)",
+        measure(|| legacy_rows.iter().for_each(|row| drop(black_box((row.i64_index_keys(), row.uuid_index_keys()))))),
+    );
+    report(
+        "IndexKeys",
+        measure(|| rows.iter().for_each(|row| drop(black_box((row.i64_index_keys(), row.uuid_index_keys()))))),
+    );
+
+    println!("building an IdxModelCache of {ITEMS} items:");
+    report("HashMap keys (deprecated)", measure(|| IdxModelCache::new(legacy_rows.clone()).unwrap()));
+    report("IndexKeys", measure(|| IdxModelCache::new(rows.clone()).unwrap()));
+}
@@ -0,0 +1,60 @@
+//! Times a Uuid index query on a transaction-aware cache with 0, 10 and 10k
+//! staged changes
+//!
+//! Run with `cargo run --release --example index_query_staging`.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use parking_lot::RwLock;
+use postgres_index_cache::{HasPrimaryKey, IdxModelCache, IndexKeys, Indexable, TransactionAwareIdxModelCache};
+use smallvec::smallvec;
+use uuid::Uuid;
+
+const ITEMS: usize = 10_000;
+const OWNERS: usize = 100;
+const QUERIES: u32 = 1_000;
+
+#[derive(Debug, Clone)]
+struct Row {
+    id: Uuid,
+    owner: Uuid,
+}
+
+impl HasPrimaryKey for Row {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for Row {
+    fn uuid_index_keys(&self) -> IndexKeys<Uuid> {
+        smallvec![(Cow::Borrowed("owner"), Some(self.owner))]
+    }
+}
+
+/// Average time of one query by owner, with `staged` other rows staged for addition
+fn measure(shared_cache: &Arc<RwLock<IdxModelCache<Row>>>, owners: &[Uuid], staged: usize) -> Duration {
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    let other_owner = Uuid::new_v4();
+    tx_cache.add_all((0..staged).map(|_| Row { id: Uuid::new_v4(), owner: other_owner }));
+
+    let started = Instant::now();
+    for query in 0..QUERIES {
+        let owner = &owners[query as usize % OWNERS];
+        let rows = tx_cache.get_by_uuid_index("owner", owner).unwrap();
+        assert_eq!(rows.len(), ITEMS / OWNERS);
+    }
+    started.elapsed() / QUERIES
+}
+
+fn main() {
+    let owners: Vec<Uuid> = (0..OWNERS).map(|_| Uuid::new_v4()).collect();
+    let rows = (0..ITEMS).map(|item| Row { id: Uuid::new_v4(), owner: owners[item % OWNERS] }).collect();
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(rows).unwrap()));
+
+    println!("querying {} of {ITEMS} cached rows by owner, averaged over {QUERIES} queries:", ITEMS / OWNERS);
+    for staged in [0, 10, 10_000] {
+        println!("  {staged:>6} staged: {:?}", measure(&shared_cache, &owners, staged));
+    }
+}
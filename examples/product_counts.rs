@@ -0,0 +1,91 @@
+//! Keeps the number of products of each user in sync with `product_index_cache`
+//!
+//! The notifications are fed to the listener directly here; in an application
+//! `listener.listen(&pool)` receives them from the database triggers.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::RwLock;
+use postgres_index_cache::{AggregatingCacheHandler, CacheAction, CacheNotification, CacheNotificationListener};
+use uuid::Uuid;
+
+/// Product counts per user, and the owner of each product for deletes
+#[derive(Debug, Default)]
+struct ProductCounts {
+    per_user: HashMap<Uuid, usize>,
+    owners: HashMap<Uuid, Uuid>,
+}
+
+impl ProductCounts {
+    fn remove_product(&mut self, product_id: Uuid) {
+        let Some(user_id) = self.owners.remove(&product_id) else {
+            return;
+        };
+        if let Some(count) = self.per_user.get_mut(&user_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_user.remove(&user_id);
+            }
+        }
+    }
+}
+
+fn fold(counts: &mut ProductCounts, action: CacheAction, product_id: Uuid, data: Option<&serde_json::Value>) {
+    match action {
+        CacheAction::Insert | CacheAction::Update => {
+            let Some(user_id) = data
+                .and_then(|data| data.get("user_id"))
+                .and_then(|user_id| serde_json::from_value::<Uuid>(user_id.clone()).ok())
+            else {
+                return;
+            };
+            // An update may move the product to another user
+            counts.remove_product(product_id);
+            counts.owners.insert(product_id, user_id);
+            *counts.per_user.entry(user_id).or_default() += 1;
+        }
+        CacheAction::Delete => counts.remove_product(product_id),
+        CacheAction::Truncate | CacheAction::Flush => *counts = ProductCounts::default(),
+    }
+}
+
+fn notification(action: &str, product_id: Uuid, user_id: Option<Uuid>) -> String {
+    let notification = CacheNotification {
+        table: "product_index_cache".to_string(),
+        action: action.to_string(),
+        id: product_id,
+        key: None,
+        data: user_id.map(|user_id| serde_json::json!({ "id": product_id, "user_id": user_id })),
+        context: None,
+    };
+    serde_json::to_string(&notification).unwrap()
+}
+
+#[tokio::main]
+async fn main() {
+    let counts = Arc::new(RwLock::new(ProductCounts::default()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(AggregatingCacheHandler::new(
+        "product_index_cache".to_string(),
+        counts.clone(),
+        fold,
+    )));
+
+    let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+    let (widget, gadget, gizmo) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    for payload in [
+        notification("insert", widget, Some(alice)),
+        notification("insert", gadget, Some(alice)),
+        notification("insert", gizmo, Some(bob)),
+        notification("update", gadget, Some(bob)),
+        notification("delete", widget, None),
+    ] {
+        listener.process_notification(&payload).await;
+    }
+
+    let counts = counts.read();
+    println!("alice has {} products", counts.per_user.get(&alice).copied().unwrap_or(0));
+    println!("bob has {} products", counts.per_user.get(&bob).copied().unwrap_or(0));
+    assert_eq!(counts.per_user.get(&alice), None);
+    assert_eq!(counts.per_user.get(&bob), Some(&2));
+}
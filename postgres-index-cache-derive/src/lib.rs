@@ -0,0 +1,548 @@
+//! Derive macros for the `postgres-index-cache` traits
+//!
+//! Enable the `derive` feature of `postgres-index-cache` and use the macros
+//! through its re-exports:
+//!
+//! ```ignore
+//! use postgres_index_cache::{HasPrimaryKey, Indexable};
+//! use uuid::Uuid;
+//!
+//! #[derive(Clone, Debug, HasPrimaryKey, Indexable)]
+//! struct ProductIndexCache {
+//!     #[cache(primary_key)]
+//!     id: Uuid,
+//!     #[cache(uuid_index)]
+//!     user_id: Uuid,
+//!     #[cache(i64_index = "name_hash")]
+//!     product_name_hash: i64,
+//!     #[cache(i64_index)]
+//!     sku_hash: Option<i64>,
+//!     #[cache(datetime_index)]
+//!     released_at: Option<DateTime<Utc>>,
+//!     #[cache(string_index)]
+//!     email_domain: String,
+//! }
+//! ```
+//!
+//! Index names default to the field name. Values are converted with `Into`,
+//! and `Option` fields map to absent index keys when they are `None`.
+//!
+//! `#[derive(HasTableName)]` uses the snake_case struct name as the table name
+//! unless it is set with `#[cache(table = "...")]` on the struct.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr, Token, Type};
+
+/// Derive `HasPrimaryKey` from the field marked `#[cache(primary_key)]`
+#[proc_macro_derive(HasPrimaryKey, attributes(cache))]
+pub fn derive_has_primary_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_has_primary_key(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `Indexable` from the fields marked `#[cache(i64_index)]`, `#[cache(uuid_index)]`,
+/// `#[cache(datetime_index)]` and `#[cache(string_index)]`
+#[proc_macro_derive(Indexable, attributes(cache))]
+pub fn derive_indexable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_indexable(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `HasTableName` from `#[cache(table = "...")]` or the snake_case struct name
+#[proc_macro_derive(HasTableName, attributes(cache))]
+pub fn derive_has_table_name(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_has_table_name(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// An index declared on a field
+struct IndexAttr {
+    name: String,
+    span: Span,
+}
+
+/// A field of the derived struct and its `#[cache(...)]` attributes
+struct CacheField {
+    ident: Ident,
+    ty: Type,
+    primary_key: Option<Span>,
+    i64_index: Option<IndexAttr>,
+    uuid_index: Option<IndexAttr>,
+    datetime_index: Option<IndexAttr>,
+    string_index: Option<IndexAttr>,
+}
+
+fn expand_has_primary_key(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = parse_fields(input)?;
+    let mut primary_keys = fields.iter().filter_map(|field| field.primary_key.map(|span| (field, span)));
+
+    let (field, _) = primary_keys.next().ok_or_else(|| {
+        syn::Error::new_spanned(
+            &input.ident,
+            "#[derive(HasPrimaryKey)] requires a field marked #[cache(primary_key)]",
+        )
+    })?;
+    if let Some((_, span)) = primary_keys.next() {
+        return Err(syn::Error::new(span, "only one field can be marked #[cache(primary_key)]"));
+    }
+
+    let name = &input.ident;
+    let ident = &field.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::postgres_index_cache::HasPrimaryKey for #name #ty_generics #where_clause {
+            fn primary_key(&self) -> ::postgres_index_cache::__private::Uuid {
+                ::core::convert::Into::into(self.#ident)
+            }
+        }
+    })
+}
+
+fn expand_has_table_name(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut table = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("cache")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table") {
+                let name: LitStr = meta.value()?.parse()?;
+                if name.value().is_empty() {
+                    return Err(syn::Error::new(name.span(), "table name must not be empty"));
+                }
+                table = Some(name.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `table`"))
+            }
+        })?;
+    }
+    let table = table.unwrap_or_else(|| to_snake_case(&input.ident.to_string()));
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::postgres_index_cache::HasTableName for #name #ty_generics #where_clause {
+            fn table_name() -> &'static str {
+                #table
+            }
+        }
+    })
+}
+
+fn expand_indexable(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = parse_fields(input)?;
+    let uuid = quote!(::postgres_index_cache::__private::Uuid);
+
+    let i64_inserts = index_inserts(
+        fields.iter().filter_map(|field| field.i64_index.as_ref().map(|index| (field, index))),
+        &quote!(i64),
+        false,
+    )?;
+    let uuid_inserts = index_inserts(
+        fields.iter().filter_map(|field| field.uuid_index.as_ref().map(|index| (field, index))),
+        &uuid,
+        false,
+    )?;
+    let datetime = quote!(::postgres_index_cache::__private::DateTime<::postgres_index_cache::__private::Utc>);
+    let datetime_inserts = index_inserts(
+        fields.iter().filter_map(|field| field.datetime_index.as_ref().map(|index| (field, index))),
+        &datetime,
+        false,
+    )?;
+    // Only override the default `datetime_index_keys` when a field asks for it
+    let datetime_keys = (!datetime_inserts.is_empty()).then(|| {
+        quote! {
+            fn datetime_index_keys(&self) -> ::postgres_index_cache::IndexKeys<#datetime> {
+                let mut keys = ::postgres_index_cache::IndexKeys::new();
+                #(#datetime_inserts)*
+                keys
+            }
+        }
+    });
+
+    let string_inserts = index_inserts(
+        fields.iter().filter_map(|field| field.string_index.as_ref().map(|index| (field, index))),
+        &quote!(::std::string::String),
+        true,
+    )?;
+    let string_keys = (!string_inserts.is_empty()).then(|| {
+        quote! {
+            fn string_index_keys(&self) -> ::postgres_index_cache::IndexKeys<::std::string::String> {
+                let mut keys = ::postgres_index_cache::IndexKeys::new();
+                #(#string_inserts)*
+                keys
+            }
+        }
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::postgres_index_cache::Indexable for #name #ty_generics #where_clause {
+            fn i64_index_keys(&self) -> ::postgres_index_cache::IndexKeys<i64> {
+                #[allow(unused_mut)]
+                let mut keys = ::postgres_index_cache::IndexKeys::new();
+                #(#i64_inserts)*
+                keys
+            }
+
+            fn uuid_index_keys(&self) -> ::postgres_index_cache::IndexKeys<#uuid> {
+                #[allow(unused_mut)]
+                let mut keys = ::postgres_index_cache::IndexKeys::new();
+                #(#uuid_inserts)*
+                keys
+            }
+
+            #datetime_keys
+
+            #string_keys
+        }
+    })
+}
+
+/// The `keys.push(...)` statements for one kind of index, rejecting duplicate names
+///
+/// Fields of types that are not `Copy`, like `String`, are cloned with `clone`.
+fn index_inserts<'a>(
+    indexes: impl Iterator<Item = (&'a CacheField, &'a IndexAttr)>,
+    value_ty: &TokenStream2,
+    clone: bool,
+) -> syn::Result<Vec<TokenStream2>> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut inserts = Vec::new();
+    for (field, index) in indexes {
+        if names.contains(&index.name.as_str()) {
+            return Err(syn::Error::new(
+                index.span,
+                format!("duplicate index name `{}`", index.name),
+            ));
+        }
+        names.push(&index.name);
+
+        let ident = &field.ident;
+        let name = &index.name;
+        let field_value = if clone {
+            quote!(::core::clone::Clone::clone(&self.#ident))
+        } else {
+            quote!(self.#ident)
+        };
+        let value = if is_option(&field.ty) {
+            quote!(#field_value.map(::core::convert::Into::<#value_ty>::into))
+        } else {
+            quote!(::core::option::Option::Some(::core::convert::Into::<#value_ty>::into(#field_value)))
+        };
+        inserts.push(quote! {
+            keys.push((::std::borrow::Cow::Borrowed(#name), #value));
+        });
+    }
+    Ok(inserts)
+}
+
+fn parse_fields(input: &DeriveInput) -> syn::Result<Vec<CacheField>> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "cache derives only support structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "cache derives only support structs with named fields",
+            ))
+        }
+    };
+
+    let mut cache_fields = Vec::new();
+    for field in fields {
+        let ident = field.ident.clone().expect("named field");
+        let mut cache_field = CacheField {
+            ident: ident.clone(),
+            ty: field.ty.clone(),
+            primary_key: None,
+            i64_index: None,
+            uuid_index: None,
+            datetime_index: None,
+            string_index: None,
+        };
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("cache")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("primary_key") {
+                    cache_field.primary_key = Some(meta.path.get_ident().expect("ident").span());
+                    Ok(())
+                } else if meta.path.is_ident("i64_index") {
+                    cache_field.i64_index = Some(parse_index_name(&meta, &ident)?);
+                    Ok(())
+                } else if meta.path.is_ident("uuid_index") {
+                    cache_field.uuid_index = Some(parse_index_name(&meta, &ident)?);
+                    Ok(())
+                } else if meta.path.is_ident("datetime_index") {
+                    cache_field.datetime_index = Some(parse_index_name(&meta, &ident)?);
+                    Ok(())
+                } else if meta.path.is_ident("string_index") {
+                    cache_field.string_index = Some(parse_index_name(&meta, &ident)?);
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "expected `primary_key`, `i64_index`, `uuid_index`, `datetime_index` or `string_index`",
+                    ))
+                }
+            })?;
+        }
+        cache_fields.push(cache_field);
+    }
+    Ok(cache_fields)
+}
+
+/// Parse `index` or `index = "name"`; the name defaults to the field name
+fn parse_index_name(meta: &syn::meta::ParseNestedMeta, field: &Ident) -> syn::Result<IndexAttr> {
+    if meta.input.peek(Token![=]) {
+        let name: LitStr = meta.value()?.parse()?;
+        if name.value().is_empty() {
+            return Err(syn::Error::new(name.span(), "index name must not be empty"));
+        }
+        Ok(IndexAttr {
+            name: name.value(),
+            span: name.span(),
+        })
+    } else {
+        Ok(IndexAttr {
+            name: field.to_string(),
+            span: field.span(),
+        })
+    }
+}
+
+/// Convert a type name to snake_case, keeping acronyms together (`HTTPLog` -> `http_log`)
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase() || previous.is_ascii_digit() || (previous.is_uppercase() && next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => {
+            path.qself.is_none()
+                && path
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|segment| segment.ident == "Option")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(
+        expander: fn(&DeriveInput) -> syn::Result<TokenStream2>,
+        input: TokenStream2,
+    ) -> syn::Result<String> {
+        let input: DeriveInput = syn::parse2(input).unwrap();
+        expander(&input).map(|tokens| tokens.to_string())
+    }
+
+    #[test]
+    fn test_expand_has_primary_key() {
+        let expanded = expand(
+            expand_has_primary_key,
+            quote! {
+                struct User {
+                    #[cache(primary_key)]
+                    id: Uuid,
+                    name: String,
+                }
+            },
+        )
+        .unwrap();
+
+        let expected = quote! {
+            impl ::postgres_index_cache::HasPrimaryKey for User {
+                fn primary_key(&self) -> ::postgres_index_cache::__private::Uuid {
+                    ::core::convert::Into::into(self.id)
+                }
+            }
+        };
+        assert_eq!(expanded, expected.to_string());
+    }
+
+    #[test]
+    fn test_expand_indexable() {
+        let expanded = expand(
+            expand_indexable,
+            quote! {
+                struct Product<T> {
+                    #[cache(primary_key)]
+                    id: Uuid,
+                    #[cache(uuid_index)]
+                    user_id: Uuid,
+                    #[cache(i64_index = "name_hash")]
+                    product_name_hash: i64,
+                    #[cache(i64_index)]
+                    sku_hash: Option<i64>,
+                    extra: T,
+                }
+            },
+        )
+        .unwrap();
+
+        let expected = quote! {
+            impl<T> ::postgres_index_cache::Indexable for Product<T> {
+                fn i64_index_keys(&self) -> ::postgres_index_cache::IndexKeys<i64> {
+                    #[allow(unused_mut)]
+                    let mut keys = ::postgres_index_cache::IndexKeys::new();
+                    keys.push((
+                        ::std::borrow::Cow::Borrowed("name_hash"),
+                        ::core::option::Option::Some(::core::convert::Into::<i64>::into(self.product_name_hash))
+                    ));
+                    keys.push((
+                        ::std::borrow::Cow::Borrowed("sku_hash"),
+                        self.sku_hash.map(::core::convert::Into::<i64>::into)
+                    ));
+                    keys
+                }
+
+                fn uuid_index_keys(&self) -> ::postgres_index_cache::IndexKeys<::postgres_index_cache::__private::Uuid> {
+                    #[allow(unused_mut)]
+                    let mut keys = ::postgres_index_cache::IndexKeys::new();
+                    keys.push((
+                        ::std::borrow::Cow::Borrowed("user_id"),
+                        ::core::option::Option::Some(::core::convert::Into::<::postgres_index_cache::__private::Uuid>::into(self.user_id))
+                    ));
+                    keys
+                }
+            }
+        };
+        assert_eq!(expanded, expected.to_string());
+    }
+
+    #[test]
+    fn test_expand_indexable_datetime_keys() {
+        let expanded = expand(
+            expand_indexable,
+            quote! {
+                struct Event {
+                    #[cache(primary_key)]
+                    id: Uuid,
+                    #[cache(datetime_index = "starts")]
+                    starts_at: DateTime<Utc>,
+                }
+            },
+        )
+        .unwrap();
+
+        let expected = quote! {
+            fn datetime_index_keys(&self) -> ::postgres_index_cache::IndexKeys<::postgres_index_cache::__private::DateTime<::postgres_index_cache::__private::Utc> > {
+                let mut keys = ::postgres_index_cache::IndexKeys::new();
+                keys.push((
+                    ::std::borrow::Cow::Borrowed("starts"),
+                    ::core::option::Option::Some(::core::convert::Into::<::postgres_index_cache::__private::DateTime<::postgres_index_cache::__private::Utc> >::into(self.starts_at))
+                ));
+                keys
+            }
+        };
+        assert!(expanded.contains(&expected.to_string()));
+    }
+
+    #[test]
+    fn test_expand_indexable_string_keys() {
+        let expanded = expand(
+            expand_indexable,
+            quote! {
+                struct User {
+                    #[cache(primary_key)]
+                    id: Uuid,
+                    #[cache(string_index = "domain")]
+                    email_domain: Option<String>,
+                }
+            },
+        )
+        .unwrap();
+
+        let expected = quote! {
+            fn string_index_keys(&self) -> ::postgres_index_cache::IndexKeys<::std::string::String> {
+                let mut keys = ::postgres_index_cache::IndexKeys::new();
+                keys.push((
+                    ::std::borrow::Cow::Borrowed("domain"),
+                    ::core::clone::Clone::clone(&self.email_domain).map(::core::convert::Into::<::std::string::String>::into)
+                ));
+                keys
+            }
+        };
+        assert!(expanded.contains(&expected.to_string()));
+    }
+
+    #[test]
+    fn test_expand_has_table_name() {
+        let expanded = expand(expand_has_table_name, quote! { struct UserIndexCache { id: Uuid } }).unwrap();
+        let expected = quote! {
+            impl ::postgres_index_cache::HasTableName for UserIndexCache {
+                fn table_name() -> &'static str {
+                    "user_index_cache"
+                }
+            }
+        };
+        assert_eq!(expanded, expected.to_string());
+
+        let expanded = expand(
+            expand_has_table_name,
+            quote! {
+                #[cache(table = "users")]
+                struct User { id: Uuid }
+            },
+        )
+        .unwrap();
+        assert!(expanded.contains("\"users\""));
+    }
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(to_snake_case("UserIndexCache"), "user_index_cache");
+        assert_eq!(to_snake_case("HTTPLog"), "http_log");
+        assert_eq!(to_snake_case("Product2Cache"), "product2_cache");
+        assert_eq!(to_snake_case("users"), "users");
+    }
+
+    #[test]
+    fn test_invalid_attributes_are_rejected() {
+        let missing = expand(expand_has_primary_key, quote! { struct User { id: Uuid } });
+        assert!(missing.is_err());
+
+        let twice = expand(
+            expand_has_primary_key,
+            quote! { struct User { #[cache(primary_key)] a: Uuid, #[cache(primary_key)] b: Uuid } },
+        );
+        assert!(twice.is_err());
+
+        let unknown = expand(expand_indexable, quote! { struct User { #[cache(index)] a: i64 } });
+        assert!(unknown.is_err());
+
+        let tuple = expand(expand_indexable, quote! { struct User(Uuid); });
+        assert!(tuple.is_err());
+    }
+}
@@ -1,51 +1,111 @@
--- Production Cache Notification Function
+-- Cache Notification Function
 --
--- This file contains the production PostgreSQL trigger function to send
--- cache invalidation notifications via LISTEN/NOTIFY when data changes occur.
+-- Generated by postgres-index-cache (TriggerSqlBuilder::build_function_sql).
 --
--- The notifications use a single channel 'cache_invalidation' and include
--- table name, action type, and the full row data in JSON format.
-
--- =====================================================================
--- Generic Notification Function
--- =====================================================================
--- This function can be reused for any table by attaching it to triggers
--- It sends a notification with the table name, action, and row data
-
-DROP FUNCTION IF EXISTS notify_cache_change() CASCADE;
+-- Sends cache invalidation notifications via LISTEN/NOTIFY when data changes
+-- occur. Each notification carries the table name, the action and the primary
+-- key, plus the row data in JSON format for inserts and updates.
+--
+-- Triggers may pass optional arguments to override the defaults:
+--   TG_ARGV[0] - the channel to notify on (default 'cache_invalidation')
+--   TG_ARGV[1] - 'true' or 'false', whether to include the row data
+--   TG_ARGV[2] - comma-separated list of columns to include in the row data
+--   TG_ARGV[3] - the table name sent in notifications (default TG_TABLE_NAME),
+--                so triggers on partitions report their partitioned table
+--   TG_ARGV[4] - comma-separated list of context columns, always sent in a
+--                top-level 'context' object even when the row data is dropped
+--
+-- Used as a statement-level AFTER TRUNCATE trigger it sends a 'truncate'
+-- notification with the nil UUID as id.
 
-CREATE OR REPLACE FUNCTION notify_cache_change()
+CREATE OR REPLACE FUNCTION "notify_cache_change"()
 RETURNS TRIGGER AS $$
 DECLARE
     notification json;
+    row_data json;
+    row_context json;
     payload text;
+    channel text := 'cache_invalidation';
+    include_data boolean := true;
+    table_name text := TG_TABLE_NAME;
 BEGIN
+    -- Read the optional trigger arguments
+    IF TG_NARGS > 0 THEN
+        channel := TG_ARGV[0];
+    END IF;
+    IF TG_NARGS > 1 THEN
+        include_data := TG_ARGV[1]::boolean;
+    END IF;
+    IF TG_NARGS > 3 AND TG_ARGV[3] <> '' THEN
+        table_name := TG_ARGV[3];
+    END IF;
+
     -- Build the notification payload
-    IF (TG_OP = 'DELETE') THEN
+    IF (TG_OP = 'TRUNCATE') THEN
+        -- There is no row for a truncate, so the nil UUID is sent as the id
         notification = json_build_object(
-            'table', TG_TABLE_NAME,
+            'table', table_name,
+            'action', 'truncate',
+            'id', '00000000-0000-0000-0000-000000000000'
+        );
+    ELSIF (TG_OP = 'DELETE') THEN
+        notification = json_build_object(
+            'table', table_name,
             'action', 'delete',
             'id', OLD.id
         );
     ELSE
-        -- For INSERT and UPDATE, include the full row data
-        notification = json_build_object(
-            'table', TG_TABLE_NAME,
-            'action', lower(TG_OP),
-            'id', NEW.id,
-            'data', row_to_json(NEW)
-        );
+        -- For INSERT and UPDATE, include the row data
+        IF include_data THEN
+            row_data = row_to_json(NEW);
+            IF TG_NARGS > 2 AND TG_ARGV[2] <> '' THEN
+                SELECT json_object_agg(key, value) INTO row_data
+                FROM json_each(row_data)
+                WHERE key = ANY (string_to_array(TG_ARGV[2], ','));
+            END IF;
+        END IF;
+
+        IF row_data IS NULL THEN
+            notification = json_build_object(
+                'table', table_name,
+                'action', lower(TG_OP),
+                'id', NEW.id
+            );
+        ELSE
+            notification = json_build_object(
+                'table', table_name,
+                'action', lower(TG_OP),
+                'id', NEW.id,
+                'data', row_data
+            );
+        END IF;
+    END IF;
+
+    -- Copy the context columns of the row
+    IF TG_OP <> 'TRUNCATE' AND TG_NARGS > 4 AND TG_ARGV[4] <> '' THEN
+        IF (TG_OP = 'DELETE') THEN
+            row_context = row_to_json(OLD);
+        ELSE
+            row_context = row_to_json(NEW);
+        END IF;
+        SELECT json_object_agg(key, value) INTO row_context
+        FROM json_each(row_context)
+        WHERE key = ANY (string_to_array(TG_ARGV[4], ','));
+        -- json values cannot be concatenated, so the context is added as jsonb
+        notification = (notification::jsonb || jsonb_build_object('context', row_context))::json;
     END IF;
 
     -- Convert to text and send notification
     payload = notification::text;
-    PERFORM pg_notify('cache_invalidation', payload);
+    PERFORM pg_notify(channel, payload);
 
     -- Return the appropriate row
-    IF (TG_OP = 'DELETE') THEN
+    IF (TG_OP = 'TRUNCATE') THEN
+        RETURN NULL;
+    ELSIF (TG_OP = 'DELETE') THEN
         RETURN OLD;
     ELSE
         RETURN NEW;
     END IF;
 END;
-$$ LANGUAGE plpgsql;
\ No newline at end of file
+$$ LANGUAGE plpgsql;
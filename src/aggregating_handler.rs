@@ -0,0 +1,85 @@
+//! Notification handler for state computed from the rows of a table
+//!
+//! [`AggregatingCacheHandler`] does the parsing, action matching and error
+//! logging; a fold function supplied by the caller updates the state.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::listener::{CacheAction, CacheNotification, CacheNotificationHandler};
+
+/// Applies one notification to the aggregated state
+///
+/// Called with the action, the primary key of the row and its data. Inserts
+/// and updates always carry data; deletes, truncates and flushes never do,
+/// and truncates and flushes come with the nil UUID.
+pub type AggregateFold<S> = dyn Fn(&mut S, CacheAction, Uuid, Option<&serde_json::Value>) + Send + Sync;
+
+/// A notification handler maintaining aggregated state, e.g. counts per owner
+///
+/// Deletes carry no row data, so a state that needs the deleted row, like
+/// the owner whose count to decrement, has to remember it from the insert.
+pub struct AggregatingCacheHandler<S: Send + Sync + 'static> {
+    table_name: String,
+    state: Arc<RwLock<S>>,
+    fold: Box<AggregateFold<S>>,
+}
+
+impl<S: Send + Sync + 'static> AggregatingCacheHandler<S> {
+    /// Create a new handler folding the notifications of a table into the state
+    pub fn new(
+        table_name: String,
+        state: Arc<RwLock<S>>,
+        fold: impl Fn(&mut S, CacheAction, Uuid, Option<&serde_json::Value>) + Send + Sync + 'static,
+    ) -> Self {
+        Self { table_name, state, fold: Box::new(fold) }
+    }
+
+    /// Gets the aggregated state
+    pub fn state(&self) -> &Arc<RwLock<S>> {
+        &self.state
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync + 'static> CacheNotificationHandler for AggregatingCacheHandler<S> {
+    async fn handle_notification(&self, notification: CacheNotification) {
+        let Some(action) = notification.cache_action() else {
+            warn!(
+                table = %notification.table,
+                action = %notification.action,
+                id = %notification.id,
+                "AggregatingCache: dropping notification: unknown action"
+            );
+            return;
+        };
+        if matches!(action, CacheAction::Insert | CacheAction::Update) && notification.data.is_none() {
+            warn!(
+                table = %notification.table,
+                action = %notification.action,
+                id = %notification.id,
+                "AggregatingCache: dropping notification: no data provided"
+            );
+            return;
+        }
+
+        let data = match action {
+            CacheAction::Insert | CacheAction::Update => notification.data.as_ref(),
+            CacheAction::Delete | CacheAction::Truncate | CacheAction::Flush => None,
+        };
+        (self.fold)(&mut self.state.write(), action, notification.id, data);
+        debug!(
+            "AggregatingCache: Applied {} of item {} for table '{}'",
+            action.as_str(),
+            notification.id,
+            notification.table
+        );
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+}
@@ -0,0 +1,226 @@
+//! Storage backends for full models
+//!
+//! [`ModelCacheBackend`] is what `MainModelCacheHandler` and
+//! `TransactionAwareMainModelCache` need from the cache underneath them.
+//! It is implemented for [`MainModelCache`] and, with the `moka` feature,
+//! for `moka::sync::Cache<K, Arc<T>>`.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::main_model_cache::{CacheStatistics, MainModelCache};
+use crate::traits::CacheKey;
+
+/// A cache of full models keyed by primary key, a `Uuid` unless `K` says otherwise
+pub trait ModelCacheBackend<T, K = Uuid>: Send + Sync {
+    /// Gets an item, counting it as a hit or miss where statistics are kept
+    fn get(&mut self, primary_key: &K) -> Option<Arc<T>>;
+
+    /// Gets an item without recording statistics where the backend allows it
+    fn peek(&self, primary_key: &K) -> Option<Arc<T>>;
+
+    /// Inserts an item, evicting others if the backend is full
+    fn insert(&mut self, item: Arc<T>);
+
+    /// Replaces an item
+    fn update(&mut self, item: Arc<T>);
+
+    /// Removes an item, returning it if it was cached
+    fn remove(&mut self, primary_key: &K) -> Option<Arc<T>>;
+
+    /// Checks if an item is cached
+    fn contains(&self, primary_key: &K) -> bool;
+
+    /// Removes all items
+    fn clear(&mut self);
+
+    /// The maximum number of items, if the backend is bounded by count
+    fn capacity(&self) -> Option<usize>;
+
+    /// Hit, miss, eviction and invalidation counters, if the backend keeps them
+    fn statistics(&self) -> Option<&CacheStatistics>;
+
+    /// The name identifying the cache in logs, if it has one
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Marks the cache as loaded, for backends that gate reads on readiness
+    fn mark_ready(&mut self) {}
+
+    /// Makes the changes of `apply`, counted as one change by backends that
+    /// count them
+    fn batch<R>(&mut self, apply: impl FnOnce(&mut Self) -> R) -> R
+    where
+        Self: Sized,
+    {
+        apply(self)
+    }
+}
+
+impl<T, K> ModelCacheBackend<T, K> for MainModelCache<T, K>
+where
+    T: CacheKey<K> + Clone + Debug + Send + Sync,
+    K: Eq + Hash + Clone + Send + Sync,
+{
+    fn get(&mut self, primary_key: &K) -> Option<Arc<T>> {
+        MainModelCache::get(self, primary_key)
+    }
+
+    fn peek(&self, primary_key: &K) -> Option<Arc<T>> {
+        MainModelCache::peek(self, primary_key).cloned()
+    }
+
+    fn insert(&mut self, item: Arc<T>) {
+        MainModelCache::insert(self, item)
+    }
+
+    fn update(&mut self, item: Arc<T>) {
+        MainModelCache::update(self, item)
+    }
+
+    fn remove(&mut self, primary_key: &K) -> Option<Arc<T>> {
+        MainModelCache::remove(self, primary_key)
+    }
+
+    fn contains(&self, primary_key: &K) -> bool {
+        MainModelCache::contains(self, primary_key)
+    }
+
+    fn clear(&mut self) {
+        MainModelCache::clear(self)
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.config().cache_size)
+    }
+
+    fn statistics(&self) -> Option<&CacheStatistics> {
+        Some(MainModelCache::statistics(self))
+    }
+
+    fn name(&self) -> Option<&str> {
+        MainModelCache::name(self)
+    }
+
+    fn mark_ready(&mut self) {
+        MainModelCache::mark_ready(self)
+    }
+
+    fn batch<R>(&mut self, apply: impl FnOnce(&mut Self) -> R) -> R {
+        MainModelCache::batch(self, apply)
+    }
+}
+
+/// moka keeps no hit or miss counters of its own, and `peek` counts as an
+/// access for its eviction policy
+#[cfg(feature = "moka")]
+impl<T, K> ModelCacheBackend<T, K> for moka::sync::Cache<K, Arc<T>>
+where
+    T: CacheKey<K> + Send + Sync + 'static,
+    K: Eq + Hash + Send + Sync + 'static,
+{
+    fn get(&mut self, primary_key: &K) -> Option<Arc<T>> {
+        moka::sync::Cache::get(self, primary_key)
+    }
+
+    fn peek(&self, primary_key: &K) -> Option<Arc<T>> {
+        moka::sync::Cache::get(self, primary_key)
+    }
+
+    fn insert(&mut self, item: Arc<T>) {
+        moka::sync::Cache::insert(self, CacheKey::<K>::cache_key(&*item), item)
+    }
+
+    fn update(&mut self, item: Arc<T>) {
+        moka::sync::Cache::insert(self, CacheKey::<K>::cache_key(&*item), item)
+    }
+
+    fn remove(&mut self, primary_key: &K) -> Option<Arc<T>> {
+        moka::sync::Cache::remove(self, primary_key)
+    }
+
+    fn contains(&self, primary_key: &K) -> bool {
+        moka::sync::Cache::contains_key(self, primary_key)
+    }
+
+    fn clear(&mut self) {
+        moka::sync::Cache::invalidate_all(self)
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.policy().max_capacity().map(|capacity| capacity as usize)
+    }
+
+    fn statistics(&self) -> Option<&CacheStatistics> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::main_model_cache::{CacheConfig, EvictionPolicy};
+    use crate::traits::HasPrimaryKey;
+    use crate::transaction_aware_main_model_cache::TransactionAwareMainModelCache;
+    use parking_lot::RwLock;
+    use postgres_unit_of_work::TransactionAware;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct TestEntity {
+        id: Uuid,
+        value: String,
+    }
+
+    impl HasPrimaryKey for TestEntity {
+        fn primary_key(&self) -> Uuid {
+            self.id
+        }
+    }
+
+    fn entity(value: &str) -> TestEntity {
+        TestEntity { id: Uuid::new_v4(), value: value.to_string() }
+    }
+
+    /// Stages an update and a removal, commits, and checks the backend
+    async fn commit_through<B: ModelCacheBackend<TestEntity> + 'static>(backend: B) {
+        let kept = entity("kept");
+        let removed = entity("removed");
+        let shared = Arc::new(RwLock::new(backend));
+        shared.write().insert(Arc::new(kept.clone()));
+        shared.write().insert(Arc::new(removed.clone()));
+
+        let tx_cache = TransactionAwareMainModelCache::new(shared.clone());
+        tx_cache.update(TestEntity { value: "updated".to_string(), ..kept.clone() });
+        tx_cache.remove(&removed.id);
+        assert_eq!(shared.read().peek(&kept.id).unwrap().value, "kept");
+
+        tx_cache.on_commit().await.unwrap();
+        assert_eq!(shared.write().get(&kept.id).unwrap().value, "updated");
+        assert!(!shared.read().contains(&removed.id));
+    }
+
+    #[tokio::test]
+    async fn test_main_model_cache_backend() {
+        commit_through(MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU))).await;
+
+        let mut cache = MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU));
+        let item = entity("a");
+        ModelCacheBackend::insert(&mut cache, Arc::new(item.clone()));
+        assert!(ModelCacheBackend::peek(&cache, &item.id).is_some());
+        assert_eq!(ModelCacheBackend::capacity(&cache), Some(10));
+        assert_eq!(cache.statistics().hits(), 0);
+    }
+
+    #[cfg(feature = "moka")]
+    #[tokio::test]
+    async fn test_moka_backend() {
+        commit_through(moka::sync::Cache::<Uuid, Arc<TestEntity>>::new(10)).await;
+
+        let cache = moka::sync::Cache::<Uuid, Arc<TestEntity>>::new(10);
+        assert_eq!(ModelCacheBackend::<TestEntity>::capacity(&cache), Some(10));
+        assert!(ModelCacheBackend::<TestEntity>::statistics(&cache).is_none());
+    }
+}
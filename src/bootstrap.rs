@@ -0,0 +1,76 @@
+//! Loading cold caches without losing concurrent changes
+//!
+//! A cache loaded from a SELECT misses the changes committed between the
+//! snapshot and the start of LISTEN. [`CacheBootstrapper`] runs the steps in
+//! the order that closes this gap:
+//!
+//! 1. the listener starts buffering notifications,
+//! 2. the bootstrapper waits until the listener's `listen` loop is listening,
+//! 3. the snapshot is loaded into the caches,
+//! 4. the buffered notifications are replayed and live dispatch resumes,
+//! 5. the caches are marked ready.
+//!
+//! After a successful load every handler of the listener is marked as loaded,
+//! so its `last_applied` is set even if no change arrives afterwards. Reads
+//! gated on readiness, e.g. a `CachedRepository` with a `ReadPolicy`, skip
+//! the caches until step 5, when they hold the current state.
+//!
+//! Every change committed after LISTEN is in the buffer, so replaying it in
+//! commit order over the snapshot ends in the current state, also for changes
+//! the snapshot already contains. Handlers created with `skip_stale_versions`
+//! skip notifications older than the loaded versions instead of re-applying them.
+
+use std::future::Future;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::listener::CacheNotificationListener;
+
+/// Loads caches from a snapshot while notifications are buffered
+pub struct CacheBootstrapper {
+    listener: Arc<CacheNotificationListener>,
+    wait_for_listen: bool,
+}
+
+impl CacheBootstrapper {
+    /// Create a bootstrapper for the listener that keeps the caches up to date
+    pub fn new(listener: Arc<CacheNotificationListener>) -> Self {
+        Self { listener, wait_for_listen: true }
+    }
+
+    /// Do not wait for the listener's `listen` loop before loading
+    ///
+    /// For notification loops that call `process_notification` themselves and
+    /// are known to be receiving when `bootstrap` is called.
+    pub fn without_waiting_for_listen(mut self) -> Self {
+        self.wait_for_listen = false;
+        self
+    }
+
+    /// Load the caches with `load` and catch up on the changes made meanwhile
+    ///
+    /// Returns the number of replayed notifications. If `load` fails, the
+    /// buffered notifications are still replayed before the error is
+    /// returned, so the listener does not stay in buffering mode.
+    pub async fn bootstrap<F, Fut, E>(&self, load: F) -> Result<usize, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        self.listener.start_buffering();
+        if self.wait_for_listen {
+            self.listener.wait_until_listening().await;
+        }
+
+        let loaded = load().await;
+        if loaded.is_ok() {
+            self.listener.registry().mark_loaded();
+        }
+        let replayed = self.listener.stop_buffering().await;
+        debug!(channel = %self.listener.channel(), replayed, "replayed notifications buffered during bootstrap");
+        if loaded.is_ok() {
+            self.listener.registry().mark_ready().await;
+        }
+        loaded.map(|()| replayed)
+    }
+}
@@ -0,0 +1,188 @@
+//! Cache-aside reads in front of a sqlx repository
+//!
+//! [`CachedRepository`] serves reads from a shared [`MainModelCache`] and
+//! falls back to the database on a miss, caching what it loads.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::main_model_cache::MainModelCache;
+use crate::traits::HasPrimaryKey;
+
+/// Loads a single row by primary key from the database
+///
+/// Implemented for any `Fn(PgPool, Uuid) -> impl Future<Output = Result<Option<T>, sqlx::Error>>`,
+/// so a closure around an existing repository is usually enough.
+#[async_trait]
+pub trait RepositoryFetch<T>: Send + Sync {
+    /// Fetches the row with the given primary key, `None` if it does not exist
+    async fn fetch(&self, pool: &PgPool, id: Uuid) -> Result<Option<T>, sqlx::Error>;
+}
+
+#[async_trait]
+impl<T, F, Fut> RepositoryFetch<T> for F
+where
+    T: Send + 'static,
+    F: Fn(PgPool, Uuid) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Option<T>, sqlx::Error>> + Send,
+{
+    async fn fetch(&self, pool: &PgPool, id: Uuid) -> Result<Option<T>, sqlx::Error> {
+        self(pool.clone(), id).await
+    }
+}
+
+/// How a [`CachedRepository`] reads while its cache is not ready
+///
+/// A cache is ready once `mark_ready` was called on it, which
+/// `CacheBootstrapper` does after loading it and replaying the changes made
+/// meanwhile. Until then a miss says nothing about the row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadPolicy {
+    /// Read through the cache whether it is ready or not
+    #[default]
+    Always,
+    /// Fetch from the database without touching the cache until it is ready
+    Bypass,
+    /// Wait up to the timeout for the cache to become ready, then bypass it
+    WaitForReady(Duration),
+}
+
+/// Cache-aside decorator for a sqlx repository
+///
+/// Every `get` that reads the cache records exactly one hit or miss in the
+/// cache statistics; reads bypassing a cache that is not ready record none.
+/// Rows that do not exist are not cached, so they are fetched again on every `get`.
+pub struct CachedRepository<T: HasPrimaryKey + Clone + Send + Sync + Debug + 'static> {
+    cache: Arc<RwLock<MainModelCache<T>>>,
+    pool: PgPool,
+    fetcher: Box<dyn RepositoryFetch<T>>,
+    /// Per-key locks for in-flight fetches; `None` unless single-flight is enabled
+    in_flight: Option<Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>>,
+    read_policy: ReadPolicy,
+}
+
+impl<T: HasPrimaryKey + Clone + Send + Sync + Debug + 'static> CachedRepository<T> {
+    /// Creates a repository reading through the given cache
+    pub fn new(
+        cache: Arc<RwLock<MainModelCache<T>>>,
+        pool: PgPool,
+        fetcher: impl RepositoryFetch<T> + 'static,
+    ) -> Self {
+        Self {
+            cache,
+            pool,
+            fetcher: Box::new(fetcher),
+            in_flight: None,
+            read_policy: ReadPolicy::Always,
+        }
+    }
+
+    /// Lets only one of several concurrent misses for the same key hit the database
+    ///
+    /// The other callers wait for that fetch and are served from the cache.
+    pub fn with_single_flight(mut self) -> Self {
+        self.in_flight = Some(Mutex::new(HashMap::new()));
+        self
+    }
+
+    /// Sets how reads behave while the cache is not ready
+    pub fn with_read_policy(mut self, read_policy: ReadPolicy) -> Self {
+        self.read_policy = read_policy;
+        self
+    }
+
+    /// Gets the cache this repository reads through
+    pub fn cache(&self) -> &Arc<RwLock<MainModelCache<T>>> {
+        &self.cache
+    }
+
+    /// Gets an item, loading and caching it on a miss
+    ///
+    /// While the cache is not ready, the read policy may send the read
+    /// straight to the database; the item is not cached then.
+    pub async fn get(&self, id: Uuid) -> Result<Option<Arc<T>>, sqlx::Error> {
+        if !self.cache_ready().await {
+            return Ok(self.fetcher.fetch(&self.pool, id).await?.map(Arc::new));
+        }
+
+        let cached = self.cache.write().get(&id);
+        if let Some(item) = cached {
+            return Ok(Some(item));
+        }
+
+        let Some(in_flight) = &self.in_flight else {
+            return self.load(id).await;
+        };
+
+        let key_lock = Arc::clone(in_flight.lock().entry(id).or_default());
+        let result = {
+            let _guard = key_lock.lock().await;
+            // A concurrent miss may have loaded the item while we waited;
+            // the miss is already recorded, so read without statistics
+            let loaded = self.cache.read().peek(&id).cloned();
+            match loaded {
+                Some(item) => Ok(Some(item)),
+                None => self.load(id).await,
+            }
+        };
+
+        let mut in_flight = in_flight.lock();
+        // Only the map and this call hold the lock: nobody else is waiting
+        if Arc::strong_count(&key_lock) == 2 {
+            in_flight.remove(&id);
+        }
+        result
+    }
+
+    /// Removes an item from the cache; the next `get` loads it again
+    ///
+    /// Returns true if the item was cached.
+    pub fn invalidate(&self, id: Uuid) -> bool {
+        self.cache.write().remove(&id).is_some()
+    }
+
+    /// Reloads an item from the database, replacing the cached copy
+    ///
+    /// The item is removed from the cache if the row no longer exists.
+    pub async fn refresh(&self, id: Uuid) -> Result<Option<Arc<T>>, sqlx::Error> {
+        match self.fetcher.fetch(&self.pool, id).await? {
+            Some(item) => {
+                let item = Arc::new(item);
+                self.cache.write().insert(Arc::clone(&item));
+                Ok(Some(item))
+            }
+            None => {
+                self.cache.write().remove(&id);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether reads may use the cache under the read policy
+    async fn cache_ready(&self) -> bool {
+        match self.read_policy {
+            ReadPolicy::Always => true,
+            ReadPolicy::Bypass => self.cache.read().is_ready(),
+            ReadPolicy::WaitForReady(timeout) => {
+                MainModelCache::wait_until(&*self.cache, timeout, |cache| cache.is_ready()).await
+            }
+        }
+    }
+
+    /// Fetches an item and caches it if it exists
+    async fn load(&self, id: Uuid) -> Result<Option<Arc<T>>, sqlx::Error> {
+        let Some(item) = self.fetcher.fetch(&self.pool, id).await? else {
+            return Ok(None);
+        };
+        let item = Arc::new(item);
+        self.cache.write().insert(Arc::clone(&item));
+        Ok(Some(item))
+    }
+}
@@ -0,0 +1,161 @@
+//! Cascading deletes of a parent row to the cached rows referencing it
+//!
+//! [`CascadeHandler`] is registered for the parent table and removes, on each
+//! delete, the entries of child caches whose foreign key index holds the
+//! deleted row's key.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::handler_stats::AppliedInfo;
+use crate::index_cache::IdxModelCache;
+use crate::listener::{CacheNotification, CacheNotificationHandler};
+use crate::lock::CacheLock;
+use crate::traits::{HasPrimaryKey, Indexable};
+
+/// A child cache and the Uuid index holding its foreign key
+#[async_trait]
+trait CascadeTarget: Send + Sync {
+    /// The index name, for log lines
+    fn index_name(&self) -> &str;
+
+    /// Removes the entries referencing `parent`, returning how many there were
+    async fn remove_children(&self, parent: Uuid) -> usize;
+}
+
+struct IndexCascade<T, L> {
+    cache: Arc<L>,
+    index_name: String,
+    item: PhantomData<fn() -> T>,
+}
+
+#[async_trait]
+impl<T, L> CascadeTarget for IndexCascade<T, L>
+where
+    T: HasPrimaryKey + Indexable + Clone + Send + Sync + Debug + 'static,
+    L: CacheLock<IdxModelCache<T>> + 'static,
+{
+    fn index_name(&self) -> &str {
+        &self.index_name
+    }
+
+    async fn remove_children(&self, parent: Uuid) -> usize {
+        self.cache.write().await.remove_by_uuid_index_value(&self.index_name, &parent).len()
+    }
+}
+
+/// A notification handler cascading the deletes of a parent table to child caches
+///
+/// A table has one handler, so when the parent table is cached too, wrap its
+/// handler with [`CascadeHandler::wrapping`]; it sees every notification
+/// before the cascade runs. Only deletes cascade: truncating a referenced
+/// table truncates its children, which notify on their own.
+///
+/// # Example
+/// ```rust,ignore
+/// let handler = CascadeHandler::wrapping(Arc::new(IndexCacheHandler::for_type(user_cache.clone())))
+///     .cascade_to(product_cache.clone(), "user_id");
+/// listener.register_handler(Arc::new(handler));
+/// ```
+pub struct CascadeHandler {
+    table_name: String,
+    parent: Option<Arc<dyn CacheNotificationHandler>>,
+    children: Vec<Box<dyn CascadeTarget>>,
+}
+
+impl CascadeHandler {
+    /// Create a handler cascading the deletes of a table that is not cached itself
+    pub fn new(table_name: String) -> Self {
+        Self { table_name, parent: None, children: Vec::new() }
+    }
+
+    /// Create a handler passing every notification to the parent table's handler first
+    pub fn wrapping(parent: Arc<dyn CacheNotificationHandler>) -> Self {
+        Self { table_name: parent.table_name().to_string(), parent: Some(parent), children: Vec::new() }
+    }
+
+    /// Remove the entries of `cache` whose Uuid index `index_name` holds the deleted key
+    pub fn cascade_to<T, L>(mut self, cache: Arc<L>, index_name: impl Into<String>) -> Self
+    where
+        T: HasPrimaryKey + Indexable + Clone + Send + Sync + Debug + 'static,
+        L: CacheLock<IdxModelCache<T>> + 'static,
+    {
+        self.children.push(Box::new(IndexCascade { cache, index_name: index_name.into(), item: PhantomData }));
+        self
+    }
+}
+
+impl CascadeHandler {
+    /// Removes the entries of every child cache referencing a deleted parent
+    async fn cascade_delete(&self, parent: Uuid) {
+        if parent.is_nil() {
+            warn!(table = %self.table_name, "Cascade: dropping delete without a key");
+            return;
+        }
+        for child in &self.children {
+            let removed = child.remove_children(parent).await;
+            debug!(
+                table = %self.table_name,
+                index = child.index_name(),
+                "Cascade: Removed {} entries referencing {}", removed, parent
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl CacheNotificationHandler for CascadeHandler {
+    async fn handle_notification(&self, notification: CacheNotification) {
+        let action = notification.action.clone();
+        let parent = notification.id;
+        if let Some(handler) = &self.parent {
+            handler.handle_notification(notification).await;
+        }
+        if action == "delete" {
+            self.cascade_delete(parent).await;
+        }
+    }
+
+    /// Passes the whole batch to the parent handler, then cascades its deletes in order
+    async fn handle_notifications(&self, notifications: Vec<CacheNotification>) {
+        let deleted: Vec<Uuid> = notifications
+            .iter()
+            .filter(|notification| notification.action == "delete")
+            .map(|notification| notification.id)
+            .collect();
+        if let Some(handler) = &self.parent {
+            handler.handle_notifications(notifications).await;
+        }
+        for parent in deleted {
+            self.cascade_delete(parent).await;
+        }
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn last_applied(&self) -> Option<AppliedInfo> {
+        self.parent.as_ref().and_then(|handler| handler.last_applied())
+    }
+
+    fn mark_loaded(&self) {
+        if let Some(handler) = &self.parent {
+            handler.mark_loaded();
+        }
+    }
+
+    async fn mark_ready(&self) {
+        if let Some(handler) = &self.parent {
+            handler.mark_ready().await;
+        }
+    }
+
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        self.parent.as_ref().and_then(|handler| handler.expected_fields())
+    }
+}
@@ -0,0 +1,146 @@
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::index_cache::IdxModelCache;
+use crate::traits::{HasPrimaryKey, Indexable};
+
+/// A change to the shared cache held back until no open transaction has the key staged
+pub type DeferredChange<T> = Box<dyn FnOnce(&mut IdxModelCache<T>) + Send>;
+
+struct CoordinatorState<T: HasPrimaryKey + Indexable + Clone> {
+    /// Number of open transactions with changes staged for each key
+    open_keys: HashMap<Uuid, usize>,
+    /// Changes for open keys, in arrival order
+    deferred: HashMap<Uuid, Vec<DeferredChange<T>>>,
+}
+
+/// Coordinates notification handlers and transaction-aware wrappers sharing one cache
+///
+/// A transaction created with `TransactionAwareIdxModelCache::new_coordinated`
+/// opens each key it stages. Notifications for open keys, delivered through an
+/// `IndexCacheHandler` created with `with_coordinator`, are deferred and applied
+/// right after the last transaction holding the key commits or rolls back, so a
+/// commit cannot overwrite a fresher value that arrived while it was open.
+pub struct SharedCacheCoordinator<T>
+where
+    T: HasPrimaryKey + Indexable + Clone + Send + Sync,
+{
+    cache: Arc<RwLock<IdxModelCache<T>>>,
+    state: Mutex<CoordinatorState<T>>,
+}
+
+impl<T> SharedCacheCoordinator<T>
+where
+    T: HasPrimaryKey + Indexable + Clone + Send + Sync,
+{
+    /// Creates a coordinator for a shared cache
+    pub fn new(cache: Arc<RwLock<IdxModelCache<T>>>) -> Self {
+        Self {
+            cache,
+            state: Mutex::new(CoordinatorState {
+                open_keys: HashMap::new(),
+                deferred: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the coordinated shared cache
+    pub fn cache(&self) -> &Arc<RwLock<IdxModelCache<T>>> {
+        &self.cache
+    }
+
+    /// Returns true if an open transaction has changes staged for the key
+    pub fn is_open(&self, primary_key: &Uuid) -> bool {
+        self.state.lock().open_keys.contains_key(primary_key)
+    }
+
+    /// Returns the number of changes waiting for transactions to complete
+    pub fn deferred_count(&self) -> usize {
+        self.state.lock().deferred.values().map(Vec::len).sum()
+    }
+
+    /// Applies a change to the shared cache now, or defers it if the key is open
+    ///
+    /// Returns true if the change was applied.
+    pub fn apply_or_defer(
+        &self,
+        primary_key: Uuid,
+        change: impl FnOnce(&mut IdxModelCache<T>) + Send + 'static,
+    ) -> bool {
+        // The state lock is released first: commits take the cache lock before it
+        let Some(change) = self.defer(primary_key, change) else {
+            return false;
+        };
+        change(&mut self.cache.write());
+        true
+    }
+
+    /// Like [`apply_or_defer`](Self::apply_or_defer), to the already locked
+    /// shared cache
+    ///
+    /// The state lock is taken while the cache lock is held, in the same
+    /// order as commits.
+    pub(crate) fn apply_or_defer_locked(
+        &self,
+        primary_key: Uuid,
+        change: impl FnOnce(&mut IdxModelCache<T>) + Send + 'static,
+        cache: &mut IdxModelCache<T>,
+    ) -> bool {
+        let Some(change) = self.defer(primary_key, change) else {
+            return false;
+        };
+        change(cache);
+        true
+    }
+
+    /// Defers a change if the key is open, or hands it back to be applied
+    fn defer<F>(&self, primary_key: Uuid, change: F) -> Option<F>
+    where
+        F: FnOnce(&mut IdxModelCache<T>) + Send + 'static,
+    {
+        let mut state = self.state.lock();
+        if state.open_keys.contains_key(&primary_key) {
+            state.deferred.entry(primary_key).or_default().push(Box::new(change));
+            debug!(%primary_key, "deferred change for key staged in an open transaction");
+            return None;
+        }
+        Some(change)
+    }
+
+    /// Drops all deferred changes, e.g. because the table was truncated
+    pub fn discard_deferred(&self) {
+        self.state.lock().deferred.clear();
+    }
+
+    /// Opens a key for a transaction
+    pub(crate) fn open(&self, primary_key: Uuid) {
+        *self.state.lock().open_keys.entry(primary_key).or_default() += 1;
+    }
+
+    /// Closes keys for a transaction and applies the changes deferred for
+    /// keys no other transaction holds, to the already locked shared cache
+    pub(crate) fn release(&self, primary_keys: impl IntoIterator<Item = Uuid>, cache: &mut IdxModelCache<T>) {
+        let mut ready = Vec::new();
+        {
+            let mut state = self.state.lock();
+            for primary_key in primary_keys {
+                let Some(count) = state.open_keys.get_mut(&primary_key) else {
+                    continue;
+                };
+                *count -= 1;
+                if *count == 0 {
+                    state.open_keys.remove(&primary_key);
+                    if let Some(changes) = state.deferred.remove(&primary_key) {
+                        ready.extend(changes);
+                    }
+                }
+            }
+        }
+        for change in ready {
+            change(cache);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Checking cached items against the database
+//!
+//! [`DriftChecker`] samples keys of a cache, loads the same rows from the
+//! database and compares them. Drift means a notification was lost or
+//! misapplied; the report tells how much, and auto-repair fixes the sampled
+//! keys.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use sqlx::PgPool;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::index_cache::IdxModelCache;
+use crate::main_model_cache::MainModelCache;
+use crate::traits::{HasPrimaryKey, Indexable};
+
+/// Loads the rows with the given primary keys from the database
+///
+/// Implemented for any `Fn(PgPool, Vec<Uuid>) -> impl Future<Output = Result<Vec<T>, sqlx::Error>>`,
+/// e.g. a closure running `SELECT ... WHERE id = ANY($1)`.
+#[async_trait]
+pub trait FetchByIds<T>: Send + Sync {
+    /// Fetches the existing rows among the given primary keys, in any order
+    async fn fetch_by_ids(&self, pool: &PgPool, ids: Vec<Uuid>) -> Result<Vec<T>, sqlx::Error>;
+}
+
+#[async_trait]
+impl<T, F, Fut> FetchByIds<T> for F
+where
+    T: Send + 'static,
+    F: Fn(PgPool, Vec<Uuid>) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Vec<T>, sqlx::Error>> + Send,
+{
+    async fn fetch_by_ids(&self, pool: &PgPool, ids: Vec<Uuid>) -> Result<Vec<T>, sqlx::Error> {
+        self(pool.clone(), ids).await
+    }
+}
+
+/// The outcome of one drift check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriftReport {
+    /// Number of keys checked
+    pub checked: usize,
+    /// Keys whose cached item differs from the row
+    pub mismatched: Vec<Uuid>,
+    /// Keys cached without a row in the database
+    pub missing_in_db: Vec<Uuid>,
+    /// Keys with a row in the database but no cached item; only keys passed
+    /// to `check_keys` can be found missing
+    pub missing_in_cache: Vec<Uuid>,
+    /// Keys fixed by auto-repair
+    pub repaired: usize,
+}
+
+impl DriftReport {
+    /// Returns true if no drift was found
+    pub fn is_clean(&self) -> bool {
+        self.mismatched.is_empty() && self.missing_in_db.is_empty() && self.missing_in_cache.is_empty()
+    }
+
+    /// Number of drifted keys
+    pub fn drifted(&self) -> usize {
+        self.mismatched.len() + self.missing_in_db.len() + self.missing_in_cache.len()
+    }
+}
+
+/// Compares a cached item with the row loaded from the database
+type Comparison<T> = dyn Fn(&T, &T) -> bool + Send + Sync;
+
+/// A cache the checker can sample and repair
+trait DriftTarget<T>: Send + Sync {
+    /// Keys of the cache for which `keep` returns true
+    fn sample(&self, keep: &dyn Fn(&Uuid) -> bool) -> Vec<Uuid>;
+
+    /// Copies of the cached items among the keys
+    fn cached(&self, keys: &[Uuid]) -> HashMap<Uuid, T>;
+
+    /// Replaces the cached item with `truth` if it is still `expected`
+    fn repair(&self, id: Uuid, expected: Option<&T>, truth: Option<&T>, same: &Comparison<T>) -> bool;
+}
+
+/// Returns true if the cached item is still the one that was checked
+fn unchanged<T>(current: Option<&T>, expected: Option<&T>, same: &Comparison<T>) -> bool {
+    match (current, expected) {
+        (Some(current), Some(expected)) => same(current, expected),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+impl<T> DriftTarget<T> for RwLock<IdxModelCache<T>>
+where
+    T: HasPrimaryKey + Indexable + Clone + Debug + Send + Sync,
+{
+    fn sample(&self, keep: &dyn Fn(&Uuid) -> bool) -> Vec<Uuid> {
+        self.read().iter().map(HasPrimaryKey::primary_key).filter(|id| keep(id)).collect()
+    }
+
+    fn cached(&self, keys: &[Uuid]) -> HashMap<Uuid, T> {
+        let cache = self.read();
+        keys.iter().filter_map(|id| cache.peek(id).map(|item| (*id, item.clone()))).collect()
+    }
+
+    fn repair(&self, id: Uuid, expected: Option<&T>, truth: Option<&T>, same: &Comparison<T>) -> bool {
+        let mut cache = self.write();
+        if !unchanged(cache.peek(&id), expected, same) {
+            return false;
+        }
+        match truth {
+            Some(item) => cache.add(item.clone()),
+            None => {
+                cache.remove(&id);
+            }
+        }
+        true
+    }
+}
+
+impl<T> DriftTarget<T> for RwLock<MainModelCache<T>>
+where
+    T: HasPrimaryKey + Clone + Debug + Send + Sync,
+{
+    fn sample(&self, keep: &dyn Fn(&Uuid) -> bool) -> Vec<Uuid> {
+        self.read().keys().filter(|id| keep(id)).copied().collect()
+    }
+
+    fn cached(&self, keys: &[Uuid]) -> HashMap<Uuid, T> {
+        let cache = self.read();
+        keys.iter().filter_map(|id| cache.peek(id).map(|item| (*id, T::clone(item)))).collect()
+    }
+
+    fn repair(&self, id: Uuid, expected: Option<&T>, truth: Option<&T>, same: &Comparison<T>) -> bool {
+        let mut cache = self.write();
+        if !unchanged(cache.peek(&id).map(|item| &**item), expected, same) {
+            return false;
+        }
+        match truth {
+            Some(item) => cache.update(item.clone()),
+            None => {
+                cache.remove(&id);
+            }
+        }
+        true
+    }
+}
+
+/// Compares a sample of cached items with the database
+///
+/// Repairs are applied only if the cached item did not change since it was
+/// compared, so a notification applied meanwhile is not overwritten with the
+/// row read before it. A row changed after it was read can still be written
+/// back by a repair; its notification corrects the cache right after.
+pub struct DriftChecker<T> {
+    target: Arc<dyn DriftTarget<T>>,
+    fetcher: Box<dyn FetchByIds<T>>,
+    same: Box<Comparison<T>>,
+    sample_rate: f64,
+    max_keys: Option<usize>,
+    auto_repair: bool,
+}
+
+impl<T: HasPrimaryKey + Clone + Debug + Send + Sync + 'static> DriftChecker<T> {
+    /// Create a checker for an index cache, comparing items with `PartialEq`
+    ///
+    /// Checks every cached key until a sample rate is set.
+    pub fn for_index_cache(cache: Arc<RwLock<IdxModelCache<T>>>, fetcher: impl FetchByIds<T> + 'static) -> Self
+    where
+        T: Indexable + PartialEq,
+    {
+        Self::from_target(cache, fetcher)
+    }
+
+    /// Create a checker for a main model cache, comparing items with `PartialEq`
+    ///
+    /// Keys that are not cached are not drift for a bounded cache, so leave
+    /// auto-repair off when checking uncached keys with `check_keys`.
+    pub fn for_main_cache(cache: Arc<RwLock<MainModelCache<T>>>, fetcher: impl FetchByIds<T> + 'static) -> Self
+    where
+        T: PartialEq,
+    {
+        Self::from_target(cache, fetcher)
+    }
+
+    fn from_target(target: Arc<dyn DriftTarget<T>>, fetcher: impl FetchByIds<T> + 'static) -> Self
+    where
+        T: PartialEq,
+    {
+        Self {
+            target,
+            fetcher: Box::new(fetcher),
+            same: Box::new(|cached: &T, row: &T| cached == row),
+            sample_rate: 1.0,
+            max_keys: None,
+            auto_repair: false,
+        }
+    }
+
+    /// Compare items with a custom function, e.g. one ignoring columns the cache does not keep
+    pub fn with_comparison(mut self, same: impl Fn(&T, &T) -> bool + Send + Sync + 'static) -> Self {
+        self.same = Box::new(same);
+        self
+    }
+
+    /// Check a random share of the cached keys per run, between 0.0 and 1.0
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Check at most this many keys per run
+    pub fn with_max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    /// Apply the database state to the cache for every drifted key
+    pub fn with_auto_repair(mut self) -> Self {
+        self.auto_repair = true;
+        self
+    }
+
+    /// Check a fresh random sample of the cached keys
+    pub async fn run_once(&self, pool: &PgPool) -> Result<DriftReport, sqlx::Error> {
+        // A new hasher per run draws a new sample
+        let hasher = RandomState::new();
+        let threshold = (self.sample_rate * u64::MAX as f64) as u64;
+        let mut keys = self.target.sample(&|id: &Uuid| self.sample_rate >= 1.0 || hasher.hash_one(id) < threshold);
+        if let Some(max_keys) = self.max_keys {
+            keys.truncate(max_keys);
+        }
+        self.check_keys(pool, keys).await
+    }
+
+    /// Check the given keys, cached or not
+    pub async fn check_keys(&self, pool: &PgPool, keys: Vec<Uuid>) -> Result<DriftReport, sqlx::Error> {
+        if keys.is_empty() {
+            return Ok(DriftReport::default());
+        }
+        let cached = self.target.cached(&keys);
+        let rows: HashMap<Uuid, T> = self
+            .fetcher
+            .fetch_by_ids(pool, keys.clone())
+            .await?
+            .into_iter()
+            .map(|row| (row.primary_key(), row))
+            .collect();
+
+        let mut report = DriftReport { checked: keys.len(), ..DriftReport::default() };
+        for id in keys {
+            let (item, row) = (cached.get(&id), rows.get(&id));
+            match (item, row) {
+                (Some(item), Some(row)) if (self.same)(item, row) => continue,
+                (Some(_), Some(_)) => report.mismatched.push(id),
+                (Some(_), None) => report.missing_in_db.push(id),
+                (None, Some(_)) => report.missing_in_cache.push(id),
+                (None, None) => continue,
+            }
+            if self.auto_repair && self.target.repair(id, item, row, &*self.same) {
+                report.repaired += 1;
+            }
+        }
+
+        if report.is_clean() {
+            debug!(checked = report.checked, "no cache drift found");
+        } else {
+            warn!(
+                checked = report.checked,
+                mismatched = report.mismatched.len(),
+                missing_in_db = report.missing_in_db.len(),
+                missing_in_cache = report.missing_in_cache.len(),
+                repaired = report.repaired,
+                "cache drift found"
+            );
+        }
+        Ok(report)
+    }
+}
@@ -1,7 +1,11 @@
+use std::fmt::{self, Debug};
+
 use postgres_unit_of_work::TransactionError;
+use uuid::Uuid;
 
 /// Error type for cache operations
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum CacheError {
     #[error("Duplicate primary key: {0}")]
     DuplicatePrimaryKey(String),
@@ -14,6 +18,72 @@ pub enum CacheError {
     
     #[error("Cache operation failed: {0}")]
     OperationFailed(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// Staged items whose cached version changed since they were staged or,
+    /// with commit validation, that are no longer cached
+    #[error("Version conflict: cached items changed since they were staged: {}", join_keys(keys))]
+    Conflict {
+        /// Primary keys of the conflicting items, sorted
+        keys: Vec<Uuid>,
+    },
+
+    #[error("Index not found: {0}")]
+    IndexNotFound(String),
+
+    /// Notification data could not be deserialized into the cached type
+    #[error("Failed to deserialize notification data for table '{table}': {source}")]
+    DeserializationFailed {
+        table: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// A handler's payload migrator rejected notification data
+    #[error("Failed to migrate notification data for table '{table}': {reason}")]
+    PayloadMigrationFailed { table: String, reason: String },
+
+    /// An item could not be serialized into a notification
+    #[error("Failed to serialize notification data for table '{table}': {source}")]
+    SerializationFailed {
+        table: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    /// Protected notification data failed verification or decryption, or
+    /// arrived unprotected while a payload key is set
+    #[error("Rejected notification data for table '{table}': {reason}")]
+    PayloadRejected { table: String, reason: String },
+
+    /// A payload is not a valid cache notification
+    #[error("Invalid notification payload: {0}")]
+    InvalidPayload(#[source] serde_json::Error),
+
+    /// More distinct items than the cache can hold would be added at once, or
+    /// a transaction would stage beyond its staging limit
+    #[error("Capacity exceeded: the limit is {limit}")]
+    CapacityExceeded { limit: usize },
+
+    /// The notification listener lost its database connection
+    #[cfg(feature = "sqlx-listener")]
+    #[error("Listener error: {0}")]
+    ListenerError(#[from] sqlx::Error),
+
+    /// Some participants of a transaction group committed before others failed
+    #[error("Partial commit: committed [{}], failed [{}]", committed.join(", "), failed.join(", "))]
+    PartialCommit {
+        /// Names of the participants that committed
+        committed: Vec<String>,
+        /// Names of the participants that failed, with their errors
+        failed: Vec<String>,
+    },
+}
+
+fn join_keys(keys: &[Uuid]) -> String {
+    keys.iter().map(Uuid::to_string).collect::<Vec<_>>().join(", ")
 }
 
 /// Result type for cache operations
@@ -25,9 +95,67 @@ impl From<CacheError> for TransactionError {
         match err {
             CacheError::CommitFailed(msg) => TransactionError::CommitFailed(msg),
             CacheError::RollbackFailed(msg) => TransactionError::RollbackFailed(msg),
-            CacheError::DuplicatePrimaryKey(msg) | CacheError::OperationFailed(msg) => {
+            CacheError::DuplicatePrimaryKey(msg)
+            | CacheError::OperationFailed(msg)
+            | CacheError::InvalidArgument(msg)
+            | CacheError::IndexNotFound(msg) => {
                 TransactionError::CommitFailed(format!("Cache error: {msg}"))
             }
+            err @ (CacheError::Conflict { .. }
+            | CacheError::DeserializationFailed { .. }
+            | CacheError::PayloadMigrationFailed { .. }
+            | CacheError::SerializationFailed { .. }
+            | CacheError::PayloadRejected { .. }
+            | CacheError::InvalidPayload(_)
+            | CacheError::CapacityExceeded { .. }) => {
+                TransactionError::CommitFailed(format!("Cache error: {err}"))
+            }
+            #[cfg(feature = "sqlx-listener")]
+            err @ CacheError::ListenerError(_) => TransactionError::CommitFailed(format!("Cache error: {err}")),
+            err @ CacheError::PartialCommit { .. } => TransactionError::CommitFailed(err.to_string()),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Staged changes a commit could not apply, with how many it did apply
+///
+/// Returned by `try_commit` of the transaction-aware caches; `on_commit`
+/// converts it into a `TransactionError`. Which changes stay staged after a
+/// failure is documented on each cache's `try_commit`.
+#[derive(Debug)]
+pub struct CommitError<K = Uuid> {
+    /// Number of staged changes applied to the shared cache
+    pub applied: usize,
+    /// The key of each change that was not applied, with the reason
+    pub failures: Vec<(K, CacheError)>,
+}
+
+impl<K> CommitError<K> {
+    /// The keys of the changes that were not applied, in the order they failed
+    pub fn failed_keys(&self) -> impl Iterator<Item = &K> {
+        self.failures.iter().map(|(key, _)| key)
+    }
+
+    /// Returns true if some staged changes were applied before others failed
+    pub fn is_partial(&self) -> bool {
+        self.applied > 0
+    }
+}
+
+impl<K: Debug> fmt::Display for CommitError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Commit applied {} changes, {} failed", self.applied, self.failures.len())?;
+        for (i, (key, err)) in self.failures.iter().enumerate() {
+            write!(f, "{} {key:?}: {err}", if i == 0 { ":" } else { ";" })?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: Debug> std::error::Error for CommitError<K> {}
+
+impl<K: Debug> From<CommitError<K>> for TransactionError {
+    fn from(err: CommitError<K>) -> Self {
+        TransactionError::CommitFailed(err.to_string())
+    }
+}
@@ -0,0 +1,196 @@
+//! The order in which `MainModelCache` evicts entries, one implementation per policy
+//!
+//! Each entry keeps its position in the order, a sequence number handed out
+//! by the cache; the order maps positions back to keys. An implementation
+//! only reacts to the events that move entries under its policy: the FIFO
+//! order has no code path for reads at all, so a read can never reorder it.
+
+use std::collections::BTreeMap;
+
+use crate::main_model_cache::{CacheConfig, EvictionPolicy};
+
+/// Keeps the keys of the cached entries in eviction order
+pub(crate) trait EvictionOrder<K> {
+    /// Appends a new entry at `position`
+    fn insert(&mut self, key: &K, position: u64);
+
+    /// Forgets the entry at `position`
+    fn remove(&mut self, position: u64);
+
+    /// Records a hit on an entry; `next` is a fresh position it may move to
+    fn read(&mut self, _key: &K, _position: &mut u64, _next: u64) {}
+
+    /// Records an update of a cached entry; `next` is a fresh position it may move to
+    fn update(&mut self, key: &K, position: &mut u64, next: u64);
+
+    /// The key to evict next
+    fn next_victim(&self) -> Option<&K>;
+
+    /// Forgets all entries
+    fn clear(&mut self);
+}
+
+/// Keys by position, front first
+#[derive(Debug)]
+struct Positions<K>(BTreeMap<u64, K>);
+
+impl<K: Clone> Positions<K> {
+    fn move_to_back(&mut self, key: &K, position: &mut u64, next: u64) {
+        self.0.remove(position);
+        *position = next;
+        self.0.insert(next, key.clone());
+    }
+}
+
+/// Least recently used first; hits, and updates unless disabled, move an entry to the back
+#[derive(Debug)]
+pub(crate) struct LruOrder<K> {
+    positions: Positions<K>,
+    touch_on_update: bool,
+}
+
+impl<K: Clone> EvictionOrder<K> for LruOrder<K> {
+    fn insert(&mut self, key: &K, position: u64) {
+        self.positions.0.insert(position, key.clone());
+    }
+
+    fn remove(&mut self, position: u64) {
+        self.positions.0.remove(&position);
+    }
+
+    fn read(&mut self, key: &K, position: &mut u64, next: u64) {
+        self.positions.move_to_back(key, position, next);
+    }
+
+    fn update(&mut self, key: &K, position: &mut u64, next: u64) {
+        if self.touch_on_update {
+            self.positions.move_to_back(key, position, next);
+        }
+    }
+
+    fn next_victim(&self) -> Option<&K> {
+        self.positions.0.values().next()
+    }
+
+    fn clear(&mut self) {
+        self.positions.0.clear();
+    }
+}
+
+/// First inserted first; only updates with `requeue_on_update` move an entry
+#[derive(Debug)]
+pub(crate) struct FifoOrder<K> {
+    positions: Positions<K>,
+    requeue_on_update: bool,
+}
+
+impl<K: Clone> EvictionOrder<K> for FifoOrder<K> {
+    fn insert(&mut self, key: &K, position: u64) {
+        self.positions.0.insert(position, key.clone());
+    }
+
+    fn remove(&mut self, position: u64) {
+        self.positions.0.remove(&position);
+    }
+
+    fn update(&mut self, key: &K, position: &mut u64, next: u64) {
+        if self.requeue_on_update {
+            self.positions.move_to_back(key, position, next);
+        }
+    }
+
+    fn next_victim(&self) -> Option<&K> {
+        self.positions.0.values().next()
+    }
+
+    fn clear(&mut self) {
+        self.positions.0.clear();
+    }
+}
+
+/// The order of the configured policy, chosen when the cache is created
+#[derive(Debug)]
+pub(crate) enum PolicyOrder<K> {
+    Lru(LruOrder<K>),
+    Fifo(FifoOrder<K>),
+}
+
+impl<K> PolicyOrder<K> {
+    pub(crate) fn for_config(config: &CacheConfig) -> Self {
+        match config.eviction_policy {
+            EvictionPolicy::LRU => Self::Lru(LruOrder {
+                positions: Positions(BTreeMap::new()),
+                touch_on_update: config.lru.unwrap_or_default().touch_on_update,
+            }),
+            EvictionPolicy::FIFO => Self::Fifo(FifoOrder {
+                positions: Positions(BTreeMap::new()),
+                requeue_on_update: config.fifo.unwrap_or_default().requeue_on_update,
+            }),
+        }
+    }
+
+    fn order(&mut self) -> &mut dyn EvictionOrder<K>
+    where
+        K: Clone,
+    {
+        match self {
+            Self::Lru(order) => order,
+            Self::Fifo(order) => order,
+        }
+    }
+}
+
+impl<K: Clone> EvictionOrder<K> for PolicyOrder<K> {
+    fn insert(&mut self, key: &K, position: u64) {
+        self.order().insert(key, position);
+    }
+
+    fn remove(&mut self, position: u64) {
+        self.order().remove(position);
+    }
+
+    fn read(&mut self, key: &K, position: &mut u64, next: u64) {
+        self.order().read(key, position, next);
+    }
+
+    fn update(&mut self, key: &K, position: &mut u64, next: u64) {
+        self.order().update(key, position, next);
+    }
+
+    fn next_victim(&self) -> Option<&K> {
+        match self {
+            Self::Lru(order) => order.next_victim(),
+            Self::Fifo(order) => order.next_victim(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.order().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(policy: EvictionPolicy) -> PolicyOrder<&'static str> {
+        PolicyOrder::for_config(&CacheConfig::new(10, policy))
+    }
+
+    #[test]
+    fn test_reads_only_move_lru_entries() {
+        for (policy, victim) in [(EvictionPolicy::LRU, "b"), (EvictionPolicy::FIFO, "a")] {
+            let mut order = order(policy);
+            let (mut a, b) = (0, 1);
+            order.insert(&"a", a);
+            order.insert(&"b", b);
+            order.read(&"a", &mut a, 2);
+            assert_eq!(order.next_victim(), Some(&victim), "{policy:?}");
+
+            order.remove(if victim == "a" { a } else { b });
+            assert_ne!(order.next_victim(), Some(&victim));
+            order.clear();
+            assert_eq!(order.next_victim(), None);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Checking at startup that handlers expect the columns their tables have
+//!
+//! A handler registered for the wrong table fails to deserialize every
+//! notification it receives, which only shows up at runtime. Handlers
+//! describe the fields they read from notification data with
+//! [`CacheNotificationHandler::expected_fields`], and
+//! [`validate_handlers_against_db`] compares them with the columns listed in
+//! `information_schema.columns`.
+//!
+//! [`CacheNotificationHandler::expected_fields`]: crate::CacheNotificationHandler::expected_fields
+
+use std::fmt;
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+
+#[cfg(feature = "sqlx-listener")]
+use sqlx::{Acquire, Postgres};
+
+#[cfg(feature = "sqlx-listener")]
+use crate::listener::CacheNotificationListener;
+
+/// The field names a type deserializes from, as derived by serde
+///
+/// Honours `#[serde(rename)]` and `rename_all`. Returns `None` for types that
+/// are not deserialized as a struct, e.g. maps or types using
+/// `#[serde(flatten)]`, whose fields serde does not list.
+pub fn serde_fields<T: DeserializeOwned>() -> Option<Vec<String>> {
+    let mut fields = None;
+    // The recorder always fails once it saw the fields, so the result is of no use
+    let _ = T::deserialize(FieldRecorder(&mut fields));
+    fields.map(|fields| fields.iter().map(|field| field.to_string()).collect())
+}
+
+/// A deserializer recording the fields of the struct asked for, then failing
+struct FieldRecorder<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for FieldRecorder<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// A handler expecting fields its table has no column for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMismatch {
+    /// The table the handler is registered for
+    pub table: String,
+    /// Fields the handler expects that are not columns of the table
+    pub missing: Vec<String>,
+}
+
+/// Result of comparing the registered handlers with the columns of their tables
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandlerSchemaReport {
+    /// Handlers expecting fields their table lacks
+    pub mismatched: Vec<FieldMismatch>,
+    /// Tables with a handler but no columns visible on the search path
+    pub missing_tables: Vec<String>,
+    /// Tables whose handler does not describe the fields it expects
+    pub unchecked: Vec<String>,
+}
+
+impl HandlerSchemaReport {
+    /// Returns true if every checked handler found all its fields
+    ///
+    /// Unchecked handlers do not count against the result.
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing_tables.is_empty()
+    }
+}
+
+impl fmt::Display for HandlerSchemaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "handler fields verified");
+        }
+        let mut problems = Vec::new();
+        for table in &self.missing_tables {
+            problems.push(format!("table '{table}' does not exist"));
+        }
+        for mismatch in &self.mismatched {
+            problems.push(format!(
+                "handler for '{}' expects missing columns {:?}",
+                mismatch.table, mismatch.missing
+            ));
+        }
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+/// Error returned by [`assert_handlers_match_db`]
+#[cfg(feature = "sqlx-listener")]
+#[derive(Debug, thiserror::Error)]
+pub enum HandlerSchemaError {
+    #[error("Failed to query table columns: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Handler field validation failed: {0}")]
+    Mismatch(HandlerSchemaReport),
+}
+
+/// Compare the fields each registered handler expects with the columns of its table
+///
+/// Columns are looked up in `information_schema.columns` for tables on the
+/// current search path. Only fields missing from a table are reported; columns
+/// a handler does not read are fine. A model with optional or defaulted fields
+/// may be reported although it would deserialize, declare its fields with the
+/// handler's `with_expected_fields` then.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use sqlx::PgPool;
+/// use postgres_index_cache::{validate_handlers_against_db, CacheNotificationListener};
+///
+/// # async fn example(pool: &PgPool, listener: &CacheNotificationListener) -> Result<(), Box<dyn std::error::Error>> {
+/// let report = validate_handlers_against_db(pool, listener).await?;
+/// if !report.is_ok() {
+///     tracing::warn!(%report, "handlers do not match their tables");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "sqlx-listener")]
+pub async fn validate_handlers_against_db<'c, A>(
+    conn: A,
+    listener: &CacheNotificationListener,
+) -> Result<HandlerSchemaReport, sqlx::Error>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut conn = conn.acquire().await?;
+    let mut report = HandlerSchemaReport::default();
+    for (table, handler) in listener.registry().handlers() {
+        let Some(expected) = handler.expected_fields() else {
+            report.unchecked.push(table);
+            continue;
+        };
+        let columns: Vec<String> = sqlx::query_scalar(
+            "SELECT column_name::text FROM information_schema.columns
+             WHERE table_name = $1 AND table_schema = ANY(current_schemas(false))",
+        )
+        .bind(&table)
+        .fetch_all(&mut *conn)
+        .await?;
+        if columns.is_empty() {
+            report.missing_tables.push(table);
+            continue;
+        }
+        let missing: Vec<String> = expected.into_iter().filter(|field| !columns.contains(field)).collect();
+        if !missing.is_empty() {
+            report.mismatched.push(FieldMismatch { table, missing });
+        }
+    }
+    Ok(report)
+}
+
+/// Like [`validate_handlers_against_db`], but fails on any mismatch
+///
+/// Convenient to fail service startup when a handler is registered for the
+/// wrong table.
+#[cfg(feature = "sqlx-listener")]
+pub async fn assert_handlers_match_db<'c, A>(
+    conn: A,
+    listener: &CacheNotificationListener,
+) -> Result<(), HandlerSchemaError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let report = validate_handlers_against_db(conn, listener).await?;
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(HandlerSchemaError::Mismatch(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Row {
+        id: uuid::Uuid,
+        #[serde(rename = "user_name")]
+        name: String,
+    }
+
+    #[test]
+    fn test_serde_fields_lists_renamed_fields() {
+        assert_eq!(serde_fields::<Row>(), Some(vec!["id".to_string(), "user_name".to_string()]));
+        assert_eq!(serde_fields::<std::collections::HashMap<String, String>>(), None);
+    }
+}
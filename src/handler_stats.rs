@@ -0,0 +1,163 @@
+//! Latency statistics of notification handlers
+//!
+//! Every handler invocation is recorded in a fixed-bucket histogram of plain
+//! atomics, so recording never blocks and needs no metrics backend. Handlers
+//! also remember the last notification they applied, so a cache that stopped
+//! receiving changes can be told apart from one whose table is quiet.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+
+/// Upper bounds of the histogram buckets in microseconds; a last bucket
+/// collects the invocations above the largest bound
+const BUCKET_BOUNDS_MICROS: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+    2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Latency statistics of one handler
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HandlerStats {
+    /// Number of invocations, including those that timed out
+    pub count: u64,
+    /// Mean duration of an invocation
+    pub mean: Duration,
+    /// Upper bound of the histogram bucket holding the 95th percentile;
+    /// the maximum for invocations above the largest bucket
+    pub p95: Duration,
+    /// Longest invocation
+    pub max: Duration,
+    /// Invocations abandoned after the handler timeout
+    pub timeouts: u64,
+    /// Retries of notifications the handler did not complete
+    pub retries: u64,
+    /// Notifications given up on after the last retry, or at once without
+    /// a retry policy
+    pub failures: u64,
+    /// Retries ended because a newer notification of the same item arrived
+    pub superseded: u64,
+}
+
+/// Durations of a handler's invocations
+#[derive(Debug, Default)]
+pub(crate) struct LatencyHistogram {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKET_BOUNDS_MICROS.len() + 1],
+}
+
+impl LatencyHistogram {
+    /// Records the duration of one invocation
+    pub(crate) fn record(&self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS_MICROS.partition_point(|bound| *bound < micros);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    /// The statistics recorded so far
+    ///
+    /// Concurrent invocations may be counted in some fields and not yet in
+    /// others.
+    pub(crate) fn stats(&self, timeouts: u64) -> HandlerStats {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return HandlerStats { timeouts, ..HandlerStats::default() };
+        }
+        let max_micros = self.max_micros.load(Ordering::Relaxed);
+
+        let rank = count.saturating_mul(95).div_ceil(100);
+        let mut seen = 0;
+        let mut p95_micros = max_micros;
+        for (bucket, counter) in self.buckets.iter().enumerate() {
+            seen += counter.load(Ordering::Relaxed);
+            if seen >= rank {
+                p95_micros = BUCKET_BOUNDS_MICROS.get(bucket).map_or(max_micros, |bound| (*bound).min(max_micros));
+                break;
+            }
+        }
+
+        HandlerStats {
+            count,
+            mean: Duration::from_micros(self.total_micros.load(Ordering::Relaxed) / count),
+            p95: Duration::from_micros(p95_micros),
+            max: Duration::from_micros(max_micros),
+            timeouts,
+            ..HandlerStats::default()
+        }
+    }
+}
+
+/// The last change a handler applied to its cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedInfo {
+    /// When the handler applied it
+    pub at: DateTime<Utc>,
+    /// The action of the notification, or "load" for a cache loaded from its table
+    pub action: String,
+    /// Primary key of the affected row; `None` for whole-table changes
+    pub key: Option<String>,
+}
+
+/// The last change a handler applied, shared with its deferred changes
+#[derive(Debug, Default)]
+pub(crate) struct LastApplied(RwLock<Option<AppliedInfo>>);
+
+impl LastApplied {
+    pub(crate) fn record(&self, action: &str, key: Option<String>) {
+        *self.0.write() = Some(AppliedInfo { at: Utc::now(), action: action.to_string(), key });
+    }
+
+    pub(crate) fn record_load(&self) {
+        self.record("load", None);
+    }
+
+    pub(crate) fn get(&self) -> Option<AppliedInfo> {
+        self.0.read().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_histogram() {
+        let stats = LatencyHistogram::default().stats(0);
+        assert_eq!(stats, HandlerStats::default());
+    }
+
+    #[test]
+    fn test_stats_from_recorded_durations() {
+        let histogram = LatencyHistogram::default();
+        for _ in 0..95 {
+            histogram.record(Duration::from_micros(200));
+        }
+        for _ in 0..5 {
+            histogram.record(Duration::from_millis(40));
+        }
+
+        let stats = histogram.stats(2);
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.mean, Duration::from_micros((95 * 200 + 5 * 40_000) / 100));
+        assert_eq!(stats.p95, Duration::from_micros(250));
+        assert_eq!(stats.max, Duration::from_millis(40));
+        assert_eq!(stats.timeouts, 2);
+
+        // One more slow invocation moves the 95th percentile to its bucket
+        histogram.record(Duration::from_millis(40));
+        assert_eq!(histogram.stats(2).p95, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_durations_above_the_largest_bucket() {
+        let histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_secs(30));
+        assert_eq!(histogram.stats(0).p95, Duration::from_secs(30));
+    }
+}
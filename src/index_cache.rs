@@ -1,24 +1,148 @@
-use std::collections::HashMap;
+use chrono::{DateTime, SubsecRound, Utc};
+use imbl::OrdSet;
+use serde::Serialize;
+use serde_json::json;
+use smallvec::SmallVec;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::ops::{Bound, Deref, RangeBounds};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
 use uuid::Uuid;
 
 use crate::error::CacheError;
-use crate::traits::{HasPrimaryKey, Indexable};
+use crate::lock::CacheLock;
+use crate::traits::{HasPrimaryKey, IndexKeys, Indexable, IsDeleted};
+
+/// Primary keys sharing one index value; most values belong to a single item.
+type Postings = SmallVec<[Uuid; 1]>;
+
+/// The postings of a hashed index by value
+type HashIndex<V> = imbl::HashMap<V, Postings>;
+
+/// The postings of an ordered index by value
+type OrdIndex<V> = imbl::OrdMap<V, Postings>;
 
 /// A generic cache for index models.
+///
+/// Items and indexes are held in persistent maps shared with the snapshots
+/// taken by `snapshot_arc` and with clones. A change copies only the map
+/// nodes on the paths to the entries it touches.
 #[derive(Debug, Clone)]
 pub struct IdxModelCache<T: HasPrimaryKey + Indexable + Clone> {
-    by_id: HashMap<Uuid, T>,
-    i64_indexes: HashMap<String, HashMap<i64, Vec<Uuid>>>,
-    uuid_indexes: HashMap<String, HashMap<Uuid, Vec<Uuid>>>,
+    storage: Arc<IdxStorage<T>>,
+    /// Identifies the cache in logs and statistics
+    name: Option<String>,
+    string_normalizers: StringNormalizers,
+    /// Signalled on every change, for `wait_until`
+    changed: Arc<Notify>,
+    /// Set by `mark_ready` once the cache is loaded
+    ready: bool,
+    /// Counts the changes, see `generation`
+    generation: u64,
+    /// Whether a change was made in the running `batch`, if any
+    batch_changed: Option<bool>,
+}
+
+/// Rewrites String index values before they are indexed or looked up, e.g.
+/// to ignore case and surrounding whitespace
+pub type StringNormalizer = dyn Fn(&str) -> String + Send + Sync;
+
+/// The normalizer of each String index that has one
+#[derive(Clone, Default)]
+struct StringNormalizers(HashMap<String, Arc<StringNormalizer>>);
+
+impl StringNormalizers {
+    fn normalize<'a>(&self, index_name: &str, value: &'a str) -> Cow<'a, str> {
+        match self.0.get(index_name) {
+            Some(normalizer) => Cow::Owned(normalizer(value)),
+            None => Cow::Borrowed(value),
+        }
+    }
+
+    fn normalize_owned(&self, index_name: &str, value: String) -> String {
+        match self.0.get(index_name) {
+            Some(normalizer) => normalizer(&value),
+            None => value,
+        }
+    }
+}
+
+impl Debug for StringNormalizers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// The items and indexes of an `IdxModelCache`
+///
+/// Cloning it copies the maps by index name, not the persistent maps they hold.
+#[derive(Debug, Clone)]
+struct IdxStorage<T> {
+    by_id: imbl::HashMap<Uuid, T>,
+    /// The keys of `by_id` in order, for `iter_sorted` and paging
+    sorted_ids: OrdSet<Uuid>,
+    i64_indexes: HashMap<String, HashIndex<i64>>,
+    uuid_indexes: HashMap<String, HashIndex<Uuid>>,
+    datetime_indexes: HashMap<String, OrdIndex<DateTime<Utc>>>,
+    string_indexes: HashMap<String, OrdIndex<String>>,
+    /// Every index name seen on an item or declared explicitly, kept when
+    /// the index becomes empty
+    declared_indexes: HashMap<IndexKind, HashSet<String>>,
+    /// Number of items per index value, kept only when enabled with `with_index_counts`
+    index_counts: Option<HashMap<String, imbl::HashMap<IndexValue, usize>>>,
+}
+
+/// An immutable view of an `IdxModelCache`, taken by `snapshot_arc`
+///
+/// Dereferences to the cache, so every read method is available. Lookups
+/// take no lock and all agree with each other; changes made to the cache
+/// after the snapshot was taken are not visible. Cloning is cheap.
+#[derive(Debug, Clone)]
+pub struct IdxSnapshot<T: HasPrimaryKey + Indexable + Clone>(IdxModelCache<T>);
+
+impl<T: HasPrimaryKey + Indexable + Clone> Deref for IdxSnapshot<T> {
+    type Target = IdxModelCache<T>;
+
+    fn deref(&self) -> &IdxModelCache<T> {
+        &self.0
+    }
+}
+
+/// A secondary index value of any kind, as reported by `IdxModelCache::index_histogram`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum IndexValue {
+    I64(i64),
+    Uuid(Uuid),
+    DateTime(DateTime<Utc>),
+    String(String),
+}
+
+/// The kinds of secondary index an item can declare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum IndexKind {
+    I64,
+    Uuid,
+    DateTime,
+    String,
+}
+
+/// Truncates a DateTime key to the microsecond precision of PostgreSQL timestamps.
+pub(crate) fn datetime_key(value: DateTime<Utc>) -> DateTime<Utc> {
+    value.trunc_subsecs(6)
 }
 
 impl<T: HasPrimaryKey + Indexable + Clone + Debug> IdxModelCache<T> {
     /// Creates a new cache from a vector of items.
     pub fn new(items: Vec<T>) -> Result<Self, CacheError> {
-        let mut by_id = HashMap::new();
-        let mut i64_indexes: HashMap<String, HashMap<i64, Vec<Uuid>>> = HashMap::new();
-        let mut uuid_indexes: HashMap<String, HashMap<Uuid, Vec<Uuid>>> = HashMap::new();
+        let mut by_id = imbl::HashMap::new();
+        let mut i64_indexes: HashMap<String, HashIndex<i64>> = HashMap::new();
+        let mut uuid_indexes: HashMap<String, HashIndex<Uuid>> = HashMap::new();
+        let mut datetime_indexes: HashMap<String, OrdIndex<DateTime<Utc>>> = HashMap::new();
+        let mut string_indexes: HashMap<String, OrdIndex<String>> = HashMap::new();
+        let mut declared_indexes: HashMap<IndexKind, HashSet<String>> = HashMap::new();
 
         for item in items {
             let primary_key = item.primary_key();
@@ -26,115 +150,635 @@ impl<T: HasPrimaryKey + Indexable + Clone + Debug> IdxModelCache<T> {
                 return Err(CacheError::DuplicatePrimaryKey(primary_key.to_string()));
             }
 
-            Self::index_item(&item, primary_key, &mut i64_indexes, &mut uuid_indexes);
+            Self::index_item(
+                &item,
+                primary_key,
+                &mut i64_indexes,
+                &mut uuid_indexes,
+                &mut datetime_indexes,
+                &mut string_indexes,
+                &mut declared_indexes,
+                &StringNormalizers::default(),
+            );
 
             by_id.insert(primary_key, item);
         }
 
         Ok(IdxModelCache {
-            by_id,
-            i64_indexes,
-            uuid_indexes,
+            storage: Arc::new(IdxStorage {
+                sorted_ids: by_id.keys().copied().collect(),
+                by_id,
+                i64_indexes,
+                uuid_indexes,
+                datetime_indexes,
+                string_indexes,
+                declared_indexes,
+                index_counts: None,
+            }),
+            name: None,
+            string_normalizers: StringNormalizers::default(),
+            changed: Arc::new(Notify::new()),
+            ready: false,
+            generation: 0,
+            batch_changed: None,
         })
     }
 
+    /// Creates a new cache from a vector of items, keeping the last occurrence
+    /// of each duplicate primary key instead of failing.
+    ///
+    /// Returns the cache and each duplicate key, once per extra occurrence.
+    /// Postings of replaced occurrences are removed from the indexes.
+    pub fn new_lenient(items: Vec<T>) -> (Self, Vec<Uuid>) {
+        let mut cache = IdxModelCache {
+            storage: Arc::new(IdxStorage {
+                by_id: imbl::HashMap::new(),
+                sorted_ids: OrdSet::new(),
+                i64_indexes: HashMap::new(),
+                uuid_indexes: HashMap::new(),
+                datetime_indexes: HashMap::new(),
+                string_indexes: HashMap::new(),
+                declared_indexes: HashMap::new(),
+                index_counts: None,
+            }),
+            name: None,
+            string_normalizers: StringNormalizers::default(),
+            changed: Arc::new(Notify::new()),
+            ready: false,
+            generation: 0,
+            batch_changed: None,
+        };
+        let mut duplicates = Vec::new();
+
+        for item in items {
+            let primary_key = item.primary_key();
+            if cache.storage.by_id.contains_key(&primary_key) {
+                duplicates.push(primary_key);
+            }
+            cache.add(item);
+        }
+
+        (cache, duplicates)
+    }
+
+    /// Names the cache, so its log lines and statistics tell it apart from other caches.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// The name set with `with_name`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Declares an i64 index, so queries on it succeed before any item has it.
+    pub fn with_i64_index(mut self, index_name: impl Into<String>) -> Self {
+        Self::declare(&mut Arc::make_mut(&mut self.storage).declared_indexes, IndexKind::I64, &index_name.into());
+        self
+    }
+
+    /// Declares a Uuid index, so queries on it succeed before any item has it.
+    pub fn with_uuid_index(mut self, index_name: impl Into<String>) -> Self {
+        Self::declare(&mut Arc::make_mut(&mut self.storage).declared_indexes, IndexKind::Uuid, &index_name.into());
+        self
+    }
+
+    /// Declares a DateTime index, so queries on it succeed before any item has it.
+    pub fn with_datetime_index(mut self, index_name: impl Into<String>) -> Self {
+        Self::declare(&mut Arc::make_mut(&mut self.storage).declared_indexes, IndexKind::DateTime, &index_name.into());
+        self
+    }
+
+    /// Declares a String index, so queries on it succeed before any item has it.
+    pub fn with_string_index(mut self, index_name: impl Into<String>) -> Self {
+        Self::declare(&mut Arc::make_mut(&mut self.storage).declared_indexes, IndexKind::String, &index_name.into());
+        self
+    }
+
+    /// Keeps the number of items per index value, for `index_histogram`.
+    /// Costs one map update per index of every added or removed item.
+    pub fn with_index_counts(mut self) -> Self {
+        let mut counts = HashMap::new();
+        for item in self.storage.by_id.values() {
+            Self::count_item(&mut counts, item, true, &self.string_normalizers);
+        }
+        Arc::make_mut(&mut self.storage).index_counts = Some(counts);
+        self
+    }
+
+    /// Normalizes the values of a String index, when items are indexed and
+    /// when they are looked up.
+    ///
+    /// With `|value| value.trim().to_lowercase()`, an item indexed under
+    /// "Alice@Example.com " is found by "alice@example.com" and the other way
+    /// around. The normalizer should be idempotent. Items already cached are
+    /// re-indexed.
+    pub fn with_string_normalizer(mut self, index_name: impl Into<String>, normalizer: Arc<StringNormalizer>) -> Self {
+        let index_name = index_name.into();
+        Self::declare(&mut Arc::make_mut(&mut self.storage).declared_indexes, IndexKind::String, &index_name);
+        self.string_normalizers.0.insert(index_name, normalizer);
+        self.reindex();
+        self
+    }
+
+    /// Normalizes a value of a String index as the index does, e.g. to hash
+    /// it for an i64 index that must agree with it.
+    pub fn normalize_string<'a>(&self, index_name: &str, value: &'a str) -> Cow<'a, str> {
+        self.string_normalizers.normalize(index_name, value)
+    }
+
+    /// The normalizer of a String index, if it has one.
+    pub(crate) fn string_normalizer(&self, index_name: &str) -> Option<Arc<StringNormalizer>> {
+        self.string_normalizers.0.get(index_name).cloned()
+    }
+
+    /// Rebuilds the indexes and counts from the cached items.
+    fn reindex(&mut self) {
+        let storage = Arc::make_mut(&mut self.storage);
+        storage.i64_indexes.clear();
+        storage.uuid_indexes.clear();
+        storage.datetime_indexes.clear();
+        storage.string_indexes.clear();
+        if let Some(counts) = &mut storage.index_counts {
+            counts.clear();
+        }
+        for (primary_key, item) in &storage.by_id {
+            Self::index_item(
+                item,
+                *primary_key,
+                &mut storage.i64_indexes,
+                &mut storage.uuid_indexes,
+                &mut storage.datetime_indexes,
+                &mut storage.string_indexes,
+                &mut storage.declared_indexes,
+                &self.string_normalizers,
+            );
+            if let Some(counts) = &mut storage.index_counts {
+                Self::count_item(counts, item, true, &self.string_normalizers);
+            }
+        }
+    }
+
     /// Adds an item to the cache. If the item already exists, it will be updated.
     pub fn add(&mut self, item: T) {
         let primary_key = item.primary_key();
-        if self.by_id.contains_key(&primary_key) {
+        if self.storage.by_id.contains_key(&primary_key) {
             self.update(item);
             return;
         }
 
-        Self::index_item(&item, primary_key, &mut self.i64_indexes, &mut self.uuid_indexes,);
+        let storage = Arc::make_mut(&mut self.storage);
+        Self::index_item(
+            &item,
+            primary_key,
+            &mut storage.i64_indexes,
+            &mut storage.uuid_indexes,
+            &mut storage.datetime_indexes,
+            &mut storage.string_indexes,
+            &mut storage.declared_indexes,
+            &self.string_normalizers,
+        );
+        if let Some(counts) = &mut storage.index_counts {
+            Self::count_item(counts, &item, true, &self.string_normalizers);
+        }
 
-        self.by_id.insert(primary_key, item);
+        storage.by_id.insert(primary_key, item);
+        storage.sorted_ids.insert(primary_key);
+        self.record_change();
     }
 
     /// Removes an item from the cache by its primary key.
     pub fn remove(&mut self, primary_key: &Uuid) -> Option<T> {
-        if let Some(item) = self.by_id.remove(primary_key) {
+        // Leaves storage shared with snapshots alone if there is nothing to remove
+        if !self.storage.by_id.contains_key(primary_key) {
+            return None;
+        }
+        let storage = Arc::make_mut(&mut self.storage);
+        if let Some(item) = storage.by_id.remove(primary_key) {
+            storage.sorted_ids.remove(primary_key);
             // i64 indexes
-            for (key_name, key_value) in item.i64_keys() {
+            for (key_name, key_value) in item.i64_index_keys() {
                 if let Some(value) = key_value {
-                    if let Some(index) = self.i64_indexes.get_mut(&key_name) {
+                    if let Some(index) = storage.i64_indexes.get_mut(&*key_name) {
                         if let Some(ids) = index.get_mut(&value) {
-                            ids.retain(|&id| id != *primary_key);
+                            ids.retain(|id| *id != *primary_key);
                             if ids.is_empty() {
                                 index.remove(&value);
                             }
                         }
                         if index.is_empty() {
-                            self.i64_indexes.remove(&key_name);
+                            storage.i64_indexes.remove(&*key_name);
                         }
                     }
                 }
             }
 
             // uuid indexes
-            for (key_name, key_value) in item.uuid_keys() {
+            for (key_name, key_value) in item.uuid_index_keys() {
                 if let Some(value) = key_value {
-                    if let Some(index) = self.uuid_indexes.get_mut(&key_name) {
+                    if let Some(index) = storage.uuid_indexes.get_mut(&*key_name) {
+                        if let Some(ids) = index.get_mut(&value) {
+                            ids.retain(|id| *id != *primary_key);
+                            if ids.is_empty() {
+                                index.remove(&value);
+                            }
+                        }
+                        if index.is_empty() {
+                            storage.uuid_indexes.remove(&*key_name);
+                        }
+                    }
+                }
+            }
+
+            // datetime indexes
+            for (key_name, key_value) in item.datetime_index_keys() {
+                if let Some(value) = key_value.map(datetime_key) {
+                    if let Some(index) = storage.datetime_indexes.get_mut(&*key_name) {
+                        if let Some(ids) = index.get_mut(&value) {
+                            ids.retain(|id| *id != *primary_key);
+                            if ids.is_empty() {
+                                index.remove(&value);
+                            }
+                        }
+                        if index.is_empty() {
+                            storage.datetime_indexes.remove(&*key_name);
+                        }
+                    }
+                }
+            }
+
+            // string indexes
+            for (key_name, key_value) in item.string_index_keys() {
+                if let Some(value) = key_value.map(|value| self.string_normalizers.normalize_owned(&key_name, value)) {
+                    if let Some(index) = storage.string_indexes.get_mut(&*key_name) {
                         if let Some(ids) = index.get_mut(&value) {
-                            ids.retain(|&id| id != *primary_key);
+                            ids.retain(|id| *id != *primary_key);
                             if ids.is_empty() {
                                 index.remove(&value);
                             }
                         }
                         if index.is_empty() {
-                            self.uuid_indexes.remove(&key_name);
+                            storage.string_indexes.remove(&*key_name);
                         }
                     }
                 }
             }
+
+            if let Some(counts) = &mut storage.index_counts {
+                Self::count_item(counts, &item, false, &self.string_normalizers);
+            }
+            self.record_change();
             return Some(item);
         }
         None
     }
 
+    /// Removes every item whose Uuid index `index_name` holds `value`, e.g. all
+    /// entries referencing a deleted parent row, and returns them.
+    ///
+    /// The items are also removed from all other indexes.
+    pub fn remove_by_uuid_index_value(&mut self, index_name: &str, value: &Uuid) -> Vec<T> {
+        let Some(primary_keys) = self.get_by_uuid_index(index_name, value).map(<[Uuid]>::to_vec) else {
+            return Vec::new();
+        };
+        self.batch(|cache| primary_keys.iter().filter_map(|primary_key| cache.remove(primary_key)).collect())
+    }
+
     /// Updates an item in the cache.
     pub fn update(&mut self, item: T) {
-        self.remove(&item.primary_key());
-        self.add(item);
+        self.batch(|cache| {
+            cache.remove(&item.primary_key());
+            cache.add(item);
+        });
+    }
+
+    /// Returns the number of changes made to the cache so far.
+    ///
+    /// Adds, updates, removes, clears and evictions each count once, a
+    /// transaction commit counts once however many items it stages. Compare
+    /// it with the value seen at the last save to persist the cache only when
+    /// it changed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Runs `f` counting the changes it makes as one generation.
+    pub(crate) fn batch<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let outer = self.batch_changed.replace(false);
+        let result = f(self);
+        let changed = self.batch_changed.take() == Some(true);
+        match outer {
+            Some(outer_changed) => self.batch_changed = Some(outer_changed || changed),
+            None if changed => self.generation += 1,
+            None => {}
+        }
+        result
+    }
+
+    /// Counts a change and wakes the waiters of `wait_until`.
+    fn record_change(&mut self) {
+        match &mut self.batch_changed {
+            Some(changed) => *changed = true,
+            None => self.generation += 1,
+        }
+        self.changed.notify_waiters();
+    }
+
+    /// Marks the cache as loaded, so reads gated on readiness use it.
+    ///
+    /// Called by `CacheBootstrapper` once the load and the replay of the
+    /// notifications buffered meanwhile are done. Clearing the cache keeps it ready.
+    pub fn mark_ready(&mut self) {
+        self.ready = true;
+        self.changed.notify_waiters();
+    }
+
+    /// Returns true once `mark_ready` was called.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Waits until the cache holds an item with the given primary key.
+    ///
+    /// Returns false if `timeout` passes first. Lets tests wait for a
+    /// notification to be applied instead of sleeping.
+    pub async fn wait_for<L: CacheLock<Self>>(cache: &L, primary_key: Uuid, timeout: Duration) -> bool {
+        Self::wait_until(cache, timeout, |cache| cache.contains_primary(&primary_key)).await
+    }
+
+    /// Waits until `condition` holds for the cache, checking it again after every change.
+    ///
+    /// Returns false if `timeout` passes first.
+    pub async fn wait_until<L: CacheLock<Self>>(
+        cache: &L,
+        timeout: Duration,
+        condition: impl Fn(&Self) -> bool,
+    ) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let guard = cache.read().await;
+                if condition(&guard) {
+                    return;
+                }
+                let changed = Arc::clone(&guard.changed);
+                let mut notified = std::pin::pin!(changed.notified());
+                // Changes take the write lock, so none slips in before the waiter is registered
+                notified.as_mut().enable();
+                drop(guard);
+                notified.await;
+            }
+        })
+        .await
+        .is_ok()
     }
 
     /// Checks if the cache contains an item with the given primary key.
     pub fn contains_primary(&self, primary_key: &Uuid) -> bool {
-        self.by_id.contains_key(primary_key)
+        self.storage.by_id.contains_key(primary_key)
     }
 
     /// Gets an item from the cache by its primary key.
     pub fn get_by_primary(&self, primary_key: &Uuid) -> Option<T> {
-        self.by_id.get(primary_key).cloned()
+        self.storage.by_id.get(primary_key).cloned()
+    }
+
+    /// Gets a reference to an item by its primary key without cloning it.
+    pub(crate) fn peek(&self, primary_key: &Uuid) -> Option<&T> {
+        self.storage.by_id.get(primary_key)
+    }
+
+    /// Returns true if an index of the given kind was seen on an item or declared.
+    pub(crate) fn has_index(&self, kind: IndexKind, index_name: &str) -> bool {
+        self.storage.declared_indexes
+            .get(&kind)
+            .is_some_and(|names| names.contains(index_name))
+    }
+
+    fn require_index(&self, kind: IndexKind, index_name: &str) -> Result<(), CacheError> {
+        if self.has_index(kind, index_name) {
+            Ok(())
+        } else {
+            Err(CacheError::IndexNotFound(index_name.to_string()))
+        }
+    }
+
+    /// Gets the primary keys for a secondary i64 index value.
+    /// Unlike `get_by_i64_index`, an index that was never seen is an error
+    /// and a known index without matches gives an empty slice.
+    pub fn try_get_by_i64_index(&self, index_name: &str, key: &i64) -> Result<&[Uuid], CacheError> {
+        self.require_index(IndexKind::I64, index_name)?;
+        Ok(self.get_by_i64_index(index_name, key).unwrap_or_default())
+    }
+
+    /// Gets the primary keys for a secondary Uuid index value.
+    /// Fails with `CacheError::IndexNotFound` like `try_get_by_i64_index`.
+    pub fn try_get_by_uuid_index(&self, index_name: &str, key: &Uuid) -> Result<&[Uuid], CacheError> {
+        self.require_index(IndexKind::Uuid, index_name)?;
+        Ok(self.get_by_uuid_index(index_name, key).unwrap_or_default())
+    }
+
+    /// Gets the primary keys for a secondary DateTime index value.
+    /// Fails with `CacheError::IndexNotFound` like `try_get_by_i64_index`.
+    pub fn try_get_by_datetime_index(&self, index_name: &str, key: &DateTime<Utc>) -> Result<&[Uuid], CacheError> {
+        self.require_index(IndexKind::DateTime, index_name)?;
+        Ok(self.get_by_datetime_index(index_name, key).unwrap_or_default())
+    }
+
+    /// Gets the primary keys within a DateTime range, ordered by value.
+    /// Fails with `CacheError::IndexNotFound` like `try_get_by_i64_index`.
+    pub fn try_get_by_datetime_range<R>(&self, index_name: &str, range: R) -> Result<Vec<Uuid>, CacheError>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        self.require_index(IndexKind::DateTime, index_name)?;
+        Ok(self.get_by_datetime_range(index_name, range))
+    }
+
+    /// Gets the primary keys for a secondary String index value.
+    /// Fails with `CacheError::IndexNotFound` like `try_get_by_i64_index`.
+    pub fn try_get_by_string_index(&self, index_name: &str, key: &str) -> Result<&[Uuid], CacheError> {
+        self.require_index(IndexKind::String, index_name)?;
+        Ok(self.get_by_string_index(index_name, key).unwrap_or_default())
+    }
+
+    /// Gets at most `limit` primary keys whose String index value starts with a prefix.
+    /// Fails with `CacheError::IndexNotFound` like `try_get_by_i64_index`.
+    pub fn try_get_by_string_prefix(&self, index_name: &str, prefix: &str, limit: usize) -> Result<Vec<Uuid>, CacheError> {
+        self.require_index(IndexKind::String, index_name)?;
+        Ok(self.get_by_string_prefix(index_name, prefix, limit))
+    }
+
+    /// Gets the primary keys for a secondary i64 index value.
+    pub fn get_by_i64_index(&self, index_name: &str, key: &i64) -> Option<&[Uuid]> {
+        self.storage.i64_indexes
+            .get(index_name)
+            .and_then(|index| index.get(key))
+            .map(SmallVec::as_slice)
+    }
+
+    /// Gets the primary keys for a secondary Uuid index value.
+    pub fn get_by_uuid_index(&self, index_name: &str, key: &Uuid) -> Option<&[Uuid]> {
+        self.storage.uuid_indexes
+            .get(index_name)
+            .and_then(|index| index.get(key))
+            .map(SmallVec::as_slice)
+    }
+
+    /// Gets the primary keys for a secondary DateTime index value.
+    /// The key is compared with microsecond precision.
+    pub fn get_by_datetime_index(&self, index_name: &str, key: &DateTime<Utc>) -> Option<&[Uuid]> {
+        self.storage.datetime_indexes
+            .get(index_name)
+            .and_then(|index| index.get(&datetime_key(*key)))
+            .map(SmallVec::as_slice)
+    }
+
+    /// Gets the primary keys whose DateTime index value lies within a range,
+    /// ordered by that value. Either bound may be open, e.g. `start..` or `..=end`.
+    pub fn get_by_datetime_range<R>(&self, index_name: &str, range: R) -> Vec<Uuid>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        let Some(index) = self.storage.datetime_indexes.get(index_name) else {
+            return Vec::new();
+        };
+        let Some(bounds) = datetime_bounds(&range) else {
+            return Vec::new();
+        };
+        index.range(bounds).flat_map(|(_, ids)| ids.iter().copied()).collect()
     }
 
-    /// Gets a vector of primary keys by a secondary i64 index.
-    pub fn get_by_i64_index(&self, index_name: &str, key: &i64) -> Option<&Vec<Uuid>> {
-        self.i64_indexes.get(index_name).and_then(|index| index.get(key))
+    /// Gets the primary keys for a secondary String index value.
+    /// The key is normalized first if the index has a normalizer.
+    pub fn get_by_string_index(&self, index_name: &str, key: &str) -> Option<&[Uuid]> {
+        let key = self.string_normalizers.normalize(index_name, key);
+        self.storage.string_indexes
+            .get(index_name)
+            .and_then(|index| index.get(&*key))
+            .map(SmallVec::as_slice)
     }
 
-    /// Gets a vector of primary keys by a secondary Uuid index.
-    pub fn get_by_uuid_index(&self, index_name: &str, key: &Uuid) -> Option<&Vec<Uuid>> {
-        self.uuid_indexes.get(index_name).and_then(|index| index.get(key))
+    /// Gets the primary keys whose String index value starts with a prefix,
+    /// ordered by that value. Stops after `limit` keys, so a short prefix
+    /// does not scan the whole index.
+    pub fn get_by_string_prefix(&self, index_name: &str, prefix: &str, limit: usize) -> Vec<Uuid> {
+        let Some(index) = self.storage.string_indexes.get(index_name) else {
+            return Vec::new();
+        };
+        let prefix = self.string_normalizers.normalize(index_name, prefix);
+        index
+            .range::<_, str>((Bound::Included(&*prefix), Bound::Unbounded))
+            .take_while(|(value, _)| value.starts_with(&*prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .take(limit)
+            .collect()
     }
 
-    /// Returns an iterator over the items in the cache.
+    /// Removes all items and indexes from the cache.
+    /// Index names stay known to the `try_get_*` queries.
+    pub fn clear(&mut self) {
+        let storage = Arc::make_mut(&mut self.storage);
+        storage.by_id.clear();
+        storage.sorted_ids.clear();
+        storage.i64_indexes.clear();
+        storage.uuid_indexes.clear();
+        storage.datetime_indexes.clear();
+        storage.string_indexes.clear();
+        if let Some(counts) = &mut storage.index_counts {
+            counts.clear();
+        }
+        self.record_change();
+    }
+
+    /// Gets the most frequent values of an index with their number of items,
+    /// by count descending, then by value. At most `limit` values are returned.
+    ///
+    /// Empty unless the counts are kept, see `with_index_counts`.
+    pub fn index_histogram(&self, index_name: &str, limit: usize) -> Vec<(IndexValue, usize)> {
+        let Some(counts) = self.storage.index_counts.as_ref().and_then(|counts| counts.get(index_name)) else {
+            return Vec::new();
+        };
+        let mut histogram: Vec<(IndexValue, usize)> = counts.iter().map(|(value, count)| (value.clone(), *count)).collect();
+        histogram.sort_unstable_by(|(a_value, a_count), (b_value, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_value.cmp(b_value))
+        });
+        histogram.truncate(limit);
+        histogram
+    }
+
+    /// Returns an iterator over the items in the cache, in no particular order.
     pub fn iter(&self) -> impl Iterator<Item = &T> {
-        self.by_id.values()
+        self.storage.by_id.values()
+    }
+
+    /// Returns an iterator over the items in the cache, ordered by primary key.
+    ///
+    /// The keys are kept sorted alongside the items, at the cost of a B-tree
+    /// entry per item (about 16 bytes plus node overhead) and an O(log n)
+    /// insert or remove on each add and remove. Each step looks the item up
+    /// by its key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = &T> {
+        self.storage.sorted_ids.iter().filter_map(|primary_key| self.storage.by_id.get(primary_key))
+    }
+
+    /// Gets at most `limit` items ordered by primary key, skipping the first `offset`.
+    ///
+    /// Skipping walks the first `offset` keys, so deep pages cost O(offset);
+    /// items added or removed between calls shift the later pages. Prefer
+    /// `page_after` to walk the whole cache.
+    pub fn page(&self, offset: usize, limit: usize) -> Vec<T> {
+        self.iter_sorted().skip(offset).take(limit).cloned().collect()
+    }
+
+    /// Gets at most `limit` items ordered by primary key, starting after `last_pk`.
+    ///
+    /// Pass the key of the last item of the previous page, starting with
+    /// `page(0, limit)`. A page costs O(log n + limit) however deep it is. An
+    /// item is never listed twice nor skipped while it stays cached, even when
+    /// other items are added or removed between calls, and `last_pk` need not
+    /// be cached anymore.
+    pub fn page_after(&self, last_pk: &Uuid, limit: usize) -> Vec<T> {
+        self.storage
+            .sorted_ids
+            .range((Bound::Excluded(*last_pk), Bound::Unbounded))
+            .filter_map(|primary_key| self.storage.by_id.get(primary_key))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Takes an immutable snapshot of the cache without copying it.
+    ///
+    /// A request can take one snapshot under the read lock and then perform
+    /// any number of lookups that agree with each other, without locking
+    /// again. Changes made to the cache afterwards leave the snapshot as it was.
+    ///
+    /// While a snapshot is alive, the first change copies the maps by index
+    /// name, O(number of indexes), but none of the items or postings. Each
+    /// change then copies only the O(log n) map nodes leading to the entries
+    /// it touches, so a change costs about the same with or without a live
+    /// snapshot.
+    pub fn snapshot_arc(&self) -> IdxSnapshot<T> {
+        IdxSnapshot(self.clone())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn index_item(
         item: &T,
         primary_key: Uuid,
-        i64_indexes: &mut HashMap<String, HashMap<i64, Vec<Uuid>>>,
-        uuid_indexes: &mut HashMap<String, HashMap<Uuid, Vec<Uuid>>>,
+        i64_indexes: &mut HashMap<String, HashIndex<i64>>,
+        uuid_indexes: &mut HashMap<String, HashIndex<Uuid>>,
+        datetime_indexes: &mut HashMap<String, OrdIndex<DateTime<Utc>>>,
+        string_indexes: &mut HashMap<String, OrdIndex<String>>,
+        declared_indexes: &mut HashMap<IndexKind, HashSet<String>>,
+        string_normalizers: &StringNormalizers,
     ) {
         // i64 indexes
-        for (key_name, key_value) in item.i64_keys() {
+        for (key_name, key_value) in item.i64_index_keys() {
+            Self::declare(declared_indexes, IndexKind::I64, &key_name);
             if let Some(value) = key_value {
-                i64_indexes
-                    .entry(key_name)
-                    .or_default()
+                named_index(i64_indexes, key_name)
                     .entry(value)
                     .or_default()
                     .push(primary_key);
@@ -142,15 +786,213 @@ impl<T: HasPrimaryKey + Indexable + Clone + Debug> IdxModelCache<T> {
         }
 
         // uuid indexes
-        for (key_name, key_value) in item.uuid_keys() {
+        for (key_name, key_value) in item.uuid_index_keys() {
+            Self::declare(declared_indexes, IndexKind::Uuid, &key_name);
             if let Some(value) = key_value {
-                uuid_indexes
-                    .entry(key_name)
+                named_index(uuid_indexes, key_name)
+                    .entry(value)
+                    .or_default()
+                    .push(primary_key);
+            }
+        }
+
+        // datetime indexes
+        for (key_name, key_value) in item.datetime_index_keys() {
+            Self::declare(declared_indexes, IndexKind::DateTime, &key_name);
+            if let Some(value) = key_value.map(datetime_key) {
+                named_index(datetime_indexes, key_name)
+                    .entry(value)
                     .or_default()
+                    .push(primary_key);
+            }
+        }
+
+        // string indexes
+        for (key_name, key_value) in item.string_index_keys() {
+            Self::declare(declared_indexes, IndexKind::String, &key_name);
+            if let Some(value) = key_value {
+                let value = string_normalizers.normalize_owned(&key_name, value);
+                named_index(string_indexes, key_name)
                     .entry(value)
                     .or_default()
                     .push(primary_key);
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Adds the index values of an item to the counts, or removes them.
+    fn count_item(
+        counts: &mut HashMap<String, imbl::HashMap<IndexValue, usize>>,
+        item: &T,
+        add: bool,
+        string_normalizers: &StringNormalizers,
+    ) {
+        let values = item
+            .i64_index_keys()
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, IndexValue::I64(value))))
+            .chain(
+                item.uuid_index_keys()
+                    .into_iter()
+                    .filter_map(|(name, value)| value.map(|value| (name, IndexValue::Uuid(value)))),
+            )
+            .chain(
+                item.datetime_index_keys()
+                    .into_iter()
+                    .filter_map(|(name, value)| value.map(|value| (name, IndexValue::DateTime(datetime_key(value))))),
+            )
+            .chain(
+                item.string_index_keys()
+                    .into_iter()
+                    .filter_map(|(name, value)| {
+                        value.map(|value| {
+                            let value = string_normalizers.normalize_owned(&name, value);
+                            (name, IndexValue::String(value))
+                        })
+                    }),
+            );
+
+        for (index_name, value) in values {
+            if add {
+                *named_index(counts, index_name).entry(value).or_default() += 1;
+            } else if let Some(index) = counts.get_mut(&*index_name) {
+                if let Some(count) = index.get_mut(&value) {
+                    *count -= 1;
+                    if *count == 0 {
+                        index.remove(&value);
+                    }
+                }
+                if index.is_empty() {
+                    counts.remove(&*index_name);
+                }
+            }
+        }
+    }
+
+    fn declare(declared_indexes: &mut HashMap<IndexKind, HashSet<String>>, kind: IndexKind, index_name: &str) {
+        let names = declared_indexes.entry(kind).or_default();
+        if !names.contains(index_name) {
+            names.insert(index_name.to_string());
+        }
+    }
+}
+
+/// Gets the index with the given name, creating it if needed.
+/// The name is only copied into an owned String for a new index.
+fn named_index<'a, I: Default>(indexes: &'a mut HashMap<String, I>, index_name: Cow<'static, str>) -> &'a mut I {
+    if !indexes.contains_key(&*index_name) {
+        indexes.insert(index_name.clone().into_owned(), I::default());
+    }
+    indexes.get_mut(&*index_name).expect("index was just inserted")
+}
+
+/// The start and end bound of a DateTime range
+pub(crate) type DateTimeBounds = (Bound<DateTime<Utc>>, Bound<DateTime<Utc>>);
+
+/// Normalizes a DateTime range to microsecond keys.
+/// Returns None for ranges that cannot contain any key, which `BTreeMap::range` would reject.
+pub(crate) fn datetime_bounds<R>(range: &R) -> Option<DateTimeBounds>
+where
+    R: RangeBounds<DateTime<Utc>>,
+{
+    let start = range.start_bound().map(|value| datetime_key(*value));
+    let end = range.end_bound().map(|value| datetime_key(*value));
+    let empty = match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => {
+            start >= end
+        }
+        _ => false,
+    };
+    (!empty).then_some((start, end))
+}
+
+impl<T: HasPrimaryKey + Indexable + Clone + Debug + IsDeleted> IdxModelCache<T> {
+    /// Removes all soft-deleted items from the cache and returns their primary keys.
+    pub fn evict_deleted(&mut self) -> Vec<Uuid> {
+        let deleted: Vec<Uuid> = self
+            .storage
+            .by_id
+            .iter()
+            .filter(|(_, item)| item.is_deleted())
+            .map(|(primary_key, _)| *primary_key)
+            .collect();
+        self.batch(|cache| {
+            for primary_key in &deleted {
+                cache.remove(primary_key);
+            }
+        });
+        deleted
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<T: HasPrimaryKey + Indexable + Clone + Debug> IdxModelCache<T> {
+    /// Draws up to `n` cached items uniformly at random, e.g. to audit the cache.
+    ///
+    /// Reservoir sampling over the items keeps only the sample in memory. The
+    /// same seed draws the same sample from an unchanged cache; the order of
+    /// the returned items is unspecified.
+    pub fn sample(&self, n: usize, rng: &mut impl rand::Rng) -> Vec<&T> {
+        crate::sampling::reservoir_sample(self.storage.by_id.values(), n, rng)
+    }
+
+    /// Draws up to `n` primary keys uniformly at random, like `sample`.
+    pub fn sample_keys(&self, n: usize, rng: &mut impl rand::Rng) -> Vec<Uuid> {
+        crate::sampling::reservoir_sample(self.storage.by_id.keys().copied(), n, rng)
+    }
+}
+
+impl<T: HasPrimaryKey + Indexable + Clone + Debug + Serialize> IdxModelCache<T> {
+    /// Serializes every cached item with its index key values, e.g. for a debugging endpoint.
+    ///
+    /// See `dump_json_limited` for the format.
+    pub fn dump_json(&self) -> serde_json::Value {
+        self.dump_json_limited(usize::MAX)
+    }
+
+    /// Serializes at most `limit` cached items, in no particular order, with their index key values.
+    ///
+    /// Returns `{"name", "entries", "truncated", "items"}`, where `entries` is
+    /// the number of cached items and each item is formatted as by
+    /// `dump_entry_json`. Only the items dumped are serialized.
+    pub fn dump_json_limited(&self, limit: usize) -> serde_json::Value {
+        let items: Vec<serde_json::Value> = self.storage.by_id.values().take(limit).map(entry_json).collect();
+        json!({
+            "name": self.name,
+            "entries": self.storage.by_id.len(),
+            "truncated": items.len() < self.storage.by_id.len(),
+            "items": items,
+        })
+    }
+
+    /// Serializes one cached item as `{"primary_key", "item", "index_keys"}`.
+    ///
+    /// `index_keys` holds the values of the item's indexes by kind and name.
+    /// An item that fails to serialize is replaced by `{"error": message}`.
+    pub fn dump_entry_json(&self, primary_key: &Uuid) -> Option<serde_json::Value> {
+        self.storage.by_id.get(primary_key).map(entry_json)
+    }
+}
+
+fn entry_json<T: HasPrimaryKey + Indexable + Serialize>(item: &T) -> serde_json::Value {
+    json!({
+        "primary_key": item.primary_key(),
+        "item": item_json(item),
+        "index_keys": {
+            "i64": index_keys_json(item.i64_index_keys()),
+            "uuid": index_keys_json(item.uuid_index_keys()),
+            "datetime": index_keys_json(item.datetime_index_keys()),
+            "string": index_keys_json(item.string_index_keys()),
+        },
+    })
+}
+
+fn index_keys_json<V: Serialize>(keys: IndexKeys<V>) -> serde_json::Map<String, serde_json::Value> {
+    keys.into_iter().map(|(name, value)| (name.into_owned(), json!(value))).collect()
+}
+
+/// Serializes a cached item for a dump, reporting a failure in place of the item.
+pub(crate) fn item_json<T: Serialize>(item: &T) -> serde_json::Value {
+    serde_json::to_value(item).unwrap_or_else(|err| json!({ "error": err.to_string() }))
+}
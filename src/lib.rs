@@ -9,6 +9,10 @@
 //! - `TransactionAwareIdxModelCache`: Transaction-aware wrapper that stages changes
 //! - `TransactionAware`: Trait for transaction lifecycle notifications (from postgres-unit-of-work)
 //! - `HasPrimaryKey` and `Indexable`: Traits for cacheable models
+//!
+//! With the `derive` feature, `#[derive(HasPrimaryKey, Indexable)]` implements
+//! both traits from `#[cache(primary_key)]`, `#[cache(i64_index)]` and
+//! `#[cache(uuid_index)]` field attributes.
 
 mod error;
 mod traits;
@@ -16,35 +20,186 @@ mod index_cache;
 mod transaction_aware_index_cache;
 mod listener;
 mod db_init;
+mod trigger_sql;
 mod main_model_cache;
+mod eviction_order;
 mod transaction_aware_main_model_cache;
+mod model_bounds;
+mod versioning;
+mod staging;
+mod transaction_group;
+mod scope;
+mod lifecycle;
+mod coordinator;
+mod registry;
+mod manager;
+mod linked_handler;
+mod aggregating_handler;
+mod cascade_handler;
+mod sink_handler;
+mod bootstrap;
+mod handler_schema;
+mod handler_stats;
+mod backend;
+mod lock;
+#[cfg(feature = "sqlx-listener")]
+mod cached_repository;
+#[cfg(feature = "sqlx-listener")]
+mod drift;
+#[cfg(feature = "sqlx-listener")]
+mod notifier;
+#[cfg(feature = "sqlx-listener")]
+mod outbox;
+#[cfg(feature = "sqlx-listener")]
+mod runtime;
+#[cfg(feature = "rand")]
+mod sampling;
+#[cfg(feature = "redis-tier")]
+mod tiered_cache;
+#[cfg(feature = "payload-protection")]
+mod payload_protection;
 
-pub use error::{CacheError, CacheResult};
-pub use traits::{HasPrimaryKey, Indexable, ValidFrom, ValidTo};
-pub use index_cache::IdxModelCache;
-pub use transaction_aware_index_cache::TransactionAwareIdxModelCache;
-pub use transaction_aware_main_model_cache::TransactionAwareMainModelCache;
+pub use error::{CacheError, CacheResult, CommitError};
+pub use traits::{CacheKey, HasPrimaryKey, HasTableName, IndexKeys, Indexable, IsDeleted, ValidFrom, ValidTo, Validity, Versioned};
+pub use index_cache::{IdxModelCache, IdxSnapshot, IndexValue, StringNormalizer};
+pub use transaction_aware_index_cache::{IdxModel, TransactionAwareIdxModelCache};
+pub use transaction_aware_main_model_cache::{MainModel, MainModelKey, TransactionAwareMainModelCache};
+pub use model_bounds::{assert_idx_model, assert_main_model};
+pub use versioning::{CommitAnomaly, CommitReport, ConflictPolicy};
+pub use staging::{AfterCommitHook, AppliedChanges, ReadSource, StagedChanges, StagedOp, StagedSize, StagingLimit};
+pub use transaction_group::{CacheTransactionGroup, TransactionParticipant};
+pub use scope::TransactionScope;
+pub use coordinator::{DeferredChange, SharedCacheCoordinator};
+pub use registry::{CacheRegistry, CacheTransaction, ParticipantRegistrar};
+pub use manager::{AggregateStatistics, CacheManager};
+pub use linked_handler::LinkedCacheHandler;
+pub use aggregating_handler::AggregatingCacheHandler;
+pub use cascade_handler::CascadeHandler;
+pub use sink_handler::{NotificationSink, SinkError, SinkHandler};
+pub use bootstrap::CacheBootstrapper;
+pub use handler_stats::{AppliedInfo, HandlerStats};
+pub use handler_schema::{serde_fields, FieldMismatch, HandlerSchemaReport};
+#[cfg(feature = "sqlx-listener")]
+pub use handler_schema::{assert_handlers_match_db, validate_handlers_against_db, HandlerSchemaError};
+pub use backend::ModelCacheBackend;
+pub use lock::CacheLock;
+#[cfg(feature = "sqlx-listener")]
+pub use cached_repository::{CachedRepository, ReadPolicy, RepositoryFetch};
+#[cfg(feature = "sqlx-listener")]
+pub use drift::{DriftChecker, DriftReport, FetchByIds};
+#[cfg(feature = "sqlx-listener")]
+pub use notifier::CacheNotifier;
+#[cfg(feature = "sqlx-listener")]
+pub use outbox::{CacheOutboxPoller, OutboxAck};
+#[cfg(feature = "sqlx-listener")]
+pub use runtime::{
+    CachePreload, CacheRuntime, CacheRuntimeBuilder, CacheRuntimeHandle, CacheStartupError, CacheTableSpec,
+    ListenerState, RuntimeStatus,
+};
+#[cfg(feature = "redis-tier")]
+pub use tiered_cache::{RedisTierConfig, TieredModelCache, TieredModelCacheHandler};
+#[cfg(feature = "payload-protection")]
+pub use payload_protection::{PayloadKey, MIN_PAYLOAD_SECRET_LEN};
 
 // Re-export main model cache components
 pub use main_model_cache::{
     MainModelCache,
     MainModelCacheHandler,
     CacheConfig,
+    CacheConfigBuilder,
+    LruOptions,
+    FifoOptions,
     CacheStatistics,
+    EntryInfo,
+    CachePressure,
+    HighWatermarkHook,
+    StatisticsSnapshot,
     EvictionPolicy,
 };
 
 // Re-export listener components
 pub use listener::{
+    CacheAction,
     CacheNotification,
     CacheNotificationHandler,
     CacheNotificationListener,
+    DeadLetterHook,
+    DuplicateInsert,
+    HandlerInfo,
+    HandlerOptions,
+    HandlerRegistry,
     IndexCacheHandler,
+    NotificationFilter,
+    NotificationOutcome,
+    ItemLoader,
+    OnDeserError,
+    PayloadMigrator,
+    PauseMode,
+    ReconnectPolicy,
+    ReplayReport,
+    ResyncMode,
+    RetryPolicy,
+    ALL_TABLES,
     DEFAULT_CACHE_CHANNEL,
 };
 
 // Re-export database initialization functions
-pub use db_init::{init_cache_triggers, cleanup_cache_triggers};
+pub use db_init::{
+    init_cache_triggers,
+    init_cache_triggers_with_channel,
+    init_cache_triggers_with_function,
+    init_cache_outbox,
+    cleanup_cache_outbox,
+    installed_version,
+    installed_version_with_function,
+    cleanup_cache_triggers,
+    cleanup_cache_triggers_with_function,
+    cleanup_cache_triggers_for_table,
+    cleanup_cache_triggers_for_table_with_function,
+    flush_table_cache,
+    flush_table_cache_with_channel,
+    list_cache_triggers,
+    list_cache_triggers_with_function,
+    init_table_trigger,
+    drop_table_trigger,
+    verify_cache_triggers,
+    verify_cache_triggers_with_function,
+    assert_cache_triggers,
+    check_handler_coverage,
+    check_handler_coverage_with_function,
+    HandlerCoverage,
+    FunctionOptions,
+    TriggerOptions,
+    TableTriggerSpec,
+    InstalledTrigger,
+    TriggerMismatch,
+    VerificationReport,
+    TriggerVerificationError,
+    TRIGGER_SCRIPT_VERSION,
+    META_TABLE,
+};
+
+// Re-export trigger SQL generation
+pub use trigger_sql::{
+    NotificationDelivery,
+    TriggerEvent,
+    TriggerSqlBuilder,
+    DEFAULT_FUNCTION_NAME,
+    DEFAULT_OUTBOX_TABLE,
+    DEFAULT_PAYLOAD_SIZE_LIMIT,
+    PAYLOAD_KEY_SETTING,
+};
+
+// Re-export the derive macros
+#[cfg(feature = "derive")]
+pub use postgres_index_cache_derive::{HasPrimaryKey, HasTableName, Indexable};
+
+#[doc(hidden)]
+pub mod __private {
+    //! Items used by the code generated by the derive macros
+    pub use chrono::{DateTime, Utc};
+    pub use uuid::Uuid;
+}
 
 // Re-export TransactionAware from postgres-unit-of-work for convenience
 pub use postgres_unit_of_work::TransactionAware;
\ No newline at end of file
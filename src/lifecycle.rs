@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::error::CacheError;
+use postgres_unit_of_work::{TransactionAware, TransactionResult};
+
+/// A reusable transaction-aware cache that counts its logical transactions
+pub trait Generational: TransactionAware + Send + Sync {
+    /// Returns the current transaction generation
+    fn generation(&self) -> u64;
+}
+
+/// Participant that only commits or rolls back the generation it was created for
+///
+/// A unit of work that completes after the wrapper has moved on to a later
+/// transaction must not apply that transaction's changes.
+pub(crate) struct GenerationParticipant<C> {
+    cache: Arc<C>,
+    generation: u64,
+}
+
+impl<C: Generational> GenerationParticipant<C> {
+    pub(crate) fn new(cache: Arc<C>) -> Self {
+        let generation = cache.generation();
+        Self { cache, generation }
+    }
+
+    fn is_current(&self) -> bool {
+        self.cache.generation() == self.generation
+    }
+}
+
+#[async_trait]
+impl<C: Generational> TransactionAware for GenerationParticipant<C> {
+    async fn on_commit(&self) -> TransactionResult<()> {
+        if !self.is_current() {
+            return Err(CacheError::OperationFailed(format!(
+                "commit from transaction generation {} after the cache moved on to generation {}",
+                self.generation,
+                self.cache.generation()
+            ))
+            .into());
+        }
+        self.cache.on_commit().await
+    }
+
+    async fn on_rollback(&self) -> TransactionResult<()> {
+        if !self.is_current() {
+            tracing::debug!(generation = self.generation, "ignoring rollback from a previous transaction");
+            return Ok(());
+        }
+        self.cache.on_rollback().await
+    }
+}
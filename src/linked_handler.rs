@@ -0,0 +1,173 @@
+//! One notification handler for the index cache and the main model cache of a table
+//!
+//! [`LinkedCacheHandler`] deserializes each payload once as the full model
+//! and derives the index model from it, so both caches see the same change.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use tracing::{debug, warn};
+
+use crate::error::CacheError;
+use crate::handler_schema::serde_fields;
+use crate::index_cache::IdxModelCache;
+use crate::listener::{CacheNotification, CacheNotificationHandler};
+use crate::lock::CacheLock;
+use crate::main_model_cache::MainModelCache;
+use crate::transaction_aware_index_cache::IdxModel;
+use crate::transaction_aware_main_model_cache::MainModel;
+use crate::traits::{HasPrimaryKey, HasTableName, Indexable};
+
+/// Derives the index model of an item from its full model
+type Projection<M, I> = dyn Fn(&M) -> I + Send + Sync;
+
+/// A notification handler keeping an `IdxModelCache` and a `MainModelCache`
+/// of the same table in sync
+///
+/// Both write locks are held while a change is applied, the index cache's
+/// first, so no reader sees one cache updated and the other not. Other code
+/// taking both locks must take them in the same order. A change is applied
+/// only once both locks are held, so a handler timeout cannot interrupt it
+/// between the two caches.
+///
+/// The locks default to `parking_lot::RwLock`. Use `tokio::sync::RwLock` for
+/// both when other code holds the two guards across an await, e.g. while it
+/// refetches a row.
+pub struct LinkedCacheHandler<I, M, LI = RwLock<IdxModelCache<I>>, LM = RwLock<MainModelCache<M>>>
+where
+    I: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static,
+    M: HasPrimaryKey + Clone + Send + Sync + 'static,
+    LI: CacheLock<IdxModelCache<I>>,
+    LM: CacheLock<MainModelCache<M>>,
+{
+    table_name: String,
+    index_cache: Arc<LI>,
+    main_cache: Arc<LM>,
+    projection: Box<Projection<M, I>>,
+}
+
+impl<I, M, LI, LM> LinkedCacheHandler<I, M, LI, LM>
+where
+    I: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static,
+    M: HasPrimaryKey + Clone + Send + Sync + 'static,
+    LI: CacheLock<IdxModelCache<I>>,
+    LM: CacheLock<MainModelCache<M>>,
+{
+    /// Create a new handler for the given caches
+    ///
+    /// `projection` derives the index model from the full model carried by
+    /// the notification.
+    pub fn new(
+        table_name: String,
+        index_cache: Arc<LI>,
+        main_cache: Arc<LM>,
+        projection: impl Fn(&M) -> I + Send + Sync + 'static,
+    ) -> Self {
+        Self { table_name, index_cache, main_cache, projection: Box::new(projection) }
+    }
+
+    /// Create a new handler for the table of the full model
+    pub fn for_type(
+        index_cache: Arc<LI>,
+        main_cache: Arc<LM>,
+        projection: impl Fn(&M) -> I + Send + Sync + 'static,
+    ) -> Self
+    where
+        M: HasTableName,
+    {
+        Self::new(M::table_name().to_string(), index_cache, main_cache, projection)
+    }
+}
+
+#[async_trait]
+impl<I, M, LI, LM> CacheNotificationHandler for LinkedCacheHandler<I, M, LI, LM>
+where
+    I: IdxModel,
+    M: MainModel + HasPrimaryKey + DeserializeOwned,
+    LI: CacheLock<IdxModelCache<I>> + 'static,
+    LM: CacheLock<MainModelCache<M>> + 'static,
+{
+    async fn handle_notification(&self, notification: CacheNotification) {
+        debug!(
+            "LinkedCache: Handling notification for table '{}': action={}, id={}",
+            notification.table, notification.action, notification.id
+        );
+
+        match notification.action.as_str() {
+            "insert" | "update" => {
+                let Some(data) = notification.data else {
+                    warn!(
+                        table = %notification.table,
+                        action = %notification.action,
+                        id = %notification.id,
+                        "LinkedCache: dropping notification: no data provided"
+                    );
+                    return;
+                };
+                let item = match serde_json::from_value::<M>(data) {
+                    Ok(item) => item,
+                    Err(source) => {
+                        let err = CacheError::DeserializationFailed { table: notification.table.clone(), source };
+                        warn!(
+                            table = %notification.table,
+                            action = %notification.action,
+                            id = %notification.id,
+                            error = %err,
+                            "LinkedCache: dropping notification: failed to decode data"
+                        );
+                        return;
+                    }
+                };
+                let index_item = (self.projection)(&item);
+
+                let mut index_cache = self.index_cache.write().await;
+                let mut main_cache = self.main_cache.write().await;
+                if notification.action == "insert" {
+                    index_cache.add(index_item);
+                    main_cache.insert(item);
+                    debug!("LinkedCache: Added item {} to both caches", notification.id);
+                } else {
+                    index_cache.update(index_item);
+                    main_cache.update(item);
+                    debug!("LinkedCache: Updated item {} in both caches", notification.id);
+                }
+            }
+            "delete" => {
+                let mut index_cache = self.index_cache.write().await;
+                let mut main_cache = self.main_cache.write().await;
+                index_cache.remove(&notification.id);
+                main_cache.remove(&notification.id);
+                debug!("LinkedCache: Removed item {} from both caches", notification.id);
+            }
+            "truncate" | "flush" => {
+                let mut index_cache = self.index_cache.write().await;
+                let mut main_cache = self.main_cache.write().await;
+                index_cache.clear();
+                main_cache.clear();
+                debug!("LinkedCache: Cleared both caches of table '{}' on {}", notification.table, notification.action);
+            }
+            _ => {
+                warn!(
+                    table = %notification.table,
+                    action = %notification.action,
+                    id = %notification.id,
+                    "LinkedCache: dropping notification: unknown action"
+                );
+            }
+        }
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    async fn mark_ready(&self) {
+        self.index_cache.write().await.mark_ready();
+        self.main_cache.write().await.mark_ready();
+    }
+
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        serde_fields::<M>()
+    }
+}
@@ -1,114 +1,1609 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use async_trait::async_trait;
-use parking_lot::RwLock;
+use futures::future::BoxFuture;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 use uuid::Uuid;
 
+use crate::coordinator::{DeferredChange, SharedCacheCoordinator};
+use crate::error::CacheError;
+use crate::handler_schema::serde_fields;
+use crate::handler_stats::{AppliedInfo, HandlerStats, LastApplied, LatencyHistogram};
 use crate::index_cache::IdxModelCache;
-use crate::traits::{HasPrimaryKey, Indexable};
+use crate::lock::CacheLock;
+use crate::transaction_aware_index_cache::IdxModel;
+use crate::traits::{HasPrimaryKey, HasTableName, Indexable, IsDeleted, Versioned};
+use crate::versioning::{is_stale, version_of, VersionOf};
 
 /// The default channel name for cache notifications
 pub const DEFAULT_CACHE_CHANNEL: &str = "cache_invalidation";
 
+/// The table name of a handler receiving the notifications of every table
+///
+/// Such a handler, e.g. a `SinkHandler` mirroring all changes to an event bus,
+/// runs after the table's own handler, or alone for tables without one.
+pub const ALL_TABLES: &str = "*";
+
 /// Notification payload structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawCacheNotification")]
 pub struct CacheNotification {
     /// The table name that was modified
     pub table: String,
-    /// The action performed: "insert", "update", or "delete"
+    /// The action performed: "insert", "update", "delete", "truncate" or "flush"
     pub action: String,
-    /// The primary key of the affected row
+    /// The primary key of the affected row; the nil UUID for "truncate" and "flush"
+    /// and for rows whose primary key is not a UUID
     pub id: Uuid,
+    /// The primary key of the affected row as sent, when it is not a UUID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
     /// Optional: the full entity data for insert/update operations
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<serde_json::Value>,
+    /// Optional: the context columns of the row, e.g. a tenant id
+    ///
+    /// Sent for every row-level action, even when the row data is omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<serde_json::Value>,
+}
+
+impl CacheNotification {
+    /// An "insert" of an item, carrying the item as data
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let payload = CacheNotification::insert("users", &user)?.to_payload();
+    /// listener.process_notification(&payload).await;
+    /// ```
+    pub fn insert<T: Serialize + HasPrimaryKey>(table: &str, item: &T) -> Result<Self, CacheError> {
+        Self::with_data(table, CacheAction::Insert, item)
+    }
+
+    /// An "update" of an item, carrying the item as data
+    pub fn update<T: Serialize + HasPrimaryKey>(table: &str, item: &T) -> Result<Self, CacheError> {
+        Self::with_data(table, CacheAction::Update, item)
+    }
+
+    /// A "delete" of the item with the given primary key
+    pub fn delete(table: &str, id: Uuid) -> Self {
+        Self::without_data(table, CacheAction::Delete, id)
+    }
+
+    /// A "flush" of all caches of the table
+    pub fn flush(table: &str) -> Self {
+        Self::without_data(table, CacheAction::Flush, Uuid::nil())
+    }
+
+    /// The payload as sent by the triggers and `CacheNotifier`
+    pub fn to_payload(&self) -> String {
+        // Every field is a string, a UUID or a JSON value
+        serde_json::to_string(self).expect("a notification always serializes")
+    }
+
+    /// Parse a payload as sent by the triggers and `CacheNotifier`
+    pub fn from_payload(payload: &str) -> Result<Self, CacheError> {
+        serde_json::from_str(payload).map_err(CacheError::InvalidPayload)
+    }
+
+    fn with_data<T: Serialize + HasPrimaryKey>(table: &str, action: CacheAction, item: &T) -> Result<Self, CacheError> {
+        let data = serde_json::to_value(item)
+            .map_err(|source| CacheError::SerializationFailed { table: table.to_string(), source })?;
+        Ok(Self { data: Some(data), ..Self::without_data(table, action, item.primary_key()) })
+    }
+
+    fn without_data(table: &str, action: CacheAction, id: Uuid) -> Self {
+        Self {
+            table: table.to_string(),
+            action: action.as_str().to_string(),
+            id,
+            key: None,
+            data: None,
+            context: None,
+        }
+    }
+
+    /// Get a single context value by column name
+    pub fn context_value(&self, column: &str) -> Option<&serde_json::Value> {
+        self.context.as_ref().and_then(|context| context.get(column))
+    }
+
+    /// The primary key of the affected row as text: `key` if set, `id` otherwise
+    pub fn raw_key(&self) -> String {
+        self.key.clone().unwrap_or_else(|| self.id.to_string())
+    }
+
+    /// The action performed, or None for an unknown action
+    pub fn cache_action(&self) -> Option<CacheAction> {
+        CacheAction::from_action(&self.action)
+    }
+}
+
+/// The action of a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheAction {
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+    /// Administrative request to clear a table's caches, sent without data
+    Flush,
+}
+
+impl CacheAction {
+    /// Parse the `action` field of a notification
+    pub fn from_action(action: &str) -> Option<Self> {
+        match action {
+            "insert" => Some(CacheAction::Insert),
+            "update" => Some(CacheAction::Update),
+            "delete" => Some(CacheAction::Delete),
+            "truncate" => Some(CacheAction::Truncate),
+            "flush" => Some(CacheAction::Flush),
+            _ => None,
+        }
+    }
+
+    /// The `action` field of a notification with this action
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheAction::Insert => "insert",
+            CacheAction::Update => "update",
+            CacheAction::Delete => "delete",
+            CacheAction::Truncate => "truncate",
+            CacheAction::Flush => "flush",
+        }
+    }
+}
+
+/// The notification as sent, with a primary key of any type
+#[derive(Deserialize)]
+struct RawCacheNotification {
+    table: String,
+    action: String,
+    #[serde(default)]
+    id: serde_json::Value,
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    context: Option<serde_json::Value>,
+}
+
+impl TryFrom<RawCacheNotification> for CacheNotification {
+    type Error = String;
+
+    fn try_from(raw: RawCacheNotification) -> Result<Self, Self::Error> {
+        // A flush concerns the whole table, whatever id it was sent with
+        if raw.action == "flush" {
+            return Ok(Self {
+                table: raw.table,
+                action: raw.action,
+                id: Uuid::nil(),
+                key: None,
+                data: None,
+                context: raw.context,
+            });
+        }
+        let key = match raw.id {
+            serde_json::Value::String(key) => key,
+            serde_json::Value::Number(key) => key.to_string(),
+            other => return Err(format!("invalid notification id: {other}")),
+        };
+        let (id, key) = match Uuid::parse_str(&key) {
+            Ok(id) => (id, raw.key),
+            Err(_) => (Uuid::nil(), Some(key)),
+        };
+        Ok(Self {
+            table: raw.table,
+            action: raw.action,
+            id,
+            key,
+            data: raw.data,
+            context: raw.context,
+        })
+    }
+}
+
+/// Predicate deciding whether a notification is dispatched to its handler
+pub type NotificationFilter = dyn Fn(&CacheNotification) -> bool + Send + Sync;
+
+/// Receives notifications a handler gave up on, with the reason
+pub type DeadLetterHook = dyn Fn(&CacheNotification, &str) + Send + Sync;
+
+/// Rewrites notification data before a handler deserializes it, e.g. to map
+/// legacy field names forward; an `Err` drops the notification with the reason
+pub type PayloadMigrator = dyn Fn(serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync;
+
+/// Deserializes notification data, migrated first if the handler has a migrator
+pub(crate) fn decode_data<T: for<'de> Deserialize<'de>>(
+    migrator: Option<&PayloadMigrator>,
+    table: &str,
+    data: serde_json::Value,
+) -> Result<T, CacheError> {
+    let data = match migrator {
+        Some(migrator) => migrator(data)
+            .map_err(|reason| CacheError::PayloadMigrationFailed { table: table.to_string(), reason })?,
+        None => data,
+    };
+    serde_json::from_value(data).map_err(|source| CacheError::DeserializationFailed { table: table.to_string(), source })
 }
 
 /// Handler trait for cache notifications
+///
+/// A handler registered with a timeout is dropped at an `.await` once the
+/// timeout expires. Handlers therefore do all their awaiting first and change
+/// their caches last, without awaiting in between, so a timeout never leaves
+/// a cache half-updated.
 #[async_trait]
 pub trait CacheNotificationHandler: Send + Sync {
     /// Handle a cache notification
     async fn handle_notification(&self, notification: CacheNotification);
+
+    /// Handle notifications of the handler's table received in one burst, in arrival order
+    ///
+    /// Called instead of `handle_notification` when the listener batches
+    /// notifications, see `CacheNotificationListener::set_batching`. The
+    /// default handles them one by one; the cache handlers override it to
+    /// apply a whole batch under one write lock.
+    async fn handle_notifications(&self, notifications: Vec<CacheNotification>) {
+        for notification in notifications {
+            self.handle_notification(notification).await;
+        }
+    }
     
     /// Get the table name this handler is responsible for
     fn table_name(&self) -> &str;
+
+    /// The last change the handler applied, for handlers that keep track
+    fn last_applied(&self) -> Option<AppliedInfo> {
+        None
+    }
+
+    /// Record that the handler's cache was just loaded from its table
+    ///
+    /// Called by `CacheBootstrapper` after a successful load, so a freshly
+    /// started service does not look as if it never received a change.
+    fn mark_loaded(&self) {}
+
+    /// Mark the handler's cache ready for reads gated on readiness
+    ///
+    /// Called by `CacheBootstrapper` once the load and the replay of the
+    /// notifications buffered meanwhile are done.
+    async fn mark_ready(&self) {}
+
+    /// The fields the handler reads from notification data, for handlers that describe them
+    ///
+    /// Compared with the columns of the handler's table by
+    /// `validate_handlers_against_db`.
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// The type name of the handler, for listings of the registered handlers
+    fn handler_type(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// A registered handler, as listed by `CacheNotificationListener::registered_handlers`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerInfo {
+    /// The table the handler is registered for
+    pub table: String,
+    /// The channel it was registered on, `None` for the listener's own channel
+    pub channel: Option<String>,
+    /// The type name of the handler, from `CacheNotificationHandler::handler_type`
+    pub handler_type: &'static str,
+}
+
+/// What an `IndexCacheHandler` does with an "insert" of an item already cached
+///
+/// Such an insert is either a replayed notification or a sign that the cache
+/// drifted from the table, e.g. after the listener missed notifications.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateInsert {
+    /// Replace the cached item without recording anything
+    #[default]
+    Overwrite,
+    /// Log a warning and count the insert; `apply` replaces the cached item
+    Warn { apply: bool },
+    /// Count the insert; `apply` replaces the cached item
+    Count { apply: bool },
+}
+
+/// What a handler does with the cached item when notification data fails to decode
+///
+/// The cached item no longer matches the row once a change to it was
+/// notified, so `Keep` serves stale data until the next notification;
+/// correctness-sensitive caches use `Invalidate`. Failures are counted by the
+/// handler's `deserialization_failures` whatever the policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnDeserError {
+    /// Leave the cached item as it is
+    #[default]
+    Keep,
+    /// Remove the item from the cache, so reads go to the database
+    Invalidate,
+    /// Reload the item with the handler's loader, or invalidate it without one
+    Refetch,
+}
+
+/// Reloads an item by its key for `OnDeserError::Refetch`, `None` when the row is gone
+pub type ItemLoader<T, K = Uuid> = dyn Fn(K) -> BoxFuture<'static, Option<T>> + Send + Sync;
+
+/// What a notification does to an index cache, worked out before the write lock is taken
+enum IdxChange<T: HasPrimaryKey + Indexable + Clone> {
+    /// A change of one item, deferred while a coordinated transaction holds the key
+    Item(Uuid, DeferredChange<T>),
+    /// Clears the cache for a truncate or, with `flush`, a flush notification
+    Clear { flush: bool },
+    Nothing,
+}
+
+/// A prepared change with the action and key to record as last applied, if any
+struct PreparedChange<T: HasPrimaryKey + Indexable + Clone> {
+    change: IdxChange<T>,
+    record: Option<(String, Option<String>)>,
 }
 
 /// A notification handler for a specific IndexCache
-pub struct IndexCacheHandler<T: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static> {
+///
+/// Log lines carry the handler's name in a `cache` field, the table name
+/// unless set with `with_name`.
+/// The cache sits behind any [`CacheLock`]; it defaults to a
+/// `parking_lot::RwLock`, use a `tokio::sync::RwLock` when code sharing the
+/// cache holds its guard across an await.
+pub struct IndexCacheHandler<T, L = RwLock<IdxModelCache<T>>>
+where
+    T: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static,
+    L: CacheLock<IdxModelCache<T>>,
+{
     table_name: String,
-    cache: Arc<RwLock<IdxModelCache<T>>>,
+    name: String,
+    cache: Arc<L>,
+    version_of: Option<VersionOf<T>>,
+    is_deleted: Option<fn(&T) -> bool>,
+    coordinator: Option<Arc<SharedCacheCoordinator<T>>>,
+    on_duplicate_insert: DuplicateInsert,
+    duplicate_inserts: Arc<AtomicU64>,
+    missing_deletes: Arc<AtomicU64>,
+    is_unchanged: Option<fn(&T, &T) -> bool>,
+    unchanged_updates: AtomicU64,
+    last_applied: LastApplied,
+    expected_fields: Option<Vec<String>>,
+    payload_migrator: Option<Arc<PayloadMigrator>>,
+    on_deser_error: OnDeserError,
+    loader: Option<Arc<ItemLoader<T>>>,
+    deserialization_failures: AtomicU64,
 }
 
-impl<T: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static> IndexCacheHandler<T> {
+impl<T, L> IndexCacheHandler<T, L>
+where
+    T: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static,
+    L: CacheLock<IdxModelCache<T>>,
+{
     /// Create a new handler for the given cache
-    pub fn new(table_name: String, cache: Arc<RwLock<IdxModelCache<T>>>) -> Self {
-        Self { table_name, cache }
+    pub fn new(table_name: String, cache: Arc<L>) -> Self {
+        Self {
+            name: table_name.clone(),
+            table_name,
+            cache,
+            version_of: None,
+            is_deleted: None,
+            coordinator: None,
+            on_duplicate_insert: DuplicateInsert::default(),
+            duplicate_inserts: Arc::new(AtomicU64::new(0)),
+            missing_deletes: Arc::new(AtomicU64::new(0)),
+            is_unchanged: None,
+            unchanged_updates: AtomicU64::new(0),
+            last_applied: LastApplied::default(),
+            expected_fields: None,
+            payload_migrator: None,
+            on_deser_error: OnDeserError::default(),
+            loader: None,
+            deserialization_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Rewrite notification data before it is deserialized
+    ///
+    /// Migrator failures are logged as `CacheError::PayloadMigrationFailed`,
+    /// apart from deserialization failures.
+    pub fn with_payload_migrator(mut self, migrator: Arc<PayloadMigrator>) -> Self {
+        self.payload_migrator = Some(migrator);
+        self
+    }
+
+    /// Declare the fields the handler reads from notification data
+    ///
+    /// By default they are derived from the cached type's `Deserialize` impl.
+    pub fn with_expected_fields(mut self, fields: &[&str]) -> Self {
+        self.expected_fields = Some(fields.iter().map(|field| field.to_string()).collect());
+        self
+    }
+
+    /// Choose what happens to the cached item when notification data fails to decode
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let loader: Arc<ItemLoader<User>> = Arc::new(move |id| -> BoxFuture<'static, Option<User>> {
+    ///     let pool = pool.clone();
+    ///     Box::pin(async move { User::fetch_by_id(&pool, id).await.ok().flatten() })
+    /// });
+    /// let handler = IndexCacheHandler::for_type(cache.clone())
+    ///     .on_deser_error(OnDeserError::Refetch)
+    ///     .with_loader(loader);
+    /// ```
+    pub fn on_deser_error(mut self, policy: OnDeserError) -> Self {
+        self.on_deser_error = policy;
+        self
+    }
+
+    /// Reload items with `loader` under `OnDeserError::Refetch`
+    pub fn with_loader(mut self, loader: Arc<ItemLoader<T>>) -> Self {
+        self.loader = Some(loader);
+        self
+    }
+
+    /// Notifications whose data failed to decode
+    pub fn deserialization_failures(&self) -> u64 {
+        self.deserialization_failures.load(Ordering::Relaxed)
+    }
+
+    /// Record inserts of items already cached instead of silently replacing them
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let handler = IndexCacheHandler::for_type(cache.clone())
+    ///     .on_duplicate_insert(DuplicateInsert::Warn { apply: true });
+    /// // Later, e.g. from a metrics exporter
+    /// let anomalies = handler.duplicate_inserts() + handler.missing_deletes();
+    /// ```
+    pub fn on_duplicate_insert(mut self, policy: DuplicateInsert) -> Self {
+        self.on_duplicate_insert = policy;
+        self
+    }
+
+    /// Inserts of items already cached, counted unless the policy is `Overwrite`
+    pub fn duplicate_inserts(&self) -> u64 {
+        self.duplicate_inserts.load(Ordering::Relaxed)
+    }
+
+    /// Deletes of items that were not cached
+    pub fn missing_deletes(&self) -> u64 {
+        self.missing_deletes.load(Ordering::Relaxed)
+    }
+
+    /// Name the cache in log lines instead of the table name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The name logged in the `cache` field
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn log_clear(&self, flush: bool) {
+        if flush {
+            info!(cache = %self.name, table = %self.table_name, "flushed cache on request");
+        } else {
+            debug!(cache = %self.name, "Cleared cache for truncated table '{}'", self.table_name);
+        }
+    }
+
+    /// Treat inserts and updates of soft-deleted items as removals
+    pub fn remove_deleted(mut self) -> Self
+    where
+        T: IsDeleted,
+    {
+        self.is_deleted = Some(<T as IsDeleted>::is_deleted);
+        self
+    }
+
+    /// Ignore inserts and updates older than the cached version of the item
+    pub fn skip_stale_versions(mut self) -> Self
+    where
+        T: Versioned,
+    {
+        self.version_of = Some(version_of::<T>());
+        self
+    }
+
+    /// Skip updates equal to the cached item without taking the write lock
+    ///
+    /// Worthwhile when triggers fire for changes to columns the cached model
+    /// does not hold.
+    pub fn skip_unchanged(mut self) -> Self
+    where
+        T: PartialEq,
+    {
+        self.is_unchanged = Some(<T as PartialEq>::eq);
+        self
+    }
+
+    /// Updates skipped because they equalled the cached item
+    pub fn unchanged_updates(&self) -> u64 {
+        self.unchanged_updates.load(Ordering::Relaxed)
+    }
+
+    /// Create a new handler for the table of the cached type
+    pub fn for_type(cache: Arc<L>) -> Self
+    where
+        T: HasTableName,
+    {
+        Self::new(T::table_name().to_string(), cache)
     }
 }
 
-#[async_trait]
-impl<T: HasPrimaryKey + Indexable + Clone + Send + Sync + std::fmt::Debug + 'static> 
-    CacheNotificationHandler for IndexCacheHandler<T> 
+impl<T: HasPrimaryKey + Indexable + Clone + Send + Sync + 'static> IndexCacheHandler<T> {
+    /// Defer notifications for keys staged in open coordinated transactions
+    ///
+    /// The handler then updates the coordinator's cache instead of the one it
+    /// was created with.
+    pub fn with_coordinator(mut self, coordinator: Arc<SharedCacheCoordinator<T>>) -> Self {
+        self.cache = coordinator.cache().clone();
+        self.coordinator = Some(coordinator);
+        self
+    }
+}
+
+impl<T, L> IndexCacheHandler<T, L>
 where
-    T: for<'de> Deserialize<'de>,
+    T: IdxModel + for<'de> Deserialize<'de>,
+    L: CacheLock<IdxModelCache<T>> + 'static,
 {
-    async fn handle_notification(&self, notification: CacheNotification) {
+    /// The change the `OnDeserError` policy makes to the item a notification failed to decode
+    async fn undecodable_change(&self, id: Uuid) -> IdxChange<T> {
+        self.deserialization_failures.fetch_add(1, Ordering::Relaxed);
+        let reloaded = match (self.on_deser_error, &self.loader) {
+            (OnDeserError::Keep, _) => return IdxChange::Nothing,
+            (OnDeserError::Refetch, Some(loader)) => loader(id).await,
+            _ => None,
+        };
+        let name = self.name.clone();
+        IdxChange::Item(
+            id,
+            Box::new(move |cache| match reloaded {
+                Some(item) => {
+                    cache.add(item);
+                    debug!(cache = %name, "Refetched item {} after a decode failure", id);
+                }
+                None => {
+                    if cache.remove(&id).is_some() {
+                        debug!(cache = %name, "Invalidated item {} after a decode failure", id);
+                    }
+                }
+            }),
+        )
+    }
+
+    /// Applies prepared changes in order, under one write lock and as one
+    /// generation of the cache
+    ///
+    /// With a coordinator, item changes for keys staged in open transactions
+    /// are deferred instead. Without changes, e.g. for skipped unchanged
+    /// updates, no lock is taken.
+    async fn apply_changes(&self, changes: Vec<PreparedChange<T>>) {
+        let mut applied = Vec::with_capacity(changes.len());
+        if changes.iter().all(|prepared| matches!(prepared.change, IdxChange::Nothing)) {
+            applied.extend(changes.into_iter().filter_map(|prepared| prepared.record));
+        } else {
+            let mut cache = self.cache.write().await;
+            cache.batch(|cache| {
+                for PreparedChange { change, record } in changes {
+                    match change {
+                        IdxChange::Item(primary_key, change) => match &self.coordinator {
+                            Some(coordinator) => {
+                                coordinator.apply_or_defer_locked(primary_key, change, cache);
+                            }
+                            None => change(cache),
+                        },
+                        IdxChange::Clear { flush } => {
+                            // Changes deferred before the truncate are superseded by it
+                            if let Some(coordinator) = &self.coordinator {
+                                coordinator.discard_deferred();
+                            }
+                            cache.clear();
+                            self.log_clear(flush);
+                        }
+                        IdxChange::Nothing => {}
+                    }
+                    applied.extend(record);
+                }
+            });
+        }
+        for (action, key) in applied {
+            self.last_applied.record(&action, key);
+        }
+    }
+
+    /// Whether an update leaves the cached item as it is, checked under a read lock
+    ///
+    /// Keys held by a coordinated transaction are never skipped, their
+    /// deferred change decides.
+    async fn is_unchanged(&self, item: &T) -> bool {
+        let Some(is_unchanged) = self.is_unchanged else {
+            return false;
+        };
+        let primary_key = item.primary_key();
+        if self.coordinator.as_ref().is_some_and(|coordinator| coordinator.is_open(&primary_key)) {
+            return false;
+        }
+        self.cache.read().await.peek(&primary_key).is_some_and(|cached| is_unchanged(item, cached))
+    }
+
+    /// Works out what a notification changes, doing all the awaiting before
+    /// any change is applied
+    ///
+    /// Updates equal to the cached item are only skipped with `check_unchanged`.
+    async fn prepare(&self, notification: CacheNotification, check_unchanged: bool) -> Option<PreparedChange<T>> {
         debug!(
+            cache = %self.name,
             "Handling notification for table '{}': action={}, id={}",
             notification.table, notification.action, notification.id
         );
 
+        let recorded = |change| {
+            let record = Some((notification.action.clone(), Some(notification.id.to_string())));
+            Some(PreparedChange { change, record })
+        };
         match notification.action.as_str() {
             "insert" | "update" => {
-                if let Some(data) = notification.data {
-                    match serde_json::from_value::<T>(data) {
-                        Ok(item) => {
-                            let mut cache = self.cache.write();
-                            if notification.action == "insert" {
-                                cache.add(item);
-                                debug!("Added item {} to cache", notification.id);
-                            } else {
-                                cache.update(item);
-                                debug!("Updated item {} in cache", notification.id);
-                            }
-                        }
-                        Err(e) => {
-                            error!(
-                                "Failed to deserialize data for {}: {}",
-                                notification.table, e
-                            );
-                        }
-                    }
-                } else {
+                let Some(data) = notification.data else {
+                    // E.g. a trigger dropped the data of an oversized row, so the cached item is stale
                     warn!(
-                        "No data provided for {} operation on table {}",
-                        notification.action, notification.table
+                        cache = %self.name,
+                        table = %notification.table,
+                        action = %notification.action,
+                        id = %notification.id,
+                        "evicting item: no data provided"
                     );
+                    let id = notification.id;
+                    let name = self.name.clone();
+                    return recorded(IdxChange::Item(
+                        id,
+                        Box::new(move |cache| {
+                            if cache.remove(&id).is_some() {
+                                debug!(cache = %name, "Evicted item {} without data from cache", id);
+                            }
+                        }),
+                    ));
+                };
+                match decode_data::<T>(self.payload_migrator.as_deref(), &notification.table, data) {
+                    Ok(item) if notification.action == "update" && check_unchanged && self.is_unchanged(&item).await => {
+                        self.unchanged_updates.fetch_add(1, Ordering::Relaxed);
+                        debug!(cache = %self.name, "Skipped unchanged item {}", notification.id);
+                        recorded(IdxChange::Nothing)
+                    }
+                    Ok(item) => {
+                        let id = notification.id;
+                        let insert = notification.action == "insert";
+                        let version_of = self.version_of;
+                        let is_deleted = self.is_deleted;
+                        let on_duplicate_insert = self.on_duplicate_insert;
+                        let duplicate_inserts = self.duplicate_inserts.clone();
+                        let name = self.name.clone();
+                        recorded(IdxChange::Item(
+                            item.primary_key(),
+                            Box::new(move |cache| {
+                                if is_stale(version_of, &item, cache.peek(&item.primary_key())) {
+                                    debug!(cache = %name, "Skipped stale version of item {}", id);
+                                } else if is_deleted.is_some_and(|is_deleted| is_deleted(&item)) {
+                                    cache.remove(&item.primary_key());
+                                    debug!(cache = %name, "Removed soft-deleted item {} from cache", id);
+                                } else if insert
+                                    && on_duplicate_insert != DuplicateInsert::Overwrite
+                                    && cache.contains_primary(&item.primary_key())
+                                {
+                                    duplicate_inserts.fetch_add(1, Ordering::Relaxed);
+                                    let apply = match on_duplicate_insert {
+                                        DuplicateInsert::Warn { apply } => {
+                                            warn!(cache = %name, id = %id, applied = apply, "insert of an item already cached");
+                                            apply
+                                        }
+                                        DuplicateInsert::Count { apply } => apply,
+                                        DuplicateInsert::Overwrite => true,
+                                    };
+                                    if apply {
+                                        cache.add(item);
+                                        debug!(cache = %name, "Replaced item {} in cache", id);
+                                    }
+                                } else if insert {
+                                    cache.add(item);
+                                    debug!(cache = %name, "Added item {} to cache", id);
+                                } else {
+                                    cache.update(item);
+                                    debug!(cache = %name, "Updated item {} in cache", id);
+                                }
+                            }),
+                        ))
+                    }
+                    Err(err) => {
+                        warn!(
+                            cache = %self.name,
+                            table = %notification.table,
+                            action = %notification.action,
+                            id = %notification.id,
+                            error = %err,
+                            policy = ?self.on_deser_error,
+                            "dropping notification: failed to decode data"
+                        );
+                        let change = self.undecodable_change(notification.id).await;
+                        Some(PreparedChange { change, record: None })
+                    }
                 }
             }
             "delete" => {
-                let mut cache = self.cache.write();
-                cache.remove(&notification.id);
-                debug!("Removed item {} from cache", notification.id);
+                let id = notification.id;
+                let name = self.name.clone();
+                let missing_deletes = self.missing_deletes.clone();
+                recorded(IdxChange::Item(
+                    id,
+                    Box::new(move |cache| {
+                        if cache.remove(&id).is_some() {
+                            debug!(cache = %name, "Removed item {} from cache", id);
+                        } else {
+                            missing_deletes.fetch_add(1, Ordering::Relaxed);
+                            debug!(cache = %name, id = %id, "delete of an item not cached");
+                        }
+                    }),
+                ))
+            }
+            "truncate" | "flush" => Some(PreparedChange {
+                change: IdxChange::Clear { flush: notification.action == "flush" },
+                record: Some((notification.action.clone(), None)),
+            }),
+            _ => {
+                warn!(
+                    cache = %self.name,
+                    table = %notification.table,
+                    action = %notification.action,
+                    id = %notification.id,
+                    "dropping notification: unknown action"
+                );
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T, L> CacheNotificationHandler for IndexCacheHandler<T, L>
+where
+    T: IdxModel + for<'de> Deserialize<'de>,
+    L: CacheLock<IdxModelCache<T>> + 'static,
+{
+    async fn handle_notification(&self, notification: CacheNotification) {
+        let prepared = self.prepare(notification, true).await;
+        self.apply_changes(prepared.into_iter().collect()).await;
+    }
+
+    /// Decodes all notifications first, then applies them under one write lock
+    async fn handle_notifications(&self, notifications: Vec<CacheNotification>) {
+        let mut prepared = Vec::with_capacity(notifications.len());
+        let mut changed = HashSet::new();
+        let mut cleared = false;
+        for notification in notifications {
+            // The cached item is only compared before an earlier notification changed it
+            let check_unchanged = !cleared && changed.insert(notification.id);
+            cleared |= matches!(notification.action.as_str(), "truncate" | "flush");
+            prepared.extend(self.prepare(notification, check_unchanged).await);
+        }
+        self.apply_changes(prepared).await;
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    fn last_applied(&self) -> Option<AppliedInfo> {
+        self.last_applied.get()
+    }
+
+    fn mark_loaded(&self) {
+        self.last_applied.record_load();
+    }
+
+    async fn mark_ready(&self) {
+        self.cache.write().await.mark_ready();
+    }
+
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        self.expected_fields.clone().or_else(serde_fields::<T>)
+    }
+}
+
+/// Execution controls of a registered handler
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use postgres_index_cache::HandlerOptions;
+///
+/// let options = HandlerOptions::default()
+///     .with_timeout(Duration::from_secs(2))
+///     .with_max_concurrency(4);
+/// assert_eq!(options.max_concurrency, Some(4));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HandlerOptions {
+    /// Abandon a handler invocation after this long; it is logged and counted
+    pub timeout: Option<Duration>,
+    /// Maximum number of invocations of the handler running at once, when
+    /// `process_notification` is called concurrently
+    pub max_concurrency: Option<usize>,
+    /// Retry invocations that did not complete, in the background
+    pub retry: Option<RetryPolicy>,
+}
+
+impl HandlerOptions {
+    /// Set the timeout of a handler invocation
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of concurrent invocations of the handler
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency.max(1));
+        self
+    }
+
+    /// Retry invocations that did not complete as the policy says
+    ///
+    /// Invocations only fail to complete by timing out, so this needs a timeout.
+    /// Failures a handler deals with itself are not retried: a loader
+    /// failing with a database error under `OnDeserError::Refetch` returns
+    /// `None`, and the item is invalidated.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// How the listener retries a notification its handler did not complete
+///
+/// Retries run in a background task, so they hold up neither the other
+/// tables nor later notifications of the same table. A newer notification
+/// of the same item ends a retry; when it arrives while an attempt runs, it
+/// is applied again after the attempt, so a late retry never leaves the
+/// older state behind, whether or not the handler skips stale versions. A
+/// truncate or flush of the table ends the retries of all its items. The
+/// backoff starts at `initial_backoff` and doubles after every retry, up to
+/// `max_backoff`.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use postgres_index_cache::{HandlerOptions, RetryPolicy};
+///
+/// let options = HandlerOptions::default()
+///     .with_timeout(Duration::from_secs(2))
+///     .with_retry(RetryPolicy::new(3).with_backoff(Duration::from_millis(50), Duration::from_secs(1)));
+/// assert_eq!(options.retry.map(|retry| retry.max_retries), Some(3));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first invocation
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between retries
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, starting after 100 milliseconds
+    /// and backing off up to 10 seconds
+    pub fn new(max_retries: u32) -> Self {
+        Self { max_retries, initial_backoff: Duration::from_millis(100), max_backoff: Duration::from_secs(10) }
+    }
+
+    /// Set the delay before the first retry and the upper bound of the delay
+    pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff.max(initial_backoff);
+        self
+    }
+
+    /// The delay before the given retry, counted from zero
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+/// How `listen` reconnects after losing its connection
+///
+/// The delay starts at `initial_delay` and doubles after every failed
+/// attempt, up to `max_delay`. The default waits 5 seconds between attempts
+/// and never gives up.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use postgres_index_cache::ReconnectPolicy;
+///
+/// let policy = ReconnectPolicy::exponential(Duration::from_millis(100), Duration::from_secs(10))
+///     .with_max_attempts(20);
+/// assert_eq!(policy.max_attempts, Some(20));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between attempts
+    pub max_delay: Duration,
+    /// Give up and return the error after this many failed attempts in a row
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// Wait the same delay before every attempt
+    pub fn fixed(delay: Duration) -> Self {
+        Self { initial_delay: delay, max_delay: delay, max_attempts: None }
+    }
+
+    /// Double the delay after every failed attempt, up to `max_delay`
+    pub fn exponential(initial_delay: Duration, max_delay: Duration) -> Self {
+        Self { initial_delay, max_delay: max_delay.max(initial_delay), max_attempts: None }
+    }
+
+    /// Give up after this many failed attempts in a row
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// The delay before the given attempt, counted from zero
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::fixed(Duration::from_secs(5))
+    }
+}
+
+/// A handler with its execution controls
+struct RegisteredHandler {
+    handler: Arc<dyn CacheNotificationHandler>,
+    /// The channel the handler receives on; the listener's own channel when `None`
+    channel: Option<String>,
+    timeout: Option<Duration>,
+    permits: Option<tokio::sync::Semaphore>,
+    retry: Option<RetryPolicy>,
+    /// Notifications being retried, by table and key
+    pending_retries: Mutex<HashMap<(String, String), PendingRetry>>,
+    next_retry: AtomicU64,
+    timeouts: AtomicU64,
+    retries: AtomicU64,
+    failures: AtomicU64,
+    superseded: AtomicU64,
+    latency: LatencyHistogram,
+}
+
+/// A notification being retried, with the newest change of its item
+/// dispatched since
+struct PendingRetry {
+    id: u64,
+    newer: Option<CacheNotification>,
+}
+
+impl RegisteredHandler {
+    fn new(handler: Arc<dyn CacheNotificationHandler>, channel: Option<String>, options: HandlerOptions) -> Self {
+        Self {
+            handler,
+            channel,
+            timeout: options.timeout,
+            permits: options.max_concurrency.map(tokio::sync::Semaphore::new),
+            retry: options.retry,
+            pending_retries: Mutex::new(HashMap::new()),
+            next_retry: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            superseded: AtomicU64::new(0),
+            latency: LatencyHistogram::default(),
+        }
+    }
+
+    fn stats(&self) -> HandlerStats {
+        HandlerStats {
+            retries: self.retries.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            superseded: self.superseded.load(Ordering::Relaxed),
+            ..self.latency.stats(self.timeouts.load(Ordering::Relaxed))
+        }
+    }
+
+    /// Retries a notification the handler did not complete in a background
+    /// task, and passes it to the dead-letter hook once no retry is left
+    fn retry_or_give_up(
+        self: Arc<Self>,
+        notification: CacheNotification,
+        slow_threshold: Option<Duration>,
+        dead_letter: Option<Arc<DeadLetterHook>>,
+    ) {
+        let Some(policy) = self.retry else {
+            self.give_up(&notification, "handler timed out", dead_letter.as_deref());
+            return;
+        };
+
+        // A retry of the same item still pending is superseded by this one
+        let key = (notification.table.clone(), notification.raw_key());
+        let id = self.next_retry.fetch_add(1, Ordering::Relaxed);
+        self.pending_retries.lock().insert(key.clone(), PendingRetry { id, newer: None });
+
+        let span = info_span!("retry_notification", table = %notification.table, id = %notification.id);
+        tokio::spawn(
+            async move {
+                for retry in 0..policy.max_retries {
+                    tokio::time::sleep(policy.backoff(retry)).await;
+                    if self.supersede(&key, id) {
+                        return;
+                    }
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                    if self.handle(notification.clone(), slow_threshold).await {
+                        debug!(retry = retry + 1, "retried notification completed");
+                        self.settle(&key, id, slow_threshold).await;
+                        return;
+                    }
+                }
+                if self.supersede(&key, id) {
+                    return;
+                }
+                self.pending_retries.lock().remove(&key);
+                let reason = format!("handler timed out {} times", policy.max_retries + 1);
+                self.give_up(&notification, &reason, dead_letter.as_deref());
+            }
+            .instrument(span),
+        );
+    }
+
+    /// Records a notification dispatched to the handler as newer than the
+    /// retries pending for its item
+    ///
+    /// A truncate or flush is newer than the retries of all items of its
+    /// table; they are undone with a delete.
+    fn note_dispatched(&self, notification: &CacheNotification) {
+        if self.retry.is_none() {
+            return;
+        }
+        let mut pending_retries = self.pending_retries.lock();
+        if pending_retries.is_empty() {
+            return;
+        }
+        match notification.cache_action() {
+            Some(CacheAction::Truncate | CacheAction::Flush) => {
+                for ((table, key), pending) in pending_retries.iter_mut().filter(|((table, _), _)| *table == notification.table) {
+                    let (id, key) = match key.parse::<Uuid>() {
+                        Ok(id) => (id, None),
+                        Err(_) => (Uuid::nil(), Some(key.clone())),
+                    };
+                    pending.newer = Some(CacheNotification { key, ..CacheNotification::delete(table, id) });
+                }
             }
             _ => {
-                warn!("Unknown action '{}' for table '{}'", notification.action, notification.table);
+                let key = (notification.table.clone(), notification.raw_key());
+                if let Some(pending) = pending_retries.get_mut(&key) {
+                    pending.newer = Some(notification.clone());
+                }
+            }
+        }
+    }
+
+    /// Ends a retry before its next attempt if a newer notification of its
+    /// item was dispatched; returns whether it did
+    fn supersede(&self, key: &(String, String), id: u64) -> bool {
+        let mut pending_retries = self.pending_retries.lock();
+        let superseded = match pending_retries.get(key) {
+            Some(pending) if pending.id == id => {
+                let superseded = pending.newer.is_some();
+                if superseded {
+                    pending_retries.remove(key);
+                }
+                superseded
+            }
+            _ => true,
+        };
+        if superseded {
+            self.superseded.fetch_add(1, Ordering::Relaxed);
+            debug!("retry superseded by a newer notification of the item");
+        }
+        superseded
+    }
+
+    /// Applies again the newest notification of an item dispatched while its
+    /// retry ran, so the retry does not leave the older state behind
+    async fn settle(&self, key: &(String, String), id: u64, slow_threshold: Option<Duration>) {
+        loop {
+            let newer = {
+                let mut pending_retries = self.pending_retries.lock();
+                match pending_retries.get_mut(key) {
+                    Some(pending) if pending.id == id => match pending.newer.take() {
+                        Some(newer) => newer,
+                        None => {
+                            pending_retries.remove(key);
+                            return;
+                        }
+                    },
+                    // A newer retry of the item applies after this one
+                    _ => return,
+                }
+            };
+            debug!(action = %newer.action, "reapplying the notification that arrived during the retry");
+            self.handle(newer, slow_threshold).await;
+        }
+    }
+
+    fn give_up(&self, notification: &CacheNotification, reason: &str, dead_letter: Option<&DeadLetterHook>) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        error!(
+            table = %notification.table,
+            action = %notification.action,
+            id = %notification.id,
+            reason,
+            "giving up on notification"
+        );
+        if let Some(dead_letter) = dead_letter {
+            dead_letter(notification, reason);
+        }
+    }
+
+    /// Runs the handler and records its duration; returns false if it timed out
+    ///
+    /// Waiting for a concurrency permit is not part of the duration.
+    async fn handle(&self, notification: CacheNotification, slow_threshold: Option<Duration>) -> bool {
+        let _permit = match &self.permits {
+            // The semaphore is never closed
+            Some(permits) => permits.acquire().await.ok(),
+            None => None,
+        };
+
+        let (table, action, id) = (notification.table.clone(), notification.action.clone(), notification.id);
+        let started = Instant::now();
+        let completed = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.handler.handle_notification(notification))
+                .await
+                .is_ok(),
+            None => {
+                self.handler.handle_notification(notification).await;
+                true
             }
+        };
+        let elapsed = started.elapsed();
+        self.latency.record(elapsed);
+
+        if !completed {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+            error!(
+                table = %table,
+                action = %action,
+                id = %id,
+                timeout_ms = self.timeout.unwrap_or_default().as_millis() as u64,
+                "handler timed out; notification abandoned"
+            );
+        } else if slow_threshold.is_some_and(|threshold| elapsed > threshold) {
+            warn!(
+                table = %table,
+                action = %action,
+                id = %id,
+                elapsed_ms = elapsed.as_millis() as u64,
+                threshold_ms = slow_threshold.unwrap_or_default().as_millis() as u64,
+                "slow notification handler"
+            );
+        }
+        completed
+    }
+
+    /// Runs the handler on a batch of notifications of one table, like `handle`
+    ///
+    /// The timeout and the recorded duration cover the whole batch.
+    async fn handle_batch(&self, notifications: Vec<CacheNotification>, slow_threshold: Option<Duration>) -> bool {
+        let _permit = match &self.permits {
+            Some(permits) => permits.acquire().await.ok(),
+            None => None,
+        };
+
+        let (table, count) = (self.handler.table_name().to_string(), notifications.len());
+        let started = Instant::now();
+        let completed = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.handler.handle_notifications(notifications))
+                .await
+                .is_ok(),
+            None => {
+                self.handler.handle_notifications(notifications).await;
+                true
+            }
+        };
+        let elapsed = started.elapsed();
+        self.latency.record(elapsed);
+
+        if !completed {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+            error!(
+                table = %table,
+                count,
+                timeout_ms = self.timeout.unwrap_or_default().as_millis() as u64,
+                "handler timed out; notification batch abandoned"
+            );
+        } else if slow_threshold.is_some_and(|threshold| elapsed > threshold) {
+            warn!(
+                table = %table,
+                count,
+                elapsed_ms = elapsed.as_millis() as u64,
+                threshold_ms = slow_threshold.unwrap_or_default().as_millis() as u64,
+                "slow notification handler"
+            );
+        }
+        completed
+    }
+}
+
+/// Checks that registering a handler for the table on `channel`, or on the
+/// listener's channel for `None`, does not move the table to another channel
+fn check_channel(
+    handlers: &HashMap<String, Arc<RegisteredHandler>>,
+    table_name: &str,
+    channel: Option<&str>,
+) -> Result<(), CacheError> {
+    match handlers.get(table_name) {
+        Some(existing) if existing.channel.as_deref() != channel => Err(CacheError::InvalidArgument(format!(
+            "table '{}' is already registered on {}",
+            table_name,
+            existing.channel.as_ref().map_or("the listener's channel".to_string(), |c| format!("channel '{c}'")),
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Handlers registered by table name, shared between clones
+///
+/// Cloning is cheap and every clone sees the same handlers, so one registry
+/// can serve the `listen` loop, an outbox poller and `replay` at once, each
+/// through its own listener. Registering a handler through any clone makes it
+/// visible to all of them.
+///
+/// A handler registered with `register_on_channel` only receives the
+/// notifications of its table arriving on that channel; other handlers
+/// receive those arriving on the listener's own channel.
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    handlers: Arc<RwLock<HashMap<String, Arc<RegisteredHandler>>>>,
+}
+
+impl HandlerRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for its table, replacing any previous one
+    ///
+    /// A handler registered with `register_on_channel` is not replaced, see
+    /// `register_with_options`.
+    pub fn register(&self, handler: Arc<dyn CacheNotificationHandler>) {
+        self.register_with_options(handler, HandlerOptions::default());
+    }
+
+    /// Register a handler for its table with a timeout and concurrency limit
+    ///
+    /// If the table already has a handler registered with
+    /// `register_on_channel`, the registration is refused and logged as an
+    /// error: replacing it would stop dispatching the notifications arriving
+    /// on the table's channel.
+    pub fn register_with_options(&self, handler: Arc<dyn CacheNotificationHandler>, options: HandlerOptions) {
+        let table_name = handler.table_name().to_string();
+        let mut handlers = self.handlers.write();
+        if let Err(err) = check_channel(&handlers, &table_name, None) {
+            error!(table = %table_name, error = %err, "refusing to register handler");
+            return;
+        }
+        debug!("Registering handler for table '{}'", table_name);
+        let registered = RegisteredHandler::new(handler, None, options);
+        handlers.insert(table_name, Arc::new(registered));
+    }
+
+    /// Register a handler for the notifications of its table on `channel`
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::InvalidArgument` if a handler for the table is
+    /// already registered on another channel, or without a channel. Replacing
+    /// the handler on the same channel is allowed.
+    pub fn register_on_channel(
+        &self,
+        channel: impl Into<String>,
+        handler: Arc<dyn CacheNotificationHandler>,
+        options: HandlerOptions,
+    ) -> Result<(), CacheError> {
+        let channel = channel.into();
+        let table_name = handler.table_name().to_string();
+        let mut handlers = self.handlers.write();
+        check_channel(&handlers, &table_name, Some(&channel))?;
+        debug!("Registering handler for table '{}' on channel '{}'", table_name, channel);
+        let registered = RegisteredHandler::new(handler, Some(channel), options);
+        handlers.insert(table_name, Arc::new(registered));
+        Ok(())
+    }
+
+    /// Channels handlers were registered on with `register_on_channel`, sorted
+    pub fn channels(&self) -> Vec<String> {
+        let mut channels: Vec<String> =
+            self.handlers.read().values().filter_map(|handler| handler.channel.clone()).collect();
+        channels.sort();
+        channels.dedup();
+        channels
+    }
+
+    /// Whether a handler is registered for the table
+    pub fn contains(&self, table: &str) -> bool {
+        self.handlers.read().contains_key(table)
+    }
+
+    /// Tables with a registered handler, sorted
+    pub fn tables(&self) -> Vec<String> {
+        let mut tables: Vec<String> = self.handlers.read().keys().cloned().collect();
+        tables.sort();
+        tables
+    }
+
+    /// The registered handlers with their channels and types, sorted by table
+    pub fn handler_infos(&self) -> Vec<HandlerInfo> {
+        let mut infos: Vec<HandlerInfo> = self
+            .handlers
+            .read()
+            .iter()
+            .map(|(table, registered)| HandlerInfo {
+                table: table.clone(),
+                channel: registered.channel.clone(),
+                handler_type: registered.handler.handler_type(),
+            })
+            .collect();
+        infos.sort_by(|a, b| a.table.cmp(&b.table));
+        infos
+    }
+
+    /// How many invocations of the handler of a table timed out
+    pub fn handler_timeouts(&self, table: &str) -> u64 {
+        self.get(table)
+            .map_or(0, |handler| handler.timeouts.load(Ordering::Relaxed))
+    }
+
+    /// Latency statistics of the handler of a table, `None` without a handler
+    pub fn handler_stats(&self, table: &str) -> Option<HandlerStats> {
+        self.get(table)
+            .map(|handler| handler.stats())
+    }
+
+    /// Latency statistics of all handlers, by table name
+    pub fn all_handler_stats(&self) -> Vec<(String, HandlerStats)> {
+        self.tables()
+            .into_iter()
+            .filter_map(|table| {
+                let stats = self.handler_stats(&table)?;
+                Some((table, stats))
+            })
+            .collect()
+    }
+
+    /// The last change the handler of a table applied
+    pub fn last_applied(&self, table: &str) -> Option<AppliedInfo> {
+        self.get(table).and_then(|handler| handler.handler.last_applied())
+    }
+
+    /// The last change applied by each handler that applied one, by table name
+    pub fn all_last_applied(&self) -> Vec<(String, AppliedInfo)> {
+        self.tables()
+            .into_iter()
+            .filter_map(|table| {
+                let applied = self.last_applied(&table)?;
+                Some((table, applied))
+            })
+            .collect()
+    }
+
+    /// Record that the caches of all handlers were just loaded from their tables
+    pub fn mark_loaded(&self) {
+        let handlers: Vec<Arc<RegisteredHandler>> = self.handlers.read().values().cloned().collect();
+        for handler in handlers {
+            handler.handler.mark_loaded();
+        }
+    }
+
+    /// Mark the caches of all handlers ready for reads gated on readiness
+    pub async fn mark_ready(&self) {
+        let handlers: Vec<Arc<RegisteredHandler>> = self.handlers.read().values().cloned().collect();
+        for handler in handlers {
+            handler.handler.mark_ready().await;
+        }
+    }
+
+    /// The handler of a table; the lock is released before it runs
+    fn get(&self, table: &str) -> Option<Arc<RegisteredHandler>> {
+        self.handlers.read().get(table).cloned()
+    }
+
+    /// The registered handlers with their table names, sorted by table
+    pub(crate) fn handlers(&self) -> Vec<(String, Arc<dyn CacheNotificationHandler>)> {
+        let mut handlers: Vec<(String, Arc<dyn CacheNotificationHandler>)> = self
+            .handlers
+            .read()
+            .iter()
+            .map(|(table, registered)| (table.clone(), registered.handler.clone()))
+            .collect();
+        handlers.sort_by(|a, b| a.0.cmp(&b.0));
+        handlers
+    }
+}
+
+/// What a paused listener does with incoming notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    /// Discard them, counting how many were discarded
+    Discard,
+    /// Keep up to `capacity` of them for `ResyncMode::Replay`; further ones are discarded
+    Buffer { capacity: usize },
+}
+
+/// How a paused listener catches up when it resumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResyncMode {
+    /// Dispatch the notifications kept while paused
+    ///
+    /// Falls back to `DropAndResync` if any were discarded.
+    Replay,
+    /// Drop the kept notifications and run the resync callbacks
+    DropAndResync,
+}
+
+/// Reloads caches after notifications were dropped
+type ResyncCallback = dyn Fn() -> BoxFuture<'static, ()> + Send + Sync;
+
+/// How many notifications `listen` collects before dispatching them together
+#[derive(Debug, Clone, Copy)]
+struct Batching {
+    max_items: usize,
+    max_delay: Duration,
+}
+
+/// The notifications of one handler in a batch, with the index of each payload
+type HandlerBatch = (Arc<RegisteredHandler>, Vec<(usize, CacheNotification)>);
+
+/// A payload as received, with the channel it arrived on if known
+struct Received {
+    channel: Option<String>,
+    payload: String,
+}
+
+/// Notifications held by a paused listener
+struct PauseState {
+    mode: PauseMode,
+    buffer: VecDeque<Received>,
+    discarded: u64,
+}
+
+/// What happened to a payload passed through the listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationOutcome {
+    /// The handler of the table ran to completion
+    Applied { table: String },
+    /// The listener's filter rejected the notification
+    Filtered { table: String },
+    /// No handler is registered for the table, or not on the channel it arrived on
+    NoHandler { table: String },
+    /// The payload is not a valid notification
+    ParseError(String),
+    /// The row data failed verification or decryption with the listener's
+    /// payload key, or arrived unprotected
+    Rejected { table: String, reason: String },
+    /// The handler did not complete, e.g. because it timed out
+    ///
+    /// Failures handlers deal with themselves, like row data that does not
+    /// deserialize, are logged by the handler and count as applied.
+    HandlerError { table: String, error: String },
+    /// Held back while the listener is paused or buffering, to be dispatched
+    /// when it resumes
+    Buffered,
+    /// Dropped because the listener is paused and does not buffer, or its
+    /// pause buffer is full
+    Discarded,
+}
+
+impl NotificationOutcome {
+    /// The table of the notification, if it was parsed and dispatched
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            Self::Applied { table } | Self::Filtered { table } | Self::NoHandler { table } => Some(table),
+            Self::HandlerError { table, .. } | Self::Rejected { table, .. } => Some(table),
+            Self::ParseError(_) | Self::Buffered | Self::Discarded => None,
         }
     }
 
-    fn table_name(&self) -> &str {
-        &self.table_name
+    /// Returns true if delivering the payload again would not change the outcome
+    ///
+    /// False for a handler that did not complete and for a discarded payload,
+    /// which a durable source like the outbox should deliver again.
+    pub fn is_consumed(&self) -> bool {
+        !matches!(self, Self::HandlerError { .. } | Self::Discarded)
+    }
+}
+
+/// The outcome of every payload of a `CacheNotificationListener::replay`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// One outcome per payload, in replay order
+    pub outcomes: Vec<NotificationOutcome>,
+}
+
+impl ReplayReport {
+    /// Number of payloads replayed
+    pub fn total(&self) -> usize {
+        self.outcomes.len()
+    }
+
+    /// Number of payloads applied by their handler
+    pub fn applied(&self) -> usize {
+        self.count(|outcome| matches!(outcome, NotificationOutcome::Applied { .. }))
+    }
+
+    /// Number of payloads rejected by the listener's filter
+    pub fn filtered(&self) -> usize {
+        self.count(|outcome| matches!(outcome, NotificationOutcome::Filtered { .. }))
+    }
+
+    /// Number of payloads for tables without a handler
+    pub fn no_handler(&self) -> usize {
+        self.count(|outcome| matches!(outcome, NotificationOutcome::NoHandler { .. }))
+    }
+
+    /// Number of payloads that could not be parsed
+    pub fn parse_errors(&self) -> usize {
+        self.count(|outcome| matches!(outcome, NotificationOutcome::ParseError(_)))
+    }
+
+    /// Number of payloads whose handler did not complete
+    pub fn handler_errors(&self) -> usize {
+        self.count(|outcome| matches!(outcome, NotificationOutcome::HandlerError { .. }))
+    }
+
+    fn count(&self, predicate: impl Fn(&NotificationOutcome) -> bool) -> usize {
+        self.outcomes.iter().filter(|outcome| predicate(outcome)).count()
     }
 }
 
 /// Listener for PostgreSQL notifications that dispatches to registered cache handlers
 pub struct CacheNotificationListener {
-    handlers: HashMap<String, Arc<dyn CacheNotificationHandler>>,
+    handlers: HandlerRegistry,
     channel: String,
+    filter: Option<Arc<NotificationFilter>>,
+    /// Payloads held back while buffering; `None` dispatches them right away
+    buffer: Mutex<Option<VecDeque<Received>>>,
+    /// Set while paused; checked before `buffer`
+    pause: Mutex<Option<PauseState>>,
+    /// Notifications discarded while paused, over all pauses
+    paused_discarded: AtomicU64,
+    resync_callbacks: Vec<Box<ResyncCallback>>,
+    /// Whether `listen` is connected and listening on the channel
+    listening: tokio::sync::watch::Sender<bool>,
+    reconnect: ReconnectPolicy,
+    slow_handler_threshold: Option<Duration>,
+    batching: Option<Batching>,
+    dead_letter: Option<Arc<DeadLetterHook>>,
+    #[cfg(feature = "payload-protection")]
+    payload_key: Option<crate::payload_protection::PayloadKey>,
 }
 
 impl CacheNotificationListener {
@@ -119,22 +1614,373 @@ impl CacheNotificationListener {
 
     /// Create a new listener with a custom channel name
     pub fn with_channel(channel: String) -> Self {
+        Self::with_registry(channel, HandlerRegistry::new())
+    }
+
+    /// Create a listener dispatching to the handlers of a shared registry
+    ///
+    /// # Example
+    /// ```rust
+    /// use postgres_index_cache::{CacheNotificationListener, HandlerRegistry, DEFAULT_CACHE_CHANNEL};
+    ///
+    /// let registry = HandlerRegistry::new();
+    /// let live = CacheNotificationListener::with_registry(DEFAULT_CACHE_CHANNEL.to_string(), registry.clone());
+    /// let outbox = CacheNotificationListener::with_registry(DEFAULT_CACHE_CHANNEL.to_string(), registry.clone());
+    /// // Handlers registered on `registry` now serve both listeners
+    /// ```
+    pub fn with_registry(channel: String, registry: HandlerRegistry) -> Self {
         Self {
-            handlers: HashMap::new(),
+            handlers: registry,
             channel,
+            filter: None,
+            buffer: Mutex::new(None),
+            pause: Mutex::new(None),
+            paused_discarded: AtomicU64::new(0),
+            resync_callbacks: Vec::new(),
+            listening: tokio::sync::watch::channel(false).0,
+            reconnect: ReconnectPolicy::default(),
+            slow_handler_threshold: None,
+            batching: None,
+            dead_letter: None,
+            #[cfg(feature = "payload-protection")]
+            payload_key: None,
+        }
+    }
+
+    /// Only dispatch notifications for which the predicate returns true
+    ///
+    /// The predicate sees the parsed notification, so it can route on the
+    /// table, the action or the context without deserializing the row data.
+    ///
+    /// # Example
+    /// ```rust
+    /// use postgres_index_cache::CacheNotificationListener;
+    ///
+    /// let mut listener = CacheNotificationListener::new();
+    /// listener.set_filter(|notification| {
+    ///     notification.context_value("tenant_id") == Some(&serde_json::json!("acme"))
+    /// });
+    /// ```
+    pub fn set_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&CacheNotification) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Arc::new(filter));
+    }
+
+    /// Pass notifications a handler gave up on to `hook`
+    ///
+    /// Called after the last retry of a handler with a `RetryPolicy`, or
+    /// right after a timeout without one, e.g. to store the notification for
+    /// a later `replay`.
+    pub fn set_dead_letter_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&CacheNotification, &str) + Send + Sync + 'static,
+    {
+        self.dead_letter = Some(Arc::new(hook));
+    }
+
+    /// Verify and decrypt the row data of every notification with `key`
+    ///
+    /// Notifications whose data is unprotected or fails verification are
+    /// dropped as `NotificationOutcome::Rejected` and passed to the
+    /// dead-letter hook. Notifications without data, like deletes, are
+    /// dispatched as they are.
+    #[cfg(feature = "payload-protection")]
+    pub fn set_payload_key(&mut self, key: crate::payload_protection::PayloadKey) {
+        self.payload_key = Some(key);
+    }
+
+    /// Replaces protected row data with its plaintext, if a payload key is set
+    #[cfg(feature = "payload-protection")]
+    fn unprotect(&self, notification: CacheNotification) -> Result<CacheNotification, NotificationOutcome> {
+        let (Some(key), Some(data)) = (&self.payload_key, &notification.data) else {
+            return Ok(notification);
+        };
+        match key.unprotect(&notification.table, &notification.action, &notification.raw_key(), data) {
+            Ok(data) => Ok(CacheNotification { data: Some(data), ..notification }),
+            Err(err) => {
+                let reason = err.to_string();
+                warn!(table = %notification.table, id = %notification.id, error = %err, "dropping notification");
+                if let Some(dead_letter) = &self.dead_letter {
+                    dead_letter(&notification, &reason);
+                }
+                Err(NotificationOutcome::Rejected { table: notification.table.clone(), reason })
+            }
         }
     }
 
+    /// Set how `listen` reconnects after losing its connection
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect = policy;
+    }
+
+    /// The registry the listener dispatches to
+    ///
+    /// Clone it to share the registered handlers with other listeners.
+    pub fn registry(&self) -> &HandlerRegistry {
+        &self.handlers
+    }
+
     /// Register a handler for a specific table
+    ///
+    /// The handler is added to the shared registry, so every listener using
+    /// the same registry dispatches to it. It does not replace a handler
+    /// registered on another channel with `register_handler_on_channel`.
     pub fn register_handler(&mut self, handler: Arc<dyn CacheNotificationHandler>) {
-        let table_name = handler.table_name().to_string();
-        debug!("Registering handler for table '{}'", table_name);
-        self.handlers.insert(table_name, handler);
+        self.handlers.register(handler);
+    }
+
+    /// Register a handler for a specific table with a timeout and concurrency limit
+    pub fn register_handler_with_options(&mut self, handler: Arc<dyn CacheNotificationHandler>, options: HandlerOptions) {
+        self.handlers.register_with_options(handler, options);
+    }
+
+    /// Register a handler for a table whose trigger notifies on its own channel
+    ///
+    /// `listen` then listens on that channel as well, and dispatches the
+    /// table's notifications only when they arrive on it, so a high-churn
+    /// table on its own channel cannot delay the others. Handlers have to be
+    /// registered before `listen` is called.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::InvalidArgument` if the table already has a
+    /// handler on another channel.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// init_table_trigger(&pool, &TriggerOptions::for_type::<Event>().with_channel("event_cache")).await?;
+    /// listener.register_handler_on_channel("event_cache", Arc::new(IndexCacheHandler::for_type(events.clone())))?;
+    /// ```
+    pub fn register_handler_on_channel(
+        &mut self,
+        channel: impl Into<String>,
+        handler: Arc<dyn CacheNotificationHandler>,
+    ) -> Result<(), CacheError> {
+        self.handlers.register_on_channel(channel, handler, HandlerOptions::default())
+    }
+
+    /// Tables with a registered handler, sorted
+    ///
+    /// Compare them with the installed triggers using `check_handler_coverage`.
+    pub fn registered_tables(&self) -> Vec<String> {
+        self.handlers.tables()
+    }
+
+    /// Whether a handler is registered for the table
+    pub fn has_handler(&self, table: &str) -> bool {
+        self.handlers.contains(table)
+    }
+
+    /// The registered handlers with their channels and types, sorted by table,
+    /// e.g. for an endpoint showing which tables a service has wired
+    pub fn registered_handlers(&self) -> Vec<HandlerInfo> {
+        self.handlers.handler_infos()
+    }
+
+    /// How many invocations of the handler of a table timed out
+    pub fn handler_timeouts(&self, table: &str) -> u64 {
+        self.handlers.handler_timeouts(table)
+    }
+
+    /// Latency statistics of the handler of a table, `None` without a handler
+    pub fn handler_stats(&self, table: &str) -> Option<HandlerStats> {
+        self.handlers.handler_stats(table)
+    }
+
+    /// Latency statistics of all handlers, by table name
+    pub fn all_handler_stats(&self) -> Vec<(String, HandlerStats)> {
+        self.handlers.all_handler_stats()
+    }
+
+    /// The last change the handler of a table applied
+    ///
+    /// Alert on it for tables that change rarely: a silent listener leaves
+    /// their caches stale without any errors.
+    pub fn last_applied(&self, table: &str) -> Option<AppliedInfo> {
+        self.handlers.last_applied(table)
+    }
+
+    /// The last change applied by each handler, by table name
+    pub fn all_last_applied(&self) -> Vec<(String, AppliedInfo)> {
+        self.handlers.all_last_applied()
+    }
+
+    /// Log a warning for every handler invocation taking longer than `threshold`
+    pub fn set_slow_handler_threshold(&mut self, threshold: Duration) {
+        self.slow_handler_threshold = Some(threshold);
+    }
+
+    /// Let `listen` collect bursts of up to `max_items` notifications,
+    /// waiting at most `max_delay` after the first, and dispatch them together
+    ///
+    /// Each handler gets the notifications of its table in one
+    /// `handle_notifications` call, so the index and main model cache
+    /// handlers take their write lock once per burst. A handler timeout then
+    /// covers the whole batch. With `max_items` of 1, the default, every
+    /// notification is dispatched as it arrives.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use postgres_index_cache::CacheNotificationListener;
+    ///
+    /// let mut listener = CacheNotificationListener::new();
+    /// listener.set_batching(100, Duration::from_millis(5));
+    /// ```
+    pub fn set_batching(&mut self, max_items: usize, max_delay: Duration) {
+        self.batching = (max_items > 1).then_some(Batching { max_items, max_delay });
+    }
+
+    /// Hold back notifications instead of dispatching them
+    ///
+    /// The payloads are kept in arrival order until `stop_buffering`.
+    pub fn start_buffering(&self) {
+        self.buffer.lock().get_or_insert_with(VecDeque::new);
+    }
+
+    /// Whether notifications are being held back
+    pub fn is_buffering(&self) -> bool {
+        self.buffer.lock().is_some()
+    }
+
+    /// Dispatch the held back notifications, then switch to live dispatch
+    ///
+    /// Notifications arriving meanwhile are queued behind the buffered ones,
+    /// so all of them are dispatched in arrival order. Returns how many were
+    /// dispatched from the buffer.
+    pub async fn stop_buffering(&self) -> usize {
+        let mut count = 0;
+        loop {
+            let received = {
+                let mut buffer = self.buffer.lock();
+                match buffer.as_mut().and_then(VecDeque::pop_front) {
+                    Some(received) => received,
+                    None => {
+                        *buffer = None;
+                        return count;
+                    }
+                }
+            };
+            self.dispatch(received.channel.as_deref(), &received.payload).await;
+            count += 1;
+        }
+    }
+
+    /// Register a callback reloading caches after a `ResyncMode::DropAndResync`
+    pub fn on_resync<F, Fut>(&mut self, callback: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.resync_callbacks
+            .push(Box::new(move || -> BoxFuture<'static, ()> { Box::pin(callback()) }));
+    }
+
+    /// Stop applying notifications, e.g. during bulk maintenance
+    ///
+    /// Pausing a paused listener only changes the mode; notifications kept so
+    /// far stay kept.
+    pub fn pause(&self, mode: PauseMode) {
+        let mut pause = self.pause.lock();
+        match pause.as_mut() {
+            Some(state) => state.mode = mode,
+            None => *pause = Some(PauseState { mode, buffer: VecDeque::new(), discarded: 0 }),
+        }
+        debug!(channel = %self.channel, ?mode, "paused notification processing");
+    }
+
+    /// Whether the listener is paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.lock().is_some()
+    }
+
+    /// Notifications discarded while paused, over all pauses
+    pub fn paused_discarded(&self) -> u64 {
+        self.paused_discarded.load(Ordering::Relaxed)
+    }
+
+    /// Resume applying notifications
+    ///
+    /// With `ResyncMode::Replay`, notifications arriving while the kept ones
+    /// are dispatched queue up behind them. With `ResyncMode::DropAndResync`,
+    /// the resync callbacks run one after another while new notifications are
+    /// buffered as in `start_buffering`, and those are dispatched afterwards.
+    /// Returns how many kept notifications were dispatched.
+    pub async fn resume(&self, mode: ResyncMode) -> usize {
+        let replay = {
+            let mut pause = self.pause.lock();
+            let Some(state) = pause.as_mut() else {
+                return 0;
+            };
+            let replay = mode == ResyncMode::Replay && state.discarded == 0;
+            if replay {
+                // Nothing arriving during the replay may be discarded
+                state.mode = PauseMode::Buffer { capacity: usize::MAX };
+            } else {
+                if mode == ResyncMode::Replay {
+                    warn!(
+                        channel = %self.channel,
+                        discarded = state.discarded,
+                        "notifications were discarded while paused; resyncing instead of replaying"
+                    );
+                }
+                *pause = None;
+                self.start_buffering();
+            }
+            replay
+        };
+
+        if !replay {
+            for callback in &self.resync_callbacks {
+                callback().await;
+            }
+            self.stop_buffering().await;
+            return 0;
+        }
+
+        let mut count = 0;
+        loop {
+            let received = {
+                let mut pause = self.pause.lock();
+                match pause.as_mut().and_then(|state| state.buffer.pop_front()) {
+                    Some(received) => received,
+                    None => {
+                        *pause = None;
+                        return count;
+                    }
+                }
+            };
+            self.dispatch(received.channel.as_deref(), &received.payload).await;
+            count += 1;
+        }
+    }
+
+    /// Whether `listen` is connected and listening on the channel
+    pub fn is_listening(&self) -> bool {
+        *self.listening.borrow()
+    }
+
+    /// Wait until `listen` is listening on the channel
+    ///
+    /// Notifications for changes committed from then on are delivered.
+    pub async fn wait_until_listening(&self) {
+        let mut listening = self.listening.subscribe();
+        // The sender lives as long as the listener, so this cannot fail
+        let _ = listening.wait_for(|listening| *listening).await;
     }
 
     /// Process a single notification payload
     /// 
     /// This method can be called from your own notification polling loop.
+    /// While the listener is paused or buffering, the payload is held back
+    /// or discarded instead. Returns what happened to the payload; failures
+    /// are logged either way, so callers without a use for it can ignore it.
+    ///
+    /// Each call runs inside a `process_notification` tracing span carrying the
+    /// table, action, id and payload size, and the handler runs in a child
+    /// `handle_notification` span, so a subscriber gets per-handler timing.
     /// 
     /// # Example
     /// ```ignore
@@ -143,23 +1989,296 @@ impl CacheNotificationListener {
     ///     listener.process_notification(&notification.payload()).await;
     /// }
     /// ```
-    pub async fn process_notification(&self, payload: &str) {
-        match serde_json::from_str::<CacheNotification>(payload) {
-            Ok(cache_notif) => {
-                if let Some(handler) = self.handlers.get(&cache_notif.table) {
-                    handler.handle_notification(cache_notif).await;
-                } else {
-                    debug!(
-                        "No handler registered for table '{}'",
-                        cache_notif.table
-                    );
+    pub async fn process_notification(&self, payload: &str) -> NotificationOutcome {
+        self.receive(None, payload).await
+    }
+
+    /// Process a notification payload received on `channel`
+    ///
+    /// Handlers registered with `register_handler_on_channel` only receive
+    /// notifications arriving on their channel, the others only those arriving
+    /// on the listener's channel. `process_notification` skips this check.
+    pub async fn process_notification_on_channel(&self, channel: &str, payload: &str) -> NotificationOutcome {
+        self.receive(Some(channel), payload).await
+    }
+
+    async fn receive(&self, channel: Option<&str>, payload: &str) -> NotificationOutcome {
+        match self.hold_back(channel, payload) {
+            Some(outcome) => outcome,
+            None => self.dispatch(channel, payload).await,
+        }
+    }
+
+    /// Keeps or discards a payload while paused or buffering
+    fn hold_back(&self, channel: Option<&str>, payload: &str) -> Option<NotificationOutcome> {
+        let received = || Received { channel: channel.map(str::to_string), payload: payload.to_string() };
+        if let Some(state) = self.pause.lock().as_mut() {
+            return Some(match state.mode {
+                PauseMode::Buffer { capacity } if state.buffer.len() < capacity => {
+                    state.buffer.push_back(received());
+                    NotificationOutcome::Buffered
+                }
+                _ => {
+                    state.discarded += 1;
+                    self.paused_discarded.fetch_add(1, Ordering::Relaxed);
+                    NotificationOutcome::Discarded
+                }
+            });
+        }
+        if let Some(buffer) = self.buffer.lock().as_mut() {
+            buffer.push_back(received());
+            return Some(NotificationOutcome::Buffered);
+        }
+        None
+    }
+
+    /// Process a burst of notification payloads, dispatching the
+    /// notifications of each table to its handler in one batch
+    ///
+    /// The handler of a table sees its notifications in arrival order,
+    /// through `handle_notifications`; bursts of different tables may be
+    /// applied in any order. The `ALL_TABLES` handler receives the whole burst
+    /// last. Returns one outcome per payload, in order.
+    pub async fn process_notifications(&self, payloads: impl IntoIterator<Item = String>) -> Vec<NotificationOutcome> {
+        let received = payloads.into_iter().map(|payload| Received { channel: None, payload }).collect();
+        self.receive_batch(received).await
+    }
+
+    async fn receive_batch(&self, received: Vec<Received>) -> Vec<NotificationOutcome> {
+        let mut outcomes: Vec<Option<NotificationOutcome>> = Vec::with_capacity(received.len());
+        let mut dispatched = Vec::new();
+        for received in received {
+            let held_back = self.hold_back(received.channel.as_deref(), &received.payload);
+            if held_back.is_none() {
+                dispatched.push((outcomes.len(), received));
+            }
+            outcomes.push(held_back);
+        }
+        for (index, outcome) in self.dispatch_batch(dispatched).await {
+            outcomes[index] = Some(outcome);
+        }
+        outcomes.into_iter().map(|outcome| outcome.expect("every payload has an outcome")).collect()
+    }
+
+    /// Run recorded payloads through the listener, e.g. to rebuild caches from a log
+    ///
+    /// The payloads are dispatched one after another like live notifications,
+    /// bypassing the buffer of `start_buffering`. Replaying a window twice
+    /// leaves the caches as replaying it once when the handlers skip stale
+    /// versions.
+    pub async fn replay(&self, payloads: impl IntoIterator<Item = String>) -> ReplayReport {
+        let mut report = ReplayReport::default();
+        for payload in payloads {
+            report.outcomes.push(self.dispatch(None, &payload).await);
+        }
+        report
+    }
+
+    /// Runs a registered handler on a notification, retrying or dead-lettering it on timeout
+    async fn run_handler(&self, handler: Arc<RegisteredHandler>, cache_notif: CacheNotification) -> NotificationOutcome {
+        let handler_span = info_span!(
+            "handle_notification",
+            table = %cache_notif.table,
+            action = %cache_notif.action,
+        );
+        handler.note_dispatched(&cache_notif);
+        let kept = (handler.retry.is_some() || self.dead_letter.is_some()).then(|| cache_notif.clone());
+        let table = cache_notif.table.clone();
+        if handler.handle(cache_notif, self.slow_handler_threshold).instrument(handler_span).await {
+            NotificationOutcome::Applied { table }
+        } else {
+            if let Some(notification) = kept {
+                handler.retry_or_give_up(notification, self.slow_handler_threshold, self.dead_letter.clone());
+            } else {
+                handler.failures.fetch_add(1, Ordering::Relaxed);
+            }
+            NotificationOutcome::HandlerError { table, error: "handler timed out".to_string() }
+        }
+    }
+
+    /// Runs a registered handler on a batch of notifications of one table,
+    /// retrying or dead-lettering each of them if the batch times out
+    async fn run_batch(
+        &self,
+        handler: Arc<RegisteredHandler>,
+        batch: Vec<(usize, CacheNotification)>,
+    ) -> Vec<(usize, NotificationOutcome)> {
+        let (indices, notifications): (Vec<usize>, Vec<CacheNotification>) = batch.into_iter().unzip();
+        let handler_span = info_span!(
+            "handle_notifications",
+            table = %handler.handler.table_name(),
+            count = notifications.len(),
+        );
+        for notification in &notifications {
+            handler.note_dispatched(notification);
+        }
+        let kept = (handler.retry.is_some() || self.dead_letter.is_some()).then(|| notifications.clone());
+        let tables: Vec<String> = notifications.iter().map(|notification| notification.table.clone()).collect();
+        let completed = handler
+            .handle_batch(notifications, self.slow_handler_threshold)
+            .instrument(handler_span)
+            .await;
+        if !completed {
+            match kept {
+                Some(kept) => {
+                    for notification in kept {
+                        handler.clone().retry_or_give_up(notification, self.slow_handler_threshold, self.dead_letter.clone());
+                    }
+                }
+                None => {
+                    handler.failures.fetch_add(tables.len() as u64, Ordering::Relaxed);
+                }
+            }
+        }
+        indices
+            .into_iter()
+            .zip(tables)
+            .map(|(index, table)| {
+                let outcome = match completed {
+                    true => NotificationOutcome::Applied { table },
+                    false => NotificationOutcome::HandlerError { table, error: "handler timed out".to_string() },
+                };
+                (index, outcome)
+            })
+            .collect()
+    }
+
+    /// Parse a payload and pass it to the handler of its table,
+    /// then to the handler of `ALL_TABLES`
+    ///
+    /// With a channel, the handler must have been registered for it.
+    async fn dispatch(&self, channel: Option<&str>, payload: &str) -> NotificationOutcome {
+        let span = info_span!(
+            "process_notification",
+            channel = %channel.unwrap_or(&self.channel),
+            payload_size = payload.len(),
+            table = tracing::field::Empty,
+            action = tracing::field::Empty,
+            id = tracing::field::Empty,
+        );
+
+        async {
+            let cache_notif = match self.parse(payload) {
+                Ok(cache_notif) => cache_notif,
+                Err(outcome) => return outcome,
+            };
+            match self.route(channel, &cache_notif.table) {
+                (Some(handler), all_tables) => {
+                    let mirrored = all_tables.map(|all_tables| (all_tables, cache_notif.clone()));
+                    let outcome = self.run_handler(handler, cache_notif).await;
+                    if let Some((all_tables, notification)) = mirrored {
+                        self.run_handler(all_tables, notification).await;
+                    }
+                    outcome
+                }
+                (None, Some(all_tables)) => self.run_handler(all_tables, cache_notif).await,
+                (None, None) => self.no_handler(channel, cache_notif),
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Parse payloads and pass the notifications of each table to its handler
+    /// in one batch, then all of them to the handler of `ALL_TABLES`
+    ///
+    /// Returns the outcomes by the index the payloads came with.
+    async fn dispatch_batch(&self, received: Vec<(usize, Received)>) -> Vec<(usize, NotificationOutcome)> {
+        let span = info_span!("process_notifications", count = received.len());
+
+        async {
+            let mut outcomes = Vec::with_capacity(received.len());
+            let mut batches: Vec<HandlerBatch> = Vec::new();
+            let mut all_tables_handler = None;
+            let mut mirrored = Vec::new();
+            // Payloads whose outcome is that of the `ALL_TABLES` handler
+            let mut mirrored_only = HashSet::new();
+            for (index, Received { channel, payload }) in received {
+                let cache_notif = match self.parse(&payload) {
+                    Ok(cache_notif) => cache_notif,
+                    Err(outcome) => {
+                        outcomes.push((index, outcome));
+                        continue;
+                    }
+                };
+                match self.route(channel.as_deref(), &cache_notif.table) {
+                    (Some(handler), all_tables) => {
+                        if let Some(all_tables) = all_tables {
+                            all_tables_handler = Some(all_tables);
+                            mirrored.push((index, cache_notif.clone()));
+                        }
+                        match batches.iter_mut().find(|(batched, _)| Arc::ptr_eq(batched, &handler)) {
+                            Some((_, batch)) => batch.push((index, cache_notif)),
+                            None => batches.push((handler, vec![(index, cache_notif)])),
+                        }
+                    }
+                    (None, Some(all_tables)) => {
+                        all_tables_handler = Some(all_tables);
+                        mirrored_only.insert(index);
+                        mirrored.push((index, cache_notif));
+                    }
+                    (None, None) => outcomes.push((index, self.no_handler(channel.as_deref(), cache_notif))),
                 }
             }
-            Err(e) => {
-                error!("Failed to parse notification payload: {}", e);
-                debug!("Payload was: {}", payload);
+
+            for (handler, batch) in batches {
+                outcomes.extend(self.run_batch(handler, batch).await);
+            }
+            if let Some(all_tables) = all_tables_handler {
+                let mirrored_outcomes = self.run_batch(all_tables, mirrored).await;
+                outcomes.extend(mirrored_outcomes.into_iter().filter(|(index, _)| mirrored_only.contains(index)));
+            }
+            outcomes
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Parse a payload, then check it against the payload key and the filter
+    fn parse(&self, payload: &str) -> Result<CacheNotification, NotificationOutcome> {
+        let cache_notif = CacheNotification::from_payload(payload).map_err(|e| {
+            warn!(
+                error = %e,
+                payload_size = payload.len(),
+                "dropping notification: failed to parse payload"
+            );
+            debug!(payload, "unparseable notification payload");
+            NotificationOutcome::ParseError(e.to_string())
+        })?;
+        let span = tracing::Span::current();
+        span.record("table", cache_notif.table.as_str());
+        span.record("action", cache_notif.action.as_str());
+        span.record("id", tracing::field::display(cache_notif.id));
+
+        #[cfg(feature = "payload-protection")]
+        let cache_notif = self.unprotect(cache_notif)?;
+
+        if let Some(filter) = &self.filter {
+            if !filter(&cache_notif) {
+                debug!(table = %cache_notif.table, "notification rejected by filter");
+                return Err(NotificationOutcome::Filtered { table: cache_notif.table });
             }
         }
+        Ok(cache_notif)
+    }
+
+    /// The handler of a table and the `ALL_TABLES` handler, if registered for the channel
+    fn route(&self, channel: Option<&str>, table: &str) -> (Option<Arc<RegisteredHandler>>, Option<Arc<RegisteredHandler>>) {
+        let on_channel = |handler: &Arc<RegisteredHandler>| {
+            channel.is_none_or(|channel| channel == handler.channel.as_deref().unwrap_or(&self.channel))
+        };
+        (self.handlers.get(table).filter(on_channel), self.handlers.get(ALL_TABLES).filter(on_channel))
+    }
+
+    fn no_handler(&self, channel: Option<&str>, cache_notif: CacheNotification) -> NotificationOutcome {
+        warn!(
+            table = %cache_notif.table,
+            action = %cache_notif.action,
+            id = %cache_notif.id,
+            channel = %channel.unwrap_or(&self.channel),
+            "dropping notification: no handler registered for table on channel"
+        );
+        NotificationOutcome::NoHandler { table: cache_notif.table }
     }
 
     /// Get the channel name this listener is using
@@ -167,6 +2286,14 @@ impl CacheNotificationListener {
         &self.channel
     }
 
+    /// All channels `listen` listens on: the listener's own channel first,
+    /// then those of handlers registered with `register_handler_on_channel`
+    pub fn channels(&self) -> Vec<String> {
+        let mut channels = vec![self.channel.clone()];
+        channels.extend(self.handlers.channels().into_iter().filter(|channel| *channel != self.channel));
+        channels
+    }
+
     /// Starts listening for notifications from PostgreSQL and processes them.
     ///
     /// This method will continuously listen for notifications on the configured
@@ -179,42 +2306,94 @@ impl CacheNotificationListener {
     ///
     /// # Errors
     ///
-    /// This function will return an error if it fails to connect to the database
-    /// or listen for notifications.
+    /// This function will return `CacheError::ListenerError` if it fails to
+    /// connect to the database or listen for notifications, or when the
+    /// reconnect policy gives up after losing the connection.
     #[cfg(feature = "sqlx-listener")]
-    pub async fn listen(&self, pool: &sqlx::PgPool) -> Result<(), sqlx::Error> {
+    pub async fn listen(&self, pool: &sqlx::PgPool) -> Result<(), CacheError> {
+        let channels = self.channels();
         let mut listener = sqlx::postgres::PgListener::connect_with(pool).await?;
-        listener.listen(&self.channel).await?;
-        debug!("Started listening on channel '{}'", self.channel);
+        listener.listen_all(channels.iter().map(String::as_str)).await?;
+        self.listening.send_replace(true);
+        debug!("Started listening on channels {:?}", channels);
 
         loop {
-            match listener.recv().await {
-                Ok(notification) => {
-                    self.process_notification(notification.payload()).await;
-                }
-                Err(e) => {
-                    error!("Error receiving notification: {}", e);
-                    // Optional: add a delay before trying to reconnect
-                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-
-                    // Attempt to reconnect
-                    match sqlx::postgres::PgListener::connect_with(pool).await {
-                        Ok(new_listener) => {
-                            listener = new_listener;
-                            if let Err(listen_err) = listener.listen(&self.channel).await {
-                                error!(
-                                    "Failed to re-listen on channel '{}': {}",
-                                    self.channel, listen_err
-                                );
-                                return Err(listen_err);
-                            }
-                            debug!("Reconnected and listening on channel '{}'", self.channel);
-                        }
-                        Err(connect_err) => {
-                            error!("Failed to reconnect to database: {}", connect_err);
-                            // Continue loop to retry connection
-                        }
+            let lost = match listener.recv().await {
+                Ok(notification) => match self.batching {
+                    Some(batching) => self.process_burst(&mut listener, notification, batching).await.err(),
+                    None => {
+                        let outcome = self.process_notification_on_channel(notification.channel(), notification.payload()).await;
+                        trace!(?outcome, "processed notification");
+                        None
                     }
+                },
+                Err(e) => Some(e),
+            };
+            if let Some(e) = lost {
+                self.listening.send_replace(false);
+                error!("Error receiving notification: {}", e);
+                listener = self.reconnect(pool, &channels).await?;
+                self.listening.send_replace(true);
+                debug!("Reconnected and listening on channel '{}'", self.channel);
+            }
+        }
+    }
+
+    /// Collect the notifications following `first` as `set_batching` says and
+    /// dispatch them together
+    ///
+    /// On a receive error, the notifications collected so far are dispatched
+    /// before the error is returned.
+    #[cfg(feature = "sqlx-listener")]
+    async fn process_burst(
+        &self,
+        listener: &mut sqlx::postgres::PgListener,
+        first: sqlx::postgres::PgNotification,
+        batching: Batching,
+    ) -> Result<(), sqlx::Error> {
+        let received = |notification: &sqlx::postgres::PgNotification| Received {
+            channel: Some(notification.channel().to_string()),
+            payload: notification.payload().to_string(),
+        };
+        let mut burst = vec![received(&first)];
+        let deadline = tokio::time::Instant::now() + batching.max_delay;
+        let mut result = Ok(());
+        while burst.len() < batching.max_items {
+            match tokio::time::timeout_at(deadline, listener.recv()).await {
+                Ok(Ok(notification)) => burst.push(received(&notification)),
+                Ok(Err(e)) => {
+                    result = Err(e);
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        let outcomes = self.receive_batch(burst).await;
+        trace!(?outcomes, "processed notification burst");
+        result
+    }
+
+    /// Connect and listen again, waiting before each attempt as the reconnect policy says
+    #[cfg(feature = "sqlx-listener")]
+    async fn reconnect(&self, pool: &sqlx::PgPool, channels: &[String]) -> Result<sqlx::postgres::PgListener, CacheError> {
+        let mut attempt = 0;
+        loop {
+            tokio::time::sleep(self.reconnect.delay(attempt)).await;
+            attempt += 1;
+            let result = async {
+                let mut listener = sqlx::postgres::PgListener::connect_with(pool).await?;
+                listener.listen_all(channels.iter().map(String::as_str)).await?;
+                Ok::<_, sqlx::Error>(listener)
+            }
+            .await;
+            match result {
+                Ok(listener) => return Ok(listener),
+                Err(err) if self.reconnect.max_attempts.is_some_and(|max| attempt >= max) => {
+                    error!("Giving up reconnecting on channel '{}' after {} attempts: {}", self.channel, attempt, err);
+                    return Err(err.into());
+                }
+                Err(err) => {
+                    error!("Failed to reconnect on channel '{}': {}", self.channel, err);
                 }
             }
         }
@@ -237,10 +2416,12 @@ mod tests {
             table: "users".to_string(),
             action: "insert".to_string(),
             id: Uuid::new_v4(),
+            key: None,
             data: Some(serde_json::json!({
                 "id": "550e8400-e29b-41d4-a716-446655440000",
                 "name": "Alice"
             })),
+            context: None,
         };
 
         let json = serde_json::to_string(&notif).unwrap();
@@ -250,4 +2431,88 @@ mod tests {
         assert_eq!(notif.action, deserialized.action);
         assert_eq!(notif.id, deserialized.id);
     }
+
+    #[test]
+    fn test_notification_constructors() {
+        #[derive(Serialize)]
+        struct Item {
+            id: Uuid,
+        }
+        impl HasPrimaryKey for Item {
+            fn primary_key(&self) -> Uuid {
+                self.id
+            }
+        }
+
+        let item = Item { id: Uuid::new_v4() };
+        let insert = CacheNotification::insert("items", &item).unwrap();
+        assert_eq!(insert.cache_action(), Some(CacheAction::Insert));
+        assert_eq!(insert.id, item.id);
+        assert_eq!(insert.data, Some(serde_json::json!({ "id": item.id })));
+        assert!(insert.key.is_none() && insert.context.is_none());
+        assert_eq!(CacheNotification::update("items", &item).unwrap().action, "update");
+
+        let delete = CacheNotification::delete("items", item.id);
+        assert_eq!(delete.to_payload(), format!(r#"{{"table":"items","action":"delete","id":"{}"}}"#, item.id));
+        let parsed = CacheNotification::from_payload(&insert.to_payload()).unwrap();
+        assert_eq!((parsed.table, parsed.id, parsed.data), ("items".to_string(), item.id, insert.data));
+
+        let flush = CacheNotification::from_payload(&CacheNotification::flush("items").to_payload()).unwrap();
+        assert_eq!(flush.cache_action(), Some(CacheAction::Flush));
+        assert!(matches!(CacheNotification::from_payload("{}"), Err(CacheError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn test_notification_context() {
+        let payload = r#"{
+            "table": "users",
+            "action": "delete",
+            "id": "550e8400-e29b-41d4-a716-446655440000",
+            "context": {"tenant_id": "acme"}
+        }"#;
+
+        let notif: CacheNotification = serde_json::from_str(payload).unwrap();
+        assert!(notif.data.is_none());
+        assert_eq!(notif.context_value("tenant_id"), Some(&serde_json::json!("acme")));
+        assert_eq!(notif.context_value("region"), None);
+    }
+
+    #[test]
+    fn test_notification_with_non_uuid_key() {
+        let payload = r#"{ "table": "countries", "action": "delete", "id": "CH" }"#;
+        let notif: CacheNotification = serde_json::from_str(payload).unwrap();
+        assert_eq!(notif.id, Uuid::nil());
+        assert_eq!(notif.key.as_deref(), Some("CH"));
+        assert_eq!(notif.raw_key(), "CH");
+
+        let payload = r#"{ "table": "orders", "action": "delete", "id": 42 }"#;
+        let notif: CacheNotification = serde_json::from_str(payload).unwrap();
+        assert_eq!(notif.raw_key(), "42");
+
+        // The key survives a round trip
+        let json = serde_json::to_string(&notif).unwrap();
+        let deserialized: CacheNotification = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.key.as_deref(), Some("42"));
+
+        let id = Uuid::new_v4();
+        let payload = format!(r#"{{ "table": "users", "action": "delete", "id": "{id}" }}"#);
+        let notif: CacheNotification = serde_json::from_str(&payload).unwrap();
+        assert_eq!(notif.id, id);
+        assert!(notif.key.is_none());
+        assert_eq!(notif.raw_key(), id.to_string());
+
+        assert!(serde_json::from_str::<CacheNotification>(r#"{ "table": "t", "action": "delete", "id": null }"#).is_err());
+    }
+
+    #[test]
+    fn test_deserialization_error_keeps_source() {
+        let source = serde_json::from_str::<CacheNotification>("{}").unwrap_err();
+        let err = CacheError::DeserializationFailed {
+            table: "users".to_string(),
+            source,
+        };
+
+        assert!(err.to_string().starts_with("Failed to deserialize notification data for table 'users'"));
+        assert!(std::error::Error::source(&err).is_some());
+    }
 }
\ No newline at end of file
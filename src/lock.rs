@@ -0,0 +1,106 @@
+//! The outer lock of a cache shared with notification handlers
+//!
+//! Handlers and the transaction-aware wrappers take the lock through
+//! [`CacheLock`], so a cache can sit behind a `parking_lot::RwLock`, the
+//! default, or a `tokio::sync::RwLock` when other code holding the guard must
+//! await, e.g. to refetch a row.
+
+use std::future::{Future, IntoFuture};
+use std::ops::{Deref, DerefMut};
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+/// A read-write lock around a cache, taken asynchronously
+///
+/// `parking_lot::RwLock` acquires immediately and blocks the thread while it
+/// waits, which suits short critical sections. `tokio::sync::RwLock` yields
+/// to the runtime instead, so its guards can be held across an await.
+pub trait CacheLock<V>: Send + Sync {
+    /// The guard of a shared lock
+    type ReadGuard<'a>: Deref<Target = V> + Send
+    where
+        Self: 'a;
+
+    /// The guard of an exclusive lock
+    type WriteGuard<'a>: DerefMut<Target = V> + Send
+    where
+        Self: 'a;
+
+    /// The guard of a shared lock that can be upgraded to an exclusive one
+    type UpgradableGuard<'a>: Deref<Target = V> + Send
+    where
+        Self: 'a;
+
+    /// Takes a shared lock
+    fn read(&self) -> impl Future<Output = Self::ReadGuard<'_>> + Send;
+
+    /// Takes an exclusive lock
+    fn write(&self) -> impl Future<Output = Self::WriteGuard<'_>> + Send;
+
+    /// Takes a shared lock that excludes writers and other upgradable
+    /// readers, but not plain readers
+    ///
+    /// Locks without upgradable reads take the exclusive lock instead.
+    fn upgradable_read(&self) -> impl Future<Output = Self::UpgradableGuard<'_>> + Send;
+
+    /// Turns an upgradable guard into an exclusive one, once the plain
+    /// readers have left
+    fn upgrade(guard: Self::UpgradableGuard<'_>) -> impl Future<Output = Self::WriteGuard<'_>> + Send;
+}
+
+impl<V: Send + Sync> CacheLock<V> for parking_lot::RwLock<V> {
+    type ReadGuard<'a> = parking_lot::RwLockReadGuard<'a, V> where Self: 'a;
+    type WriteGuard<'a> = parking_lot::RwLockWriteGuard<'a, V> where Self: 'a;
+    type UpgradableGuard<'a> = parking_lot::RwLockUpgradableReadGuard<'a, V> where Self: 'a;
+
+    fn read(&self) -> impl Future<Output = Self::ReadGuard<'_>> + Send {
+        std::future::ready(parking_lot::RwLock::read(self))
+    }
+
+    fn write(&self) -> impl Future<Output = Self::WriteGuard<'_>> + Send {
+        std::future::ready(parking_lot::RwLock::write(self))
+    }
+
+    fn upgradable_read(&self) -> impl Future<Output = Self::UpgradableGuard<'_>> + Send {
+        std::future::ready(parking_lot::RwLock::upgradable_read(self))
+    }
+
+    fn upgrade(guard: Self::UpgradableGuard<'_>) -> impl Future<Output = Self::WriteGuard<'_>> + Send {
+        std::future::ready(parking_lot::RwLockUpgradableReadGuard::upgrade(guard))
+    }
+}
+
+impl<V: Send + Sync> CacheLock<V> for tokio::sync::RwLock<V> {
+    type ReadGuard<'a> = tokio::sync::RwLockReadGuard<'a, V> where Self: 'a;
+    type WriteGuard<'a> = tokio::sync::RwLockWriteGuard<'a, V> where Self: 'a;
+    type UpgradableGuard<'a> = tokio::sync::RwLockWriteGuard<'a, V> where Self: 'a;
+
+    fn read(&self) -> impl Future<Output = Self::ReadGuard<'_>> + Send {
+        tokio::sync::RwLock::read(self)
+    }
+
+    fn write(&self) -> impl Future<Output = Self::WriteGuard<'_>> + Send {
+        tokio::sync::RwLock::write(self)
+    }
+
+    fn upgradable_read(&self) -> impl Future<Output = Self::UpgradableGuard<'_>> + Send {
+        tokio::sync::RwLock::write(self)
+    }
+
+    fn upgrade(guard: Self::UpgradableGuard<'_>) -> impl Future<Output = Self::WriteGuard<'_>> + Send {
+        std::future::ready(guard)
+    }
+}
+
+/// Runs a future that awaits nothing but `parking_lot` locks
+///
+/// A `parking_lot` lock is taken when its future is created, so such a future
+/// is ready on its first poll. The transaction-aware wrappers implement their
+/// blocking methods this way, over the async ones they offer for any lock.
+pub(crate) fn block_on_parking_lot<F: IntoFuture>(future: F) -> F::Output {
+    let mut future = pin!(future.into_future());
+    match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("a future awaiting only parking_lot locks is ready on its first poll"),
+    }
+}
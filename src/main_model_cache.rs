@@ -1,31 +1,80 @@
-use std::collections::{HashMap, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
 use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
 use uuid::Uuid;
 
-use crate::traits::{HasPrimaryKey, ValidFrom, ValidTo};
-use crate::listener::{CacheNotification, CacheNotificationHandler};
+use crate::backend::ModelCacheBackend;
+use crate::error::{CacheError, CacheResult};
+use crate::eviction_order::{EvictionOrder, PolicyOrder};
+use crate::index_cache::item_json;
+use crate::traits::{CacheKey, HasPrimaryKey, HasTableName, IsDeleted, ValidFrom, ValidTo, Validity, Versioned};
+use crate::versioning::{is_stale, version_of, VersionOf};
+use crate::handler_schema::serde_fields;
+use crate::handler_stats::{AppliedInfo, LastApplied};
+use crate::listener::{decode_data, CacheNotification, CacheNotificationHandler, ItemLoader, OnDeserError, PayloadMigrator};
+use crate::lock::CacheLock;
+use crate::transaction_aware_main_model_cache::MainModel;
 
 /// Eviction policy for the cache
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Serialized as `"lru"` or `"fifo"`; the upper-case names are accepted too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum EvictionPolicy {
     /// Least Recently Used - evicts the least recently accessed entry
+    #[serde(alias = "LRU")]
     LRU,
     /// First In First Out - evicts the oldest entry
+    #[serde(alias = "FIFO")]
     FIFO,
 }
 
+/// Why a read missed, for `CacheStatistics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MissReason {
+    /// The key was not cached
+    Absent,
+    /// The entry's TTL had passed
+    TtlExpired,
+    /// The item was outside its validity range
+    Invalid,
+}
+
+/// Why an entry was evicted, for `CacheStatistics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EvictionReason {
+    /// Capacity or soft deletion
+    Other,
+    /// The entry's TTL had passed
+    TtlExpired,
+    /// The item was outside its validity range
+    Invalid,
+}
+
 /// Statistics for cache operations
+///
+/// Misses and evictions are counted by cause; `misses` and `evictions`
+/// return their sums.
 #[derive(Debug)]
 pub struct CacheStatistics {
     hits: AtomicU64,
-    misses: AtomicU64,
+    absent_misses: AtomicU64,
+    ttl_expired_misses: AtomicU64,
+    validity_misses: AtomicU64,
     evictions: AtomicU64,
+    ttl_expired_evictions: AtomicU64,
+    validity_evictions: AtomicU64,
     invalidations: AtomicU64,
 }
 
@@ -33,8 +82,12 @@ impl CacheStatistics {
     fn new() -> Self {
         Self {
             hits: AtomicU64::new(0),
-            misses: AtomicU64::new(0),
+            absent_misses: AtomicU64::new(0),
+            ttl_expired_misses: AtomicU64::new(0),
+            validity_misses: AtomicU64::new(0),
             evictions: AtomicU64::new(0),
+            ttl_expired_evictions: AtomicU64::new(0),
+            validity_evictions: AtomicU64::new(0),
             invalidations: AtomicU64::new(0),
         }
     }
@@ -44,14 +97,39 @@ impl CacheStatistics {
         self.hits.load(Ordering::Relaxed)
     }
 
-    /// Get the number of cache misses
+    /// Get the number of cache misses, whatever their cause
     pub fn misses(&self) -> u64 {
-        self.misses.load(Ordering::Relaxed)
+        self.absent_misses() + self.ttl_expired_misses() + self.validity_misses()
+    }
+
+    /// Get the number of misses for keys that were not cached
+    pub fn absent_misses(&self) -> u64 {
+        self.absent_misses.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of misses for entries whose TTL had passed
+    pub fn ttl_expired_misses(&self) -> u64 {
+        self.ttl_expired_misses.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of misses for items outside their validity range, from `get_with_validity_check` and `get_valid_at`
+    pub fn validity_misses(&self) -> u64 {
+        self.validity_misses.load(Ordering::Relaxed)
     }
 
-    /// Get the number of evictions
+    /// Get the number of evictions, whatever their cause
     pub fn evictions(&self) -> u64 {
-        self.evictions.load(Ordering::Relaxed)
+        self.evictions.load(Ordering::Relaxed) + self.ttl_expired_evictions() + self.validity_evictions()
+    }
+
+    /// Get the number of evictions of entries whose TTL had passed
+    pub fn ttl_expired_evictions(&self) -> u64 {
+        self.ttl_expired_evictions.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of evictions of items outside their validity range
+    pub fn validity_evictions(&self) -> u64 {
+        self.validity_evictions.load(Ordering::Relaxed)
     }
 
     /// Get the number of invalidations
@@ -70,16 +148,38 @@ impl CacheStatistics {
         }
     }
 
+    /// Reset all counters to zero
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.absent_misses.store(0, Ordering::Relaxed);
+        self.ttl_expired_misses.store(0, Ordering::Relaxed);
+        self.validity_misses.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+        self.ttl_expired_evictions.store(0, Ordering::Relaxed);
+        self.validity_evictions.store(0, Ordering::Relaxed);
+        self.invalidations.store(0, Ordering::Relaxed);
+    }
+
     fn record_hit(&self) {
         self.hits.fetch_add(1, Ordering::Relaxed);
     }
 
-    fn record_miss(&self) {
-        self.misses.fetch_add(1, Ordering::Relaxed);
+    fn record_miss(&self, reason: MissReason) {
+        let counter = match reason {
+            MissReason::Absent => &self.absent_misses,
+            MissReason::TtlExpired => &self.ttl_expired_misses,
+            MissReason::Invalid => &self.validity_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
     }
 
-    fn record_eviction(&self) {
-        self.evictions.fetch_add(1, Ordering::Relaxed);
+    fn record_eviction(&self, reason: EvictionReason) {
+        let counter = match reason {
+            EvictionReason::Other => &self.evictions,
+            EvictionReason::TtlExpired => &self.ttl_expired_evictions,
+            EvictionReason::Invalid => &self.validity_evictions,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
     }
 
     fn record_invalidation(&self) {
@@ -87,48 +187,284 @@ impl CacheStatistics {
     }
 }
 
+/// The statistics of one cache at a point in time, from `MainModelCache::statistics_snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatisticsSnapshot {
+    /// The name of the cache, if it was given one with `with_name`
+    pub name: Option<String>,
+    /// Number of cached items
+    pub entries: usize,
+    /// Number of cache hits
+    pub hits: u64,
+    /// Number of cache misses
+    pub misses: u64,
+    /// Number of evictions
+    pub evictions: u64,
+    /// Number of invalidations
+    pub invalidations: u64,
+}
+
+impl StatisticsSnapshot {
+    /// Calculate the cache hit rate (hits / (hits + misses))
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// A read-only view of a cache entry's metadata, from `MainModelCache::entry_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// When the item was first inserted; updates keep it
+    pub inserted_at: DateTime<Utc>,
+    /// When the item was last read or updated
+    pub last_accessed: DateTime<Utc>,
+    /// Time since `inserted_at`
+    pub age: Duration,
+    /// Time since `last_accessed`
+    pub idle: Duration,
+    /// Time left until the TTL expires, if the entry has one
+    pub remaining_ttl: Option<Duration>,
+    /// The item's valid_to, for caches created with `with_validity`
+    pub valid_to: Option<DateTime<Utc>>,
+    /// When the entry expires, whichever of TTL and valid_to comes first
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Hits on the entry, halved by each decay
+    pub access_count: u32,
+}
+
+/// How close a cache is to its capacity, from `MainModelCache::pressure`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachePressure {
+    /// Number of cached items
+    pub len: usize,
+    /// Maximum number of items, the configured `cache_size`
+    pub capacity: usize,
+    /// `len / capacity`; 1.0 for a disabled cache, which has no room at all
+    pub fill_ratio: f64,
+}
+
+/// Called when a cache fills up past its high watermark
+pub type HighWatermarkHook = dyn Fn(&CachePressure) + Send + Sync;
+
+/// A high watermark and whether the cache is above it
+struct HighWatermark {
+    threshold: f64,
+    hook: Arc<HighWatermarkHook>,
+    above: bool,
+}
+
 /// Entry metadata for cache management
 #[derive(Debug, Clone)]
 struct CacheEntry<T> {
-    value: T,
+    value: Arc<T>,
     inserted_at: DateTime<Utc>,
     last_accessed: DateTime<Utc>,
+    /// Position in the insertion order; kept across updates
+    inserted_seq: u64,
+    /// Stamp of the last hit or update, ordering `hot_entries` and `cold_entries`
+    accessed_seq: u64,
+    /// Position in the eviction order, moved only as the policy says
+    evict_seq: u64,
+    /// TTL of this entry, overriding the configured one
+    ttl: Option<Duration>,
+    /// When the entry stops being valid, from its TTL and valid_to
+    expires_at: Option<DateTime<Utc>>,
+    /// Hits on the entry, saturating; updates do not count
+    access_count: u32,
 }
 
 impl<T> CacheEntry<T> {
-    fn new(value: T) -> Self {
+    fn new(value: Arc<T>, seq: u64) -> Self {
         let now = Utc::now();
         Self {
             value,
             inserted_at: now,
             last_accessed: now,
+            inserted_seq: seq,
+            accessed_seq: seq,
+            evict_seq: seq,
+            ttl: None,
+            expires_at: None,
+            access_count: 0,
         }
     }
 
-    fn access(&mut self) {
+    /// Records a use at the given stamp
+    fn renew(&mut self, seq: u64) {
         self.last_accessed = Utc::now();
+        self.accessed_seq = seq;
     }
 }
 
 /// Configuration for MainModelCache
-#[derive(Debug, Clone)]
+///
+/// Deserializes from service configuration files, with the TTL written as a
+/// duration string such as `"30s"`, `"5m"` or `"1h30m"`:
+///
+/// ```rust
+/// use postgres_index_cache::{CacheConfig, EvictionPolicy};
+///
+/// let config: CacheConfig = serde_json::from_str(
+///     r#"{ "cache_size": 1000, "eviction_policy": "lru", "ttl": "5m" }"#,
+/// ).unwrap();
+/// assert_eq!(config.eviction_policy, EvictionPolicy::LRU);
+/// assert_eq!(config.ttl, Some(std::time::Duration::from_secs(300)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CacheConfig {
-    /// Maximum number of entries in the cache
+    /// Maximum number of entries in the cache; 0 disables the cache
     pub cache_size: usize,
     /// Eviction policy to use when cache is full
     pub eviction_policy: EvictionPolicy,
     /// Optional TTL for cache entries
+    #[serde(default, with = "ttl_format", skip_serializing_if = "Option::is_none")]
     pub ttl: Option<Duration>,
+    /// Options of the LRU policy; `validate` rejects them with another policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lru: Option<LruOptions>,
+    /// Options of the FIFO policy; `validate` rejects them with another policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fifo: Option<FifoOptions>,
+}
+
+/// Options of the LRU eviction policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LruOptions {
+    /// Whether an update counts as a use, moving the entry to the back of the
+    /// LRU order; on by default, off to let only reads keep an entry cached
+    pub touch_on_update: bool,
+}
+
+impl Default for LruOptions {
+    fn default() -> Self {
+        Self { touch_on_update: true }
+    }
+}
+
+/// Options of the FIFO eviction policy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FifoOptions {
+    /// Whether an update queues the entry behind all others, as if it was
+    /// inserted anew; off by default, so entries leave in first insertion order
+    pub requeue_on_update: bool,
 }
 
 impl CacheConfig {
     /// Create a new cache configuration
+    ///
+    /// See `builder` for the policy options.
     pub fn new(cache_size: usize, eviction_policy: EvictionPolicy) -> Self {
         Self {
             cache_size,
             eviction_policy,
             ttl: None,
+            lru: None,
+            fifo: None,
+        }
+    }
+
+    /// Start building a configuration, checked by `CacheConfigBuilder::build`
+    ///
+    /// ```rust
+    /// use postgres_index_cache::{CacheConfig, EvictionPolicy, FifoOptions};
+    /// use std::time::Duration;
+    ///
+    /// let config = CacheConfig::builder()
+    ///     .with_cache_size(1000)
+    ///     .with_eviction_policy(EvictionPolicy::FIFO)
+    ///     .with_fifo_options(FifoOptions { requeue_on_update: true })
+    ///     .with_ttl(Duration::from_secs(300))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config.cache_size, 1000);
+    /// ```
+    pub fn builder() -> CacheConfigBuilder {
+        CacheConfigBuilder::default()
+    }
+
+    /// Create a configuration for a disabled cache, the same as a `cache_size` of 0
+    ///
+    /// A disabled cache stores nothing: inserts and updates are ignored and
+    /// every get is a miss. Caching can thus be turned off in a configuration
+    /// file without changing code.
+    pub fn disabled() -> Self {
+        Self::new(0, EvictionPolicy::LRU)
+    }
+
+    /// Returns true if the cache is disabled, i.e. `cache_size` is 0
+    pub fn is_disabled(&self) -> bool {
+        self.cache_size == 0
+    }
+
+    /// Set the TTL for cache entries
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Checks the configuration, e.g. after loading it from a file
+    ///
+    /// Fails with `CacheError::InvalidArgument` if the TTL is zero, which
+    /// would expire every entry as soon as it is stored; a `cache_size` of 0
+    /// is the way to disable a cache.
+    ///
+    /// Options of another eviction policy than the configured one are
+    /// rejected the same way, as they would be silently ignored.
+    pub fn validate(&self) -> CacheResult<()> {
+        if self.ttl.is_some_and(|ttl| ttl.is_zero()) {
+            return Err(CacheError::InvalidArgument(
+                "ttl must be greater than zero; set cache_size to 0 to disable the cache".to_string(),
+            ));
+        }
+        let mismatched = match self.eviction_policy {
+            EvictionPolicy::LRU => self.fifo.is_some().then_some("fifo"),
+            EvictionPolicy::FIFO => self.lru.is_some().then_some("lru"),
+        };
+        if let Some(options) = mismatched {
+            return Err(CacheError::InvalidArgument(format!(
+                "{options} options cannot be combined with the {:?} eviction policy",
+                self.eviction_policy
+            )));
         }
+        Ok(())
+    }
+
+    /// Whether an update moves the entry to the back of the LRU order
+    fn touches_on_update(&self) -> bool {
+        self.lru.is_none_or(|lru| lru.touch_on_update)
+    }
+}
+
+/// Builds a [`CacheConfig`], see `CacheConfig::builder`
+///
+/// The cache size must be set; the policy defaults to LRU.
+#[derive(Debug, Clone, Default)]
+pub struct CacheConfigBuilder {
+    cache_size: Option<usize>,
+    eviction_policy: Option<EvictionPolicy>,
+    ttl: Option<Duration>,
+    lru: Option<LruOptions>,
+    fifo: Option<FifoOptions>,
+}
+
+impl CacheConfigBuilder {
+    /// Set the maximum number of entries; 0 disables the cache
+    pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    /// Set the eviction policy
+    pub fn with_eviction_policy(mut self, eviction_policy: EvictionPolicy) -> Self {
+        self.eviction_policy = Some(eviction_policy);
+        self
     }
 
     /// Set the TTL for cache entries
@@ -136,78 +472,277 @@ impl CacheConfig {
         self.ttl = Some(ttl);
         self
     }
+
+    /// Set the options of the LRU policy
+    pub fn with_lru_options(mut self, lru: LruOptions) -> Self {
+        self.lru = Some(lru);
+        self
+    }
+
+    /// Set the options of the FIFO policy
+    pub fn with_fifo_options(mut self, fifo: FifoOptions) -> Self {
+        self.fifo = Some(fifo);
+        self
+    }
+
+    /// Build the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::InvalidArgument` if the cache size is not set or
+    /// `CacheConfig::validate` rejects the configuration.
+    pub fn build(self) -> Result<CacheConfig, CacheError> {
+        let cache_size = self.cache_size.ok_or_else(|| {
+            CacheError::InvalidArgument("cache_size must be set; set it to 0 to disable the cache".to_string())
+        })?;
+        let config = CacheConfig {
+            cache_size,
+            eviction_policy: self.eviction_policy.unwrap_or(EvictionPolicy::LRU),
+            ttl: self.ttl,
+            lru: self.lru,
+            fifo: self.fifo,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Serde format of the TTL: a string of whole numbers with units, e.g. `"1h30m"`
+///
+/// The units are `d`, `h`, `m`, `s` and `ms`. A TTL is written with the
+/// largest unit that divides it evenly.
+mod ttl_format {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    const UNITS: [(&str, u64); 5] = [("d", 86_400_000), ("h", 3_600_000), ("m", 60_000), ("s", 1_000), ("ms", 1)];
+
+    pub(super) fn serialize<S: Serializer>(ttl: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match ttl {
+            Some(ttl) => serializer.serialize_str(&format(*ttl)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|ttl| parse(&ttl).map_err(D::Error::custom))
+            .transpose()
+    }
+
+    pub(super) fn format(ttl: Duration) -> String {
+        let millis = ttl.as_millis() as u64;
+        if millis == 0 {
+            return "0s".to_string();
+        }
+        // Milliseconds divide everything, so a unit is always found
+        let (unit, size) = UNITS.iter().find(|(_, size)| millis.is_multiple_of(*size)).unwrap_or(&("ms", 1));
+        format!("{}{}", millis / size, unit)
+    }
+
+    pub(super) fn parse(ttl: &str) -> Result<Duration, String> {
+        let invalid = || format!("invalid TTL '{ttl}', expected e.g. \"30s\", \"5m\" or \"1h30m\"");
+        let mut rest = ttl.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut millis: u64 = 0;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+            let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = &rest[digits..];
+            let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+            let size = UNITS
+                .iter()
+                .find(|(unit, _)| *unit == &rest[..unit_len])
+                .map(|(_, size)| *size)
+                .ok_or_else(invalid)?;
+            rest = &rest[unit_len..];
+            millis = value
+                .checked_mul(size)
+                .and_then(|value| millis.checked_add(value))
+                .ok_or_else(invalid)?;
+        }
+        Ok(Duration::from_millis(millis))
+    }
 }
 
+/// Reads one end of an item's validity range, `None` when it is open
+type ValidityBound<T> = fn(&T) -> Option<DateTime<Utc>>;
+
 /// A generic cache for main models with eviction policies
-pub struct MainModelCache<T: HasPrimaryKey + Clone> {
+///
+/// Items are keyed by `K`, the `Uuid` primary key unless the model
+/// implements `CacheKey` for another key type.
+pub struct MainModelCache<T: CacheKey<K> + Clone, K: Eq + Hash + Clone = Uuid> {
     /// Main storage indexed by primary key
-    entries: HashMap<Uuid, CacheEntry<T>>,
-    /// Access order tracking (for LRU and FIFO)
-    access_order: VecDeque<Uuid>,
+    entries: HashMap<K, CacheEntry<T>>,
+    /// Keys by first insertion, oldest first, for expiry and sampling
+    insertion_order: BTreeMap<u64, K>,
+    /// Keys in the order the configured policy evicts them
+    eviction_order: PolicyOrder<K>,
+    /// Next position in the insertion and eviction orders
+    next_seq: u64,
     /// Configuration
     config: CacheConfig,
     /// Statistics
     statistics: CacheStatistics,
+    /// Insertion positions of the entries ordered by expiry; stale items are dropped lazily
+    expiry_queue: BinaryHeap<Reverse<(DateTime<Utc>, u64)>>,
+    /// Reads valid_to; set only when constructed for a `Validity` type
+    valid_to_of: Option<ValidityBound<T>>,
+    /// Reads valid_from; set only when constructed for a `Validity` type
+    valid_from_of: Option<ValidityBound<T>>,
+    /// Entries whose valid_from was in the future when they were stored
+    not_yet_valid: HashSet<K>,
+    /// Identifies the cache in logs and statistics
+    name: Option<String>,
+    /// Hits after which all access counts are halved; no decay when `None`
+    access_decay: Option<u64>,
+    /// Hits since the access counts were last halved
+    hits_since_decay: u64,
+    high_watermark: Option<HighWatermark>,
+    /// Signalled on every change, for `wait_until`
+    changed: Arc<Notify>,
+    /// Set by `mark_ready` once the cache is loaded
+    ready: bool,
+    /// Counts the changes, see `generation`
+    generation: u64,
+    /// Whether a change was made in the running `batch`, if any
+    batch_changed: Option<bool>,
 }
 
 impl<T: HasPrimaryKey + Clone + Debug> MainModelCache<T> {
     /// Creates a new empty cache with the given configuration
     pub fn new(config: CacheConfig) -> Self {
+        Self::keyed(config)
+    }
+
+    /// Creates a new empty cache that also schedules expiry from each item's valid_to
+    pub fn with_validity(config: CacheConfig) -> Self
+    where
+        T: Validity,
+    {
+        Self::keyed_with_validity(config)
+    }
+}
+
+impl<T: CacheKey<K> + Clone + Debug, K: Eq + Hash + Clone> MainModelCache<T, K> {
+    /// Creates a new empty cache keyed by `K`, e.g. `MainModelCache::<Country, String>::keyed(config)`
+    pub fn keyed(config: CacheConfig) -> Self {
         Self {
             entries: HashMap::new(),
-            access_order: VecDeque::new(),
+            insertion_order: BTreeMap::new(),
+            eviction_order: PolicyOrder::for_config(&config),
+            next_seq: 0,
             config,
             statistics: CacheStatistics::new(),
+            expiry_queue: BinaryHeap::new(),
+            valid_to_of: None,
+            valid_from_of: None,
+            not_yet_valid: HashSet::new(),
+            name: None,
+            access_decay: None,
+            hits_since_decay: 0,
+            high_watermark: None,
+            changed: Arc::new(Notify::new()),
+            ready: false,
+            generation: 0,
+            batch_changed: None,
+        }
+    }
+
+    /// Names the cache, so its log lines and statistics tell it apart from other caches
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// The name set with `with_name`
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Calls `hook` when an insert fills the cache to `threshold` of its capacity
+    ///
+    /// The hook fires once per crossing: removals, evictions of expired
+    /// entries or a clear that take the fill below the threshold arm it
+    /// again. It runs while the cache is borrowed mutably, so it must not
+    /// lock the cache itself.
+    pub fn on_high_watermark(mut self, threshold: f64, hook: Arc<HighWatermarkHook>) -> Self {
+        self.high_watermark = Some(HighWatermark { threshold, hook, above: false });
+        self.check_high_watermark();
+        self
+    }
+
+    /// Halves all access counts after every `hits` hits, so old hotness fades
+    ///
+    /// A `hits` of 0 turns the decay off.
+    pub fn with_access_decay(mut self, hits: u64) -> Self {
+        self.access_decay = (hits > 0).then_some(hits);
+        self
+    }
+
+    /// Like `keyed`, but also schedules expiry from each item's valid_to
+    pub fn keyed_with_validity(config: CacheConfig) -> Self
+    where
+        T: Validity,
+    {
+        Self {
+            valid_to_of: Some(|item: &T| item.validity().1),
+            valid_from_of: Some(|item: &T| item.validity().0),
+            ..Self::keyed(config)
         }
     }
 
     /// Gets an item from the cache by its primary key
     /// Returns None if the item is not in cache or is no longer valid
-    pub fn get(&mut self, primary_key: &Uuid) -> Option<T> {
+    ///
+    /// The item is shared with the cache, so a hit does not clone it.
+    pub fn get(&mut self, primary_key: &K) -> Option<Arc<T>> {
         // Check if entry exists
         if let Some(entry) = self.entries.get(primary_key) {
             // Check TTL expiration
-            let should_evict = if let Some(ttl) = self.config.ttl {
-                let elapsed = Utc::now().signed_duration_since(entry.inserted_at);
-                elapsed.to_std().ok().is_some_and(|d| d > ttl)
-            } else {
-                false
-            };
-
-            if should_evict {
+            if self.is_ttl_expired(entry) {
                 // Entry has expired, remove it
                 let _ = entry; // Release borrow
                 self.remove_internal(primary_key);
-                self.statistics.record_miss();
+                self.statistics.record_miss(MissReason::TtlExpired);
                 return None;
             }
 
-            let result = entry.value.clone();
+            let result = Arc::clone(&entry.value);
             let _ = entry; // Release borrow
 
             // Update access time and order
-            if let Some(entry) = self.entries.get_mut(primary_key) {
-                entry.access();
-            }
-
-            // Update access order for LRU policy
-            if self.config.eviction_policy == EvictionPolicy::LRU {
-                self.access_order.retain(|&id| id != *primary_key);
-                self.access_order.push_back(*primary_key);
-            }
+            self.touch(primary_key);
+            self.count_access(primary_key);
 
             self.statistics.record_hit();
             Some(result)
         } else {
-            self.statistics.record_miss();
+            self.statistics.record_miss(MissReason::Absent);
             None
         }
     }
 
     /// Inserts or updates an item in the cache
     /// If the cache is full, evicts entries according to the eviction policy
-    pub fn insert(&mut self, item: T) {
-        let primary_key = item.primary_key();
+    ///
+    /// Inserting an existing key is an update: it counts as an access but
+    /// keeps the entry's place in the FIFO order. A key that was removed or
+    /// expired starts over at the back of both orders.
+    ///
+    /// Accepts an item or an `Arc` of one; an item is wrapped once here.
+    /// A disabled cache ignores the item.
+    pub fn insert(&mut self, item: impl Into<Arc<T>>) {
+        if self.config.is_disabled() {
+            return;
+        }
+        let item = item.into();
+        let primary_key = CacheKey::<K>::cache_key(&*item);
 
         // If item already exists, update it
         if self.entries.contains_key(&primary_key) {
@@ -216,30 +751,51 @@ impl<T: HasPrimaryKey + Clone + Debug> MainModelCache<T> {
         }
 
         // Check if we need to evict
-        while self.entries.len() >= self.config.cache_size && !self.access_order.is_empty() {
+        while self.entries.len() >= self.config.cache_size && !self.entries.is_empty() {
             self.evict_one();
         }
 
         // Insert the new entry
-        let entry = CacheEntry::new(item);
-        self.entries.insert(primary_key, entry);
-        self.access_order.push_back(primary_key);
+        let seq = self.next_seq();
+        self.entries.insert(primary_key.clone(), CacheEntry::new(item, seq));
+        self.insertion_order.insert(seq, primary_key.clone());
+        self.eviction_order.insert(&primary_key, seq);
+        self.schedule_expiry(primary_key);
+        self.check_high_watermark();
+        self.record_change();
+    }
+
+    /// Inserts or updates an item with its own TTL instead of the configured one
+    ///
+    /// The TTL stays with the entry across later updates.
+    pub fn insert_with_ttl(&mut self, item: impl Into<Arc<T>>, ttl: Duration) {
+        let item = item.into();
+        let primary_key = CacheKey::<K>::cache_key(&*item);
+        self.insert(item);
+        if let Some(entry) = self.entries.get_mut(&primary_key) {
+            entry.ttl = Some(ttl);
+        }
+        self.schedule_expiry(primary_key);
     }
 
     /// Updates an existing item in the cache
     /// If the item doesn't exist, it will be inserted
-    pub fn update(&mut self, item: T) {
-        let primary_key = item.primary_key();
+    ///
+    /// An update moves the entry to the back of the LRU order only, unless
+    /// `LruOptions` or `FifoOptions` say otherwise.
+    pub fn update(&mut self, item: impl Into<Arc<T>>) {
+        let item = item.into();
+        let primary_key = CacheKey::<K>::cache_key(&*item);
         
+        let seq = self.next_seq();
         if let Some(entry) = self.entries.get_mut(&primary_key) {
             entry.value = item;
-            entry.access();
-            
-            // Update access order for LRU
-            if self.config.eviction_policy == EvictionPolicy::LRU {
-                self.access_order.retain(|&id| id != primary_key);
-                self.access_order.push_back(primary_key);
+            if self.config.touches_on_update() {
+                entry.renew(seq);
             }
+            self.eviction_order.update(&primary_key, &mut entry.evict_seq, seq);
+            self.schedule_expiry(primary_key);
+            self.record_change();
         } else {
             self.insert(item);
         }
@@ -247,16 +803,87 @@ impl<T: HasPrimaryKey + Clone + Debug> MainModelCache<T> {
 
     /// Removes an item from the cache by its primary key
     /// Returns the removed item if it existed
-    pub fn remove(&mut self, primary_key: &Uuid) -> Option<T> {
+    pub fn remove(&mut self, primary_key: &K) -> Option<Arc<T>> {
         self.statistics.record_invalidation();
-        self.remove_internal(primary_key)
+        let removed = self.remove_internal(primary_key);
+        self.check_high_watermark();
+        removed
+    }
+
+    /// Marks the cache as loaded, so reads gated on readiness use it
+    ///
+    /// Called by `CacheBootstrapper` once the load and the replay of the
+    /// notifications buffered meanwhile are done. Clearing the cache keeps it ready.
+    pub fn mark_ready(&mut self) {
+        self.ready = true;
+        self.changed.notify_waiters();
+    }
+
+    /// Returns true once `mark_ready` was called
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Returns the number of changes made to the cache so far
+    ///
+    /// Inserts, updates, removes, clears and evictions each count once, a
+    /// transaction commit counts once however many items it stages. Expiry
+    /// on reads counts as a remove. Compare it with the value seen at the
+    /// last save to persist the cache only when it changed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Waits until the cache holds an item with the given key
+    ///
+    /// Returns false if `timeout` passes first. Lets tests wait for a
+    /// notification to be applied instead of sleeping.
+    pub async fn wait_for<L: CacheLock<Self>>(cache: &L, primary_key: K, timeout: Duration) -> bool {
+        Self::wait_until(cache, timeout, |cache| cache.contains(&primary_key)).await
+    }
+
+    /// Waits until `condition` holds for the cache, checking it again after every change
+    ///
+    /// Returns false if `timeout` passes first. Reads for the condition go
+    /// through `&self`, so they do not count as hits or misses.
+    pub async fn wait_until<L: CacheLock<Self>>(
+        cache: &L,
+        timeout: Duration,
+        condition: impl Fn(&Self) -> bool,
+    ) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let guard = cache.read().await;
+                if condition(&guard) {
+                    return;
+                }
+                let changed = Arc::clone(&guard.changed);
+                let mut notified = std::pin::pin!(changed.notified());
+                // Changes take the write lock, so none slips in before the waiter is registered
+                notified.as_mut().enable();
+                drop(guard);
+                notified.await;
+            }
+        })
+        .await
+        .is_ok()
     }
 
     /// Checks if the cache contains an item with the given primary key
-    pub fn contains(&self, primary_key: &Uuid) -> bool {
+    pub fn contains(&self, primary_key: &K) -> bool {
         self.entries.contains_key(primary_key)
     }
 
+    /// Gets an item without recording statistics or touching the access order
+    pub(crate) fn peek(&self, primary_key: &K) -> Option<&Arc<T>> {
+        self.entries.get(primary_key).map(|entry| &entry.value)
+    }
+
+    /// Iterates over the cached keys in no particular order
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.keys()
+    }
+
     /// Returns the number of items currently in the cache
     pub fn len(&self) -> usize {
         self.entries.len()
@@ -270,7 +897,12 @@ impl<T: HasPrimaryKey + Clone + Debug> MainModelCache<T> {
     /// Clears all entries from the cache
     pub fn clear(&mut self) {
         self.entries.clear();
-        self.access_order.clear();
+        self.insertion_order.clear();
+        self.eviction_order.clear();
+        self.expiry_queue.clear();
+        self.not_yet_valid.clear();
+        self.check_high_watermark();
+        self.record_change();
     }
 
     /// Gets the cache statistics
@@ -278,175 +910,561 @@ impl<T: HasPrimaryKey + Clone + Debug> MainModelCache<T> {
         &self.statistics
     }
 
+    /// Copies the statistics and size of the cache, labelled with its name
+    pub fn statistics_snapshot(&self) -> StatisticsSnapshot {
+        StatisticsSnapshot {
+            name: self.name.clone(),
+            entries: self.len(),
+            hits: self.statistics.hits(),
+            misses: self.statistics.misses(),
+            evictions: self.statistics.evictions(),
+            invalidations: self.statistics.invalidations(),
+        }
+    }
+
     /// Gets the cache configuration
     pub fn config(&self) -> &CacheConfig {
         &self.config
     }
 
-    /// Evicts all expired or invalid entries from the cache
-    /// This performs a lazy cleanup based on TTL only
+    /// Returns true if the cache was configured with a `cache_size` of 0 and stores nothing
+    pub fn is_disabled(&self) -> bool {
+        self.config.is_disabled()
+    }
+
+    /// Evicts all expired entries from the cache
+    /// Expiry comes from the TTL and, for caches created with `with_validity`, valid_to
     /// For validity checks with ValidFrom/ValidTo, use the extension methods
-    pub fn evict_invalid(&mut self) -> usize {
-        let mut to_remove = Vec::new();
+    ///
+    /// Returns the keys of the evicted entries.
+    pub fn evict_invalid(&mut self) -> Vec<K> {
+        self.evict_due(Utc::now())
+    }
 
-        for (key, entry) in &self.entries {
-            // Check TTL expiration
-            if let Some(ttl) = self.config.ttl {
-                let elapsed = Utc::now().signed_duration_since(entry.inserted_at);
-                if elapsed.to_std().ok().is_some_and(|d| d > ttl) {
-                    to_remove.push(*key);
+    /// Evicts the entries whose expiry is at or before `now`
+    ///
+    /// Only due entries are visited, in expiry order. Returns the keys of the
+    /// evicted entries in that order.
+    pub fn evict_due(&mut self, now: DateTime<Utc>) -> Vec<K> {
+        self.batch(|cache| {
+            let mut evicted = Vec::new();
+            while let Some(&Reverse((expires_at, seq))) = cache.expiry_queue.peek() {
+                if expires_at > now {
+                    break;
+                }
+                cache.expiry_queue.pop();
+                if let Some(primary_key) = cache.queued_key(expires_at, seq).cloned() {
+                    let reason = match cache.entries.get(&primary_key).and_then(|entry| cache.ttl_expiry_of(entry)) {
+                        Some(ttl_expiry) if ttl_expiry == expires_at => EvictionReason::TtlExpired,
+                        _ => EvictionReason::Invalid,
+                    };
+                    cache.remove_internal(&primary_key);
+                    cache.statistics.record_eviction(reason);
+                    evicted.push(primary_key);
                 }
             }
+            cache.check_high_watermark();
+            evicted
+        })
+    }
+
+    /// Returns the earliest time at which an entry expires, from TTL and valid_to
+    ///
+    /// Returns None if no entry has a known expiry.
+    pub fn next_expiry(&self) -> Option<DateTime<Utc>> {
+        self.expiry_queue.peek().map(|Reverse((expires_at, _))| *expires_at)
+    }
+
+    /// Gets the metadata of an entry without counting an access
+    ///
+    /// Neither the statistics nor the LRU order change.
+    pub fn entry_info(&self, primary_key: &K) -> Option<EntryInfo> {
+        self.entries.get(primary_key).map(|entry| self.info_of(entry, Utc::now()))
+    }
+
+    /// Lists the `n` most recently used entries, most recent first
+    ///
+    /// Sorts all entries by their last use, so it suits diagnostics rather
+    /// than hot paths.
+    pub fn hot_entries(&self, n: usize) -> Vec<(K, EntryInfo)> {
+        let now = Utc::now();
+        self.by_recency()
+            .into_iter()
+            .rev()
+            .take(n)
+            .map(|(key, entry)| (key.clone(), self.info_of(entry, now)))
+            .collect()
+    }
+
+    /// Lists the `n` least recently used entries, least recent first
+    ///
+    /// Sorts all entries like `hot_entries`.
+    pub fn cold_entries(&self, n: usize) -> Vec<(K, EntryInfo)> {
+        let now = Utc::now();
+        self.by_recency()
+            .into_iter()
+            .take(n)
+            .map(|(key, entry)| (key.clone(), self.info_of(entry, now)))
+            .collect()
+    }
+
+    /// Lists the `n` entries with the most hits, most hit first
+    ///
+    /// Neither the statistics nor the LRU order change. Ties are listed in
+    /// no particular order.
+    pub fn top_accessed(&self, n: usize) -> Vec<(K, u64)> {
+        let mut counts: Vec<(K, u64)> = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (key.clone(), u64::from(entry.access_count)))
+            .collect();
+        counts.sort_unstable_by_key(|(_, count)| Reverse(*count));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Halves the access counts of all entries
+    ///
+    /// Called after every `hits` hits with `with_access_decay`, or by the
+    /// caller on its own schedule.
+    pub fn decay_access_counts(&mut self) {
+        for entry in self.entries.values_mut() {
+            entry.access_count /= 2;
         }
+        self.hits_since_decay = 0;
+    }
 
-        let count = to_remove.len();
-        for key in to_remove {
-            self.remove_internal(&key);
-            self.statistics.record_eviction();
+    /// Counts a hit on an entry, decaying all counts when due
+    fn count_access(&mut self, primary_key: &K) {
+        if let Some(entry) = self.entries.get_mut(primary_key) {
+            entry.access_count = entry.access_count.saturating_add(1);
+        }
+        self.hits_since_decay += 1;
+        if self.access_decay.is_some_and(|hits| self.hits_since_decay >= hits) {
+            self.decay_access_counts();
         }
+    }
 
-        count
+    fn info_of(&self, entry: &CacheEntry<T>, now: DateTime<Utc>) -> EntryInfo {
+        let since = |time: DateTime<Utc>| now.signed_duration_since(time).to_std().unwrap_or_default();
+        let remaining_ttl = entry.ttl.or(self.config.ttl).map(|ttl| ttl.saturating_sub(since(entry.inserted_at)));
+        EntryInfo {
+            inserted_at: entry.inserted_at,
+            last_accessed: entry.last_accessed,
+            age: since(entry.inserted_at),
+            idle: since(entry.last_accessed),
+            remaining_ttl,
+            valid_to: self.valid_to_of.and_then(|valid_to_of| valid_to_of(&entry.value)),
+            expires_at: entry.expires_at,
+            access_count: entry.access_count,
+        }
     }
 
     /// Internal remove that doesn't record statistics
-    fn remove_internal(&mut self, primary_key: &Uuid) -> Option<T> {
-        self.access_order.retain(|&id| id != *primary_key);
-        self.entries.remove(primary_key).map(|entry| entry.value)
+    fn remove_internal(&mut self, primary_key: &K) -> Option<Arc<T>> {
+        self.not_yet_valid.remove(primary_key);
+        let entry = self.entries.remove(primary_key)?;
+        self.insertion_order.remove(&entry.inserted_seq);
+        self.eviction_order.remove(entry.evict_seq);
+        self.prune_expiry_queue();
+        self.record_change();
+        Some(entry.value)
     }
 
-    /// Evicts one entry based on the eviction policy
-    fn evict_one(&mut self) {
-        let key_to_evict = match self.config.eviction_policy {
-            EvictionPolicy::LRU => {
-                // Remove the least recently used (front of the deque)
-                self.access_order.pop_front()
-            }
-            EvictionPolicy::FIFO => {
-                // Remove the oldest inserted (front of the deque)
-                self.access_order.pop_front()
+    /// Counts a change and wakes the waiters of `wait_until`
+    fn record_change(&mut self) {
+        match &mut self.batch_changed {
+            Some(changed) => *changed = true,
+            None => self.generation += 1,
+        }
+        self.changed.notify_waiters();
+    }
+
+    /// Runs `f` counting the changes it makes as one generation
+    pub(crate) fn batch<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let outer = self.batch_changed.replace(false);
+        let result = f(self);
+        let changed = self.batch_changed.take() == Some(true);
+        match outer {
+            Some(outer_changed) => self.batch_changed = Some(outer_changed || changed),
+            None if changed => self.generation += 1,
+            None => {}
+        }
+        result
+    }
+
+    /// Takes the next position in the insertion and eviction orders
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    /// Records a hit, moving the entry in the eviction order if the policy says so
+    fn touch(&mut self, primary_key: &K) {
+        let seq = self.next_seq();
+        let Some(entry) = self.entries.get_mut(primary_key) else {
+            return;
+        };
+        entry.renew(seq);
+        self.eviction_order.read(primary_key, &mut entry.evict_seq, seq);
+    }
+
+    /// The entries by their last hit or update, least recent first
+    fn by_recency(&self) -> Vec<(&K, &CacheEntry<T>)> {
+        let mut entries: Vec<(&K, &CacheEntry<T>)> = self.entries.iter().collect();
+        entries.sort_unstable_by_key(|(_, entry)| entry.accessed_seq);
+        entries
+    }
+
+    /// Whether the TTL of an entry, its own or the configured one, has passed
+    fn is_ttl_expired(&self, entry: &CacheEntry<T>) -> bool {
+        let Some(ttl) = entry.ttl.or(self.config.ttl) else {
+            return false;
+        };
+        let elapsed = Utc::now().signed_duration_since(entry.inserted_at);
+        elapsed.to_std().ok().is_some_and(|d| d > ttl)
+    }
+
+    /// Computes when the TTL of an entry, its own or the configured one, passes
+    fn ttl_expiry_of(&self, entry: &CacheEntry<T>) -> Option<DateTime<Utc>> {
+        entry
+            .ttl
+            .or(self.config.ttl)
+            .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+            .and_then(|ttl| entry.inserted_at.checked_add_signed(ttl))
+    }
+
+    /// Computes when an entry expires, whichever of TTL and valid_to comes first
+    fn expiry_of(&self, entry: &CacheEntry<T>) -> Option<DateTime<Utc>> {
+        let ttl_expiry = self.ttl_expiry_of(entry);
+        let valid_to = self.valid_to_of.and_then(|valid_to_of| valid_to_of(&entry.value));
+        match (ttl_expiry, valid_to) {
+            (Some(ttl_expiry), Some(valid_to)) => Some(ttl_expiry.min(valid_to)),
+            (ttl_expiry, valid_to) => ttl_expiry.or(valid_to),
+        }
+    }
+
+    /// Recomputes the expiry of an entry and queues it if it changed
+    fn schedule_expiry(&mut self, primary_key: K) {
+        let Some(entry) = self.entries.get(&primary_key) else {
+            return;
+        };
+        let seq = entry.inserted_seq;
+        let valid_from = self.valid_from_of.and_then(|valid_from_of| valid_from_of(&entry.value));
+        let expires_at = self.expiry_of(entry);
+        let unchanged = expires_at == entry.expires_at;
+        if valid_from.is_some_and(|valid_from| valid_from > Utc::now()) {
+            self.not_yet_valid.insert(primary_key.clone());
+        } else {
+            self.not_yet_valid.remove(&primary_key);
+        }
+        if unchanged {
+            return;
+        }
+        if let Some(entry) = self.entries.get_mut(&primary_key) {
+            entry.expires_at = expires_at;
+        }
+        if let Some(expires_at) = expires_at {
+            self.expiry_queue.push(Reverse((expires_at, seq)));
+        }
+        self.prune_expiry_queue();
+    }
+
+    /// The key of a queued expiry, or None if the entry was removed or rescheduled
+    fn queued_key(&self, expires_at: DateTime<Utc>, seq: u64) -> Option<&K> {
+        let primary_key = self.insertion_order.get(&seq)?;
+        let entry = self.entries.get(primary_key)?;
+        (entry.expires_at == Some(expires_at)).then_some(primary_key)
+    }
+
+    /// Drops queued expiries of removed or rescheduled entries
+    ///
+    /// Keeps the head of the queue current so `next_expiry` can peek, and
+    /// rebuilds the queue when stale items outnumber the live ones.
+    fn prune_expiry_queue(&mut self) {
+        while let Some(&Reverse((expires_at, seq))) = self.expiry_queue.peek() {
+            if self.queued_key(expires_at, seq).is_some() {
+                break;
             }
+            self.expiry_queue.pop();
+        }
+
+        if self.expiry_queue.len() > 2 * self.entries.len() + 16 {
+            self.expiry_queue = self
+                .entries
+                .values()
+                .filter_map(|entry| entry.expires_at.map(|expires_at| Reverse((expires_at, entry.inserted_seq))))
+                .collect();
+        }
+    }
+
+    /// How full the cache is
+    pub fn pressure(&self) -> CachePressure {
+        let capacity = self.config.cache_size;
+        let fill_ratio = if capacity == 0 { 1.0 } else { self.entries.len() as f64 / capacity as f64 };
+        CachePressure { len: self.entries.len(), capacity, fill_ratio }
+    }
+
+    /// The key the eviction policy would evict next, without evicting it
+    ///
+    /// The least recently used key under LRU, the oldest inserted under FIFO.
+    /// Neither the statistics nor the LRU order change.
+    pub fn peek_eviction_candidate(&self) -> Option<K> {
+        self.eviction_order.next_victim().cloned()
+    }
+
+    /// Fires the high watermark hook when the fill reached its threshold, re-arms it below
+    fn check_high_watermark(&mut self) {
+        let pressure = self.pressure();
+        let Some(watermark) = &mut self.high_watermark else {
+            return;
         };
+        if pressure.fill_ratio < watermark.threshold {
+            watermark.above = false;
+        } else if !watermark.above {
+            watermark.above = true;
+            (watermark.hook)(&pressure);
+        }
+    }
 
-        if let Some(key) = key_to_evict {
-            self.entries.remove(&key);
-            self.statistics.record_eviction();
+    /// Evicts one entry based on the eviction policy
+    fn evict_one(&mut self) {
+        if let Some(key) = self.peek_eviction_candidate() {
+            self.remove_internal(&key);
+            self.statistics.record_eviction(EvictionReason::Other);
         }
     }
 
 }
 
+/// Whether `at` lies in the validity range bounded by `valid_from` and `valid_to`; open ends are unbounded
+fn is_within(valid_from: Option<DateTime<Utc>>, valid_to: Option<DateTime<Utc>>, at: DateTime<Utc>) -> bool {
+    valid_from.is_none_or(|valid_from| at >= valid_from) && valid_to.is_none_or(|valid_to| at <= valid_to)
+}
+
 /// Extension trait for MainModelCache when T implements ValidFrom
-impl<T: HasPrimaryKey + Clone + Debug + ValidFrom> MainModelCache<T> {
+impl<T: CacheKey<K> + Clone + Debug + ValidFrom, K: Eq + Hash + Clone> MainModelCache<T, K> {
     /// Checks if an item is valid based on ValidFrom
     pub fn is_valid_from(&self, item: &T) -> bool {
-        if let Some(valid_from) = item.valid_from() {
-            Utc::now() >= valid_from
-        } else {
-            true
-        }
+        is_within(item.valid_from(), None, Utc::now())
     }
 }
 
 /// Extension trait for MainModelCache when T implements ValidTo
-impl<T: HasPrimaryKey + Clone + Debug + ValidTo> MainModelCache<T> {
+impl<T: CacheKey<K> + Clone + Debug + ValidTo, K: Eq + Hash + Clone> MainModelCache<T, K> {
     /// Checks if an item is valid based on ValidTo
     pub fn is_valid_to(&self, item: &T) -> bool {
-        if let Some(valid_to) = item.valid_to() {
-            Utc::now() <= valid_to
-        } else {
-            true
-        }
+        is_within(None, item.valid_to(), Utc::now())
     }
 }
 
-/// Extension trait for MainModelCache when T implements both ValidFrom and ValidTo
-impl<T: HasPrimaryKey + Clone + Debug + ValidFrom + ValidTo> MainModelCache<T> {
-    /// Checks if an item is currently valid based on both ValidFrom and ValidTo
+/// Extension trait for MainModelCache when T implements Validity
+impl<T: CacheKey<K> + Clone + Debug + Validity, K: Eq + Hash + Clone> MainModelCache<T, K> {
+    /// Checks if an item is currently valid based on both ends of its validity range
     pub fn is_fully_valid(&self, item: &T) -> bool {
-        self.is_valid_from(item) && self.is_valid_to(item)
+        self.is_valid_at(item, Utc::now())
+    }
+
+    /// Checks if an item's validity range contains `at`
+    pub fn is_valid_at(&self, item: &T, at: DateTime<Utc>) -> bool {
+        let (valid_from, valid_to) = item.validity();
+        is_within(valid_from, valid_to, at)
     }
 
     /// Gets an item from the cache with full validity checking
-    pub fn get_with_validity_check(&mut self, primary_key: &Uuid) -> Option<T> {
+    pub fn get_with_validity_check(&mut self, primary_key: &K) -> Option<Arc<T>> {
+        self.get_checked_at(primary_key, Utc::now(), true)
+    }
+
+    /// Gets an item from the cache if it was valid at `at`, e.g. to reprocess past events
+    ///
+    /// Unlike `get_with_validity_check`, an item not valid at `at` stays
+    /// cached, as it may be valid now; the read counts as an `Invalid` miss.
+    /// Items whose TTL expired are removed either way.
+    pub fn get_valid_at(&mut self, primary_key: &K, at: DateTime<Utc>) -> Option<Arc<T>> {
+        self.get_checked_at(primary_key, at, false)
+    }
+
+    /// Gets an item if it is valid at `at`, removing it when it is not and `evict_invalid` is set
+    fn get_checked_at(&mut self, primary_key: &K, at: DateTime<Utc>, evict_invalid: bool) -> Option<Arc<T>> {
         // First check validity without mutable borrow
         if let Some(entry) = self.entries.get(primary_key) {
             // Check full validity
-            if !self.is_fully_valid(&entry.value) {
+            if !self.is_valid_at(&entry.value, at) {
                 let _ = entry; // Release borrow
-                self.remove_internal(primary_key);
-                self.statistics.record_miss();
+                if evict_invalid {
+                    self.remove_internal(primary_key);
+                }
+                self.statistics.record_miss(MissReason::Invalid);
                 return None;
             }
 
             // Check TTL expiration
-            let should_evict = if let Some(ttl) = self.config.ttl {
-                let elapsed = Utc::now().signed_duration_since(entry.inserted_at);
-                elapsed.to_std().ok().is_some_and(|d| d > ttl)
-            } else {
-                false
-            };
-
-            if should_evict {
+            if self.is_ttl_expired(entry) {
                 let _ = entry; // Release borrow
                 self.remove_internal(primary_key);
-                self.statistics.record_miss();
+                self.statistics.record_miss(MissReason::TtlExpired);
                 return None;
             }
 
-            let result = entry.value.clone();
+            let result = Arc::clone(&entry.value);
             let _ = entry; // Release borrow
 
             // Now update with mutable borrow
-            if let Some(entry) = self.entries.get_mut(primary_key) {
-                entry.access();
-            }
-
-            if self.config.eviction_policy == EvictionPolicy::LRU {
-                self.access_order.retain(|&id| id != *primary_key);
-                self.access_order.push_back(*primary_key);
-            }
+            self.touch(primary_key);
 
             self.statistics.record_hit();
             Some(result)
         } else {
-            self.statistics.record_miss();
+            self.statistics.record_miss(MissReason::Absent);
             None
         }
     }
 
     /// Evicts all expired or invalid entries from the cache
     /// This performs a lazy cleanup based on ValidFrom, ValidTo, and TTL
-    pub fn evict_invalid_with_validity(&mut self) -> usize {
-        let mut to_remove = Vec::new();
-
-        for (key, entry) in &self.entries {
-            let mut should_remove = false;
-
-            // Check validity
-            if !self.is_fully_valid(&entry.value) {
-                should_remove = true;
+    ///
+    /// Caches created with `with_validity` only visit due and not yet valid
+    /// entries; other caches are scanned in full. Returns the keys of the
+    /// evicted entries.
+    pub fn evict_invalid_with_validity(&mut self) -> Vec<K> {
+        self.batch(|cache| {
+            if cache.valid_from_of.is_some() {
+                let now = Utc::now();
+                let mut evicted = cache.evict_due(now);
+                let not_yet_valid: Vec<K> = cache.not_yet_valid.iter().cloned().collect();
+                for key in not_yet_valid {
+                    let valid_from = cache.entries.get(&key).and_then(|entry| entry.value.validity().0);
+                    if valid_from.is_some_and(|valid_from| valid_from > now) {
+                        cache.remove_internal(&key);
+                        cache.statistics.record_eviction(EvictionReason::Invalid);
+                        evicted.push(key);
+                    } else {
+                        cache.not_yet_valid.remove(&key);
+                    }
+                }
+                return evicted;
             }
 
-            // Check TTL expiration
-            if let Some(ttl) = self.config.ttl {
-                let elapsed = Utc::now().signed_duration_since(entry.inserted_at);
-                if elapsed.to_std().ok().is_some_and(|d| d > ttl) {
-                    should_remove = true;
+            let mut to_remove = Vec::new();
+
+            for (key, entry) in &cache.entries {
+                // Check validity first, as get_with_validity_check does
+                if !cache.is_fully_valid(&entry.value) {
+                    to_remove.push((key.clone(), EvictionReason::Invalid));
+                } else if cache.is_ttl_expired(entry) {
+                    to_remove.push((key.clone(), EvictionReason::TtlExpired));
                 }
             }
 
-            if should_remove {
-                to_remove.push(*key);
+            for (key, reason) in &to_remove {
+                cache.remove_internal(key);
+                cache.statistics.record_eviction(*reason);
             }
-        }
 
-        let count = to_remove.len();
-        for key in to_remove {
-            self.remove_internal(&key);
-            self.statistics.record_eviction();
-        }
+            to_remove.into_iter().map(|(key, _)| key).collect()
+        })
+    }
+}
+
+/// Extension trait for MainModelCache when T implements IsDeleted
+impl<T: CacheKey<K> + Clone + Debug + IsDeleted, K: Eq + Hash + Clone> MainModelCache<T, K> {
+    /// Evicts all soft-deleted entries from the cache
+    /// Returns the keys of the evicted entries
+    pub fn evict_deleted(&mut self) -> Vec<K> {
+        let to_remove: Vec<K> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.value.is_deleted())
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        self.batch(|cache| {
+            for key in &to_remove {
+                cache.remove_internal(key);
+                cache.statistics.record_eviction(EvictionReason::Other);
+            }
+        });
+
+        to_remove
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<T: CacheKey<K> + Clone + Debug, K: Eq + Hash + Clone> MainModelCache<T, K> {
+    /// Draws up to `n` cached items uniformly at random, e.g. to audit the cache
+    ///
+    /// Reservoir sampling over the insertion order keeps only the sample in
+    /// memory, and the same seed draws the same sample from caches filled the
+    /// same way. Like `entry_info`, sampling changes neither the statistics
+    /// nor the LRU order; expired entries not yet evicted can be drawn.
+    pub fn sample(&self, n: usize, rng: &mut impl rand::Rng) -> Vec<&T> {
+        let entries = self.insertion_order.values().filter_map(|key| self.entries.get(key));
+        crate::sampling::reservoir_sample(entries, n, rng)
+            .into_iter()
+            .map(|entry| &*entry.value)
+            .collect()
+    }
+
+    /// Draws up to `n` keys uniformly at random, like `sample`
+    pub fn sample_keys(&self, n: usize, rng: &mut impl rand::Rng) -> Vec<K> {
+        crate::sampling::reservoir_sample(self.insertion_order.values(), n, rng)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+}
+
+impl<T: CacheKey<K> + Clone + Debug + Serialize, K: Eq + Hash + Clone + Serialize> MainModelCache<T, K> {
+    /// Serializes every cached item with its entry metadata, e.g. for a debugging endpoint
+    ///
+    /// See `dump_json_limited` for the format.
+    pub fn dump_json(&self) -> serde_json::Value {
+        self.dump_json_limited(usize::MAX)
+    }
 
-        count
+    /// Serializes the `limit` most recently used items with their entry metadata
+    ///
+    /// Returns `{"name", "entries", "truncated", "items"}`, where `entries` is
+    /// the number of cached items and each item is formatted as by
+    /// `dump_entry_json`. Only the items dumped are serialized. Like
+    /// `entry_info`, dumping changes neither the statistics nor the LRU order.
+    pub fn dump_json_limited(&self, limit: usize) -> serde_json::Value {
+        let now = Utc::now();
+        let items: Vec<serde_json::Value> = self
+            .by_recency()
+            .into_iter()
+            .rev()
+            .take(limit)
+            .map(|(key, entry)| self.entry_json(key, entry, now))
+            .collect();
+        serde_json::json!({
+            "name": self.name,
+            "entries": self.entries.len(),
+            "truncated": items.len() < self.entries.len(),
+            "items": items,
+        })
+    }
+
+    /// Serializes one cached item as `{"key", "item", "inserted_at", "last_accessed", "expires_at", "valid_to"}`
+    ///
+    /// An item that fails to serialize is replaced by `{"error": message}`.
+    pub fn dump_entry_json(&self, primary_key: &K) -> Option<serde_json::Value> {
+        let entry = self.entries.get(primary_key)?;
+        Some(self.entry_json(primary_key, entry, Utc::now()))
+    }
+
+    fn entry_json(&self, key: &K, entry: &CacheEntry<T>, now: DateTime<Utc>) -> serde_json::Value {
+        let info = self.info_of(entry, now);
+        serde_json::json!({
+            "key": key,
+            "item": item_json(&*entry.value),
+            "inserted_at": info.inserted_at,
+            "last_accessed": info.last_accessed,
+            "expires_at": info.expires_at,
+            "valid_to": info.valid_to,
+        })
     }
 }
 
@@ -454,7 +1472,7 @@ impl<T: HasPrimaryKey + Clone + Debug + ValidFrom + ValidTo> MainModelCache<T> {
 mod tests {
     use super::*;
 
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Serialize)]
     struct TestEntity {
         id: Uuid,
         value: String,
@@ -483,6 +1501,27 @@ mod tests {
         assert_eq!(retrieved.value, "test");
     }
 
+    #[test]
+    fn test_get_shares_stored_item() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let mut cache = MainModelCache::new(config);
+
+        let entity = Arc::new(TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        });
+        cache.insert(Arc::clone(&entity));
+
+        // Hits hand out the stored allocation instead of cloning the item
+        let first = cache.get(&entity.id).unwrap();
+        let second = cache.get(&entity.id).unwrap();
+        assert!(Arc::ptr_eq(&first, &entity));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let removed = cache.remove(&entity.id).unwrap();
+        assert!(Arc::ptr_eq(&removed, &entity));
+    }
+
     #[test]
     fn test_lru_eviction() {
         let config = CacheConfig::new(2, EvictionPolicy::LRU);
@@ -549,6 +1588,278 @@ mod tests {
         assert!(cache.contains(&entity3.id));
     }
 
+    fn entity(value: &str) -> TestEntity {
+        TestEntity { id: Uuid::new_v4(), value: value.to_string() }
+    }
+
+    /// Fills a cache of capacity 2 with a and b, applies `change`, then inserts
+    /// a third item and returns whether a survived the eviction
+    fn keeps_first_after(config: CacheConfig, change: impl FnOnce(&mut MainModelCache<TestEntity>, &TestEntity)) -> bool {
+        let mut cache = MainModelCache::new(config);
+        let a = entity("a");
+        cache.insert(a.clone());
+        cache.insert(entity("b"));
+        change(&mut cache, &a);
+        cache.insert(entity("c"));
+        assert_eq!(cache.len(), 2);
+        cache.contains(&a.id)
+    }
+
+    #[test]
+    fn test_update_keeps_fifo_order_and_renews_lru_order() {
+        for (policy, kept) in [(EvictionPolicy::FIFO, false), (EvictionPolicy::LRU, true)] {
+            let update = |cache: &mut MainModelCache<TestEntity>, a: &TestEntity| {
+                cache.update(TestEntity { value: "updated".to_string(), ..a.clone() });
+            };
+            assert_eq!(keeps_first_after(CacheConfig::new(2, policy), update), kept, "update with {policy:?}");
+
+            let reinsert = |cache: &mut MainModelCache<TestEntity>, a: &TestEntity| cache.insert(a.clone());
+            assert_eq!(keeps_first_after(CacheConfig::new(2, policy), reinsert), kept, "insert of a cached key with {policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_removed_or_expired_entry_starts_over_when_reinserted() {
+        for policy in [EvictionPolicy::FIFO, EvictionPolicy::LRU] {
+            let remove_then_reinsert = |cache: &mut MainModelCache<TestEntity>, a: &TestEntity| {
+                cache.remove(&a.id);
+                cache.insert(a.clone());
+            };
+            assert!(keeps_first_after(CacheConfig::new(2, policy), remove_then_reinsert), "remove then insert with {policy:?}");
+
+            let expire_then_reinsert = |cache: &mut MainModelCache<TestEntity>, a: &TestEntity| {
+                cache.insert_with_ttl(a.clone(), Duration::from_secs(1));
+                assert_eq!(cache.evict_due(Utc::now() + chrono::Duration::seconds(2)), vec![a.id]);
+                cache.insert(a.clone());
+            };
+            assert!(keeps_first_after(CacheConfig::new(2, policy), expire_then_reinsert), "expiry then insert with {policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_policy_options_change_what_an_update_does() {
+        let update = |cache: &mut MainModelCache<TestEntity>, a: &TestEntity| {
+            cache.update(TestEntity { value: "updated".to_string(), ..a.clone() });
+        };
+        let config = |policy| CacheConfig::builder().with_cache_size(2).with_eviction_policy(policy);
+
+        let requeue = config(EvictionPolicy::FIFO).with_fifo_options(FifoOptions { requeue_on_update: true });
+        assert!(keeps_first_after(requeue.build().unwrap(), update));
+        let no_touch = config(EvictionPolicy::LRU).with_lru_options(LruOptions { touch_on_update: false });
+        assert!(!keeps_first_after(no_touch.build().unwrap(), update));
+        assert!(keeps_first_after(config(EvictionPolicy::LRU).build().unwrap(), update));
+    }
+
+    #[test]
+    fn test_requeued_entry_still_expires() {
+        let config = CacheConfig::builder()
+            .with_cache_size(10)
+            .with_eviction_policy(EvictionPolicy::FIFO)
+            .with_fifo_options(FifoOptions { requeue_on_update: true })
+            .with_ttl(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let mut cache = MainModelCache::new(config);
+        let a = entity("a");
+        cache.insert(a.clone());
+        cache.update(a.clone());
+
+        assert_eq!(cache.evict_due(Utc::now() + chrono::Duration::minutes(2)), vec![a.id]);
+    }
+
+    /// Deterministic stand-in for random choices, `rand` being optional
+    fn lcg(seed: u64) -> impl FnMut(usize) -> usize {
+        let mut state = seed;
+        move |bound| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize % bound
+        }
+    }
+
+    /// Removes the eviction candidate until the cache is empty, returning the keys in turn
+    fn drain_in_eviction_order(cache: &mut MainModelCache<TestEntity>) -> Vec<Uuid> {
+        let mut drained = Vec::new();
+        while let Some(key) = cache.peek_eviction_candidate() {
+            cache.remove(&key);
+            drained.push(key);
+        }
+        drained
+    }
+
+    #[test]
+    fn test_eviction_order_under_interleaved_reads_and_updates() {
+        for policy in [EvictionPolicy::FIFO, EvictionPolicy::LRU] {
+            for seed in 0..50 {
+                let mut random = lcg(seed);
+                let mut cache = MainModelCache::new(CacheConfig::new(8, policy));
+                // The expected eviction order, front first
+                let mut expected: Vec<TestEntity> = Vec::new();
+                for step in 0..200 {
+                    if expected.is_empty() || random(4) == 0 {
+                        let item = entity(&step.to_string());
+                        cache.insert(item.clone());
+                        if expected.len() == 8 {
+                            expected.remove(0);
+                        }
+                        expected.push(item);
+                        continue;
+                    }
+                    let position = random(expected.len());
+                    let item = expected[position].clone();
+                    match random(3) {
+                        0 => {
+                            cache.get(&item.id);
+                        }
+                        1 => cache.update(TestEntity { value: format!("updated {step}"), ..item.clone() }),
+                        _ => cache.insert(item.clone()),
+                    }
+                    if policy == EvictionPolicy::LRU {
+                        expected.remove(position);
+                        expected.push(item);
+                    }
+                }
+                let expected: Vec<Uuid> = expected.iter().map(|item| item.id).collect();
+                assert_eq!(drain_in_eviction_order(&mut cache), expected, "{policy:?} with seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_config_builder_validates() {
+        let built = CacheConfig::builder()
+            .with_cache_size(10)
+            .with_ttl(Duration::from_secs(300))
+            .build()
+            .unwrap();
+        assert_eq!(built, CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(300)));
+
+        let invalid = |builder: CacheConfigBuilder| matches!(builder.build(), Err(CacheError::InvalidArgument(_)));
+        assert!(invalid(CacheConfig::builder()));
+        assert!(invalid(CacheConfig::builder().with_cache_size(10).with_ttl(Duration::ZERO)));
+        assert!(invalid(CacheConfig::builder().with_cache_size(10).with_fifo_options(FifoOptions::default())));
+        assert!(invalid(
+            CacheConfig::builder()
+                .with_cache_size(10)
+                .with_eviction_policy(EvictionPolicy::FIFO)
+                .with_lru_options(LruOptions::default())
+        ));
+
+        // Options loaded from a file are checked by validate
+        let config: CacheConfig = serde_json::from_str(
+            r#"{ "cache_size": 10, "eviction_policy": "fifo", "fifo": { "requeue_on_update": true } }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.fifo, Some(FifoOptions { requeue_on_update: true }));
+        let config: CacheConfig =
+            serde_json::from_str(r#"{ "cache_size": 10, "eviction_policy": "fifo", "lru": {} }"#).unwrap();
+        assert_eq!(config.lru, Some(LruOptions { touch_on_update: true }));
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_cache_stores_nothing() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::disabled())));
+        let switzerland = Country { code: "CH".to_string(), name: "Switzerland".to_string() };
+        {
+            let mut cache = shared.write();
+            assert!(cache.is_disabled());
+            cache.insert(switzerland.clone());
+            cache.update(switzerland.clone());
+            cache.insert_with_ttl(switzerland.clone(), Duration::from_secs(60));
+            assert!(cache.is_empty());
+            assert!(cache.get(&"CH".to_string()).is_none());
+            assert_eq!((cache.statistics().hits(), cache.statistics().misses()), (0, 1));
+            assert_eq!(cache.statistics().evictions(), 0);
+            assert!(cache.next_expiry().is_none());
+        }
+
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone());
+        let notification: CacheNotification = serde_json::from_str(
+            r#"{ "table": "countries", "action": "insert", "id": "CH", "data": { "code": "CH", "name": "Switzerland" } }"#,
+        )
+        .unwrap();
+        handler.handle_notification(notification).await;
+        assert!(shared.read().is_empty());
+    }
+
+    #[test]
+    fn test_statistics_snapshot_is_labelled_with_the_name() {
+        let mut cache = MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU)).with_name("entities");
+        let entity = TestEntity { id: Uuid::new_v4(), value: "test".to_string() };
+        cache.insert(entity.clone());
+        cache.get(&entity.id);
+        cache.get(&Uuid::new_v4());
+
+        assert_eq!(
+            cache.statistics_snapshot(),
+            StatisticsSnapshot {
+                name: Some("entities".to_string()),
+                entries: 1,
+                hits: 1,
+                misses: 1,
+                evictions: 0,
+                invalidations: 0,
+            }
+        );
+        assert_eq!(cache.statistics_snapshot().hit_rate(), 0.5);
+        assert_eq!(MainModelCache::<TestEntity>::new(CacheConfig::new(1, EvictionPolicy::LRU)).name(), None);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_sample_leaves_order_and_statistics_alone() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut cache = MainModelCache::new(CacheConfig::new(100, EvictionPolicy::LRU));
+        for i in 0..50 {
+            cache.insert(TestEntity { id: Uuid::new_v4(), value: i.to_string() });
+        }
+        let coldest = cache.cold_entries(1)[0].0;
+
+        let sample = cache.sample(5, &mut StdRng::seed_from_u64(3));
+        assert_eq!(sample.len(), 5);
+        let ids: HashSet<Uuid> = sample.iter().map(|item| item.id).collect();
+        assert_eq!(ids.len(), 5);
+        assert_eq!(cache.sample_keys(5, &mut StdRng::seed_from_u64(3)), sample.iter().map(|item| item.id).collect::<Vec<_>>());
+        assert_eq!(cache.sample(60, &mut StdRng::seed_from_u64(3)).len(), 50);
+
+        assert_eq!((cache.statistics().hits(), cache.statistics().misses()), (0, 0));
+        assert_eq!(cache.cold_entries(1)[0].0, coldest);
+    }
+
+    #[test]
+    fn test_dump_json_lists_most_recently_used_first() {
+        let mut cache = MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU)).with_name("entities");
+        let first = TestEntity { id: Uuid::new_v4(), value: "first".to_string() };
+        let second = TestEntity { id: Uuid::new_v4(), value: "second".to_string() };
+        cache.insert(first.clone());
+        cache.insert(second.clone());
+        cache.get(&first.id);
+        let hits = cache.statistics().hits();
+
+        let dump = cache.dump_json_limited(1);
+        assert_eq!(dump["name"], "entities");
+        assert_eq!(dump["entries"], 2);
+        assert_eq!(dump["truncated"], true);
+        assert_eq!(dump["items"].as_array().unwrap().len(), 1);
+        assert_eq!(dump["items"][0]["key"], serde_json::json!(first.id));
+        assert_eq!(dump["items"][0]["item"]["value"], "first");
+        assert!(dump["items"][0]["inserted_at"].is_string());
+        assert!(dump["items"][0]["last_accessed"].is_string());
+        assert_eq!(cache.dump_json()["truncated"], false);
+
+        let entry = cache.dump_entry_json(&second.id).unwrap();
+        assert_eq!(entry["item"]["value"], "second");
+        assert!(entry["expires_at"].is_null());
+        assert!(cache.dump_entry_json(&Uuid::new_v4()).is_none());
+
+        // Dumping is not an access
+        assert_eq!(cache.statistics().hits(), hits);
+        assert_eq!(cache.hot_entries(1)[0].0, first.id);
+    }
+
     #[test]
     fn test_statistics() {
         let config = CacheConfig::new(10, EvictionPolicy::LRU);
@@ -571,76 +1882,1018 @@ mod tests {
 
         assert_eq!(cache.statistics().hit_rate(), 0.5);
     }
+
+    #[derive(Debug, Clone)]
+    struct SoftDeletedEntity {
+        id: Uuid,
+        deleted: bool,
+    }
+
+    impl HasPrimaryKey for SoftDeletedEntity {
+        fn primary_key(&self) -> Uuid {
+            self.id
+        }
+    }
+
+    impl IsDeleted for SoftDeletedEntity {
+        fn is_deleted(&self) -> bool {
+            self.deleted
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct ValidEntity {
+        id: Uuid,
+        valid_to: Option<DateTime<Utc>>,
+    }
+
+    impl HasPrimaryKey for ValidEntity {
+        fn primary_key(&self) -> Uuid {
+            self.id
+        }
+    }
+
+    impl ValidFrom for ValidEntity {
+        fn valid_from(&self) -> Option<DateTime<Utc>> {
+            None
+        }
+    }
+
+    impl ValidTo for ValidEntity {
+        fn valid_to(&self) -> Option<DateTime<Utc>> {
+            self.valid_to
+        }
+    }
+
+    #[test]
+    fn test_statistics_count_misses_and_evictions_by_cause() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(60));
+        let mut cache = MainModelCache::with_validity(config);
+        let ended = ValidEntity { id: Uuid::new_v4(), valid_to: Some(Utc::now() - chrono::Duration::seconds(1)) };
+        let short_lived = ValidEntity { id: Uuid::new_v4(), valid_to: None };
+        cache.insert(ended.clone());
+        cache.insert_with_ttl(short_lived.clone(), Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert!(cache.get_with_validity_check(&ended.id).is_none());
+        assert!(cache.get(&short_lived.id).is_none());
+        assert!(cache.get(&Uuid::new_v4()).is_none());
+        let statistics = cache.statistics();
+        assert_eq!(statistics.validity_misses(), 1);
+        assert_eq!(statistics.ttl_expired_misses(), 1);
+        assert_eq!(statistics.absent_misses(), 1);
+        assert_eq!(statistics.misses(), 3);
+
+        cache.insert(ended.clone());
+        cache.insert_with_ttl(short_lived.clone(), Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(cache.evict_invalid().len(), 2);
+        let statistics = cache.statistics();
+        assert_eq!(statistics.validity_evictions(), 1);
+        assert_eq!(statistics.ttl_expired_evictions(), 1);
+        assert_eq!(statistics.evictions(), 2);
+    }
+
+    #[test]
+    fn test_get_valid_at_keeps_items_not_valid_then() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let mut cache = MainModelCache::new(config);
+        let now = Utc::now();
+        let ended = ValidEntity { id: Uuid::new_v4(), valid_to: Some(now - chrono::Duration::hours(1)) };
+        let current = ValidEntity { id: Uuid::new_v4(), valid_to: Some(now + chrono::Duration::hours(1)) };
+        cache.insert(ended.clone());
+        cache.insert(current.clone());
+
+        assert!(cache.is_valid_at(&ended, now - chrono::Duration::hours(2)));
+        assert!(!cache.is_valid_at(&current, now + chrono::Duration::hours(2)));
+        assert_eq!(cache.get_valid_at(&ended.id, now - chrono::Duration::hours(2)).map(|item| item.id), Some(ended.id));
+
+        // Not valid at the queried time, but still cached for now-based reads
+        assert!(cache.get_valid_at(&current.id, now + chrono::Duration::hours(2)).is_none());
+        assert_eq!(cache.statistics().validity_misses(), 1);
+        assert_eq!(cache.get_with_validity_check(&current.id).map(|item| item.id), Some(current.id));
+
+        // The now-based read still evicts what is no longer valid
+        assert!(cache.get_with_validity_check(&ended.id).is_none());
+        assert!(!cache.contains(&ended.id));
+    }
+
+    #[test]
+    fn test_next_expiry_from_ttl() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(60));
+        let mut cache = MainModelCache::new(config);
+        assert!(cache.next_expiry().is_none());
+
+        let before = Utc::now();
+        cache.insert(TestEntity { id: Uuid::new_v4(), value: "test".to_string() });
+
+        let next_expiry = cache.next_expiry().unwrap();
+        assert!(next_expiry >= before + chrono::Duration::seconds(60));
+        assert!(next_expiry <= Utc::now() + chrono::Duration::seconds(60));
+    }
+
+    #[test]
+    fn test_next_expiry_follows_valid_to() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let mut cache = MainModelCache::with_validity(config);
+
+        let now = Utc::now();
+        let soon = ValidEntity { id: Uuid::new_v4(), valid_to: Some(now + chrono::Duration::hours(1)) };
+        let later = ValidEntity { id: Uuid::new_v4(), valid_to: Some(now + chrono::Duration::hours(2)) };
+        let forever = ValidEntity { id: Uuid::new_v4(), valid_to: None };
+        cache.insert(later.clone());
+        cache.insert(soon.clone());
+        cache.insert(forever);
+        assert_eq!(cache.next_expiry(), soon.valid_to);
+
+        // Extending the validity reschedules the entry
+        let extended = ValidEntity { valid_to: Some(now + chrono::Duration::hours(3)), ..soon.clone() };
+        cache.update(extended.clone());
+        assert_eq!(cache.next_expiry(), later.valid_to);
+
+        cache.remove(&later.id);
+        assert_eq!(cache.next_expiry(), extended.valid_to);
+
+        cache.clear();
+        assert!(cache.next_expiry().is_none());
+    }
+
+    #[test]
+    fn test_next_expiry_ignores_valid_to_without_validity() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let mut cache = MainModelCache::new(config);
+
+        cache.insert(ValidEntity { id: Uuid::new_v4(), valid_to: Some(Utc::now()) });
+        assert!(cache.next_expiry().is_none());
+    }
+
+    #[test]
+    fn test_evict_due_visits_due_entries() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(3600));
+        let mut cache = MainModelCache::with_validity(config);
+
+        let now = Utc::now();
+        let short = ValidEntity { id: Uuid::new_v4(), valid_to: None };
+        let ending = ValidEntity { id: Uuid::new_v4(), valid_to: Some(now + chrono::Duration::minutes(5)) };
+        let long = ValidEntity { id: Uuid::new_v4(), valid_to: None };
+        cache.insert_with_ttl(short.clone(), Duration::from_secs(60));
+        cache.insert(ending.clone());
+        cache.insert(long.clone());
+
+        // The per-entry TTL survives an update
+        cache.update(short.clone());
+
+        assert!(cache.evict_due(now).is_empty());
+        assert_eq!(cache.evict_due(now + chrono::Duration::minutes(2)), vec![short.id]);
+        assert!(!cache.contains(&short.id));
+        assert_eq!(cache.evict_due(now + chrono::Duration::minutes(10)), vec![ending.id]);
+        assert!(!cache.contains(&ending.id));
+        assert!(cache.contains(&long.id));
+        assert_eq!(cache.statistics().evictions(), 2);
+
+        // Removed entries are not evicted again when their expiry comes up
+        cache.remove(&long.id);
+        assert!(cache.evict_due(now + chrono::Duration::hours(2)).is_empty());
+    }
+
+    #[test]
+    fn test_generation_counts_changes() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(60));
+        let mut cache = MainModelCache::new(config);
+        assert_eq!(cache.generation(), 0);
+
+        let first = entity("first");
+        cache.insert(first.clone());
+        cache.insert(entity("second"));
+        cache.update(first.clone());
+        assert_eq!(cache.generation(), 3);
+
+        // Reads and removing a missing key change nothing
+        cache.get(&first.id);
+        cache.remove(&Uuid::new_v4());
+        assert_eq!(cache.generation(), 3);
+
+        // Evicting both due entries counts once
+        assert_eq!(cache.evict_due(Utc::now() + chrono::Duration::minutes(2)).len(), 2);
+        assert_eq!(cache.generation(), 4);
+        cache.clear();
+        assert_eq!(cache.generation(), 5);
+    }
+
+    #[test]
+    fn test_config_from_serde() {
+        let config: CacheConfig =
+            serde_json::from_str(r#"{ "cache_size": 100, "eviction_policy": "fifo", "ttl": "1h30m" }"#).unwrap();
+        assert_eq!(config.cache_size, 100);
+        assert_eq!(config.eviction_policy, EvictionPolicy::FIFO);
+        assert_eq!(config.ttl, Some(Duration::from_secs(5400)));
+        assert!(config.validate().is_ok());
+
+        let config: CacheConfig = serde_json::from_str(r#"{ "cache_size": 0, "eviction_policy": "LRU" }"#).unwrap();
+        assert_eq!(config.eviction_policy, EvictionPolicy::LRU);
+        assert!(config.ttl.is_none());
+        assert!(config.is_disabled());
+        assert!(config.validate().is_ok());
+
+        let config: CacheConfig =
+            serde_json::from_str(r#"{ "cache_size": 10, "eviction_policy": "lru", "ttl": "0s" }"#).unwrap();
+        assert!(matches!(config.validate(), Err(CacheError::InvalidArgument(_))));
+
+        let round_trip = CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(300));
+        let json = serde_json::to_value(&round_trip).unwrap();
+        assert_eq!(json, serde_json::json!({ "cache_size": 10, "eviction_policy": "lru", "ttl": "5m" }));
+        let parsed: CacheConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.ttl, round_trip.ttl);
+    }
+
+    #[test]
+    fn test_ttl_format() {
+        assert_eq!(ttl_format::parse("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(ttl_format::parse("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(ttl_format::parse("1d2h"), Ok(Duration::from_secs(93_600)));
+        for invalid in ["", "30", "s", "5 m", "5w", "-1s"] {
+            assert!(ttl_format::parse(invalid).is_err(), "{invalid}");
+        }
+
+        assert_eq!(ttl_format::format(Duration::from_secs(90)), "90s");
+        assert_eq!(ttl_format::format(Duration::from_secs(7200)), "2h");
+        assert_eq!(ttl_format::format(Duration::from_millis(1500)), "1500ms");
+        assert_eq!(ttl_format::format(Duration::ZERO), "0s");
+    }
+
+    #[test]
+    fn test_entry_info_does_not_count_as_access() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU).with_ttl(Duration::from_secs(60));
+        let mut cache = MainModelCache::with_validity(config);
+
+        let valid_to = Utc::now() + chrono::Duration::seconds(30);
+        let first = ValidEntity { id: Uuid::new_v4(), valid_to: Some(valid_to) };
+        let second = ValidEntity { id: Uuid::new_v4(), valid_to: None };
+        let third = ValidEntity { id: Uuid::new_v4(), valid_to: None };
+        cache.insert(first.clone());
+        cache.insert(second.clone());
+        cache.insert(third.clone());
+        cache.get(&second.id);
+
+        let info = cache.entry_info(&first.id).unwrap();
+        assert_eq!(info.valid_to, Some(valid_to));
+        assert_eq!(info.expires_at, Some(valid_to));
+        assert!(info.remaining_ttl.unwrap() <= Duration::from_secs(60));
+        assert!(info.age <= Duration::from_secs(1));
+        assert!(info.last_accessed >= info.inserted_at);
+        assert!(cache.entry_info(&Uuid::new_v4()).is_none());
+
+        // Looking at entries leaves the order and statistics alone
+        let keys = |entries: Vec<(Uuid, EntryInfo)>| entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(keys(cache.cold_entries(2)), vec![first.id, third.id]);
+        assert_eq!(keys(cache.hot_entries(2)), vec![second.id, third.id]);
+        assert_eq!(keys(cache.cold_entries(10)).len(), 3);
+        assert_eq!((cache.statistics().hits(), cache.statistics().misses()), (1, 0));
+    }
+
+    #[test]
+    fn test_pressure_and_high_watermark() {
+        let crossings = Arc::new(AtomicU64::new(0));
+        let hook = {
+            let crossings = crossings.clone();
+            Arc::new(move |pressure: &CachePressure| {
+                assert!(pressure.fill_ratio >= 0.75);
+                crossings.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+        let mut cache = MainModelCache::new(CacheConfig::new(4, EvictionPolicy::LRU)).on_high_watermark(0.75, hook);
+        let entities: Vec<TestEntity> =
+            (0..5).map(|i| TestEntity { id: Uuid::new_v4(), value: i.to_string() }).collect();
+
+        for entity in &entities[..3] {
+            cache.insert(entity.clone());
+        }
+        cache.get(&entities[0].id);
+        assert_eq!(cache.pressure(), CachePressure { len: 3, capacity: 4, fill_ratio: 0.75 });
+        assert_eq!(cache.peek_eviction_candidate(), Some(entities[1].id));
+        assert_eq!(crossings.load(Ordering::Relaxed), 1);
+
+        // Staying above the threshold, evictions included, does not fire again
+        cache.insert(entities[3].clone());
+        cache.insert(entities[4].clone());
+        assert!(!cache.contains(&entities[1].id));
+        assert_eq!(crossings.load(Ordering::Relaxed), 1);
+
+        // Dropping below re-arms the hook
+        cache.remove(&entities[0].id);
+        cache.remove(&entities[2].id);
+        cache.insert(entities[0].clone());
+        assert_eq!(crossings.load(Ordering::Relaxed), 2);
+        assert_eq!(MainModelCache::<TestEntity>::new(CacheConfig::disabled()).pressure().fill_ratio, 1.0);
+    }
+
+    #[test]
+    fn test_top_accessed_counts_hits_and_decays() {
+        let mut cache = MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU)).with_access_decay(8);
+        let entities: Vec<TestEntity> =
+            (0..3).map(|i| TestEntity { id: Uuid::new_v4(), value: i.to_string() }).collect();
+        for entity in &entities {
+            cache.insert(entity.clone());
+        }
+        for _ in 0..4 {
+            cache.get(&entities[1].id);
+        }
+        cache.get(&entities[2].id);
+        cache.update(entities[0].clone());
+
+        // Updates are no hits, and listing the counts does not touch the LRU order
+        assert_eq!(cache.top_accessed(2), vec![(entities[1].id, 4), (entities[2].id, 1)]);
+        assert_eq!(cache.entry_info(&entities[0].id).unwrap().access_count, 0);
+        assert_eq!(cache.cold_entries(1)[0].0, entities[1].id);
+
+        // The eighth hit halves every count
+        for _ in 0..3 {
+            cache.get(&entities[2].id);
+        }
+        assert_eq!(cache.entry_info(&entities[1].id).unwrap().access_count, 2);
+        assert_eq!(cache.entry_info(&entities[2].id).unwrap().access_count, 2);
+        cache.decay_access_counts();
+        assert_eq!(cache.entry_info(&entities[1].id).unwrap().access_count, 1);
+    }
+
+    #[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+    struct Country {
+        code: String,
+        name: String,
+    }
+
+    impl CacheKey<String> for Country {
+        fn cache_key(&self) -> String {
+            self.code.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_string_keyed_cache() {
+        use crate::transaction_aware_main_model_cache::TransactionAwareMainModelCache;
+        use postgres_unit_of_work::TransactionAware;
+
+        let config = CacheConfig::new(2, EvictionPolicy::FIFO).with_ttl(Duration::from_secs(60));
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(config)));
+        shared.write().insert(Country { code: "CH".to_string(), name: "Switzerland".to_string() });
+        assert_eq!(shared.write().get(&"CH".to_string()).unwrap().name, "Switzerland");
+
+        let tx_cache: TransactionAwareMainModelCache<Country, MainModelCache<Country, String>, String> =
+            TransactionAwareMainModelCache::new(shared.clone());
+        tx_cache.insert(Country { code: "DE".to_string(), name: "Germany".to_string() });
+        tx_cache.remove(&"CH".to_string());
+        assert!(tx_cache.contains(&"DE".to_string()));
+        tx_cache.on_commit().await.unwrap();
+        assert!(!shared.read().contains(&"CH".to_string()));
+
+        // Inserting a third country evicts the oldest by insertion
+        shared.write().insert(Country { code: "FR".to_string(), name: "France".to_string() });
+        shared.write().insert(Country { code: "IT".to_string(), name: "Italy".to_string() });
+        assert!(!shared.read().contains(&"DE".to_string()));
+        assert!(shared.read().next_expiry().is_some());
+        assert_eq!(shared.write().evict_due(Utc::now() + chrono::Duration::minutes(2)).len(), 2);
+        assert!(shared.read().is_empty());
+
+        // The handler parses the notification id into the key type
+        shared.write().insert(Country { code: "AT".to_string(), name: "Austria".to_string() });
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone());
+        let notification: CacheNotification =
+            serde_json::from_str(r#"{ "table": "countries", "action": "delete", "id": "AT" }"#).unwrap();
+        handler.handle_notification(notification).await;
+        assert!(!shared.read().contains(&"AT".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_flush_clears_cache_and_optionally_statistics() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        let flush: CacheNotification =
+            serde_json::from_str(r#"{ "table": "countries", "action": "flush" }"#).unwrap();
+
+        shared.write().insert(Country { code: "CH".to_string(), name: "Switzerland".to_string() });
+        shared.write().get(&"CH".to_string());
+        MainModelCacheHandler::new("countries".to_string(), shared.clone()).handle_notification(flush.clone()).await;
+        assert!(shared.read().is_empty());
+        assert_eq!(shared.read().statistics().hits(), 1);
+
+        shared.write().insert(Country { code: "DE".to_string(), name: "Germany".to_string() });
+        MainModelCacheHandler::new("countries".to_string(), shared.clone())
+            .reset_statistics_on_flush()
+            .handle_notification(flush)
+            .await;
+        assert!(shared.read().is_empty());
+        assert_eq!(shared.read().statistics().hits(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_handler_migrates_legacy_payloads() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone()).with_payload_migrator(
+            Arc::new(|mut data: serde_json::Value| -> Result<serde_json::Value, String> {
+                let title = data.get_mut("title").map(serde_json::Value::take).ok_or("no title")?;
+                data["name"] = title;
+                Ok(data)
+            }),
+        );
+
+        let insert = |data: &str| -> CacheNotification {
+            serde_json::from_str(&format!(r#"{{ "table": "countries", "action": "insert", "id": "AT", "data": {data} }}"#))
+                .unwrap()
+        };
+        handler.handle_notification(insert(r#"{ "code": "AT", "title": "Austria" }"#)).await;
+        assert_eq!(shared.write().get(&"AT".to_string()).unwrap().name, "Austria");
+
+        // Without the legacy field the migrator fails and the cache keeps the item
+        handler.handle_notification(insert(r#"{ "code": "AT", "name": "Österreich" }"#)).await;
+        assert_eq!(shared.write().get(&"AT".to_string()).unwrap().name, "Austria");
+    }
+
+    #[tokio::test]
+    async fn test_handler_over_tokio_lock() {
+        let shared = Arc::new(tokio::sync::RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        shared.write().await.insert(Country { code: "AT".to_string(), name: "Austria".to_string() });
+
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone());
+        let notification: CacheNotification =
+            serde_json::from_str(r#"{ "table": "countries", "action": "delete", "id": "AT" }"#).unwrap();
+        handler.handle_notification(notification).await;
+        assert!(shared.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_wakes_on_insert_and_times_out() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        let writer = shared.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            writer.write().insert(Country { code: "FR".to_string(), name: "France".to_string() });
+        });
+
+        assert!(MainModelCache::wait_for(&*shared, "FR".to_string(), Duration::from_secs(5)).await);
+        assert!(!MainModelCache::wait_for(&*shared, "IT".to_string(), Duration::from_millis(20)).await);
+
+        let writer = shared.clone();
+        tokio::spawn(async move {
+            writer.write().remove(&"FR".to_string());
+        });
+        assert!(MainModelCache::wait_until(&*shared, Duration::from_secs(5), |cache| cache.is_empty()).await);
+    }
+
+    #[tokio::test]
+    async fn test_handler_skips_unchanged_updates() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        shared.write().insert(Country { code: "CH".to_string(), name: "Switzerland".to_string() });
+        let update = |name: &str| {
+            serde_json::from_value::<CacheNotification>(serde_json::json!({
+                "table": "countries", "action": "update", "id": "CH", "data": { "code": "CH", "name": name }
+            }))
+            .unwrap()
+        };
+
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone()).skip_unchanged();
+        handler.handle_notification(update("Switzerland")).await;
+        assert_eq!(handler.unchanged_updates(), 1);
+
+        handler.handle_notification(update("Schweiz")).await;
+        assert_eq!(handler.unchanged_updates(), 1);
+        assert_eq!(shared.read().peek(&"CH".to_string()).unwrap().name, "Schweiz");
+    }
+
+    #[tokio::test]
+    async fn test_handler_applies_notification_batch_as_one_generation() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        shared.write().insert(Country { code: "CH".to_string(), name: "Switzerland".to_string() });
+        shared.write().insert(Country { code: "AT".to_string(), name: "Austria".to_string() });
+        let notification = |action: &str, code: &str, name: &str| {
+            serde_json::from_value::<CacheNotification>(serde_json::json!({
+                "table": "countries", "action": action, "id": code, "data": { "code": code, "name": name }
+            }))
+            .unwrap()
+        };
+        let generation = shared.read().generation();
+
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone()).skip_unchanged();
+        handler
+            .handle_notifications(vec![
+                notification("update", "CH", "Schweiz"),
+                // Equal to the cached item, but not to the one the batch left
+                notification("update", "CH", "Switzerland"),
+                notification("update", "AT", "Austria"),
+                notification("delete", "AT", "Austria"),
+                notification("insert", "LI", "Liechtenstein"),
+            ])
+            .await;
+
+        let cache = shared.read();
+        assert_eq!(cache.generation(), generation + 1);
+        assert_eq!(handler.unchanged_updates(), 1);
+        assert_eq!(cache.peek(&"CH".to_string()).unwrap().name, "Switzerland");
+        assert!(!cache.contains(&"AT".to_string()));
+        assert!(cache.contains(&"LI".to_string()));
+        assert_eq!(handler.last_applied().unwrap().key.as_deref(), Some("LI"));
+    }
+
+    #[tokio::test]
+    async fn test_handler_evicts_items_notified_without_data() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        shared.write().insert(Country { code: "AT".to_string(), name: "Austria".to_string() });
+
+        // E.g. sent by a trigger whose payload was too large for the row data
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone());
+        let notification: CacheNotification =
+            serde_json::from_str(r#"{ "table": "countries", "action": "update", "id": "AT" }"#).unwrap();
+        handler.handle_notification(notification).await;
+        assert!(!shared.read().contains(&"AT".to_string()));
+        assert_eq!(handler.last_applied().unwrap().key.as_deref(), Some("AT"));
+    }
+
+    #[tokio::test]
+    async fn test_handler_on_deser_error() {
+        let shared = Arc::new(RwLock::new(MainModelCache::<Country, String>::keyed(CacheConfig::new(
+            10,
+            EvictionPolicy::LRU,
+        ))));
+        let austria = Country { code: "AT".to_string(), name: "Austria".to_string() };
+        let malformed = || {
+            serde_json::from_value::<CacheNotification>(serde_json::json!({
+                "table": "countries", "action": "update", "id": "AT", "data": { "code": 43 }
+            }))
+            .unwrap()
+        };
+
+        // Kept by default, but counted
+        shared.write().insert(austria.clone());
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone());
+        handler.handle_notification(malformed()).await;
+        assert_eq!(handler.deserialization_failures(), 1);
+        assert!(shared.read().contains(&"AT".to_string()));
+
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone()).on_deser_error(OnDeserError::Invalidate);
+        handler.handle_notification(malformed()).await;
+        assert_eq!(handler.deserialization_failures(), 1);
+        assert!(!shared.read().contains(&"AT".to_string()));
+
+        // Refetched through the loader, which is handed the parsed key
+        let loader: Arc<ItemLoader<Country, String>> = Arc::new(|code: String| -> futures::future::BoxFuture<'static, Option<Country>> {
+            Box::pin(async move { Some(Country { code, name: "Österreich".to_string() }) })
+        });
+        let handler = MainModelCacheHandler::new("countries".to_string(), shared.clone())
+            .on_deser_error(OnDeserError::Refetch)
+            .with_loader(loader);
+        handler.handle_notification(malformed()).await;
+        assert_eq!(shared.read().peek(&"AT".to_string()).unwrap().name, "Österreich");
+    }
+
+    #[test]
+    fn test_evict_deleted() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let mut cache = MainModelCache::new(config);
+
+        let live = SoftDeletedEntity { id: Uuid::new_v4(), deleted: false };
+        let deleted = SoftDeletedEntity { id: Uuid::new_v4(), deleted: true };
+        cache.insert(live.clone());
+        cache.insert(deleted.clone());
+
+        assert_eq!(cache.evict_deleted(), vec![deleted.id]);
+        assert!(cache.contains(&live.id));
+        assert!(!cache.contains(&deleted.id));
+        assert_eq!(cache.statistics().evictions(), 1);
+    }
 }
 
 /// A notification handler for MainModelCache
-pub struct MainModelCacheHandler<T: HasPrimaryKey + Clone + Send + Sync + 'static> {
+///
+/// The cache can be any `ModelCacheBackend`; it defaults to `MainModelCache`.
+/// Log lines carry the handler's name in a `cache` field, the table name
+/// unless set with `with_name`.
+/// The id of a delete notification is parsed into the key type `K` with `FromStr`.
+/// The lock `L` is any [`CacheLock`], a `parking_lot::RwLock` by default.
+pub struct MainModelCacheHandler<T, B = MainModelCache<T>, K = Uuid, L = RwLock<B>>
+where
+    T: CacheKey<K> + Clone + Send + Sync + 'static,
+    B: ModelCacheBackend<T, K>,
+    L: CacheLock<B>,
+{
     table_name: String,
-    cache: Arc<RwLock<MainModelCache<T>>>,
+    name: String,
+    cache: Arc<L>,
+    version_of: Option<VersionOf<T>>,
+    is_deleted: Option<fn(&T) -> bool>,
+    reset_statistics_on_flush: bool,
+    is_unchanged: Option<fn(&T, &T) -> bool>,
+    unchanged_updates: AtomicU64,
+    last_applied: LastApplied,
+    expected_fields: Option<Vec<String>>,
+    payload_migrator: Option<Arc<PayloadMigrator>>,
+    on_deser_error: OnDeserError,
+    loader: Option<Arc<ItemLoader<T, K>>>,
+    deserialization_failures: AtomicU64,
+    key: PhantomData<fn() -> K>,
+    backend: PhantomData<fn() -> B>,
 }
 
-impl<T: HasPrimaryKey + Clone + Send + Sync + 'static> MainModelCacheHandler<T> {
+impl<T, B, K, L> MainModelCacheHandler<T, B, K, L>
+where
+    T: CacheKey<K> + Clone + Send + Sync + 'static,
+    B: ModelCacheBackend<T, K>,
+    L: CacheLock<B>,
+{
     /// Create a new handler for the given cache
-    pub fn new(table_name: String, cache: Arc<RwLock<MainModelCache<T>>>) -> Self {
-        Self { table_name, cache }
+    pub fn new(table_name: String, cache: Arc<L>) -> Self {
+        Self {
+            name: table_name.clone(),
+            table_name,
+            cache,
+            version_of: None,
+            is_deleted: None,
+            reset_statistics_on_flush: false,
+            is_unchanged: None,
+            unchanged_updates: AtomicU64::new(0),
+            last_applied: LastApplied::default(),
+            expected_fields: None,
+            payload_migrator: None,
+            on_deser_error: OnDeserError::default(),
+            loader: None,
+            deserialization_failures: AtomicU64::new(0),
+            key: PhantomData,
+            backend: PhantomData,
+        }
+    }
+
+    /// Rewrite notification data before it is deserialized
+    ///
+    /// Migrator failures are logged as `CacheError::PayloadMigrationFailed`,
+    /// apart from deserialization failures.
+    pub fn with_payload_migrator(mut self, migrator: Arc<PayloadMigrator>) -> Self {
+        self.payload_migrator = Some(migrator);
+        self
+    }
+
+    /// Declare the fields the handler reads from notification data
+    ///
+    /// By default they are derived from the cached type's `Deserialize` impl.
+    pub fn with_expected_fields(mut self, fields: &[&str]) -> Self {
+        self.expected_fields = Some(fields.iter().map(|field| field.to_string()).collect());
+        self
+    }
+
+    /// Treat inserts and updates of soft-deleted items as removals
+    pub fn remove_deleted(mut self) -> Self
+    where
+        T: IsDeleted,
+    {
+        self.is_deleted = Some(<T as IsDeleted>::is_deleted);
+        self
+    }
+
+    /// Ignore inserts and updates older than the cached version of the item
+    pub fn skip_stale_versions(mut self) -> Self
+    where
+        T: Versioned,
+    {
+        self.version_of = Some(version_of::<T>());
+        self
+    }
+
+    /// Choose what happens to the cached item when notification data fails to decode
+    pub fn on_deser_error(mut self, policy: OnDeserError) -> Self {
+        self.on_deser_error = policy;
+        self
+    }
+
+    /// Reload items with `loader` under `OnDeserError::Refetch`
+    pub fn with_loader(mut self, loader: Arc<ItemLoader<T, K>>) -> Self {
+        self.loader = Some(loader);
+        self
+    }
+
+    /// Notifications whose data failed to decode
+    pub fn deserialization_failures(&self) -> u64 {
+        self.deserialization_failures.load(Ordering::Relaxed)
+    }
+
+    /// Name the cache in log lines instead of the table name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// The name logged in the `cache` field
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reset the cache statistics as well when a flush notification arrives
+    pub fn reset_statistics_on_flush(mut self) -> Self {
+        self.reset_statistics_on_flush = true;
+        self
+    }
+
+    /// Skip updates equal to the cached item without taking the write lock
+    pub fn skip_unchanged(mut self) -> Self
+    where
+        T: PartialEq,
+    {
+        self.is_unchanged = Some(<T as PartialEq>::eq);
+        self
+    }
+
+    /// Updates skipped because they equalled the cached item
+    pub fn unchanged_updates(&self) -> u64 {
+        self.unchanged_updates.load(Ordering::Relaxed)
+    }
+
+    /// Whether an update leaves the cached item as it is, checked under a read lock
+    async fn is_unchanged(&self, item: &T) -> bool {
+        let Some(is_unchanged) = self.is_unchanged else {
+            return false;
+        };
+        let cached = self.cache.read().await.peek(&CacheKey::<K>::cache_key(item));
+        cached.is_some_and(|cached| is_unchanged(item, &cached))
+    }
+
+    /// Create a new handler for the table of the cached type
+    pub fn for_type(cache: Arc<L>) -> Self
+    where
+        T: HasTableName,
+    {
+        Self::new(T::table_name().to_string(), cache)
     }
 }
 
-#[async_trait]
-impl<T: HasPrimaryKey + Clone + Send + Sync + Debug + 'static> CacheNotificationHandler
-    for MainModelCacheHandler<T>
+/// What a notification does to a main model cache, worked out before the write lock is taken
+enum MainChange<T, K> {
+    /// An insert or update, checked for staleness and soft deletes under the lock
+    Upsert { item: T, insert: bool },
+    Remove(K),
+    /// Caches the item reloaded after a decode failure, or invalidates the cached one
+    Reload(K, Option<T>),
+    /// Clears the cache for a truncate or, with `flush`, a flush notification
+    Clear { flush: bool },
+    Nothing,
+}
+
+/// A prepared change with the key as sent, and the action to record as last applied if any
+struct PreparedMainChange<T, K> {
+    change: MainChange<T, K>,
+    id: String,
+    action: Option<String>,
+}
+
+impl<T, B, K, L> MainModelCacheHandler<T, B, K, L>
 where
-    T: for<'de> serde::Deserialize<'de>,
+    T: MainModel<K> + for<'de> serde::Deserialize<'de>,
+    B: ModelCacheBackend<T, K>,
+    K: FromStr + Send + 'static,
+    L: CacheLock<B> + 'static,
 {
-    async fn handle_notification(&self, notification: CacheNotification) {
+    /// The change the `OnDeserError` policy makes to the item a notification failed to decode
+    async fn undecodable_change(&self, id: &str) -> Option<MainChange<T, K>> {
+        self.deserialization_failures.fetch_add(1, Ordering::Relaxed);
+        if self.on_deser_error == OnDeserError::Keep {
+            return None;
+        }
+        let primary_key = id.parse::<K>().ok()?;
+        let reloaded = match (self.on_deser_error, &self.loader) {
+            (OnDeserError::Refetch, Some(loader)) => loader(primary_key).await,
+            _ => None,
+        };
+        Some(MainChange::Reload(id.parse::<K>().ok()?, reloaded))
+    }
+
+    /// Works out what a notification changes, doing all the awaiting before
+    /// any change is applied
+    ///
+    /// Updates equal to the cached item are only skipped with `check_unchanged`.
+    async fn prepare(&self, notification: CacheNotification, check_unchanged: bool) -> Option<PreparedMainChange<T, K>> {
+        let id = notification.raw_key();
         tracing::debug!(
+            cache = %self.name,
             "MainModelCache: Handling notification for table '{}': action={}, id={}",
-            notification.table, notification.action, notification.id
+            notification.table, notification.action, id
         );
 
-        match notification.action.as_str() {
+        let change = match notification.action.as_str() {
             "insert" | "update" => {
-                if let Some(data) = notification.data {
-                    match serde_json::from_value::<T>(data) {
-                        Ok(item) => {
-                            let mut cache = self.cache.write();
-                            if notification.action == "insert" {
-                                cache.insert(item);
-                                tracing::debug!("MainModelCache: Added item {} to cache", notification.id);
-                            } else {
-                                cache.update(item);
-                                tracing::debug!("MainModelCache: Updated item {} in cache", notification.id);
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!(
-                                "MainModelCache: Failed to deserialize data for {}: {}",
-                                notification.table, e
-                            );
-                        }
-                    }
-                } else {
+                let Some(data) = notification.data else {
+                    // E.g. a trigger dropped the data of an oversized row, so the cached item is stale
                     tracing::warn!(
-                        "MainModelCache: No data provided for {} operation on table {}",
-                        notification.action, notification.table
+                        cache = %self.name,
+                        table = %notification.table,
+                        action = %notification.action,
+                        id = %id,
+                        "MainModelCache: evicting item: no data provided"
                     );
+                    let primary_key = id.parse::<K>().ok()?;
+                    return Some(PreparedMainChange {
+                        change: MainChange::Remove(primary_key),
+                        id,
+                        action: Some(notification.action),
+                    });
+                };
+                match decode_data::<T>(self.payload_migrator.as_deref(), &notification.table, data) {
+                    Ok(item) if notification.action == "update" && check_unchanged && self.is_unchanged(&item).await => {
+                        self.unchanged_updates.fetch_add(1, Ordering::Relaxed);
+                        tracing::debug!(cache = %self.name, "MainModelCache: Skipped unchanged item {}", id);
+                        MainChange::Nothing
+                    }
+                    Ok(item) => MainChange::Upsert { item, insert: notification.action == "insert" },
+                    Err(err) => {
+                        tracing::warn!(
+                            cache = %self.name,
+                            table = %notification.table,
+                            action = %notification.action,
+                            id = %id,
+                            error = %err,
+                            policy = ?self.on_deser_error,
+                            "MainModelCache: dropping notification: failed to decode data"
+                        );
+                        let change = self.undecodable_change(&id).await?;
+                        return Some(PreparedMainChange { change, id, action: None });
+                    }
                 }
             }
-            "delete" => {
-                let mut cache = self.cache.write();
-                cache.remove(&notification.id);
-                tracing::debug!("MainModelCache: Removed item {} from cache", notification.id);
-            }
+            "delete" => match id.parse::<K>() {
+                Ok(primary_key) => MainChange::Remove(primary_key),
+                Err(_) => {
+                    tracing::warn!(
+                        cache = %self.name,
+                        table = %notification.table,
+                        id = %id,
+                        "MainModelCache: dropping notification: id is not a valid cache key"
+                    );
+                    return None;
+                }
+            },
+            "truncate" | "flush" => MainChange::Clear { flush: notification.action == "flush" },
             _ => {
                 tracing::warn!(
-                    "MainModelCache: Unknown action '{}' for table '{}'",
-                    notification.action, notification.table
+                    cache = %self.name,
+                    table = %notification.table,
+                    action = %notification.action,
+                    id = %id,
+                    "MainModelCache: dropping notification: unknown action"
                 );
+                return None;
+            }
+        };
+        Some(PreparedMainChange { change, id, action: Some(notification.action) })
+    }
+
+    /// Applies prepared changes in order, under one write lock and as one
+    /// generation of the cache
+    ///
+    /// Without changes, e.g. for skipped unchanged updates, no lock is taken.
+    async fn apply_changes(&self, changes: Vec<PreparedMainChange<T, K>>) {
+        if changes.iter().all(|prepared| matches!(prepared.change, MainChange::Nothing)) {
+            for PreparedMainChange { id, action, .. } in changes {
+                if let Some(action) = action {
+                    self.last_applied.record(&action, Some(id));
+                }
+            }
+            return;
+        }
+        let mut cache = self.cache.write().await;
+        cache.batch(|cache| {
+            for prepared in changes {
+                self.apply_change(cache, prepared);
+            }
+        });
+    }
+
+    /// Applies a prepared change to the locked cache and records it as last applied
+    fn apply_change(&self, cache: &mut B, prepared: PreparedMainChange<T, K>) {
+        let PreparedMainChange { change, id, action } = prepared;
+        let key = match change {
+            MainChange::Upsert { item, insert } => {
+                let primary_key = CacheKey::<K>::cache_key(&item);
+                let cached = cache.peek(&primary_key);
+                if is_stale(self.version_of, &item, cached.as_deref()) {
+                    tracing::debug!(cache = %self.name, "MainModelCache: Skipped stale version of item {}", id);
+                } else if self.is_deleted.is_some_and(|is_deleted| is_deleted(&item)) {
+                    cache.remove(&primary_key);
+                    tracing::debug!(cache = %self.name, "MainModelCache: Removed soft-deleted item {} from cache", id);
+                } else if insert {
+                    cache.insert(Arc::new(item));
+                    tracing::debug!(cache = %self.name, "MainModelCache: Added item {} to cache", id);
+                } else {
+                    cache.update(Arc::new(item));
+                    tracing::debug!(cache = %self.name, "MainModelCache: Updated item {} in cache", id);
+                }
+                Some(id)
+            }
+            MainChange::Remove(primary_key) => {
+                cache.remove(&primary_key);
+                tracing::debug!(cache = %self.name, "MainModelCache: Removed item {} from cache", id);
+                Some(id)
+            }
+            MainChange::Reload(primary_key, reloaded) => {
+                match reloaded {
+                    Some(item) => {
+                        cache.insert(Arc::new(item));
+                        tracing::debug!(cache = %self.name, "MainModelCache: Refetched item {} after a decode failure", id);
+                    }
+                    None => {
+                        if cache.remove(&primary_key).is_some() {
+                            tracing::debug!(cache = %self.name, "MainModelCache: Invalidated item {} after a decode failure", id);
+                        }
+                    }
+                }
+                Some(id)
             }
+            MainChange::Clear { flush } => {
+                cache.clear();
+                if !flush {
+                    tracing::debug!(cache = %self.name, "MainModelCache: Cleared cache for truncated table '{}'", self.table_name);
+                } else {
+                    if self.reset_statistics_on_flush {
+                        if let Some(statistics) = cache.statistics() {
+                            statistics.reset();
+                        }
+                    }
+                    tracing::info!(cache = %self.name, table = %self.table_name, "MainModelCache: flushed cache on request");
+                }
+                None
+            }
+            MainChange::Nothing => Some(id),
+        };
+        if let Some(action) = action {
+            self.last_applied.record(&action, key);
         }
     }
+}
+
+#[async_trait]
+impl<T, B, K, L> CacheNotificationHandler for MainModelCacheHandler<T, B, K, L>
+where
+    T: MainModel<K> + for<'de> serde::Deserialize<'de>,
+    B: ModelCacheBackend<T, K>,
+    K: FromStr + Send + 'static,
+    L: CacheLock<B> + 'static,
+{
+    async fn handle_notification(&self, notification: CacheNotification) {
+        // A disabled cache holds nothing to change, so skip parsing the data
+        if self.cache.read().await.capacity() == Some(0) {
+            return;
+        }
+        let prepared = self.prepare(notification, true).await;
+        self.apply_changes(prepared.into_iter().collect()).await;
+    }
+
+    /// Decodes all notifications first, then applies them under one write lock
+    async fn handle_notifications(&self, notifications: Vec<CacheNotification>) {
+        if self.cache.read().await.capacity() == Some(0) {
+            return;
+        }
+        let mut prepared = Vec::with_capacity(notifications.len());
+        let mut changed = HashSet::new();
+        let mut cleared = false;
+        for notification in notifications {
+            // The cached item is only compared before an earlier notification changed it
+            let check_unchanged = !cleared && changed.insert(notification.raw_key());
+            cleared |= matches!(notification.action.as_str(), "truncate" | "flush");
+            prepared.extend(self.prepare(notification, check_unchanged).await);
+        }
+        self.apply_changes(prepared).await;
+    }
 
     fn table_name(&self) -> &str {
         &self.table_name
     }
+
+    fn last_applied(&self) -> Option<AppliedInfo> {
+        self.last_applied.get()
+    }
+
+    fn mark_loaded(&self) {
+        self.last_applied.record_load();
+    }
+
+    async fn mark_ready(&self) {
+        self.cache.write().await.mark_ready();
+    }
+
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        self.expected_fields.clone().or_else(serde_fields::<T>)
+    }
 }
\ No newline at end of file
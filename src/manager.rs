@@ -0,0 +1,212 @@
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use std::any::Any;
+use std::sync::Arc;
+
+use crate::error::{CacheError, CacheResult};
+use crate::index_cache::IdxModelCache;
+use crate::listener::{CacheNotificationHandler, CacheNotificationListener, IndexCacheHandler};
+use crate::main_model_cache::{MainModelCache, MainModelCacheHandler};
+use crate::traits::HasTableName;
+use crate::transaction_aware_index_cache::IdxModel;
+use crate::transaction_aware_main_model_cache::MainModel;
+
+/// Hit, miss and size counters summed over every cache of a `CacheManager`
+///
+/// Index caches keep no hit or miss counters, so they only add to `entries`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AggregateStatistics {
+    /// Number of registered caches
+    pub caches: usize,
+    /// Number of items across all caches
+    pub entries: usize,
+    /// Hits across all main model caches
+    pub hits: u64,
+    /// Misses across all main model caches
+    pub misses: u64,
+    /// Evictions across all main model caches
+    pub evictions: u64,
+    /// Invalidations across all main model caches
+    pub invalidations: u64,
+}
+
+impl AggregateStatistics {
+    /// Calculate the overall hit rate (hits / (hits + misses))
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// A registered cache behind type-erased operations
+struct ManagedCache {
+    name: String,
+    /// The `Arc<RwLock<..>>` of the cache, for the typed accessors
+    cache: Arc<dyn Any + Send + Sync>,
+    clear: Box<dyn Fn() + Send + Sync>,
+    add_statistics: Box<dyn Fn(&mut AggregateStatistics) + Send + Sync>,
+    handler: Box<dyn Fn() -> Arc<dyn CacheNotificationHandler> + Send + Sync>,
+}
+
+/// Shared caches of different types, held by name
+///
+/// Replaces one `Arc<RwLock<..>>` field per cache: the caches are registered
+/// once and looked up by name and type where they are needed. The manager can
+/// clear them together, sum their statistics and build the notification
+/// listener that keeps them in sync with the database.
+#[derive(Default)]
+pub struct CacheManager {
+    caches: Vec<ManagedCache>,
+}
+
+impl CacheManager {
+    /// Creates an empty manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&mut self, cache: ManagedCache) -> CacheResult<()> {
+        if self.caches.iter().any(|existing| existing.name == cache.name) {
+            return Err(CacheError::InvalidArgument(format!(
+                "a cache named '{}' is already registered",
+                cache.name
+            )));
+        }
+        self.caches.push(cache);
+        Ok(())
+    }
+
+    /// Registers a shared index cache under a name
+    ///
+    /// Fails with `CacheError::InvalidArgument` if the name is taken.
+    pub fn register_index_cache<T>(
+        &mut self,
+        name: impl Into<String>,
+        shared_cache: Arc<RwLock<IdxModelCache<T>>>,
+    ) -> CacheResult<()>
+    where
+        T: IdxModel + HasTableName + DeserializeOwned,
+    {
+        let name = name.into();
+        let handler_name = name.clone();
+        let clear_cache = shared_cache.clone();
+        let stats_cache = shared_cache.clone();
+        let handler_cache = shared_cache.clone();
+        self.register(ManagedCache {
+            name,
+            cache: shared_cache,
+            clear: Box::new(move || clear_cache.write().clear()),
+            add_statistics: Box::new(move |statistics: &mut AggregateStatistics| {
+                statistics.entries += stats_cache.read().iter().count();
+            }),
+            handler: Box::new(move || {
+                Arc::new(IndexCacheHandler::for_type(handler_cache.clone()).with_name(handler_name.clone()))
+                    as Arc<dyn CacheNotificationHandler>
+            }),
+        })
+    }
+
+    /// Registers a shared main model cache under a name
+    ///
+    /// Fails with `CacheError::InvalidArgument` if the name is taken.
+    pub fn register_main_model_cache<T>(
+        &mut self,
+        name: impl Into<String>,
+        shared_cache: Arc<RwLock<MainModelCache<T>>>,
+    ) -> CacheResult<()>
+    where
+        T: MainModel + HasTableName + DeserializeOwned,
+    {
+        let name = name.into();
+        let handler_name = name.clone();
+        let clear_cache = shared_cache.clone();
+        let stats_cache = shared_cache.clone();
+        let handler_cache = shared_cache.clone();
+        self.register(ManagedCache {
+            name,
+            cache: shared_cache,
+            clear: Box::new(move || clear_cache.write().clear()),
+            add_statistics: Box::new(move |statistics: &mut AggregateStatistics| {
+                let cache = stats_cache.read();
+                let cache_statistics = cache.statistics();
+                statistics.entries += cache.len();
+                statistics.hits += cache_statistics.hits();
+                statistics.misses += cache_statistics.misses();
+                statistics.evictions += cache_statistics.evictions();
+                statistics.invalidations += cache_statistics.invalidations();
+            }),
+            handler: Box::new(move || {
+                Arc::new(MainModelCacheHandler::for_type(handler_cache.clone()).with_name(handler_name.clone()))
+                    as Arc<dyn CacheNotificationHandler>
+            }),
+        })
+    }
+
+    /// Returns an index cache, or None if no index cache of type `T` is
+    /// registered under the name
+    pub fn index_cache<T>(&self, name: &str) -> Option<Arc<RwLock<IdxModelCache<T>>>>
+    where
+        T: IdxModel,
+    {
+        self.get(name)?.cache.clone().downcast().ok()
+    }
+
+    /// Returns a main model cache, or None if no main model cache of type
+    /// `T` is registered under the name
+    pub fn main_model_cache<T>(&self, name: &str) -> Option<Arc<RwLock<MainModelCache<T>>>>
+    where
+        T: MainModel,
+    {
+        self.get(name)?.cache.clone().downcast().ok()
+    }
+
+    /// Returns the names of the registered caches, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.caches.iter().map(|cache| cache.name.as_str()).collect()
+    }
+
+    /// Clears every registered cache
+    pub fn clear_all(&self) {
+        for cache in &self.caches {
+            (cache.clear)();
+        }
+    }
+
+    /// Sums the statistics of every registered cache
+    ///
+    /// Each cache is read on its own, so the totals are not a consistent
+    /// snapshot while other threads write to the caches.
+    pub fn statistics(&self) -> AggregateStatistics {
+        let mut statistics = AggregateStatistics { caches: self.caches.len(), ..Default::default() };
+        for cache in &self.caches {
+            (cache.add_statistics)(&mut statistics);
+        }
+        statistics
+    }
+
+    /// Registers a handler for every cache with the listener
+    ///
+    /// Each handler is created with `for_type`, so it listens to the table of
+    /// the cached type, and logs under the name the cache was registered with. Caches of the same table replace each other's handler,
+    /// the last registered one winning.
+    pub fn register_handlers(&self, listener: &mut CacheNotificationListener) {
+        for cache in &self.caches {
+            listener.register_handler((cache.handler)());
+        }
+    }
+
+    /// Creates a listener on the default channel with a handler for every cache
+    pub fn listener(&self) -> CacheNotificationListener {
+        let mut listener = CacheNotificationListener::new();
+        self.register_handlers(&mut listener);
+        listener
+    }
+
+    fn get(&self, name: &str) -> Option<&ManagedCache> {
+        self.caches.iter().find(|cache| cache.name == name)
+    }
+}
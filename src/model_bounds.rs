@@ -0,0 +1,55 @@
+//! Compile-time checks that a model can be cached
+//!
+//! Kept apart from the caches so the bounds reported by a failed assertion
+//! point at a stable location.
+
+use crate::transaction_aware_index_cache::IdxModel;
+use crate::transaction_aware_main_model_cache::MainModel;
+
+/// Fails to compile unless `T` can be cached in an index cache
+///
+/// A missing bound is reported at the assertion rather than deep inside
+/// the cache or handler using the model.
+///
+/// # Example
+/// ```rust
+/// use postgres_index_cache::{assert_idx_model, HasPrimaryKey, Indexable};
+/// use uuid::Uuid;
+///
+/// #[derive(Debug, Clone)]
+/// struct UserIdx {
+///     id: Uuid,
+/// }
+///
+/// impl HasPrimaryKey for UserIdx {
+///     fn primary_key(&self) -> Uuid {
+///         self.id
+///     }
+/// }
+///
+/// impl Indexable for UserIdx {}
+///
+/// const _: () = assert_idx_model::<UserIdx>();
+/// ```
+pub const fn assert_idx_model<T: IdxModel>() {}
+
+/// Fails to compile unless `T` can be cached in a main model cache keyed by `K`
+///
+/// # Example
+/// ```rust
+/// use postgres_index_cache::{assert_main_model, CacheKey};
+///
+/// #[derive(Debug, Clone)]
+/// struct Country {
+///     code: String,
+/// }
+///
+/// impl CacheKey<String> for Country {
+///     fn cache_key(&self) -> String {
+///         self.code.clone()
+///     }
+/// }
+///
+/// const _: () = assert_main_model::<Country, String>();
+/// ```
+pub const fn assert_main_model<T: MainModel<K>, K>() {}
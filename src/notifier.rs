@@ -0,0 +1,221 @@
+//! Cache notifications sent from application code
+//!
+//! [`CacheNotifier`] sends the same payloads as the notification triggers, for
+//! writes the triggers do not see, such as those made by stored procedures.
+//! Sent on a transaction, a notification is delivered only when it commits.
+
+use serde::Serialize;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::error::CacheResult;
+use crate::listener::{CacheNotification, DEFAULT_CACHE_CHANNEL};
+use crate::traits::HasPrimaryKey;
+use crate::trigger_sql::DEFAULT_PAYLOAD_SIZE_LIMIT;
+
+/// Sends cache notifications with `pg_notify`
+#[derive(Debug, Clone)]
+pub struct CacheNotifier {
+    channel: String,
+    payload_size_limit: Option<usize>,
+    #[cfg(feature = "payload-protection")]
+    payload_key: Option<crate::payload_protection::PayloadKey>,
+}
+
+impl CacheNotifier {
+    /// Create a notifier for the default channel
+    pub fn new() -> Self {
+        Self::with_channel(DEFAULT_CACHE_CHANNEL.to_string())
+    }
+
+    /// Create a notifier for a custom channel
+    pub fn with_channel(channel: String) -> Self {
+        Self {
+            channel,
+            payload_size_limit: Some(DEFAULT_PAYLOAD_SIZE_LIMIT),
+            #[cfg(feature = "payload-protection")]
+            payload_key: None,
+        }
+    }
+
+    /// Encrypt and sign the item data with `key`, for listeners set up with the same key
+    #[cfg(feature = "payload-protection")]
+    pub fn with_payload_key(mut self, key: crate::payload_protection::PayloadKey) -> Self {
+        self.payload_key = Some(key);
+        self
+    }
+
+    /// Drop the item data from payloads larger than `limit` bytes, which
+    /// handlers answer by evicting the item; `None` sends every payload in full
+    pub fn payload_size_limit(mut self, limit: Option<usize>) -> Self {
+        self.payload_size_limit = limit;
+        self
+    }
+
+    /// Get the channel name
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Notify the listeners that an item was inserted
+    pub async fn notify_insert<'e, E, T>(&self, executor: E, table: &str, item: &T) -> CacheResult<()>
+    where
+        E: Executor<'e, Database = Postgres>,
+        T: Serialize + HasPrimaryKey,
+    {
+        let notification = CacheNotification::insert(table, item)?;
+        self.notify(executor, &notification).await
+    }
+
+    /// Notify the listeners that an item was updated
+    pub async fn notify_update<'e, E, T>(&self, executor: E, table: &str, item: &T) -> CacheResult<()>
+    where
+        E: Executor<'e, Database = Postgres>,
+        T: Serialize + HasPrimaryKey,
+    {
+        let notification = CacheNotification::update(table, item)?;
+        self.notify(executor, &notification).await
+    }
+
+    /// Notify the listeners that the item with the given primary key was deleted
+    pub async fn notify_delete<'e, E>(&self, executor: E, table: &str, id: Uuid) -> CacheResult<()>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        self.notify(executor, &CacheNotification::delete(table, id)).await
+    }
+
+    /// Ask the listeners to clear their caches of the table
+    ///
+    /// Meant for operators, e.g. when a cache is suspected to be corrupt;
+    /// every instance listening on the channel drops the table's items.
+    pub async fn notify_flush<'e, E>(&self, executor: E, table: &str) -> CacheResult<()>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        self.notify(executor, &CacheNotification::flush(table)).await
+    }
+
+    /// Send a notification as built by the caller
+    pub async fn notify<'e, E>(&self, executor: E, notification: &CacheNotification) -> CacheResult<()>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let payload = self.payload(notification)?;
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(&self.channel)
+            .bind(payload)
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+
+    /// Serialize a notification as it is sent, without the item data if the
+    /// payload exceeds the size limit
+    ///
+    /// With a payload key, the item data is protected before the size check.
+    pub fn payload(&self, notification: &CacheNotification) -> CacheResult<String> {
+        #[cfg(feature = "payload-protection")]
+        if let (Some(key), Some(data)) = (&self.payload_key, &notification.data) {
+            let data = key.protect(&notification.table, &notification.action, &notification.raw_key(), data)?;
+            return Ok(self.fit(&CacheNotification { data: Some(data), ..notification.clone() }));
+        }
+        Ok(self.fit(notification))
+    }
+
+    fn fit(&self, notification: &CacheNotification) -> String {
+        let payload = notification.to_payload();
+        match self.payload_size_limit {
+            Some(limit) if payload.len() > limit && notification.data.is_some() => {
+                CacheNotification { data: None, ..notification.clone() }.to_payload()
+            }
+            _ => payload,
+        }
+    }
+}
+
+impl Default for CacheNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listener::CacheAction;
+
+    #[derive(Debug, Clone, Serialize, serde::Deserialize, PartialEq)]
+    struct TestEntity {
+        id: Uuid,
+        value: String,
+    }
+
+    impl HasPrimaryKey for TestEntity {
+        fn primary_key(&self) -> Uuid {
+            self.id
+        }
+    }
+
+    #[test]
+    fn test_payload_round_trips_through_the_listener_format() {
+        let item = TestEntity { id: Uuid::new_v4(), value: "test".to_string() };
+        let notification = CacheNotification::update("test_entities", &item).unwrap();
+        let payload = CacheNotifier::new().payload(&notification).unwrap();
+
+        let parsed = CacheNotification::from_payload(&payload).unwrap();
+        assert_eq!(parsed.table, "test_entities");
+        assert_eq!(parsed.action, "update");
+        assert_eq!(parsed.id, item.id);
+        assert!(parsed.key.is_none() && parsed.context.is_none());
+        assert_eq!(serde_json::from_value::<TestEntity>(parsed.data.unwrap()).unwrap(), item);
+    }
+
+    #[test]
+    fn test_flush_is_parsed_without_id() {
+        let parsed: CacheNotification =
+            serde_json::from_str(r#"{"table": "test_entities", "action": "flush"}"#).unwrap();
+        assert_eq!(parsed.cache_action(), Some(CacheAction::Flush));
+        assert_eq!(parsed.id, Uuid::nil());
+
+        let parsed: CacheNotification =
+            serde_json::from_str(r#"{"table": "test_entities", "action": "flush", "id": "not-a-key"}"#).unwrap();
+        assert_eq!(parsed.id, Uuid::nil());
+        assert!(parsed.key.is_none());
+    }
+
+    #[test]
+    fn test_payload_drops_data_over_the_size_limit() {
+        let item = TestEntity { id: Uuid::new_v4(), value: "x".repeat(100) };
+        let notification = CacheNotification::insert("test_entities", &item).unwrap();
+
+        let payload = CacheNotifier::new().payload_size_limit(Some(50)).payload(&notification).unwrap();
+        let parsed = CacheNotification::from_payload(&payload).unwrap();
+        assert_eq!(parsed.id, item.id);
+        assert!(parsed.data.is_none());
+
+        let payload = CacheNotifier::new().payload_size_limit(None).payload(&notification).unwrap();
+        assert!(serde_json::from_str::<CacheNotification>(&payload).unwrap().data.is_some());
+    }
+
+    #[cfg(feature = "payload-protection")]
+    #[test]
+    fn test_payload_protects_data_with_key() {
+        use crate::payload_protection::PayloadKey;
+
+        let key = PayloadKey::new(&[7; 32]).unwrap();
+        let item = TestEntity { id: Uuid::new_v4(), value: "secret".to_string() };
+        let notification = CacheNotification::insert("test_entities", &item).unwrap();
+        let payload = CacheNotifier::new().with_payload_key(key.clone()).payload(&notification).unwrap();
+        assert!(!payload.contains("secret"));
+
+        let parsed = CacheNotification::from_payload(&payload).unwrap();
+        assert_eq!(parsed.id, item.id);
+        let data = key.unprotect("test_entities", "insert", &item.id.to_string(), &parsed.data.unwrap()).unwrap();
+        assert_eq!(serde_json::from_value::<TestEntity>(data).unwrap(), item);
+
+        // Deletes carry no data to protect
+        let payload = CacheNotifier::new().with_payload_key(key).payload(&CacheNotification::delete("test_entities", item.id)).unwrap();
+        assert_eq!(payload, CacheNotification::delete("test_entities", item.id).to_payload());
+    }
+}
@@ -0,0 +1,165 @@
+//! Polling the outbox table written by notification functions in outbox delivery
+//!
+//! [`CacheOutboxPoller`] reads the notifications of its listener's channel
+//! from the outbox table in `seq` order and feeds them through
+//! `CacheNotificationListener::process_notification`. Rows are acknowledged
+//! only after they were processed, so every notification is applied at least
+//! once, even if the poller stops in between. A row whose handler does not
+//! complete, or which a paused listener discards, is not acknowledged; the
+//! batch stops there and the next poll delivers it again.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use sqlx::PgPool;
+use tracing::{debug, error, warn};
+
+use crate::error::CacheError;
+use crate::listener::CacheNotificationListener;
+use crate::trigger_sql::{quote_ident, DEFAULT_OUTBOX_TABLE};
+
+/// What the poller does with the rows it has processed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutboxAck {
+    /// Delete processed rows; for a single poller per channel
+    #[default]
+    Delete,
+    /// Keep the rows and remember the last processed `seq`; for one poller
+    /// per replica, each keeping its own cache up to date
+    ///
+    /// The rows have to be removed by a retention job. `seq` is assigned when
+    /// a row is written, so a row whose transaction commits after a later
+    /// `seq` was already polled is skipped.
+    Cursor,
+}
+
+/// Polls the outbox table and dispatches its notifications to a listener
+///
+/// It can run next to the listener's `listen` loop. A notification arriving
+/// both ways is applied twice, which leaves the cache unchanged unless a newer
+/// version was applied in between; handlers created with
+/// `skip_stale_versions` ignore such late copies of inserts and updates.
+pub struct CacheOutboxPoller {
+    listener: Arc<CacheNotificationListener>,
+    table: String,
+    interval: Duration,
+    batch_size: i64,
+    ack: OutboxAck,
+    last_seq: AtomicI64,
+}
+
+impl CacheOutboxPoller {
+    /// Create a poller of the default outbox table, polling every second in batches of 100
+    pub fn new(listener: Arc<CacheNotificationListener>) -> Self {
+        Self {
+            listener,
+            table: DEFAULT_OUTBOX_TABLE.to_string(),
+            interval: Duration::from_secs(1),
+            batch_size: 100,
+            ack: OutboxAck::Delete,
+            last_seq: AtomicI64::new(0),
+        }
+    }
+
+    /// Set the outbox table to poll
+    pub fn with_table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Set the time between polls once the outbox is drained
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the maximum number of rows processed per poll
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1) as i64;
+        self
+    }
+
+    /// Set what happens to processed rows
+    pub fn with_ack(mut self, ack: OutboxAck) -> Self {
+        self.ack = ack;
+        self
+    }
+
+    /// Start after the given `seq` in `OutboxAck::Cursor` mode, e.g. the
+    /// newest row at the time the caches were loaded
+    pub fn start_after(self, seq: i64) -> Self {
+        self.last_seq.store(seq, Ordering::Relaxed);
+        self
+    }
+
+    /// The `seq` of the last processed row
+    pub fn last_seq(&self) -> i64 {
+        self.last_seq.load(Ordering::Relaxed)
+    }
+
+    /// Process one batch of notifications and return how many were acknowledged
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::ListenerError` if the outbox cannot be read or
+    /// the processed rows cannot be deleted. Rows that were processed but not
+    /// deleted are processed again by the next poll.
+    pub async fn poll_once(&self, pool: &PgPool) -> Result<usize, CacheError> {
+        let table = quote_ident(&self.table)?;
+        let sql = match self.ack {
+            OutboxAck::Delete => format!("SELECT seq, payload FROM {table} WHERE channel = $1 ORDER BY seq LIMIT $2"),
+            OutboxAck::Cursor => {
+                format!("SELECT seq, payload FROM {table} WHERE channel = $1 AND seq > $3 ORDER BY seq LIMIT $2")
+            }
+        };
+        let mut query = sqlx::query_as::<_, (i64, String)>(&sql)
+            .bind(self.listener.channel())
+            .bind(self.batch_size);
+        if self.ack == OutboxAck::Cursor {
+            query = query.bind(self.last_seq());
+        }
+        let rows = query.fetch_all(pool).await?;
+
+        let mut consumed = 0;
+        for (seq, payload) in &rows {
+            let outcome = self.listener.process_notification(payload).await;
+            if !outcome.is_consumed() {
+                warn!(table = %self.table, seq, ?outcome, "outbox notification not consumed, polling it again later");
+                break;
+            }
+            consumed += 1;
+        }
+        let rows = &rows[..consumed];
+        let Some(&(last_seq, _)) = rows.last() else {
+            return Ok(0);
+        };
+
+        if self.ack == OutboxAck::Delete {
+            let seqs: Vec<i64> = rows.iter().map(|(seq, _)| *seq).collect();
+            sqlx::query(&format!("DELETE FROM {table} WHERE seq = ANY($1)"))
+                .bind(seqs)
+                .execute(pool)
+                .await?;
+        }
+        self.last_seq.store(last_seq, Ordering::Relaxed);
+        debug!(table = %self.table, count = rows.len(), last_seq, "processed outbox notifications");
+        Ok(rows.len())
+    }
+
+    /// Poll the outbox until the task is cancelled
+    ///
+    /// Full batches are followed by the next poll right away; the interval
+    /// is waited once the outbox is drained or reading it failed.
+    pub async fn run(&self, pool: &PgPool) {
+        loop {
+            match self.poll_once(pool).await {
+                Ok(count) if count as i64 == self.batch_size => continue,
+                Ok(_) => {}
+                Err(err) => {
+                    error!(table = %self.table, error = %err, "failed to poll the cache outbox");
+                }
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
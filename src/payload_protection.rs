@@ -0,0 +1,215 @@
+//! Encryption and signing of the row data carried by notifications
+//!
+//! Any role allowed to LISTEN on a channel can read its NOTIFY payloads. With
+//! a [`PayloadKey`], the row data is sent as a blob encrypted with
+//! AES-256-CBC and signed with HMAC-SHA256 over the ciphertext; the table,
+//! action, id and context stay readable, so notifications are still routed
+//! and filtered as before.
+//!
+//! The blob is a JSON string `v1.<iv>.<ciphertext>.<mac>`, each part
+//! hex-encoded. The MAC covers the table name, the action and the primary key
+//! as text, each followed by a zero byte, then the IV and the ciphertext, so
+//! data cannot be replayed in a notification of another table, action or row. The
+//! encryption and MAC keys are derived from the secret with HMAC-SHA256 over
+//! fixed labels. The trigger function built with
+//! `TriggerSqlBuilder::protect_data` produces the same format with pgcrypto.
+
+use std::fmt;
+use aes::Aes256;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::CacheError;
+use crate::trigger_sql::{ENCRYPTION_KEY_LABEL, MAC_KEY_LABEL, PROTECTED_DATA_VERSION};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The minimum length of a payload secret in bytes
+pub const MIN_PAYLOAD_SECRET_LEN: usize = 32;
+
+/// The symmetric key protecting the row data of notifications
+///
+/// Configure the same secret on the `CacheNotifier`, on the listeners, and
+/// hex-encoded in the `PAYLOAD_KEY_SETTING` setting of the sessions whose
+/// writes fire the triggers.
+///
+/// # Example
+/// ```rust,ignore
+/// let key = PayloadKey::from_hex(&std::env::var("CACHE_PAYLOAD_KEY")?)?;
+/// listener.set_payload_key(key.clone());
+/// let notifier = CacheNotifier::new().with_payload_key(key);
+/// ```
+#[derive(Clone)]
+pub struct PayloadKey {
+    encryption: [u8; 32],
+    mac: [u8; 32],
+}
+
+impl fmt::Debug for PayloadKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PayloadKey(..)")
+    }
+}
+
+impl PayloadKey {
+    /// Derive the encryption and MAC keys from `secret`
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::InvalidArgument` if the secret is shorter than
+    /// [`MIN_PAYLOAD_SECRET_LEN`] bytes.
+    pub fn new(secret: &[u8]) -> Result<Self, CacheError> {
+        if secret.len() < MIN_PAYLOAD_SECRET_LEN {
+            return Err(CacheError::InvalidArgument(format!(
+                "payload secret must be at least {MIN_PAYLOAD_SECRET_LEN} bytes"
+            )));
+        }
+        Ok(Self { encryption: derive(secret, ENCRYPTION_KEY_LABEL), mac: derive(secret, MAC_KEY_LABEL) })
+    }
+
+    /// Derive the keys from a hex-encoded secret, as set for the triggers
+    pub fn from_hex(secret: &str) -> Result<Self, CacheError> {
+        let secret = hex::decode(secret.trim())
+            .map_err(|err| CacheError::InvalidArgument(format!("payload secret is not hex: {err}")))?;
+        Self::new(&secret)
+    }
+
+    /// Encrypt and sign the row data of a notification
+    ///
+    /// `id` is the primary key as sent in the notification, see
+    /// `CacheNotification::raw_key`.
+    pub fn protect(
+        &self,
+        table: &str,
+        action: &str,
+        id: &str,
+        data: &serde_json::Value,
+    ) -> Result<serde_json::Value, CacheError> {
+        let mut iv = [0u8; 16];
+        getrandom::getrandom(&mut iv)
+            .map_err(|err| CacheError::OperationFailed(format!("failed to generate an IV: {err}")))?;
+        let ciphertext = cbc::Encryptor::<Aes256>::new_from_slices(&self.encryption, &iv)
+            .expect("key and IV have the cipher's sizes")
+            .encrypt_padded_vec_mut::<Pkcs7>(data.to_string().as_bytes());
+        let mac = self.sign(table, action, id, &iv, &ciphertext).finalize().into_bytes();
+        Ok(serde_json::Value::String(format!(
+            "{PROTECTED_DATA_VERSION}.{}.{}.{}",
+            hex::encode(iv),
+            hex::encode(&ciphertext),
+            hex::encode(mac)
+        )))
+    }
+
+    /// Verify and decrypt row data protected by `protect` or by the trigger function
+    ///
+    /// # Errors
+    ///
+    /// Returns `CacheError::PayloadRejected` if the data is not protected, its
+    /// signature does not match the table, action and id, or it does not
+    /// decrypt to JSON.
+    pub fn unprotect(
+        &self,
+        table: &str,
+        action: &str,
+        id: &str,
+        data: &serde_json::Value,
+    ) -> Result<serde_json::Value, CacheError> {
+        let rejected = |reason: &str| CacheError::PayloadRejected { table: table.to_string(), reason: reason.to_string() };
+        let blob = data.as_str().ok_or_else(|| rejected("row data is not protected"))?;
+        let mut parts = blob.split('.');
+        let (Some(PROTECTED_DATA_VERSION), Some(iv), Some(ciphertext), Some(mac), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(rejected("unknown format of protected row data"));
+        };
+        let decode = |part: &str| hex::decode(part).map_err(|_| rejected("protected row data is not hex"));
+        let (iv, ciphertext, mac) = (decode(iv)?, decode(ciphertext)?, decode(mac)?);
+
+        self.sign(table, action, id, &iv, &ciphertext)
+            .verify_slice(&mac)
+            .map_err(|_| rejected("signature does not match"))?;
+        let plaintext = cbc::Decryptor::<Aes256>::new_from_slices(&self.encryption, &iv)
+            .map_err(|_| rejected("IV has the wrong size"))?
+            .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+            .map_err(|_| rejected("row data does not decrypt"))?;
+        serde_json::from_slice(&plaintext).map_err(|_| rejected("decrypted row data is not JSON"))
+    }
+
+    fn sign(&self, table: &str, action: &str, id: &str, iv: &[u8], ciphertext: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.mac).expect("HMAC takes keys of any size");
+        for field in [table, action, id] {
+            mac.update(field.as_bytes());
+            mac.update(&[0]);
+        }
+        mac.update(iv);
+        mac.update(ciphertext);
+        mac
+    }
+}
+
+fn derive(secret: &[u8], label: &str) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(label.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ID: &str = "5f0c3f7e-3a8e-4d5e-9d1c-0a6f8f5f6b0e";
+
+    fn key(byte: u8) -> PayloadKey {
+        PayloadKey::new(&[byte; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_protected_data_round_trips() {
+        let data = json!({ "id": "5f0c3f7e-3a8e-4d5e-9d1c-0a6f8f5f6b0e", "email": "alice@example.com" });
+        let protected = key(1).protect("users", "insert", ID, &data).unwrap();
+
+        let blob = protected.as_str().unwrap();
+        assert!(blob.starts_with("v1."));
+        assert!(!blob.contains("alice"));
+        assert_eq!(key(1).unprotect("users", "insert", ID, &protected).unwrap(), data);
+    }
+
+    #[test]
+    fn test_tampered_or_foreign_data_is_rejected() {
+        let data = json!({ "email": "alice@example.com" });
+        let protected = key(1).protect("users", "update", ID, &data).unwrap();
+        let rejected = |result: Result<serde_json::Value, CacheError>| matches!(result, Err(CacheError::PayloadRejected { .. }));
+
+        assert!(rejected(key(2).unprotect("users", "update", ID, &protected)));
+        assert!(rejected(key(1).unprotect("accounts", "update", ID, &protected)));
+        assert!(rejected(key(1).unprotect("users", "update", ID, &data)));
+
+        let mut blob = protected.as_str().unwrap().to_string();
+        let flipped = if blob.ends_with('0') { '1' } else { '0' };
+        blob.pop();
+        blob.push(flipped);
+        assert!(rejected(key(1).unprotect("users", "update", ID, &json!(blob))));
+    }
+
+    #[test]
+    fn test_data_replayed_for_another_action_or_id_is_rejected() {
+        let data = json!({ "email": "alice@example.com" });
+        let protected = key(1).protect("users", "update", ID, &data).unwrap();
+        let rejected = |result: Result<serde_json::Value, CacheError>| matches!(result, Err(CacheError::PayloadRejected { .. }));
+
+        assert!(rejected(key(1).unprotect("users", "insert", ID, &protected)));
+        assert!(rejected(key(1).unprotect("users", "update", "0b6c2f1e-8d4a-4f3b-a1e2-7c9d5e3f1a20", &protected)));
+        // The separators keep fields from shifting into each other
+        assert!(rejected(key(1).unprotect("users\0update", "", ID, &protected)));
+    }
+
+    #[test]
+    fn test_short_or_malformed_secrets_are_refused() {
+        assert!(PayloadKey::new(&[0; 16]).is_err());
+        assert!(PayloadKey::from_hex("not hex").is_err());
+        assert!(PayloadKey::from_hex(&"ab".repeat(32)).is_ok());
+    }
+}
@@ -0,0 +1,140 @@
+use parking_lot::RwLock;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::{CacheError, CacheResult};
+use crate::index_cache::IdxModelCache;
+use crate::main_model_cache::MainModelCache;
+use crate::transaction_aware_index_cache::{IdxModel, TransactionAwareIdxModelCache};
+use crate::transaction_aware_main_model_cache::{MainModel, TransactionAwareMainModelCache};
+use crate::transaction_group::{CacheTransactionGroup, TransactionParticipant};
+
+/// Something transaction participants can be registered with, usually a unit of work
+///
+/// Implemented for closures, so a unit of work can be adapted in place:
+/// `&mut |participant: TransactionParticipant| uow.register_transaction_aware(participant)`.
+pub trait ParticipantRegistrar {
+    /// Registers a participant to be committed or rolled back with the transaction
+    fn register_participant(&mut self, participant: TransactionParticipant);
+}
+
+impl<F> ParticipantRegistrar for F
+where
+    F: FnMut(TransactionParticipant),
+{
+    fn register_participant(&mut self, participant: TransactionParticipant) {
+        self(participant)
+    }
+}
+
+/// Wraps a shared cache for one transaction, adding the wrapper to the group
+type WrapperFactory = Box<dyn Fn(&str, &mut CacheTransactionGroup) -> Arc<dyn Any + Send + Sync> + Send + Sync>;
+
+/// Shared caches by name, wrapped and registered together for each transaction
+///
+/// `begin_transaction` creates a transaction-aware wrapper for every cache and
+/// registers them with the unit of work as one `CacheTransactionGroup`, so a
+/// repository can only get a wrapper that is already registered.
+#[derive(Default)]
+pub struct CacheRegistry {
+    caches: Vec<(String, WrapperFactory)>,
+}
+
+impl CacheRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&mut self, name: String, factory: WrapperFactory) -> CacheResult<()> {
+        if self.caches.iter().any(|(existing, _)| *existing == name) {
+            return Err(CacheError::InvalidArgument(format!(
+                "a cache named '{name}' is already registered"
+            )));
+        }
+        self.caches.push((name, factory));
+        Ok(())
+    }
+
+    /// Registers a shared index cache under a name
+    ///
+    /// Fails with `CacheError::InvalidArgument` if the name is taken.
+    pub fn register_index_cache<T>(
+        &mut self,
+        name: impl Into<String>,
+        shared_cache: Arc<RwLock<IdxModelCache<T>>>,
+    ) -> CacheResult<()>
+    where
+        T: IdxModel,
+    {
+        self.register(
+            name.into(),
+            Box::new(move |name: &str, group: &mut CacheTransactionGroup| {
+                group.add_index_cache(name, shared_cache.clone()) as Arc<dyn Any + Send + Sync>
+            }),
+        )
+    }
+
+    /// Registers a shared main model cache under a name
+    ///
+    /// Fails with `CacheError::InvalidArgument` if the name is taken.
+    pub fn register_main_model_cache<T>(
+        &mut self,
+        name: impl Into<String>,
+        shared_cache: Arc<RwLock<MainModelCache<T>>>,
+    ) -> CacheResult<()>
+    where
+        T: MainModel,
+    {
+        self.register(
+            name.into(),
+            Box::new(move |name: &str, group: &mut CacheTransactionGroup| {
+                group.add_main_model_cache(name, shared_cache.clone()) as Arc<dyn Any + Send + Sync>
+            }),
+        )
+    }
+
+    /// Returns the names of the registered caches, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.caches.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Wraps every registered cache for a new transaction and registers the
+    /// wrappers with `registrar` as a single participant
+    pub fn begin_transaction(&self, registrar: &mut impl ParticipantRegistrar) -> CacheTransaction {
+        let mut group = CacheTransactionGroup::new();
+        let handles = self
+            .caches
+            .iter()
+            .map(|(name, factory)| (name.clone(), factory(name, &mut group)))
+            .collect();
+        registrar.register_participant(Arc::new(group));
+        CacheTransaction { handles }
+    }
+}
+
+/// Typed handles to the transaction-aware wrappers of one transaction
+pub struct CacheTransaction {
+    handles: HashMap<String, Arc<dyn Any + Send + Sync>>,
+}
+
+impl CacheTransaction {
+    /// Returns the wrapper of an index cache, or None if no index cache of
+    /// type `T` is registered under the name
+    pub fn index_cache<T>(&self, name: &str) -> Option<Arc<TransactionAwareIdxModelCache<T>>>
+    where
+        T: IdxModel,
+    {
+        self.handles.get(name)?.clone().downcast().ok()
+    }
+
+    /// Returns the wrapper of a main model cache, or None if no main model
+    /// cache of type `T` is registered under the name
+    pub fn main_model_cache<T>(&self, name: &str) -> Option<Arc<TransactionAwareMainModelCache<T>>>
+    where
+        T: MainModel,
+    {
+        self.handles.get(name)?.clone().downcast().ok()
+    }
+}
@@ -0,0 +1,406 @@
+//! Starting the cache infrastructure of a service in the right order
+//!
+//! [`CacheRuntimeBuilder`] collects the cached tables of a service. The
+//! [`CacheRuntime`] it builds starts them in the order that neither misses
+//! changes nor fails silently:
+//!
+//! 1. the triggers are verified, and installed first if asked to, and the
+//!    handlers' expected fields are checked against their tables if asked to,
+//! 2. the listener is spawned and buffers notifications once it listens,
+//! 3. the caches are preloaded,
+//! 4. the buffered notifications are replayed and live dispatch resumes.
+//!
+//! Each phase fails with its own [`CacheStartupError`] variant.
+
+use std::future::Future;
+use std::sync::Arc;
+use async_trait::async_trait;
+use futures::future::{select, Either};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::bootstrap::CacheBootstrapper;
+use crate::db_init::{
+    check_handler_coverage_with_function, init_cache_triggers_with_function, init_table_trigger,
+    verify_cache_triggers_with_function, FunctionOptions,
+    TableTriggerSpec, TriggerOptions, TriggerVerificationError, VerificationReport,
+};
+use crate::error::CacheError;
+use crate::handler_schema::{assert_handlers_match_db, HandlerSchemaError};
+use crate::handler_stats::{AppliedInfo, HandlerStats};
+use crate::index_cache::IdxModelCache;
+use crate::listener::{
+    CacheNotificationHandler, CacheNotificationListener, HandlerOptions, IndexCacheHandler, ReconnectPolicy,
+};
+use crate::traits::{HasPrimaryKey, HasTableName, Indexable};
+
+/// Loads the rows of a table into its cache
+///
+/// Implemented for any `Fn(PgPool) -> impl Future<Output = Result<usize, sqlx::Error>>`
+/// returning the number of loaded rows.
+#[async_trait]
+pub trait CachePreload: Send + Sync {
+    /// Replaces the cache contents with the rows of the table and returns how many were loaded
+    async fn preload(&self, pool: &PgPool) -> Result<usize, sqlx::Error>;
+}
+
+#[async_trait]
+impl<F, Fut> CachePreload for F
+where
+    F: Fn(PgPool) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<usize, sqlx::Error>> + Send,
+{
+    async fn preload(&self, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        self(pool.clone()).await
+    }
+}
+
+/// A cached table: its trigger, how its cache is preloaded and its notification handler
+pub struct CacheTableSpec {
+    trigger: TableTriggerSpec,
+    preload: Box<dyn CachePreload>,
+    handler: Arc<dyn CacheNotificationHandler>,
+    handler_options: HandlerOptions,
+}
+
+impl CacheTableSpec {
+    /// Create a spec from its parts
+    pub fn new(
+        trigger: TableTriggerSpec,
+        preload: impl CachePreload + 'static,
+        handler: Arc<dyn CacheNotificationHandler>,
+    ) -> Self {
+        Self { trigger, preload: Box::new(preload), handler, handler_options: HandlerOptions::default() }
+    }
+
+    /// Create a spec for an index cache kept up to date by an `IndexCacheHandler`
+    ///
+    /// `load` queries all rows of the table; they replace the cache contents.
+    pub fn index_cache<T, F, Fut>(cache: Arc<RwLock<IdxModelCache<T>>>, load: F) -> Self
+    where
+        T: HasPrimaryKey + HasTableName + Indexable + Clone + Send + Sync + std::fmt::Debug + 'static,
+        T: for<'de> Deserialize<'de>,
+        F: Fn(PgPool) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<T>, sqlx::Error>> + Send + 'static,
+    {
+        let handler = Arc::new(IndexCacheHandler::for_type(cache.clone()));
+        let preload = move |pool: PgPool| {
+            let rows = load(pool);
+            let cache = cache.clone();
+            async move {
+                let rows = rows.await?;
+                let count = rows.len();
+                let mut cache = cache.write();
+                cache.clear();
+                for row in rows {
+                    cache.add(row);
+                }
+                Ok::<usize, sqlx::Error>(count)
+            }
+        };
+        Self::new(TableTriggerSpec::for_type::<T>(), preload, handler)
+    }
+
+    /// Set the trigger expected on the table, e.g. for another schema or other events
+    pub fn with_trigger(mut self, trigger: TableTriggerSpec) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// Set the timeout and concurrency limit of the handler
+    pub fn with_handler_options(mut self, options: HandlerOptions) -> Self {
+        self.handler_options = options;
+        self
+    }
+}
+
+/// Error returned by [`CacheRuntime::start`], one variant per phase
+#[derive(Debug, thiserror::Error)]
+pub enum CacheStartupError {
+    #[error("Failed to install cache triggers: {0}")]
+    TriggerInstallation(#[source] sqlx::Error),
+
+    #[error(transparent)]
+    TriggerVerification(#[from] TriggerVerificationError),
+
+    #[error(transparent)]
+    HandlerSchema(#[from] HandlerSchemaError),
+
+    #[error("Failed to start the notification listener: {0}")]
+    Listener(#[source] CacheError),
+
+    #[error("Failed to preload the cache of table '{table}': {source}")]
+    Preload {
+        table: String,
+        #[source]
+        source: sqlx::Error,
+    },
+}
+
+/// Builds a [`CacheRuntime`]
+pub struct CacheRuntimeBuilder {
+    pool: PgPool,
+    tables: Vec<CacheTableSpec>,
+    function: FunctionOptions,
+    listener: Option<CacheNotificationListener>,
+    reconnect: ReconnectPolicy,
+    install_triggers: bool,
+    validate_handler_fields: bool,
+}
+
+impl CacheRuntimeBuilder {
+    /// Create a builder for the given pool, using the default notification function
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tables: Vec::new(),
+            function: FunctionOptions::default(),
+            listener: None,
+            reconnect: ReconnectPolicy::default(),
+            install_triggers: false,
+            validate_handler_fields: false,
+        }
+    }
+
+    /// Add a cached table
+    pub fn with_table(mut self, table: CacheTableSpec) -> Self {
+        self.tables.push(table);
+        self
+    }
+
+    /// Use a custom notification function; the listener listens on its channel
+    pub fn with_function(mut self, function: FunctionOptions) -> Self {
+        self.function = function;
+        self
+    }
+
+    /// Register the handlers on this listener instead of a new one, e.g. to
+    /// keep its filter or resync callbacks
+    pub fn with_listener(mut self, listener: CacheNotificationListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Set how the listener reconnects after losing its connection
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// Install the notification function and the table triggers before
+    /// verifying them, for services that own their schema
+    pub fn install_triggers(mut self) -> Self {
+        self.install_triggers = true;
+        self
+    }
+
+    /// Fail the start when a handler expects fields its table has no column for
+    ///
+    /// See [`validate_handlers_against_db`](crate::validate_handlers_against_db).
+    pub fn validate_handler_fields(mut self) -> Self {
+        self.validate_handler_fields = true;
+        self
+    }
+
+    /// Build the runtime; nothing happens until it is started
+    pub fn build(self) -> CacheRuntime {
+        let mut listener = self
+            .listener
+            .unwrap_or_else(|| CacheNotificationListener::with_channel(self.function.channel.clone()));
+        listener.set_reconnect_policy(self.reconnect);
+        CacheRuntime {
+            pool: self.pool,
+            tables: self.tables,
+            function: self.function,
+            listener,
+            install_triggers: self.install_triggers,
+            validate_handler_fields: self.validate_handler_fields,
+        }
+    }
+}
+
+/// The cache infrastructure of a service, ready to start
+pub struct CacheRuntime {
+    pool: PgPool,
+    tables: Vec<CacheTableSpec>,
+    function: FunctionOptions,
+    listener: CacheNotificationListener,
+    install_triggers: bool,
+    validate_handler_fields: bool,
+}
+
+impl CacheRuntime {
+    /// Verify the triggers, start the listener and preload the caches
+    ///
+    /// Triggers on tables of other services are only logged; missing,
+    /// disabled or mismatched triggers on the runtime's tables fail the start.
+    /// On failure, the listener task is stopped again.
+    pub async fn start(self) -> Result<CacheRuntimeHandle, CacheStartupError> {
+        if self.install_triggers {
+            self.install().await.map_err(CacheStartupError::TriggerInstallation)?;
+        }
+        self.verify().await?;
+
+        let CacheRuntime { pool, tables, function, mut listener, validate_handler_fields, .. } = self;
+        for table in &tables {
+            listener.register_handler_with_options(table.handler.clone(), table.handler_options);
+        }
+        // Also covers handlers registered on a listener passed with `with_listener`
+        let coverage = check_handler_coverage_with_function(&pool, &function, &listener)
+            .await
+            .map_err(TriggerVerificationError::Database)?;
+        for trigger in &coverage.triggers_without_handler {
+            warn!(schema = %trigger.schema, table = %trigger.table, "cache trigger on a table without a handler in this runtime");
+        }
+        for table in &coverage.handlers_without_trigger {
+            warn!(%table, "cache handler for a table without a cache trigger");
+        }
+        if validate_handler_fields {
+            assert_handlers_match_db(&pool, &listener).await?;
+        }
+        let listener = Arc::new(listener);
+
+        listener.start_buffering();
+        let mut task = tokio::spawn({
+            let listener = listener.clone();
+            let pool = pool.clone();
+            async move { listener.listen(&pool).await }
+        });
+
+        // Wait for LISTEN, unless the listener fails to connect first
+        if let Either::Right((result, _)) = select(Box::pin(listener.wait_until_listening()), &mut task).await {
+            listener.stop_buffering().await;
+            let err = match result {
+                Ok(Err(err)) => err,
+                Ok(Ok(())) => CacheError::OperationFailed("the listener stopped before listening".to_string()),
+                Err(err) => CacheError::OperationFailed(format!("the listener task failed: {err}")),
+            };
+            return Err(CacheStartupError::Listener(err));
+        }
+
+        let mut preloaded = Vec::with_capacity(tables.len());
+        let load = {
+            let (pool, tables, preloaded) = (&pool, &tables, &mut preloaded);
+            move || async move {
+                for table in tables {
+                    let count = table.preload.preload(pool).await.map_err(|source| CacheStartupError::Preload {
+                        table: table.trigger.table.clone(),
+                        source,
+                    })?;
+                    debug!(table = %table.trigger.table, count, "preloaded cache");
+                    preloaded.push((table.trigger.table.clone(), count));
+                }
+                Ok::<(), CacheStartupError>(())
+            }
+        };
+        let replayed = match CacheBootstrapper::new(listener.clone()).without_waiting_for_listen().bootstrap(load).await {
+            Ok(replayed) => replayed,
+            Err(err) => {
+                task.abort();
+                return Err(err);
+            }
+        };
+
+        Ok(CacheRuntimeHandle { listener, task, preloaded, replayed })
+    }
+
+    async fn install(&self) -> Result<(), sqlx::Error> {
+        init_cache_triggers_with_function(&self.pool, &self.function).await?;
+        for table in &self.tables {
+            let mut options = TriggerOptions::new(table.trigger.table.clone())
+                .with_events(&table.trigger.events)
+                .with_function(&self.function);
+            if let Some(schema) = &table.trigger.schema {
+                options = options.with_schema(schema.clone());
+            }
+            init_table_trigger(&self.pool, &options).await?;
+        }
+        Ok(())
+    }
+
+    async fn verify(&self) -> Result<(), TriggerVerificationError> {
+        let expected: Vec<TableTriggerSpec> = self.tables.iter().map(|table| table.trigger.clone()).collect();
+        let report = verify_cache_triggers_with_function(&self.pool, &self.function, &expected).await?;
+        // Triggers on tables of other services are logged by `start` once the handlers are registered
+        let report = VerificationReport { extra: Vec::new(), ..report };
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(TriggerVerificationError::Mismatch(report))
+        }
+    }
+}
+
+/// State of the listener of a started runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerState {
+    /// Connected and listening on the channel
+    Listening,
+    /// Lost the connection and reconnecting as the reconnect policy says
+    Reconnecting,
+    /// The listener task ended, because the reconnect policy gave up or after `shutdown`
+    Stopped,
+}
+
+/// Status of a started runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeStatus {
+    /// State of the listener
+    pub listener: ListenerState,
+    /// Number of rows preloaded per table, in the order the tables were added
+    pub preloaded: Vec<(String, usize)>,
+    /// Number of notifications buffered during the preload and replayed after it
+    pub replayed: usize,
+    /// Latency statistics of the handlers, by table name
+    pub handlers: Vec<(String, HandlerStats)>,
+    /// The last change applied by each handler, by table name; the preload counts
+    pub last_applied: Vec<(String, AppliedInfo)>,
+}
+
+/// A started runtime
+pub struct CacheRuntimeHandle {
+    listener: Arc<CacheNotificationListener>,
+    task: JoinHandle<Result<(), CacheError>>,
+    preloaded: Vec<(String, usize)>,
+    replayed: usize,
+}
+
+impl CacheRuntimeHandle {
+    /// The listener dispatching to the tables' handlers, e.g. to pause it
+    pub fn listener(&self) -> &Arc<CacheNotificationListener> {
+        &self.listener
+    }
+
+    /// The current status of the runtime
+    pub fn status(&self) -> RuntimeStatus {
+        let listener = if self.task.is_finished() {
+            ListenerState::Stopped
+        } else if self.listener.is_listening() {
+            ListenerState::Listening
+        } else {
+            ListenerState::Reconnecting
+        };
+        RuntimeStatus {
+            listener,
+            preloaded: self.preloaded.clone(),
+            replayed: self.replayed,
+            handlers: self.listener.all_handler_stats(),
+            last_applied: self.listener.all_last_applied(),
+        }
+    }
+
+    /// Stop the listener
+    ///
+    /// Returns the error the listener stopped with, if it stopped by itself.
+    pub async fn shutdown(self) -> Result<(), CacheError> {
+        self.task.abort();
+        match self.task.await {
+            Ok(result) => result,
+            Err(err) if err.is_cancelled() => Ok(()),
+            Err(err) => Err(CacheError::OperationFailed(format!("the listener task failed: {err}"))),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Random samples of cached items
+//!
+//! The caches draw samples with reservoir sampling over their internal
+//! maps, so a sample of `n` items needs memory for `n` references only, not
+//! for all keys.
+
+use rand::Rng;
+
+/// Draws up to `n` items of `items` uniformly at random (Algorithm R)
+///
+/// Every item has the same chance to be chosen. The items are returned in no
+/// particular order; fewer than `n` only if there are fewer items.
+pub(crate) fn reservoir_sample<I: Iterator>(items: I, n: usize, rng: &mut impl Rng) -> Vec<I::Item> {
+    let mut reservoir = Vec::with_capacity(n.min(items.size_hint().0));
+    if n == 0 {
+        return reservoir;
+    }
+    for (seen, item) in items.enumerate() {
+        if seen < n {
+            reservoir.push(item);
+        } else {
+            let slot = rng.gen_range(0..=seen);
+            if slot < n {
+                reservoir[slot] = item;
+            }
+        }
+    }
+    reservoir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sample_sizes() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!(reservoir_sample(0..100, 0, &mut rng).is_empty());
+        assert_eq!(reservoir_sample(0..3, 10, &mut rng), vec![0, 1, 2]);
+
+        let mut sample = reservoir_sample(0..100, 10, &mut rng);
+        sample.sort_unstable();
+        sample.dedup();
+        assert_eq!(sample.len(), 10);
+    }
+
+    #[test]
+    fn test_same_seed_draws_same_sample() {
+        let draw = |seed| reservoir_sample(0..1000, 5, &mut StdRng::seed_from_u64(seed));
+        assert_eq!(draw(42), draw(42));
+        assert_ne!(draw(42), draw(43));
+    }
+
+    #[test]
+    fn test_every_item_is_drawn_about_equally_often() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut counts = [0usize; 10];
+        for _ in 0..10_000 {
+            for item in reservoir_sample(0..10, 2, &mut rng) {
+                counts[item] += 1;
+            }
+        }
+        // Each item is expected 2000 times
+        assert!(counts.iter().all(|count| (1800..2200).contains(count)), "{counts:?}");
+    }
+}
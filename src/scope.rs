@@ -0,0 +1,55 @@
+use std::ops::Deref;
+
+use postgres_unit_of_work::{TransactionAware, TransactionResult};
+
+/// A transaction-aware cache whose uncommitted changes can be discarded without awaiting
+pub trait DiscardChanges: TransactionAware {
+    /// Rolls back uncommitted changes, as `on_rollback` does
+    fn discard_changes(&self);
+}
+
+/// Guard for one transaction on a transaction-aware cache
+///
+/// Created by `scoped()`. Dereferences to the cache, so changes are staged
+/// through the guard. Dropping the guard without calling `commit` or
+/// `rollback`, for example while unwinding from a panic, rolls the changes
+/// back so they cannot leak into the next transaction.
+pub struct TransactionScope<'a, C: DiscardChanges> {
+    cache: &'a C,
+    finished: bool,
+}
+
+impl<'a, C: DiscardChanges> TransactionScope<'a, C> {
+    pub(crate) fn new(cache: &'a C) -> Self {
+        Self { cache, finished: false }
+    }
+
+    /// Applies the changes, consuming the guard
+    pub async fn commit(mut self) -> TransactionResult<()> {
+        self.finished = true;
+        self.cache.on_commit().await
+    }
+
+    /// Discards the changes, consuming the guard
+    pub async fn rollback(mut self) -> TransactionResult<()> {
+        self.finished = true;
+        self.cache.on_rollback().await
+    }
+}
+
+impl<C: DiscardChanges> Deref for TransactionScope<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.cache
+    }
+}
+
+impl<C: DiscardChanges> Drop for TransactionScope<'_, C> {
+    fn drop(&mut self) {
+        if !self.finished {
+            tracing::debug!("transaction scope dropped without commit, discarding changes");
+            self.cache.discard_changes();
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Mirroring notifications to an external sink, e.g. an event bus
+//!
+//! [`SinkHandler`] passes each notification of its table to a
+//! [`NotificationSink`], after the table's cache handler if it wraps one.
+//! Registered for [`ALL_TABLES`], it receives the notifications of every
+//! table, after their own handlers.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use async_trait::async_trait;
+use tracing::{debug, error, info_span, Instrument};
+
+use crate::handler_stats::AppliedInfo;
+use crate::listener::{CacheNotification, CacheNotificationHandler, DeadLetterHook, RetryPolicy, ALL_TABLES};
+
+/// Error returned by a [`NotificationSink`]
+#[derive(Debug, thiserror::Error)]
+pub enum SinkError {
+    /// The sink could not take the notification now, e.g. while reconnecting;
+    /// it is retried if the handler has a retry policy
+    #[error("sink unavailable: {0}")]
+    Unavailable(String),
+
+    /// The sink refused the notification; it is not retried
+    #[error("sink rejected the notification: {0}")]
+    Rejected(String),
+}
+
+/// Where a [`SinkHandler`] publishes notifications
+///
+/// # Example
+/// ```rust,ignore
+/// struct NatsSink(async_nats::Client);
+///
+/// #[async_trait]
+/// impl NotificationSink for NatsSink {
+///     async fn publish(&self, notification: &CacheNotification) -> Result<(), SinkError> {
+///         let subject = format!("cache.{}.{}", notification.table, notification.action);
+///         self.0
+///             .publish(subject, notification.to_payload().into())
+///             .await
+///             .map_err(|err| SinkError::Unavailable(err.to_string()))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait NotificationSink: Send + Sync {
+    /// Publish a notification
+    async fn publish(&self, notification: &CacheNotification) -> Result<(), SinkError>;
+}
+
+/// The sink with its retry and dead-letter configuration, shared with retry tasks
+struct SinkState {
+    table_name: String,
+    sink: Arc<dyn NotificationSink>,
+    retry: Option<RetryPolicy>,
+    dead_letter: Option<Arc<DeadLetterHook>>,
+    published: AtomicU64,
+    failures: AtomicU64,
+}
+
+impl SinkState {
+    /// Retries an unavailable sink in a background task, and passes the
+    /// notification to the dead-letter hook once no retry is left
+    fn retry_or_give_up(self: Arc<Self>, notification: CacheNotification, err: SinkError) {
+        let policy = match (&err, self.retry) {
+            (SinkError::Unavailable(_), Some(policy)) if policy.max_retries > 0 => policy,
+            _ => {
+                self.give_up(&notification, &err.to_string());
+                return;
+            }
+        };
+
+        let span = info_span!("retry_sink", table = %notification.table, id = %notification.id);
+        tokio::spawn(
+            async move {
+                let mut last_err = err;
+                for retry in 0..policy.max_retries {
+                    tokio::time::sleep(policy.backoff(retry)).await;
+                    match self.sink.publish(&notification).await {
+                        Ok(()) => {
+                            self.published.fetch_add(1, Ordering::Relaxed);
+                            debug!(retry = retry + 1, "retried notification published");
+                            return;
+                        }
+                        Err(err @ SinkError::Rejected(_)) => {
+                            self.give_up(&notification, &err.to_string());
+                            return;
+                        }
+                        Err(err) => last_err = err,
+                    }
+                }
+                let reason = format!("{last_err}, after {} retries", policy.max_retries);
+                self.give_up(&notification, &reason);
+            }
+            .instrument(span),
+        );
+    }
+
+    fn give_up(&self, notification: &CacheNotification, reason: &str) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        error!(
+            table = %notification.table,
+            action = %notification.action,
+            id = %notification.id,
+            sink = %self.table_name,
+            reason,
+            "giving up on publishing notification"
+        );
+        if let Some(dead_letter) = &self.dead_letter {
+            dead_letter(notification, reason);
+        }
+    }
+}
+
+/// A notification handler publishing the notifications of a table to a sink
+///
+/// A table has one handler, so when the table is cached too, wrap its handler
+/// with [`SinkHandler::wrapping`]. The sink then runs after the cache handler
+/// returned, so a consumer of the sink never sees a change before this
+/// service's cache does. A handler registered for [`ALL_TABLES`] with
+/// [`SinkHandler::for_all_tables`] runs after each table's own handler.
+///
+/// Sink failures are retried and dead-lettered independently of the cache:
+/// the policy set with `with_retry` applies to [`SinkError::Unavailable`]
+/// only, and retries run in the background, so a retried notification may be
+/// published after later ones. Give the sink its own timeout; a timeout of the
+/// handler's registration covers the cache handler and the first attempt.
+///
+/// # Example
+/// ```rust,ignore
+/// let sink: Arc<dyn NotificationSink> = Arc::new(NatsSink(client));
+/// let users = SinkHandler::wrapping(Arc::new(IndexCacheHandler::for_type(user_cache.clone())), sink.clone())
+///     .with_retry(RetryPolicy::new(5))
+///     .with_dead_letter(Arc::new(|notification, reason| tracing::error!(?notification, reason, "not mirrored")));
+/// listener.register_handler(Arc::new(users));
+/// ```
+pub struct SinkHandler {
+    inner: Option<Arc<dyn CacheNotificationHandler>>,
+    state: Arc<SinkState>,
+}
+
+impl SinkHandler {
+    /// Create a handler publishing the notifications of a table that is not cached
+    pub fn new(table_name: String, sink: Arc<dyn NotificationSink>) -> Self {
+        Self {
+            inner: None,
+            state: Arc::new(SinkState {
+                table_name,
+                sink,
+                retry: None,
+                dead_letter: None,
+                published: AtomicU64::new(0),
+                failures: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Create a handler publishing the notifications of every table, see [`ALL_TABLES`]
+    pub fn for_all_tables(sink: Arc<dyn NotificationSink>) -> Self {
+        Self::new(ALL_TABLES.to_string(), sink)
+    }
+
+    /// Create a handler passing every notification to the table's cache handler
+    /// and publishing it once that handler returned
+    pub fn wrapping(inner: Arc<dyn CacheNotificationHandler>, sink: Arc<dyn NotificationSink>) -> Self {
+        let table_name = inner.table_name().to_string();
+        Self { inner: Some(inner), ..Self::new(table_name, sink) }
+    }
+
+    /// Retry publishing while the sink is unavailable
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.state_mut().retry = Some(retry);
+        self
+    }
+
+    /// Pass notifications the sink did not take to `hook`, with the reason
+    pub fn with_dead_letter(mut self, hook: Arc<DeadLetterHook>) -> Self {
+        self.state_mut().dead_letter = Some(hook);
+        self
+    }
+
+    /// How many notifications were published, retries included
+    pub fn published(&self) -> u64 {
+        self.state.published.load(Ordering::Relaxed)
+    }
+
+    /// How many notifications were given up on
+    pub fn failures(&self) -> u64 {
+        self.state.failures.load(Ordering::Relaxed)
+    }
+
+    /// Publishes a notification, handing failures to the retry task
+    async fn publish(&self, notification: CacheNotification) {
+        match self.state.sink.publish(&notification).await {
+            Ok(()) => {
+                self.state.published.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => self.state.clone().retry_or_give_up(notification, err),
+        }
+    }
+
+    /// The state is only shared once a retry task runs, never while building
+    fn state_mut(&mut self) -> &mut SinkState {
+        Arc::get_mut(&mut self.state).expect("sink state is not shared while building")
+    }
+}
+
+#[async_trait]
+impl CacheNotificationHandler for SinkHandler {
+    async fn handle_notification(&self, notification: CacheNotification) {
+        if let Some(handler) = &self.inner {
+            handler.handle_notification(notification.clone()).await;
+        }
+        self.publish(notification).await;
+    }
+
+    /// Passes the whole batch to the cache handler, then publishes it in order
+    async fn handle_notifications(&self, notifications: Vec<CacheNotification>) {
+        if let Some(handler) = &self.inner {
+            handler.handle_notifications(notifications.clone()).await;
+        }
+        for notification in notifications {
+            self.publish(notification).await;
+        }
+    }
+
+    fn table_name(&self) -> &str {
+        &self.state.table_name
+    }
+
+    fn last_applied(&self) -> Option<AppliedInfo> {
+        self.inner.as_ref().and_then(|handler| handler.last_applied())
+    }
+
+    fn mark_loaded(&self) {
+        if let Some(handler) = &self.inner {
+            handler.mark_loaded();
+        }
+    }
+
+    async fn mark_ready(&self) {
+        if let Some(handler) = &self.inner {
+            handler.mark_ready().await;
+        }
+    }
+
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        self.inner.as_ref().and_then(|handler| handler.expected_fields())
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::error::{CacheError, CacheResult};
+use crate::traits::HasPrimaryKey;
+
+/// A staged change, applied to the shared cache in staging order on commit
+#[derive(Debug, Clone, PartialEq)]
+pub enum StagedOp<T, K = Uuid> {
+    /// Add an item to the cache
+    Add(T),
+    /// Replace a cached item
+    Update(T),
+    /// Remove the item with this primary key
+    Remove(K),
+}
+
+impl<T: HasPrimaryKey> StagedOp<T> {
+    /// The primary key the change applies to
+    pub fn primary_key(&self) -> Uuid {
+        match self {
+            StagedOp::Add(item) | StagedOp::Update(item) => item.primary_key(),
+            StagedOp::Remove(primary_key) => *primary_key,
+        }
+    }
+}
+
+/// Where a transaction-aware cache found an item it returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadSource {
+    /// An item added in this transaction
+    StagedAddition,
+    /// A staged update of an item
+    StagedUpdate,
+    /// The shared cache, or its snapshot in snapshot mode; write-through
+    /// changes are read from there as well
+    Shared,
+}
+
+/// A snapshot of the changes a transaction-aware cache applies on commit
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagedChanges<T, K = Uuid> {
+    /// Items staged for addition
+    pub additions: Vec<T>,
+    /// Items staged for update
+    pub updates: Vec<T>,
+    /// Primary keys staged for deletion
+    pub deletions: Vec<K>,
+}
+
+impl<T, K> StagedChanges<T, K> {
+    /// Returns true if no changes are staged
+    pub fn is_empty(&self) -> bool {
+        self.additions.is_empty() && self.updates.is_empty() && self.deletions.is_empty()
+    }
+
+    /// Returns the total number of staged changes
+    pub fn len(&self) -> usize {
+        self.additions.len() + self.updates.len() + self.deletions.len()
+    }
+}
+
+impl<T, K> From<Vec<StagedOp<T, K>>> for StagedChanges<T, K> {
+    fn from(ops: Vec<StagedOp<T, K>>) -> Self {
+        let mut changes = StagedChanges {
+            additions: Vec::new(),
+            updates: Vec::new(),
+            deletions: Vec::new(),
+        };
+        for op in ops {
+            match op {
+                StagedOp::Add(item) => changes.additions.push(item),
+                StagedOp::Update(item) => changes.updates.push(item),
+                StagedOp::Remove(primary_key) => changes.deletions.push(primary_key),
+            }
+        }
+        changes
+    }
+}
+
+/// The most a transaction-aware cache stages through its `try_*` methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StagingLimit {
+    /// Staged additions, updates and deletions, or changes written through
+    Operations(usize),
+    /// Estimated bytes, see `StagedSize::estimated_bytes`
+    Bytes(usize),
+}
+
+impl StagingLimit {
+    /// Fails with `CacheError::CapacityExceeded` if `size` is beyond the limit
+    pub(crate) fn check(self, size: StagedSize) -> CacheResult<()> {
+        match self {
+            StagingLimit::Operations(limit) if size.operations > limit => Err(CacheError::CapacityExceeded { limit }),
+            StagingLimit::Bytes(limit) if size.estimated_bytes > limit => Err(CacheError::CapacityExceeded { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How much a transaction-aware cache holds until commit or rollback
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StagedSize {
+    /// Staged additions, updates and deletions, or changes written through
+    pub operations: usize,
+    /// The in-memory size of the items and deleted keys held, excluding data
+    /// the items point to, such as the contents of strings
+    pub estimated_bytes: usize,
+}
+
+impl StagedSize {
+    pub(crate) fn new<T, K>(items: usize, deletions: usize) -> Self {
+        Self {
+            operations: items + deletions,
+            estimated_bytes: items * std::mem::size_of::<T>() + deletions * std::mem::size_of::<K>(),
+        }
+    }
+}
+
+/// The changes a commit applied to the shared cache
+///
+/// Changes skipped because of a version conflict are not included. In
+/// write-through mode, each key written in the transaction yields its net
+/// change.
+pub type AppliedChanges<T, K = Uuid> = StagedChanges<T, K>;
+
+/// Called with the changes a commit applied, once the shared cache is unlocked
+pub type AfterCommitHook<T, K = Uuid> = dyn Fn(&AppliedChanges<T, K>) + Send + Sync;
+
+/// The after-commit hooks of a transaction-aware cache
+pub(crate) struct AfterCommitHooks<T, K = Uuid> {
+    hooks: Vec<Arc<AfterCommitHook<T, K>>>,
+    /// Whether the hooks also run for commits that applied nothing
+    pub(crate) on_empty: bool,
+}
+
+impl<T, K> Default for AfterCommitHooks<T, K> {
+    fn default() -> Self {
+        Self { hooks: Vec::new(), on_empty: false }
+    }
+}
+
+impl<T, K> AfterCommitHooks<T, K> {
+    pub(crate) fn add(&mut self, hook: Arc<AfterCommitHook<T, K>>) {
+        self.hooks.push(hook);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Runs every hook, logging the ones that panic
+    pub(crate) fn run(&self, changes: AppliedChanges<T, K>, cache: Option<&str>) {
+        if changes.is_empty() && !self.on_empty {
+            return;
+        }
+        for hook in &self.hooks {
+            if std::panic::catch_unwind(AssertUnwindSafe(|| hook(&changes))).is_err() {
+                tracing::error!(cache, changes = changes.len(), "after-commit hook panicked");
+            }
+        }
+    }
+}
+
+/// The order in which keys were last staged
+///
+/// Staged changes are kept per key, so a key staged several times yields a
+/// single change, ordered by its most recent staging.
+#[derive(Debug)]
+pub(crate) struct StagingOrder<K = Uuid> {
+    next: u64,
+    positions: HashMap<K, u64>,
+}
+
+impl<K> Default for StagingOrder<K> {
+    fn default() -> Self {
+        Self { next: 0, positions: HashMap::new() }
+    }
+}
+
+impl<K: Eq + Hash + Clone> StagingOrder<K> {
+    /// Moves a key to the end of the staging order
+    pub(crate) fn touch(&mut self, primary_key: K) {
+        self.positions.insert(primary_key, self.next);
+        self.next += 1;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.positions.clear();
+        self.next = 0;
+    }
+
+    /// Builds the changes for all staged keys in staging order
+    ///
+    /// Keys whose changes cancelled out, such as an addition that was removed
+    /// again, produce no change.
+    pub(crate) fn ops<T: Clone>(
+        &self,
+        additions: &HashMap<K, T>,
+        updates: &HashMap<K, T>,
+        deletions: &HashSet<K>,
+    ) -> Vec<StagedOp<T, K>> {
+        let mut keys: Vec<(&K, &u64)> = self.positions.iter().collect();
+        keys.sort_by_key(|(_, position)| **position);
+        keys.into_iter()
+            .filter_map(|(primary_key, _)| {
+                if let Some(item) = additions.get(primary_key) {
+                    Some(StagedOp::Add(item.clone()))
+                } else if let Some(item) = updates.get(primary_key) {
+                    Some(StagedOp::Update(item.clone()))
+                } else if deletions.contains(primary_key) {
+                    Some(StagedOp::Remove(primary_key.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Like `ops`, but moves the changes out of the staging maps instead of
+    /// cloning them, leaving the maps and the order empty
+    pub(crate) fn take_ops<T>(
+        &mut self,
+        additions: &mut HashMap<K, T>,
+        updates: &mut HashMap<K, T>,
+        deletions: &mut HashSet<K>,
+    ) -> Vec<StagedOp<T, K>> {
+        let mut keys: Vec<(K, u64)> = self.positions.drain().collect();
+        keys.sort_by_key(|(_, position)| *position);
+        self.next = 0;
+        let ops = keys
+            .into_iter()
+            .filter_map(|(primary_key, _)| {
+                if let Some(item) = additions.remove(&primary_key) {
+                    Some(StagedOp::Add(item))
+                } else if let Some(item) = updates.remove(&primary_key) {
+                    Some(StagedOp::Update(item))
+                } else if deletions.remove(&primary_key) {
+                    Some(StagedOp::Remove(primary_key))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        additions.clear();
+        updates.clear();
+        deletions.clear();
+        ops
+    }
+}
+
+/// The state of a key before a write-through change, restored on rollback
+#[derive(Debug)]
+pub(crate) struct UndoEntry<T, K = Uuid> {
+    pub(crate) primary_key: K,
+    /// The item cached before the change, or None if there was none
+    pub(crate) previous: Option<T>,
+}
+
+/// The net change of each key written through, in the order the keys were
+/// first written, given the items now cached
+pub(crate) fn write_through_ops<T, K: Eq + Hash + Clone>(
+    undo_entries: Vec<UndoEntry<T, K>>,
+    current: impl Fn(&K) -> Option<T>,
+) -> Vec<StagedOp<T, K>> {
+    let mut seen = HashSet::new();
+    undo_entries
+        .into_iter()
+        .filter(|entry| seen.insert(entry.primary_key.clone()))
+        .filter_map(|entry| match (entry.previous, current(&entry.primary_key)) {
+            (None, Some(item)) => Some(StagedOp::Add(item)),
+            (Some(_), Some(item)) => Some(StagedOp::Update(item)),
+            (Some(_), None) => Some(StagedOp::Remove(entry.primary_key)),
+            (None, None) => None,
+        })
+        .collect()
+}
@@ -0,0 +1,281 @@
+//! Two-level model cache: an in-memory front backed by a shared Redis tier
+//!
+//! Reads check the in-memory [`MainModelCache`] first, then Redis. Writes and
+//! invalidations go to both tiers. Redis failures are logged and the cache
+//! keeps working from memory only.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::listener::{CacheNotification, CacheNotificationHandler};
+use crate::main_model_cache::{MainModelCache, MainModelCacheHandler};
+use crate::traits::{HasPrimaryKey, HasTableName, IsDeleted, Versioned};
+
+/// Configuration of the Redis tier
+#[derive(Debug, Clone)]
+pub struct RedisTierConfig {
+    /// Prefix of the Redis keys, followed by `:` and the primary key
+    pub key_prefix: String,
+    /// Optional time-to-live of the Redis entries
+    pub ttl: Option<Duration>,
+}
+
+impl RedisTierConfig {
+    /// Create a new configuration with the given key prefix and no TTL
+    pub fn new(key_prefix: impl Into<String>) -> Self {
+        Self {
+            key_prefix: key_prefix.into(),
+            ttl: None,
+        }
+    }
+
+    /// Set the time-to-live of the Redis entries
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+/// A `MainModelCache` backed by a Redis tier shared between replicas
+pub struct TieredModelCache<T: HasPrimaryKey + Clone + Send + Sync + 'static> {
+    memory: Arc<RwLock<MainModelCache<T>>>,
+    redis: ConnectionManager,
+    config: RedisTierConfig,
+}
+
+impl<T> TieredModelCache<T>
+where
+    T: HasPrimaryKey + Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Create a tiered cache in front of the given in-memory cache and Redis connection
+    pub fn new(memory: Arc<RwLock<MainModelCache<T>>>, redis: ConnectionManager, config: RedisTierConfig) -> Self {
+        Self { memory, redis, config }
+    }
+
+    /// Gets the in-memory tier
+    pub fn memory(&self) -> &Arc<RwLock<MainModelCache<T>>> {
+        &self.memory
+    }
+
+    /// Gets the Redis tier configuration
+    pub fn config(&self) -> &RedisTierConfig {
+        &self.config
+    }
+
+    /// Gets the Redis key of an item
+    pub fn redis_key(&self, primary_key: &Uuid) -> String {
+        format!("{}:{}", self.config.key_prefix, primary_key)
+    }
+
+    /// Gets an item from memory, then from Redis; `None` is a miss in both tiers
+    ///
+    /// An item found in Redis is copied into memory.
+    pub async fn get(&self, primary_key: &Uuid) -> Option<Arc<T>> {
+        let cached = self.memory.write().get(primary_key);
+        if cached.is_some() {
+            return cached;
+        }
+
+        let key = self.redis_key(primary_key);
+        let mut redis = self.redis.clone();
+        let payload = match redis.get::<_, Option<String>>(&key).await {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::warn!(key = %key, error = %err, "TieredModelCache: Redis read failed, using memory only");
+                return None;
+            }
+        };
+
+        match serde_json::from_str::<T>(&payload?) {
+            Ok(item) => {
+                let item = Arc::new(item);
+                self.memory.write().insert(Arc::clone(&item));
+                Some(item)
+            }
+            Err(err) => {
+                tracing::warn!(key = %key, error = %err, "TieredModelCache: ignoring undecodable Redis entry");
+                None
+            }
+        }
+    }
+
+    /// Inserts an item into both tiers
+    pub async fn insert(&self, item: impl Into<Arc<T>>) {
+        let item = item.into();
+        self.memory.write().insert(Arc::clone(&item));
+        self.store(&item).await;
+    }
+
+    /// Updates an item in both tiers
+    pub async fn update(&self, item: impl Into<Arc<T>>) {
+        let item = item.into();
+        self.memory.write().update(Arc::clone(&item));
+        self.store(&item).await;
+    }
+
+    /// Removes an item from both tiers
+    /// Returns the item removed from memory, if any
+    pub async fn remove(&self, primary_key: &Uuid) -> Option<Arc<T>> {
+        let removed = self.memory.write().remove(primary_key);
+        self.delete_key(primary_key).await;
+        removed
+    }
+
+    /// Clears both tiers, deleting every Redis key under the prefix
+    pub async fn clear(&self) {
+        self.memory.write().clear();
+        if let Err(err) = self.delete_all().await {
+            tracing::warn!(
+                prefix = %self.config.key_prefix,
+                error = %err,
+                "TieredModelCache: Redis clear failed, using memory only"
+            );
+        }
+    }
+
+    /// Deletes the Redis key of an item
+    pub(crate) async fn delete_key(&self, primary_key: &Uuid) {
+        let key = self.redis_key(primary_key);
+        let mut redis = self.redis.clone();
+        if let Err(err) = redis.del::<_, ()>(&key).await {
+            tracing::warn!(key = %key, error = %err, "TieredModelCache: Redis delete failed, using memory only");
+        }
+    }
+
+    /// Writes an item to Redis
+    async fn store(&self, item: &T) {
+        let key = self.redis_key(&item.primary_key());
+        let payload = match serde_json::to_string(item) {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::warn!(key = %key, error = %err, "TieredModelCache: item not written to Redis");
+                return;
+            }
+        };
+
+        let mut redis = self.redis.clone();
+        let result = match self.config.ttl {
+            Some(ttl) => redis.set_ex::<_, _, ()>(&key, payload, ttl.as_secs().max(1)).await,
+            None => redis.set::<_, _, ()>(&key, payload).await,
+        };
+        if let Err(err) = result {
+            tracing::warn!(key = %key, error = %err, "TieredModelCache: Redis write failed, using memory only");
+        }
+    }
+
+    /// Deletes every Redis key under the prefix
+    async fn delete_all(&self) -> redis::RedisResult<()> {
+        let pattern = format!("{}:*", self.config.key_prefix);
+        let mut redis = self.redis.clone();
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(100)
+                .query_async(&mut redis)
+                .await?;
+            if !keys.is_empty() {
+                redis.del::<_, ()>(keys).await?;
+            }
+            if next == 0 {
+                return Ok(());
+            }
+            cursor = next;
+        }
+    }
+}
+
+/// A notification handler for a `TieredModelCache`
+///
+/// Updates the in-memory tier like `MainModelCacheHandler` and deletes the
+/// Redis key of every changed row, so replicas reload it on their next read.
+pub struct TieredModelCacheHandler<T: HasPrimaryKey + Clone + Debug + Send + Sync + 'static> {
+    memory: MainModelCacheHandler<T>,
+    cache: Arc<TieredModelCache<T>>,
+}
+
+impl<T> TieredModelCacheHandler<T>
+where
+    T: HasPrimaryKey + Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Create a new handler for the given cache
+    pub fn new(table_name: String, cache: Arc<TieredModelCache<T>>) -> Self {
+        let memory = MainModelCacheHandler::new(table_name, Arc::clone(cache.memory()));
+        Self { memory, cache }
+    }
+
+    /// Treat inserts and updates of soft-deleted items as removals
+    pub fn remove_deleted(mut self) -> Self
+    where
+        T: IsDeleted,
+    {
+        self.memory = self.memory.remove_deleted();
+        self
+    }
+
+    /// Ignore inserts and updates older than the cached version of the item
+    pub fn skip_stale_versions(mut self) -> Self
+    where
+        T: Versioned,
+    {
+        self.memory = self.memory.skip_stale_versions();
+        self
+    }
+
+    /// Create a new handler for the table of the cached type
+    pub fn for_type(cache: Arc<TieredModelCache<T>>) -> Self
+    where
+        T: HasTableName,
+    {
+        Self::new(T::table_name().to_string(), cache)
+    }
+}
+
+#[async_trait]
+impl<T> CacheNotificationHandler for TieredModelCacheHandler<T>
+where
+    T: HasPrimaryKey + Clone + Debug + Serialize + DeserializeOwned + Send + Sync + Debug + 'static,
+{
+    async fn handle_notification(&self, notification: CacheNotification) {
+        match notification.action.as_str() {
+            "insert" | "update" | "delete" => {
+                self.cache.delete_key(&notification.id).await;
+                tracing::debug!("TieredModelCache: Deleted Redis key of item {}", notification.id);
+            }
+            "truncate" | "flush" => {
+                if let Err(err) = self.cache.delete_all().await {
+                    tracing::warn!(
+                        table = %notification.table,
+                        error = %err,
+                        "TieredModelCache: Redis clear failed, using memory only"
+                    );
+                }
+            }
+            _ => {}
+        }
+        self.memory.handle_notification(notification).await;
+    }
+
+    fn table_name(&self) -> &str {
+        self.memory.table_name()
+    }
+
+    async fn mark_ready(&self) {
+        self.memory.mark_ready().await;
+    }
+
+    fn expected_fields(&self) -> Option<Vec<String>> {
+        self.memory.expected_fields()
+    }
+}
@@ -1,4 +1,7 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::Arc;
+use smallvec::SmallVec;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -8,15 +11,125 @@ pub trait HasPrimaryKey {
     fn primary_key(&self) -> Uuid;
 }
 
+impl<T: HasPrimaryKey + ?Sized> HasPrimaryKey for Arc<T> {
+    fn primary_key(&self) -> Uuid {
+        (**self).primary_key()
+    }
+}
+
+/// The key a `MainModelCache` stores a model under.
+///
+/// Every `HasPrimaryKey` type is keyed by its `Uuid`. Models with a natural
+/// key, such as a country code, implement `CacheKey<String>` instead and are
+/// cached in a `MainModelCache<T, String>`.
+pub trait CacheKey<K> {
+    /// Returns the cache key of the model.
+    fn cache_key(&self) -> K;
+}
+
+impl<T: HasPrimaryKey + ?Sized> CacheKey<Uuid> for T {
+    fn cache_key(&self) -> Uuid {
+        self.primary_key()
+    }
+}
+
+/// Secondary keys of one item, each paired with the name of its index.
+/// Up to four keys are stored without allocating; names are usually
+/// `Cow::Borrowed` string literals.
+pub type IndexKeys<V> = SmallVec<[(Cow<'static, str>, Option<V>); 4]>;
+
 /// A trait for models that have secondary indexes.
+///
+/// Implement the `*_index_keys` methods. Implementations of the deprecated
+/// map-returning methods keep working: the `*_index_keys` defaults convert
+/// their maps.
 pub trait Indexable {
+    /// Returns the i64 secondary keys.
+    #[allow(deprecated)]
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        keys_from_map(self.i64_keys())
+    }
+
+    /// Returns the Uuid secondary keys.
+    #[allow(deprecated)]
+    fn uuid_index_keys(&self) -> IndexKeys<Uuid> {
+        keys_from_map(self.uuid_keys())
+    }
+
+    /// Returns the DateTime secondary keys.
+    /// Defaults to no DateTime indexes.
+    #[allow(deprecated)]
+    fn datetime_index_keys(&self) -> IndexKeys<DateTime<Utc>> {
+        keys_from_map(self.datetime_keys())
+    }
+
+    /// Returns the String secondary keys, ordered for prefix queries.
+    /// Values are compared byte-wise, so normalize them, e.g. lowercase,
+    /// the way they are queried.
+    /// Defaults to no String indexes.
+    fn string_index_keys(&self) -> IndexKeys<String> {
+        IndexKeys::new()
+    }
+
     /// Returns a map of i64 secondary keys.
     /// The key of the map is the name of the index.
-    fn i64_keys(&self) -> HashMap<String, Option<i64>>;
+    #[deprecated(note = "implement `i64_index_keys` instead")]
+    fn i64_keys(&self) -> HashMap<String, Option<i64>> {
+        HashMap::new()
+    }
 
     /// Returns a map of Uuid secondary keys.
     /// The key of the map is the name of the index.
-    fn uuid_keys(&self) -> HashMap<String, Option<Uuid>>;
+    #[deprecated(note = "implement `uuid_index_keys` instead")]
+    fn uuid_keys(&self) -> HashMap<String, Option<Uuid>> {
+        HashMap::new()
+    }
+
+    /// Returns a map of DateTime secondary keys.
+    /// The key of the map is the name of the index.
+    #[deprecated(note = "implement `datetime_index_keys` instead")]
+    fn datetime_keys(&self) -> HashMap<String, Option<DateTime<Utc>>> {
+        HashMap::new()
+    }
+}
+
+fn keys_from_map<V>(map: HashMap<String, Option<V>>) -> IndexKeys<V> {
+    map.into_iter().map(|(name, value)| (Cow::Owned(name), value)).collect()
+}
+
+/// Finds the value of the named index among an item's keys.
+/// Returns None if the item has no such index.
+pub(crate) fn index_value<V: Copy>(keys: &IndexKeys<V>, index_name: &str) -> Option<Option<V>> {
+    keys.iter().find(|(name, _)| name == index_name).map(|(_, value)| *value)
+}
+
+/// Finds the value of the named String index among an item's keys.
+/// Like `index_value`, but borrows the value.
+pub(crate) fn string_index_value<'a>(keys: &'a IndexKeys<String>, index_name: &str) -> Option<Option<&'a str>> {
+    keys.iter().find(|(name, _)| name == index_name).map(|(_, value)| value.as_deref())
+}
+
+/// A trait for models stored in a known database table.
+/// Handlers and trigger installers read the table name from the type,
+/// so it is not repeated as a string at every construction site.
+pub trait HasTableName {
+    /// Returns the name of the table the model is stored in.
+    fn table_name() -> &'static str;
+}
+
+/// A trait for models carrying a row version, e.g. an optimistic-lock counter.
+/// When implemented, staged writes can be checked against the cached version
+/// at commit and notification handlers can skip out-of-order updates.
+pub trait Versioned {
+    /// Returns the version of the model; newer versions compare greater.
+    fn version(&self) -> u64;
+}
+
+/// A trait for models that are soft-deleted, e.g. through a `deleted_at` column.
+/// When implemented, handlers can drop deleted rows instead of caching them.
+pub trait IsDeleted {
+    /// Returns true if the model has been soft-deleted.
+    fn is_deleted(&self) -> bool;
 }
 
 /// A trait for models that have a validity start time.
@@ -33,4 +146,17 @@ pub trait ValidTo {
     /// Returns the timestamp until which this entity remains valid.
     /// If None, the entity is considered valid indefinitely.
     fn valid_to(&self) -> Option<DateTime<Utc>>;
-}
\ No newline at end of file
+}
+
+/// A trait combining the validity start and end time of a model.
+/// Implemented automatically for every type implementing both `ValidFrom` and `ValidTo`.
+pub trait Validity {
+    /// Returns the `(valid_from, valid_to)` range of this entity.
+    fn validity(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+}
+
+impl<T: ValidFrom + ValidTo> Validity for T {
+    fn validity(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        (self.valid_from(), self.valid_to())
+    }
+}
@@ -1,28 +1,75 @@
 use async_trait::async_trait;
-use parking_lot::RwLock;
+use chrono::{DateTime, Utc};
+use parking_lot::{RwLock, RwLockWriteGuard};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::index_cache::IdxModelCache;
-use crate::traits::{HasPrimaryKey, Indexable};
+use crate::coordinator::SharedCacheCoordinator;
+use crate::error::{CacheError, CacheResult, CommitError};
+use crate::index_cache::{datetime_bounds, datetime_key, IdxModelCache, IndexKind};
+use crate::lifecycle::{GenerationParticipant, Generational};
+use crate::lock::{block_on_parking_lot, CacheLock};
+use crate::scope::{DiscardChanges, TransactionScope};
+use crate::transaction_group::TransactionParticipant;
+use crate::staging::{
+    write_through_ops, AfterCommitHook, AfterCommitHooks, ReadSource, StagedChanges, StagedOp, StagedSize, StagingLimit,
+    StagingOrder, UndoEntry,
+};
+use crate::traits::{index_value, string_index_value, HasPrimaryKey, Indexable, Versioned};
+use crate::versioning::{version_of, CommitAnomaly, CommitReport, ConflictPolicy, VersionOf};
 use postgres_unit_of_work::{TransactionAware, TransactionResult};
 
 /// A trait alias for types that can be used in the cache
-pub trait IdxModel: Clone + HasPrimaryKey + Indexable + Send + Sync + Debug {}
-impl<T> IdxModel for T where T: Clone + HasPrimaryKey + Indexable + Send + Sync + Debug {}
+///
+/// The transaction-aware caches, the registries and the notification handlers
+/// all require it. Check a model against it next to its definition with
+/// [`assert_idx_model`](crate::assert_idx_model).
+pub trait IdxModel: Clone + HasPrimaryKey + Indexable + Send + Sync + Debug + 'static {}
+impl<T> IdxModel for T where T: Clone + HasPrimaryKey + Indexable + Send + Sync + Debug + 'static {}
 
 /// A transaction-aware wrapper around IdxModelCache that stages changes
 /// and applies them only on commit.
-pub struct TransactionAwareIdxModelCache<T>
+///
+/// The shared cache sits behind any [`CacheLock`], a `parking_lot::RwLock` by
+/// default. The blocking methods need the default lock; each has an `_async`
+/// variant that takes any lock, e.g. a `tokio::sync::RwLock`.
+pub struct TransactionAwareIdxModelCache<T, L = RwLock<IdxModelCache<T>>>
 where
     T: IdxModel,
+    L: CacheLock<IdxModelCache<T>>,
 {
-    shared_cache: Arc<RwLock<IdxModelCache<T>>>,
+    shared_cache: Arc<L>,
     local_additions: RwLock<HashMap<Uuid, T>>,
     local_updates: RwLock<HashMap<Uuid, T>>,
     local_deletions: RwLock<HashSet<Uuid>>,
+    staging_order: RwLock<StagingOrder>,
+    /// Copy of the shared cache that reads fall through to, in snapshot mode
+    snapshot: RwLock<Option<IdxModelCache<T>>>,
+    /// How to undo changes already applied, in write-through mode
+    undo_log: Option<RwLock<Vec<UndoEntry<T>>>>,
+    /// Set by commit and rollback, cleared when the next change is made
+    completed: AtomicBool,
+    /// Incremented by `begin` and `reset`
+    generation: AtomicU64,
+    version_of: Option<VersionOf<T>>,
+    conflict_policy: ConflictPolicy,
+    /// Cached version of each staged key when it was first staged
+    base_versions: RwLock<HashMap<Uuid, Option<u64>>>,
+    /// Defers notifications for keys this transaction has staged
+    coordinator: Option<Arc<SharedCacheCoordinator<T>>>,
+    /// Keys opened in the coordinator by this transaction
+    held_keys: RwLock<HashSet<Uuid>>,
+    /// Identifies the cache in logs; the shared cache's name by default
+    name: Option<String>,
+    after_commit: AfterCommitHooks<T>,
+    /// Whether keys staged for update or deletion must still be cached at commit
+    validate_presence: bool,
+    staging_limit: Option<StagingLimit>,
 }
 
 impl<T> TransactionAwareIdxModelCache<T>
@@ -31,42 +78,950 @@ where
 {
     /// Creates a new transaction-aware cache wrapper
     pub fn new(shared_cache: Arc<RwLock<IdxModelCache<T>>>) -> Self {
+        block_on_parking_lot(Self::new_async(shared_cache))
+    }
+
+    /// Creates a transaction-aware cache wrapper that detects version conflicts
+    ///
+    /// The cached version of each key is remembered when the key is first
+    /// staged. If another commit changed it in the meantime, `on_commit`
+    /// resolves the conflict according to `policy`.
+    pub fn with_conflict_policy(
+        shared_cache: Arc<RwLock<IdxModelCache<T>>>,
+        policy: ConflictPolicy,
+    ) -> Self
+    where
+        T: Versioned,
+    {
+        block_on_parking_lot(Self::with_conflict_policy_async(shared_cache, policy))
+    }
+
+    /// Creates a transaction-aware cache wrapper with snapshot-isolated reads
+    ///
+    /// The shared cache is cloned at construction and again after every
+    /// commit or rollback. Reads that are not answered by staged changes are
+    /// served from that copy, so repeated reads within one transaction see
+    /// the same items even while other writers update the shared cache.
+    /// Commits still apply to the live shared cache.
+    ///
+    /// This is opt-in because the snapshot holds on to the items and index
+    /// entries the shared cache has since replaced or removed, until the next
+    /// refresh.
+    pub fn new_with_snapshot(shared_cache: Arc<RwLock<IdxModelCache<T>>>) -> Self {
+        block_on_parking_lot(Self::new_with_snapshot_async(shared_cache))
+    }
+
+    /// Creates a transaction-aware cache wrapper that writes through to the shared cache
+    ///
+    /// Changes are applied to the shared cache immediately, so other readers
+    /// see them before the transaction ends. Each change records the previous
+    /// state of its key; `on_commit` discards those records and `on_rollback`
+    /// restores them in reverse order.
+    pub fn new_write_through(shared_cache: Arc<RwLock<IdxModelCache<T>>>) -> Self {
+        block_on_parking_lot(Self::new_write_through_async(shared_cache))
+    }
+
+    /// Creates a transaction-aware cache wrapper coordinated with notification handlers
+    ///
+    /// Notifications for keys this transaction has staged, delivered through
+    /// a handler sharing the coordinator, are applied only after the
+    /// transaction commits or rolls back, so a commit cannot overwrite them.
+    pub fn new_coordinated(coordinator: Arc<SharedCacheCoordinator<T>>) -> Self {
+        Self {
+            coordinator: Some(coordinator.clone()),
+            ..Self::new(coordinator.cache().clone())
+        }
+    }
+
+    /// Starts a new logical transaction on a reused wrapper
+    ///
+    /// Fails with `CacheError::OperationFailed` if changes from an earlier
+    /// transaction are still pending. Returns the new generation; participants
+    /// created by `participant()` for earlier generations can no longer commit.
+    pub fn begin(&self) -> CacheResult<u64> {
+        block_on_parking_lot(self.begin_async())
+    }
+
+    /// Discards all pending changes, undoing write-through changes, and
+    /// starts a new generation
+    pub fn reset(&self) -> u64 {
+        block_on_parking_lot(self.reset_async())
+    }
+
+    /// Starts a transaction whose changes are rolled back if the returned
+    /// guard is dropped before `commit`
+    pub fn scoped(&self) -> TransactionScope<'_, Self> {
+        self.completed.store(false, Ordering::SeqCst);
+        TransactionScope::new(self)
+    }
+
+    /// Commits like `on_commit`, returning the staged changes that no longer
+    /// matched the shared cache and what was done about them
+    ///
+    /// Anomalies are found only for versioned wrappers, created with
+    /// `with_conflict_policy`, or with `with_commit_validation` set. Under
+    /// `ConflictPolicy::Fail` they fail the commit with `CacheError::Conflict`.
+    pub fn commit_with_report(&self) -> CacheResult<CommitReport> {
+        block_on_parking_lot(self.commit_with_report_async())
+    }
+
+    /// Commits like `on_commit`, failing with each staged change that was not applied
+    ///
+    /// Under `ConflictPolicy::Fail`, each conflicting key fails with
+    /// `CacheError::Conflict` and nothing is applied, so `applied` is 0. The
+    /// staged changes are discarded all the same: they were made on top of
+    /// items that changed since, so committing them again would fail again.
+    /// Stage them anew on top of the current items instead. Unlike
+    /// `on_commit`, committing again after completion is no error.
+    pub fn try_commit(&self) -> Result<CommitReport, CommitError> {
+        block_on_parking_lot(self.try_commit_async())
+    }
+
+    /// Stages an item for addition to the cache
+    pub fn add(&self, item: T) {
+        block_on_parking_lot(self.add_async(item))
+    }
+
+    /// Like `add`, failing with `CacheError::CapacityExceeded` instead of
+    /// staging beyond the limit set by `with_staging_limit`
+    pub fn try_add(&self, item: T) -> CacheResult<()> {
+        block_on_parking_lot(self.try_add_async(item))
+    }
+
+    /// Like `update`, failing with `CacheError::CapacityExceeded` instead of
+    /// staging beyond the limit set by `with_staging_limit`
+    pub fn try_update(&self, item: T) -> CacheResult<Option<T>> {
+        block_on_parking_lot(self.try_update_async(item))
+    }
+
+    /// Stages an item for update in the cache
+    ///
+    /// Returns the value this transaction saw before the update, or `None`
+    /// if the item did not exist or was already staged for removal.
+    pub fn update(&self, item: T) -> Option<T> {
+        block_on_parking_lot(self.update_async(item))
+    }
+
+    /// Stages an item for removal from the cache
+    ///
+    /// Returns the value this transaction saw before the removal, or `None`
+    /// if the item did not exist or was already staged for removal.
+    pub fn remove(&self, primary_key: &Uuid) -> Option<T> {
+        block_on_parking_lot(self.remove_async(primary_key))
+    }
+
+    /// Stages many items for addition, taking each lock once rather than per item
+    pub fn add_all(&self, items: impl IntoIterator<Item = T>) {
+        block_on_parking_lot(self.add_all_async(items))
+    }
+
+    /// Stages many items for update, taking each lock once rather than per item
+    pub fn update_all(&self, items: impl IntoIterator<Item = T>) {
+        block_on_parking_lot(self.update_all_async(items))
+    }
+
+    /// Stages many keys for removal, taking each lock once rather than per key
+    pub fn remove_all(&self, primary_keys: &[Uuid]) {
+        block_on_parking_lot(self.remove_all_async(primary_keys))
+    }
+
+    /// Gets an item by primary key, considering staged changes
+    pub fn get_by_primary(&self, primary_key: &Uuid) -> Option<T> {
+        block_on_parking_lot(self.get_by_primary_async(primary_key))
+    }
+
+    /// Like `get_by_primary`, also telling where the item was found
+    pub fn get_by_primary_traced(&self, primary_key: &Uuid) -> Option<(T, ReadSource)> {
+        block_on_parking_lot(self.get_by_primary_traced_async(primary_key))
+    }
+
+    /// Where `get_by_primary` finds an item with this primary key, or `None`
+    /// if it finds none
+    pub fn read_source(&self, primary_key: &Uuid) -> Option<ReadSource> {
+        block_on_parking_lot(self.read_source_async(primary_key))
+    }
+
+    /// Like `get_by_i64_index`, with the source of each item
+    pub fn get_by_i64_index_traced(&self, key: &str, value: &i64) -> CacheResult<Vec<(T, ReadSource)>> {
+        block_on_parking_lot(self.get_by_i64_index_traced_async(key, value))
+    }
+
+    /// Like `get_by_uuid_index`, with the source of each item
+    pub fn get_by_uuid_index_traced(&self, key: &str, value: &Uuid) -> CacheResult<Vec<(T, ReadSource)>> {
+        block_on_parking_lot(self.get_by_uuid_index_traced_async(key, value))
+    }
+
+    /// Like `get_by_datetime_index`, with the source of each item
+    pub fn get_by_datetime_index_traced(&self, key: &str, value: &DateTime<Utc>) -> CacheResult<Vec<(T, ReadSource)>> {
+        block_on_parking_lot(self.get_by_datetime_index_traced_async(key, value))
+    }
+
+    /// Like `get_by_datetime_range`, with the source of each item
+    pub fn get_by_datetime_range_traced<R>(&self, key: &str, range: R) -> CacheResult<Vec<(T, ReadSource)>>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        block_on_parking_lot(self.get_by_datetime_range_traced_async(key, range))
+    }
+
+    /// Like `get_by_string_index`, with the source of each item
+    pub fn get_by_string_index_traced(&self, key: &str, value: &str) -> CacheResult<Vec<(T, ReadSource)>> {
+        block_on_parking_lot(self.get_by_string_index_traced_async(key, value))
+    }
+
+    /// Like `get_by_string_prefix`, with the source of each item
+    pub fn get_by_string_prefix_traced(&self, key: &str, prefix: &str, limit: usize) -> CacheResult<Vec<(T, ReadSource)>> {
+        block_on_parking_lot(self.get_by_string_prefix_traced_async(key, prefix, limit))
+    }
+
+    /// Gets items by i64 index, considering staged changes
+    ///
+    /// Fails with `CacheError::IndexNotFound` if neither the shared cache nor
+    /// a staged item has an i64 index with this name.
+    pub fn get_by_i64_index(&self, key: &str, value: &i64) -> CacheResult<Vec<T>> {
+        block_on_parking_lot(self.get_by_i64_index_async(key, value))
+    }
+
+    /// Gets items by uuid index, considering staged changes
+    ///
+    /// Fails with `CacheError::IndexNotFound` if neither the shared cache nor
+    /// a staged item has a Uuid index with this name.
+    pub fn get_by_uuid_index(&self, key: &str, value: &Uuid) -> CacheResult<Vec<T>> {
+        block_on_parking_lot(self.get_by_uuid_index_async(key, value))
+    }
+
+    /// Gets items by DateTime index, considering staged changes
+    ///
+    /// Fails with `CacheError::IndexNotFound` if neither the shared cache nor
+    /// a staged item has a DateTime index with this name.
+    pub fn get_by_datetime_index(&self, key: &str, value: &DateTime<Utc>) -> CacheResult<Vec<T>> {
+        block_on_parking_lot(self.get_by_datetime_index_async(key, value))
+    }
+
+    /// Gets items whose DateTime index value lies within a range, considering staged changes.
+    /// Items are ordered by that value.
+    ///
+    /// Fails with `CacheError::IndexNotFound` like `get_by_datetime_index`.
+    pub fn get_by_datetime_range<R>(&self, key: &str, range: R) -> CacheResult<Vec<T>>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        block_on_parking_lot(self.get_by_datetime_range_async(key, range))
+    }
+
+    /// Gets items by String index, considering staged changes
+    ///
+    /// Fails with `CacheError::IndexNotFound` if neither the shared cache nor
+    /// a staged item has a String index with this name.
+    pub fn get_by_string_index(&self, key: &str, value: &str) -> CacheResult<Vec<T>> {
+        block_on_parking_lot(self.get_by_string_index_async(key, value))
+    }
+
+    /// Gets at most `limit` items whose String index value starts with a prefix,
+    /// considering staged changes. Items are ordered by that value.
+    ///
+    /// Fails with `CacheError::IndexNotFound` like `get_by_string_index`.
+    pub fn get_by_string_prefix(&self, key: &str, prefix: &str, limit: usize) -> CacheResult<Vec<T>> {
+        block_on_parking_lot(self.get_by_string_prefix_async(key, prefix, limit))
+    }
+
+    /// Checks if the cache contains an item by primary key, considering staged changes
+    pub fn contains_primary(&self, primary_key: &Uuid) -> bool {
+        block_on_parking_lot(self.contains_primary_async(primary_key))
+    }
+}
+
+impl<T, L> TransactionAwareIdxModelCache<T, L>
+where
+    T: IdxModel,
+    L: CacheLock<IdxModelCache<T>>,
+{
+    /// Like `new`, for a shared cache behind any lock
+    pub async fn new_async(shared_cache: Arc<L>) -> Self {
+        let name = shared_cache.read().await.name().map(str::to_string);
         Self {
             shared_cache,
             local_additions: RwLock::new(HashMap::new()),
             local_updates: RwLock::new(HashMap::new()),
             local_deletions: RwLock::new(HashSet::new()),
+            staging_order: RwLock::new(StagingOrder::default()),
+            snapshot: RwLock::new(None),
+            undo_log: None,
+            completed: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            version_of: None,
+            conflict_policy: ConflictPolicy::default(),
+            base_versions: RwLock::new(HashMap::new()),
+            coordinator: None,
+            held_keys: RwLock::new(HashSet::new()),
+            name,
+            after_commit: AfterCommitHooks::default(),
+            validate_presence: false,
+            staging_limit: None,
         }
     }
 
-    /// Stages an item for addition to the cache
-    pub fn add(&self, item: T) {
+    /// Like `with_conflict_policy`, for a shared cache behind any lock
+    pub async fn with_conflict_policy_async(shared_cache: Arc<L>, policy: ConflictPolicy) -> Self
+    where
+        T: Versioned,
+    {
+        Self {
+            version_of: Some(version_of::<T>()),
+            conflict_policy: policy,
+            ..Self::new_async(shared_cache).await
+        }
+    }
+
+    /// Like `new_with_snapshot`, for a shared cache behind any lock
+    pub async fn new_with_snapshot_async(shared_cache: Arc<L>) -> Self {
+        let snapshot = IdxModelCache::clone(&*shared_cache.read().await);
+        Self {
+            snapshot: RwLock::new(Some(snapshot)),
+            ..Self::new_async(shared_cache).await
+        }
+    }
+
+    /// Like `new_write_through`, for a shared cache behind any lock
+    pub async fn new_write_through_async(shared_cache: Arc<L>) -> Self {
+        Self {
+            undo_log: Some(RwLock::new(Vec::new())),
+            ..Self::new_async(shared_cache).await
+        }
+    }
+
+    /// Names the wrapper in log lines instead of the shared cache's name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// The name logged in the `cache` field
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Calls `hook` with the changes each commit applied, e.g. to publish
+    /// application events
+    ///
+    /// Hooks run after the shared cache is unlocked, and neither on rollback
+    /// nor for commits that applied nothing, unless `run_hooks_on_empty_commit`
+    /// is set. A panicking hook is logged and leaves the cache and the other
+    /// hooks unaffected.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let mut cache = TransactionAwareIdxModelCache::new(shared.clone());
+    /// cache.on_after_commit(Arc::new(|changes: &AppliedChanges<User>| {
+    ///     for user in &changes.additions {
+    ///         events.publish(UserCreated(user.id));
+    ///     }
+    /// }));
+    /// ```
+    pub fn on_after_commit(&mut self, hook: Arc<AfterCommitHook<T>>) {
+        self.after_commit.add(hook);
+    }
+
+    /// Also run the after-commit hooks for commits that applied no change
+    pub fn run_hooks_on_empty_commit(&mut self) {
+        self.after_commit.on_empty = true;
+    }
+
+    /// Checks at commit that keys staged for update or deletion are still
+    /// cached, resolving the ones that are not according to `policy`
+    ///
+    /// Without it, an update staged for a key that a notification deleted in
+    /// the meantime re-adds the row on commit. `policy` replaces the one
+    /// given to `with_conflict_policy`, which then covers version conflicts
+    /// too; with `LastWriteWins`, anomalies are only reported by
+    /// `commit_with_report`.
+    pub fn with_commit_validation(mut self, policy: ConflictPolicy) -> Self {
+        self.validate_presence = true;
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// Limits what `try_add` and `try_update` stage, so a repository can stop
+    /// caching a transaction that grows too large
+    ///
+    /// `add` and `update` stage regardless of the limit.
+    pub fn with_staging_limit(mut self, limit: StagingLimit) -> Self {
+        self.staging_limit = Some(limit);
+        self
+    }
+
+    /// The limit set by `with_staging_limit`
+    pub fn staging_limit(&self) -> Option<StagingLimit> {
+        self.staging_limit
+    }
+
+    /// Like `begin`, for a shared cache behind any lock
+    pub async fn begin_async(&self) -> CacheResult<u64> {
+        if self.is_dirty() {
+            return Err(CacheError::OperationFailed(
+                "cannot begin a transaction while changes are pending; commit, roll back or reset first".to_string(),
+            ));
+        }
+        self.refresh_snapshot(&*self.shared_cache.read().await);
+        self.completed.store(false, Ordering::SeqCst);
+        Ok(self.generation.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    /// Like `reset`, for a shared cache behind any lock
+    pub async fn reset_async(&self) -> u64 {
+        self.rollback_changes().await;
+        self.completed.store(false, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Returns the current transaction generation
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns a participant to register with a unit of work for the current generation
+    ///
+    /// Unlike registering the wrapper itself, the participant refuses to
+    /// commit once `begin` or `reset` has started a later transaction.
+    pub fn participant(self: &Arc<Self>) -> TransactionParticipant
+    where
+        T: 'static,
+        L: 'static,
+    {
+        Arc::new(GenerationParticipant::new(self.clone()))
+    }
+
+    /// Returns true once the transaction was committed or rolled back and
+    /// no change has been made since
+    pub fn is_completed(&self) -> bool {
+        self.completed.load(Ordering::SeqCst)
+    }
+
+    /// Undoes write-through changes and discards staged ones
+    async fn rollback_changes(&self) {
+        if let Some(undo_log) = &self.undo_log {
+            let mut shared = self.shared_cache.write().await;
+            shared.batch(|cache| {
+                for entry in undo_log.write().drain(..).rev() {
+                    match entry.previous {
+                        Some(item) => cache.update(item),
+                        None => {
+                            cache.remove(&entry.primary_key);
+                        }
+                    }
+                }
+            });
+            self.release_keys(&mut shared);
+        }
+        self.clear_staged();
+        self.refresh_snapshot(&*self.shared_cache.read().await);
+        self.completed.store(true, Ordering::SeqCst);
+    }
+
+    /// Opens a key in the coordinator the first time this transaction stages it
+    fn hold_key(&self, primary_key: Uuid) {
+        self.hold_keys([primary_key]);
+    }
+
+    /// Like `hold_key`, for many keys under one lock
+    fn hold_keys(&self, primary_keys: impl IntoIterator<Item = Uuid>) {
+        if let Some(coordinator) = &self.coordinator {
+            let mut held_keys = self.held_keys.write();
+            for primary_key in primary_keys {
+                if held_keys.insert(primary_key) {
+                    coordinator.open(primary_key);
+                }
+            }
+        }
+    }
+
+    /// Closes the held keys, applying notifications deferred for them
+    fn release_keys(&self, shared: &mut IdxModelCache<T>) {
+        if let Some(coordinator) = &self.coordinator {
+            let keys: Vec<Uuid> = self.held_keys.write().drain().collect();
+            coordinator.release(keys, shared);
+        }
+    }
+
+    /// In write-through mode, records how to undo a change to a key and
+    /// returns the locked shared cache to apply it to, with the key's previous value
+    async fn write_through(&self, primary_key: Uuid) -> Option<(L::WriteGuard<'_>, Option<T>)> {
+        let undo_log = self.undo_log.as_ref()?;
+        let shared = self.shared_cache.write().await;
+        let previous = shared.get_by_primary(&primary_key);
+        undo_log.write().push(UndoEntry {
+            primary_key,
+            previous: previous.clone(),
+        });
+        Some((shared, previous))
+    }
+
+    /// In write-through mode, locks the shared cache and the undo log once
+    /// for a batch of changes
+    #[allow(clippy::type_complexity)]
+    async fn write_through_batch(&self) -> Option<(L::WriteGuard<'_>, RwLockWriteGuard<'_, Vec<UndoEntry<T>>>)> {
+        let undo_log = self.undo_log.as_ref()?;
+        let shared = self.shared_cache.write().await;
+        Some((shared, undo_log.write()))
+    }
+
+    /// Runs a read against the snapshot in snapshot mode, or the shared cache otherwise
+    async fn read_base<R>(&self, read: impl FnOnce(&IdxModelCache<T>) -> R) -> R {
+        if let Some(snapshot) = self.snapshot.read().as_ref() {
+            return read(snapshot);
+        }
+        read(&*self.shared_cache.read().await)
+    }
+
+    /// Takes a fresh snapshot of the shared cache, if in snapshot mode
+    fn refresh_snapshot(&self, shared: &IdxModelCache<T>) {
+        if let Some(snapshot) = self.snapshot.write().as_mut() {
+            *snapshot = shared.clone();
+        }
+    }
+
+    /// Remembers the cached version of a key the first time it is staged
+    async fn record_base_version(&self, primary_key: Uuid) {
+        self.record_base_versions(&[primary_key]).await;
+    }
+
+    /// Like `record_base_version`, for many keys under one lock
+    async fn record_base_versions(&self, primary_keys: &[Uuid]) {
+        let Some(version_of) = self.version_of else {
+            return;
+        };
+        let new_keys: Vec<Uuid> = {
+            let base_versions = self.base_versions.read();
+            primary_keys
+                .iter()
+                .filter(|primary_key| !base_versions.contains_key(primary_key))
+                .copied()
+                .collect()
+        };
+        if new_keys.is_empty() {
+            return;
+        }
+        let bases: Vec<(Uuid, Option<u64>)> = self
+            .read_base(|cache| {
+                new_keys
+                    .into_iter()
+                    .map(|primary_key| (primary_key, cache.peek(&primary_key).map(version_of)))
+                    .collect()
+            })
+            .await;
+        let mut base_versions = self.base_versions.write();
+        for (primary_key, base) in bases {
+            base_versions.entry(primary_key).or_insert(base);
+        }
+    }
+
+    /// Staged changes that no longer match the shared cache, sorted by key
+    fn commit_anomalies(&self, ops: &[StagedOp<T>], shared: &IdxModelCache<T>) -> Vec<CommitAnomaly> {
+        let mut anomalies = Vec::new();
+        if let Some(version_of) = self.version_of {
+            for (primary_key, base) in self.base_versions.read().iter() {
+                let found = shared.peek(primary_key).map(version_of);
+                if found != *base {
+                    anomalies.push(CommitAnomaly::VersionChanged {
+                        key: *primary_key,
+                        expected: *base,
+                        found,
+                    });
+                }
+            }
+        }
+        if self.validate_presence {
+            let version_changed: HashSet<Uuid> = anomalies.iter().map(CommitAnomaly::key).collect();
+            for op in ops {
+                let primary_key = op.primary_key();
+                if shared.contains_primary(&primary_key) || version_changed.contains(&primary_key) {
+                    continue;
+                }
+                match op {
+                    StagedOp::Update(_) => anomalies.push(CommitAnomaly::MissingForUpdate(primary_key)),
+                    StagedOp::Remove(_) => anomalies.push(CommitAnomaly::MissingForDelete(primary_key)),
+                    StagedOp::Add(_) => {}
+                }
+            }
+        }
+        anomalies.sort_by_key(CommitAnomaly::key);
+        anomalies
+    }
+
+    /// Clears all staged changes (useful for testing or manual rollback)
+    pub fn clear_staged(&self) {
+        // Only coordinated wrappers hold keys; the coordinator's cache is the shared cache
+        if let Some(coordinator) = &self.coordinator {
+            if !self.held_keys.read().is_empty() {
+                self.release_keys(&mut coordinator.cache().write());
+            }
+        }
+        self.local_additions.write().clear();
+        self.local_updates.write().clear();
+        self.local_deletions.write().clear();
+        self.staging_order.write().clear();
+        self.base_versions.write().clear();
+    }
+
+    /// Returns the number of staged additions
+    pub fn staged_additions_count(&self) -> usize {
+        self.local_additions.read().len()
+    }
+
+    /// Returns the number of staged updates
+    pub fn staged_updates_count(&self) -> usize {
+        self.local_updates.read().len()
+    }
+
+    /// Returns the number of staged deletions
+    pub fn staged_deletions_count(&self) -> usize {
+        self.local_deletions.read().len()
+    }
+
+    /// Returns how much is staged or, in write-through mode, not yet committed
+    pub fn staged_size(&self) -> StagedSize {
+        let (items, deletions) = self.staged_counts();
+        StagedSize::new::<T, Uuid>(items, deletions)
+    }
+
+    /// Items held by the staged changes or the undo log, and staged deletions
+    fn staged_counts(&self) -> (usize, usize) {
+        let written_through = self.undo_log.as_ref().map_or(0, |undo_log| undo_log.read().len());
+        (
+            self.local_additions.read().len() + self.local_updates.read().len() + written_through,
+            self.local_deletions.read().len(),
+        )
+    }
+
+    /// Fails if staging an item for `primary_key` would go beyond the staging limit
+    fn check_staging_limit(&self, primary_key: &Uuid) -> CacheResult<()> {
+        let Some(limit) = self.staging_limit else {
+            return Ok(());
+        };
+        let (items, deletions) = self.staged_counts();
+        if self.undo_log.is_some() {
+            return limit.check(StagedSize::new::<T, Uuid>(items + 1, deletions));
+        }
+        // Replacing a staged item does not grow the staged changes
+        if self.local_additions.read().contains_key(primary_key) || self.local_updates.read().contains_key(primary_key) {
+            return Ok(());
+        }
+        let deletions = deletions - usize::from(self.local_deletions.read().contains(primary_key));
+        limit.check(StagedSize::new::<T, Uuid>(items + 1, deletions))
+    }
+
+    /// Returns true if any change is staged or, in write-through mode, not yet committed
+    pub fn is_dirty(&self) -> bool {
+        self.undo_log.as_ref().is_some_and(|undo_log| !undo_log.read().is_empty()) || self.has_staged_changes()
+    }
+
+    /// Returns a copy of the staged changes, each in staging order
+    pub fn staged_changes(&self) -> StagedChanges<T> {
+        self.staged_ops().into()
+    }
+
+    /// Returns the staged changes in the order they are applied on commit
+    pub fn staged_ops(&self) -> Vec<StagedOp<T>> {
+        self.staging_order.read().ops(
+            &self.local_additions.read(),
+            &self.local_updates.read(),
+            &self.local_deletions.read(),
+        )
+    }
+
+    /// Like `commit_with_report`, for a shared cache behind any lock
+    pub async fn commit_with_report_async(&self) -> CacheResult<CommitReport> {
+        if self.completed.swap(true, Ordering::SeqCst) {
+            return Err(already_completed());
+        }
+        self.apply_staged()
+            .await
+            .map_err(|err| CacheError::Conflict { keys: err.failed_keys().copied().collect() })
+    }
+
+    /// Like `try_commit`, for a shared cache behind any lock
+    pub async fn try_commit_async(&self) -> Result<CommitReport, CommitError> {
+        self.completed.store(true, Ordering::SeqCst);
+        self.apply_staged().await
+    }
+
+    /// Applies the staged changes and clears them; the caller marks the transaction completed
+    async fn apply_staged(&self) -> Result<CommitReport, CommitError> {
+        let span = tracing::info_span!(
+            "idx_cache_commit",
+            cache = self.name.as_deref(),
+            additions = self.local_additions.read().len(),
+            updates = self.local_updates.read().len(),
+            deletions = self.local_deletions.read().len(),
+        );
+
+        async {
+            // Applied already; kept to report their net changes to the hooks
+            let written_through = self
+                .undo_log
+                .as_ref()
+                .map(|undo_log| std::mem::take(&mut *undo_log.write()))
+                .unwrap_or_default();
+
+            // Clears what is left of the staging state on every exit, including a
+            // panic while applying; declared before the lock so it runs after the
+            // lock is released
+            let _clear = ClearStagedOnDrop(self);
+
+            // Move the staged changes out before locking the shared cache
+            let ops = self.staging_order.write().take_ops(
+                &mut self.local_additions.write(),
+                &mut self.local_updates.write(),
+                &mut self.local_deletions.write(),
+            );
+
+            // Readers may continue while the changes are validated
+            let shared = self.shared_cache.upgradable_read().await;
+            let anomalies = self.commit_anomalies(&ops, &shared);
+            let mut conflicts: Vec<Uuid> = anomalies.iter().map(CommitAnomaly::key).collect();
+            conflicts.dedup();
+            if !anomalies.is_empty() {
+                tracing::warn!(
+                    conflicts = conflicts.len(),
+                    policy = ?self.conflict_policy,
+                    "staged cache changes no longer match the shared cache"
+                );
+                if self.conflict_policy == ConflictPolicy::Fail {
+                    let mut shared = L::upgrade(shared).await;
+                    self.release_keys(&mut shared);
+                    self.refresh_snapshot(&shared);
+                    let failures = conflicts
+                        .into_iter()
+                        .map(|key| (key, CacheError::Conflict { keys: vec![key] }))
+                        .collect();
+                    return Err(CommitError { applied: 0, failures });
+                }
+            }
+            if self.conflict_policy != ConflictPolicy::Skip {
+                conflicts.clear();
+            }
+            let skipped = |id: &Uuid| conflicts.binary_search(id).is_ok();
+            let mut report = CommitReport {
+                applied: 0,
+                anomalies,
+                skipped: Vec::new(),
+            };
+
+            let mut shared = L::upgrade(shared).await;
+            let run_hooks = !self.after_commit.is_empty();
+            let mut applied = Vec::new();
+            if run_hooks {
+                applied = write_through_ops(written_through, |primary_key| shared.get_by_primary(primary_key));
+            }
+            // The whole commit counts as one change of the shared cache
+            shared.batch(|cache| {
+                for op in ops {
+                    if skipped(&op.primary_key()) {
+                        report.skipped.push(op.primary_key());
+                        continue;
+                    }
+                    report.applied += 1;
+                    if run_hooks {
+                        applied.push(op.clone());
+                    }
+                    match op {
+                        StagedOp::Add(item) => cache.add(item),
+                        StagedOp::Update(item) => cache.update(item),
+                        StagedOp::Remove(id) => {
+                            cache.remove(&id);
+                        }
+                    }
+                }
+            });
+            self.release_keys(&mut shared);
+            self.refresh_snapshot(&shared);
+            drop(shared);
+
+            if run_hooks {
+                self.after_commit.run(applied.into(), self.name.as_deref());
+            }
+            report.skipped.sort();
+            Ok(report)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Like `add`, for a shared cache behind any lock
+    pub async fn add_async(&self, item: T) {
         let primary_key = item.primary_key();
+        self.completed.store(false, Ordering::SeqCst);
+        self.hold_key(primary_key);
+        if let Some((mut shared, _)) = self.write_through(primary_key).await {
+            shared.add(item);
+            return;
+        }
+        self.record_base_version(primary_key).await;
+        self.staging_order.write().touch(primary_key);
         self.local_deletions.write().remove(&primary_key);
         self.local_additions.write().insert(primary_key, item);
     }
 
-    /// Stages an item for update in the cache
-    pub fn update(&self, item: T) {
+    /// Like `try_add`, for a shared cache behind any lock
+    pub async fn try_add_async(&self, item: T) -> CacheResult<()> {
+        self.check_staging_limit(&item.primary_key())?;
+        self.add_async(item).await;
+        Ok(())
+    }
+
+    /// Like `try_update`, for a shared cache behind any lock
+    pub async fn try_update_async(&self, item: T) -> CacheResult<Option<T>> {
+        self.check_staging_limit(&item.primary_key())?;
+        Ok(self.update_async(item).await)
+    }
+
+    /// Like `update`, for a shared cache behind any lock
+    pub async fn update_async(&self, item: T) -> Option<T> {
         let primary_key = item.primary_key();
-        self.local_deletions.write().remove(&primary_key);
+        self.completed.store(false, Ordering::SeqCst);
+        self.hold_key(primary_key);
+        if let Some((mut shared, previous)) = self.write_through(primary_key).await {
+            shared.update(item);
+            return previous;
+        }
+        self.record_base_version(primary_key).await;
+        self.staging_order.write().touch(primary_key);
+        let was_deleted = self.local_deletions.write().remove(&primary_key);
         if let Some(local_item) = self.local_additions.write().get_mut(&primary_key) {
-            *local_item = item;
+            return Some(std::mem::replace(local_item, item));
+        }
+        let previous = self.local_updates.write().insert(primary_key, item);
+        if was_deleted {
+            return None;
+        }
+        if previous.is_some() {
+            return previous;
+        }
+        self.read_base(|cache| cache.peek(&primary_key).cloned()).await
+    }
+
+    /// Like `remove`, for a shared cache behind any lock
+    pub async fn remove_async(&self, primary_key: &Uuid) -> Option<T> {
+        self.completed.store(false, Ordering::SeqCst);
+        self.hold_key(*primary_key);
+        if let Some((mut shared, previous)) = self.write_through(*primary_key).await {
+            shared.remove(primary_key);
+            return previous;
+        }
+        self.record_base_version(*primary_key).await;
+        self.staging_order.write().touch(*primary_key);
+        let updated = self.local_updates.write().remove(primary_key);
+        if let Some(added) = self.local_additions.write().remove(primary_key) {
+            return Some(added);
+        }
+        if !self.local_deletions.write().insert(*primary_key) {
+            return None;
+        }
+        if updated.is_some() {
+            return updated;
+        }
+        self.read_base(|cache| cache.peek(primary_key).cloned()).await
+    }
+
+    /// Like `add_all`, for a shared cache behind any lock
+    pub async fn add_all_async(&self, items: impl IntoIterator<Item = T>) {
+        let items: Vec<T> = items.into_iter().collect();
+        if items.is_empty() {
+            return;
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        let primary_keys: Vec<Uuid> = items.iter().map(T::primary_key).collect();
+        self.hold_keys(primary_keys.iter().copied());
+        if let Some((mut shared, mut undo_log)) = self.write_through_batch().await {
+            for item in items {
+                let primary_key = item.primary_key();
+                let previous = shared.get_by_primary(&primary_key);
+                undo_log.push(UndoEntry { primary_key, previous });
+                shared.add(item);
+            }
             return;
         }
-        self.local_updates.write().insert(primary_key, item);
+        self.record_base_versions(&primary_keys).await;
+        let mut staging_order = self.staging_order.write();
+        let mut additions = self.local_additions.write();
+        let mut deletions = self.local_deletions.write();
+        for item in items {
+            let primary_key = item.primary_key();
+            staging_order.touch(primary_key);
+            deletions.remove(&primary_key);
+            additions.insert(primary_key, item);
+        }
     }
 
-    /// Stages an item for removal from the cache
-    pub fn remove(&self, primary_key: &Uuid) {
-        if self.local_additions.write().remove(primary_key).is_none() {
-            self.local_deletions.write().insert(*primary_key);
+    /// Like `update_all`, for a shared cache behind any lock
+    pub async fn update_all_async(&self, items: impl IntoIterator<Item = T>) {
+        let items: Vec<T> = items.into_iter().collect();
+        if items.is_empty() {
+            return;
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        let primary_keys: Vec<Uuid> = items.iter().map(T::primary_key).collect();
+        self.hold_keys(primary_keys.iter().copied());
+        if let Some((mut shared, mut undo_log)) = self.write_through_batch().await {
+            for item in items {
+                let primary_key = item.primary_key();
+                let previous = shared.get_by_primary(&primary_key);
+                undo_log.push(UndoEntry { primary_key, previous });
+                shared.update(item);
+            }
+            return;
+        }
+        self.record_base_versions(&primary_keys).await;
+        let mut staging_order = self.staging_order.write();
+        let mut additions = self.local_additions.write();
+        let mut updates = self.local_updates.write();
+        let mut deletions = self.local_deletions.write();
+        for item in items {
+            let primary_key = item.primary_key();
+            staging_order.touch(primary_key);
+            deletions.remove(&primary_key);
+            if let Some(local_item) = additions.get_mut(&primary_key) {
+                *local_item = item;
+            } else {
+                updates.insert(primary_key, item);
+            }
         }
-        self.local_updates.write().remove(primary_key);
     }
 
-    /// Gets an item by primary key, considering staged changes
-    pub fn get_by_primary(&self, primary_key: &Uuid) -> Option<T> {
+    /// Like `remove_all`, for a shared cache behind any lock
+    pub async fn remove_all_async(&self, primary_keys: &[Uuid]) {
+        if primary_keys.is_empty() {
+            return;
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        self.hold_keys(primary_keys.iter().copied());
+        if let Some((mut shared, mut undo_log)) = self.write_through_batch().await {
+            for primary_key in primary_keys {
+                let previous = shared.get_by_primary(primary_key);
+                undo_log.push(UndoEntry {
+                    primary_key: *primary_key,
+                    previous,
+                });
+                shared.remove(primary_key);
+            }
+            return;
+        }
+        self.record_base_versions(primary_keys).await;
+        let mut staging_order = self.staging_order.write();
+        let mut additions = self.local_additions.write();
+        let mut updates = self.local_updates.write();
+        let mut deletions = self.local_deletions.write();
+        for primary_key in primary_keys {
+            staging_order.touch(*primary_key);
+            updates.remove(primary_key);
+            if additions.remove(primary_key).is_none() {
+                deletions.insert(*primary_key);
+            }
+        }
+    }
+
+    /// Like `get_by_primary`, for a shared cache behind any lock
+    pub async fn get_by_primary_async(&self, primary_key: &Uuid) -> Option<T> {
         if self.local_deletions.read().contains(primary_key) {
             return None;
         }
@@ -76,95 +1031,264 @@ where
         if let Some(item) = self.local_updates.read().get(primary_key) {
             return Some(item.clone());
         }
-        self.shared_cache.read().get_by_primary(primary_key)
+        self.read_base(|cache| cache.get_by_primary(primary_key)).await
     }
 
-    /// Gets items by i64 index, considering staged changes
-    pub fn get_by_i64_index(&self, key: &str, value: &i64) -> Vec<T> {
-        let mut result_map = HashMap::new();
-
-        // 1. Get from shared cache
-        if let Some(pks) = self.shared_cache.read().get_by_i64_index(key, value) {
-            for pk in pks {
-                // Use get_by_primary which is transaction-aware for updates and deletions of these specific items
-                if let Some(item) = self.get_by_primary(pk) {
-                    result_map.insert(*pk, item);
-                }
-            }
+    /// Like `get_by_primary_traced`, for a shared cache behind any lock
+    pub async fn get_by_primary_traced_async(&self, primary_key: &Uuid) -> Option<(T, ReadSource)> {
+        if self.local_deletions.read().contains(primary_key) {
+            return None;
+        }
+        if let Some(item) = self.local_additions.read().get(primary_key) {
+            return Some((item.clone(), ReadSource::StagedAddition));
         }
+        if let Some(item) = self.local_updates.read().get(primary_key) {
+            return Some((item.clone(), ReadSource::StagedUpdate));
+        }
+        self.read_base(|cache| cache.get_by_primary(primary_key))
+            .await
+            .map(|item| (item, ReadSource::Shared))
+    }
 
-        // 2. Check local additions for new items that match
-        for item in self.local_additions.read().values() {
-            if let Some(Some(item_value)) = item.i64_keys().get(key) {
-                if item_value == value {
-                    result_map.insert(item.primary_key(), item.clone());
-                }
-            }
+    /// Like `read_source`, for a shared cache behind any lock
+    pub async fn read_source_async(&self, primary_key: &Uuid) -> Option<ReadSource> {
+        if self.local_deletions.read().contains(primary_key) {
+            None
+        } else if self.local_additions.read().contains_key(primary_key) {
+            Some(ReadSource::StagedAddition)
+        } else if self.local_updates.read().contains_key(primary_key) {
+            Some(ReadSource::StagedUpdate)
+        } else if self.read_base(|cache| cache.contains_primary(primary_key)).await {
+            Some(ReadSource::Shared)
+        } else {
+            None
         }
-        
-        // 3. Check local updates for items that might now match or un-match
-        for item in self.local_updates.read().values() {
-            if let Some(Some(item_value)) = item.i64_keys().get(key) {
-                if item_value == value {
-                    // It matches now, so add/update it
-                    result_map.insert(item.primary_key(), item.clone());
+    }
+
+    /// Like `get_by_i64_index_traced`, for a shared cache behind any lock
+    pub async fn get_by_i64_index_traced_async(&self, key: &str, value: &i64) -> CacheResult<Vec<(T, ReadSource)>> {
+        self.get_by_i64_index_async(key, value).await.map(|items| self.with_sources(items))
+    }
+
+    /// Like `get_by_uuid_index_traced`, for a shared cache behind any lock
+    pub async fn get_by_uuid_index_traced_async(&self, key: &str, value: &Uuid) -> CacheResult<Vec<(T, ReadSource)>> {
+        self.get_by_uuid_index_async(key, value).await.map(|items| self.with_sources(items))
+    }
+
+    /// Like `get_by_datetime_index_traced`, for a shared cache behind any lock
+    pub async fn get_by_datetime_index_traced_async(
+        &self,
+        key: &str,
+        value: &DateTime<Utc>,
+    ) -> CacheResult<Vec<(T, ReadSource)>> {
+        self.get_by_datetime_index_async(key, value).await.map(|items| self.with_sources(items))
+    }
+
+    /// Like `get_by_datetime_range_traced`, for a shared cache behind any lock
+    pub async fn get_by_datetime_range_traced_async<R>(&self, key: &str, range: R) -> CacheResult<Vec<(T, ReadSource)>>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        self.get_by_datetime_range_async(key, range).await.map(|items| self.with_sources(items))
+    }
+
+    /// Like `get_by_string_index_traced`, for a shared cache behind any lock
+    pub async fn get_by_string_index_traced_async(&self, key: &str, value: &str) -> CacheResult<Vec<(T, ReadSource)>> {
+        self.get_by_string_index_async(key, value).await.map(|items| self.with_sources(items))
+    }
+
+    /// Like `get_by_string_prefix_traced`, for a shared cache behind any lock
+    pub async fn get_by_string_prefix_traced_async(
+        &self,
+        key: &str,
+        prefix: &str,
+        limit: usize,
+    ) -> CacheResult<Vec<(T, ReadSource)>> {
+        self.get_by_string_prefix_async(key, prefix, limit).await.map(|items| self.with_sources(items))
+    }
+
+    /// Pairs items returned by a query with the staged map or cache they came from
+    fn with_sources(&self, items: Vec<T>) -> Vec<(T, ReadSource)> {
+        let additions = self.local_additions.read();
+        let updates = self.local_updates.read();
+        items
+            .into_iter()
+            .map(|item| {
+                let primary_key = item.primary_key();
+                let source = if additions.contains_key(&primary_key) {
+                    ReadSource::StagedAddition
+                } else if updates.contains_key(&primary_key) {
+                    ReadSource::StagedUpdate
                 } else {
-                    // It doesn't match anymore, so remove it
-                    result_map.remove(&item.primary_key());
-                }
-            } else {
-                // The key was removed in the update, so it doesn't match
-                result_map.remove(&item.primary_key());
-            }
-        }
+                    ReadSource::Shared
+                };
+                (item, source)
+            })
+            .collect()
+    }
 
-        result_map.into_values().collect()
+    /// Like `get_by_i64_index`, for a shared cache behind any lock
+    pub async fn get_by_i64_index_async(&self, key: &str, value: &i64) -> CacheResult<Vec<T>> {
+        self.check_index(IndexKind::I64, key, |item| index_value(&item.i64_index_keys(), key).is_some()).await?;
+        if !self.has_staged_changes() {
+            return Ok(self.read_base(|cache| materialize(cache, cache.get_by_i64_index(key, value))).await);
+        }
+        let shared_pks = self
+            .read_base(|cache| cache.get_by_i64_index(key, value).map(<[Uuid]>::to_vec).unwrap_or_default())
+            .await;
+        Ok(self
+            .merge_staged(shared_pks, |item| {
+                matches!(index_value(&item.i64_index_keys(), key), Some(Some(item_value)) if item_value == *value)
+            })
+            .await)
     }
 
-    /// Gets items by uuid index, considering staged changes
-    pub fn get_by_uuid_index(&self, key: &str, value: &Uuid) -> Vec<T> {
-        let mut result_map = HashMap::new();
-
-        // 1. Get from shared cache
-        if let Some(pks) = self.shared_cache.read().get_by_uuid_index(key, value) {
-            for pk in pks {
-                // Use get_by_primary which is transaction-aware for updates and deletions of these specific items
-                if let Some(item) = self.get_by_primary(pk) {
-                    result_map.insert(*pk, item);
-                }
-            }
+    /// Like `get_by_uuid_index`, for a shared cache behind any lock
+    pub async fn get_by_uuid_index_async(&self, key: &str, value: &Uuid) -> CacheResult<Vec<T>> {
+        self.check_index(IndexKind::Uuid, key, |item| index_value(&item.uuid_index_keys(), key).is_some()).await?;
+        if !self.has_staged_changes() {
+            return Ok(self.read_base(|cache| materialize(cache, cache.get_by_uuid_index(key, value))).await);
         }
+        let shared_pks = self
+            .read_base(|cache| cache.get_by_uuid_index(key, value).map(<[Uuid]>::to_vec).unwrap_or_default())
+            .await;
+        Ok(self
+            .merge_staged(shared_pks, |item| {
+                matches!(index_value(&item.uuid_index_keys(), key), Some(Some(item_value)) if item_value == *value)
+            })
+            .await)
+    }
 
-        // 2. Check local additions for new items that match
-        for item in self.local_additions.read().values() {
-            if let Some(Some(item_value)) = item.uuid_keys().get(key) {
-                if item_value == value {
-                    result_map.insert(item.primary_key(), item.clone());
-                }
-            }
+    /// Like `get_by_datetime_index`, for a shared cache behind any lock
+    pub async fn get_by_datetime_index_async(&self, key: &str, value: &DateTime<Utc>) -> CacheResult<Vec<T>> {
+        self.check_index(IndexKind::DateTime, key, |item| index_value(&item.datetime_index_keys(), key).is_some())
+            .await?;
+        let value = datetime_key(*value);
+        let shared_pks = self
+            .read_base(|cache| cache.get_by_datetime_index(key, &value).map(<[Uuid]>::to_vec).unwrap_or_default())
+            .await;
+        Ok(self
+            .merge_staged(shared_pks, |item| {
+                matches!(index_value(&item.datetime_index_keys(), key), Some(Some(item_value)) if datetime_key(item_value) == value)
+            })
+            .await)
+    }
+
+    /// Like `get_by_datetime_range`, for a shared cache behind any lock
+    pub async fn get_by_datetime_range_async<R>(&self, key: &str, range: R) -> CacheResult<Vec<T>>
+    where
+        R: RangeBounds<DateTime<Utc>>,
+    {
+        self.check_index(IndexKind::DateTime, key, |item| index_value(&item.datetime_index_keys(), key).is_some())
+            .await?;
+        let Some(bounds) = datetime_bounds(&range) else {
+            return Ok(Vec::new());
+        };
+        let shared_pks = self.read_base(|cache| cache.get_by_datetime_range(key, bounds)).await;
+        let mut items = self
+            .merge_staged(shared_pks, |item| {
+                matches!(index_value(&item.datetime_index_keys(), key), Some(Some(item_value)) if bounds.contains(&datetime_key(item_value)))
+            })
+            .await;
+        items.sort_by_key(|item| index_value(&item.datetime_index_keys(), key).flatten());
+        Ok(items)
+    }
+
+    /// Like `get_by_string_index`, for a shared cache behind any lock
+    pub async fn get_by_string_index_async(&self, key: &str, value: &str) -> CacheResult<Vec<T>> {
+        self.check_index(IndexKind::String, key, |item| string_index_value(&item.string_index_keys(), key).is_some())
+            .await?;
+        let (shared_pks, normalizer) = self
+            .read_base(|cache| {
+                let shared_pks = cache.get_by_string_index(key, value).map(<[Uuid]>::to_vec).unwrap_or_default();
+                (shared_pks, cache.string_normalizer(key))
+            })
+            .await;
+        let normalize = |value: &str| normalizer.as_ref().map_or_else(|| value.to_string(), |normalize| normalize(value));
+        let value = normalize(value);
+        Ok(self
+            .merge_staged(shared_pks, |item| {
+                matches!(string_index_value(&item.string_index_keys(), key), Some(Some(item_value)) if normalize(item_value) == value)
+            })
+            .await)
+    }
+
+    /// Like `get_by_string_prefix`, for a shared cache behind any lock
+    pub async fn get_by_string_prefix_async(&self, key: &str, prefix: &str, limit: usize) -> CacheResult<Vec<T>> {
+        self.check_index(IndexKind::String, key, |item| string_index_value(&item.string_index_keys(), key).is_some())
+            .await?;
+        // Every staged key can drop one shared match, so fetch that many more
+        let staged = self.local_additions.read().len() + self.local_updates.read().len() + self.local_deletions.read().len();
+        let (shared_pks, normalizer) = self
+            .read_base(|cache| {
+                let shared_pks = cache.get_by_string_prefix(key, prefix, limit.saturating_add(staged));
+                (shared_pks, cache.string_normalizer(key))
+            })
+            .await;
+        let normalize = |value: &str| normalizer.as_ref().map_or_else(|| value.to_string(), |normalize| normalize(value));
+        let prefix = normalize(prefix);
+        let mut items = self
+            .merge_staged(shared_pks, |item| {
+                matches!(string_index_value(&item.string_index_keys(), key), Some(Some(item_value)) if normalize(item_value).starts_with(&prefix))
+            })
+            .await;
+        items.sort_by_cached_key(|item| string_index_value(&item.string_index_keys(), key).flatten().map(normalize));
+        items.truncate(limit);
+        Ok(items)
+    }
+
+    /// Fails unless the shared cache or a staged item has an index of this kind and name
+    async fn check_index(&self, kind: IndexKind, key: &str, declares: impl Fn(&T) -> bool) -> CacheResult<()> {
+        let known = self.read_base(|cache| cache.has_index(kind, key)).await
+            || self.local_additions.read().values().any(&declares)
+            || self.local_updates.read().values().any(&declares);
+        if known {
+            Ok(())
+        } else {
+            Err(CacheError::IndexNotFound(key.to_string()))
         }
-        
-        // 3. Check local updates for items that might now match or un-match
-        for item in self.local_updates.read().values() {
-            if let Some(Some(item_value)) = item.uuid_keys().get(key) {
-                if item_value == value {
-                    // It matches now, so add/update it
-                    result_map.insert(item.primary_key(), item.clone());
-                } else {
-                    // It doesn't match anymore, so remove it
-                    result_map.remove(&item.primary_key());
-                }
-            } else {
-                // The key was removed in the update, so it doesn't match
-                result_map.remove(&item.primary_key());
-            }
+    }
+
+    /// Merges primary keys found in the shared cache with staged items matching an index query
+    ///
+    /// Shared items are cloned under a single lock and only if no staged
+    /// change replaces them, so a query clones each result once.
+    async fn merge_staged(&self, mut shared_pks: Vec<Uuid>, matches: impl Fn(&T) -> bool) -> Vec<T> {
+        // Staged keys are answered by their staged item, or not at all if deleted
+        {
+            let additions = self.local_additions.read();
+            let updates = self.local_updates.read();
+            let deletions = self.local_deletions.read();
+            shared_pks.retain(|pk| !additions.contains_key(pk) && !updates.contains_key(pk) && !deletions.contains(pk));
         }
+        let mut items: Vec<T> = self
+            .read_base(|cache| {
+                shared_pks.iter().filter_map(|pk| cache.peek(pk)).filter(|item| matches(item)).cloned().collect()
+            })
+            .await;
 
-        result_map.into_values().collect()
+        // A staged addition hides a staged update of the same key, as in `get_by_primary`
+        let additions = self.local_additions.read();
+        let updates = self.local_updates.read();
+        items.extend(additions.values().filter(|item| matches(item)).cloned());
+        items.extend(
+            updates
+                .iter()
+                .filter(|(pk, item)| !additions.contains_key(pk) && matches(item))
+                .map(|(_, item)| item.clone()),
+        );
+        items
     }
 
-    /// Checks if the cache contains an item by primary key, considering staged changes
-    pub fn contains_primary(&self, primary_key: &Uuid) -> bool {
+    /// Returns true if anything is staged; queries read the shared cache alone otherwise
+    fn has_staged_changes(&self) -> bool {
+        !self.local_additions.read().is_empty()
+            || !self.local_updates.read().is_empty()
+            || !self.local_deletions.read().is_empty()
+    }
+
+    /// Like `contains_primary`, for a shared cache behind any lock
+    pub async fn contains_primary_async(&self, primary_key: &Uuid) -> bool {
         if self.local_deletions.read().contains(primary_key) {
             return false;
         }
@@ -174,36 +1298,63 @@ where
         if self.local_updates.read().contains_key(primary_key) {
             return true;
         }
-        self.shared_cache.read().contains_primary(primary_key)
+        self.read_base(|cache| cache.contains_primary(primary_key)).await
     }
 }
 
+/// Clones the items of the primary keys an index query found in the cache
+fn materialize<T: IdxModel>(cache: &IdxModelCache<T>, primary_keys: Option<&[Uuid]>) -> Vec<T> {
+    primary_keys.unwrap_or_default().iter().filter_map(|pk| cache.peek(pk).cloned()).collect()
+}
+
 #[async_trait]
-impl<T> TransactionAware for TransactionAwareIdxModelCache<T>
+impl<T, L> TransactionAware for TransactionAwareIdxModelCache<T, L>
 where
     T: IdxModel,
+    L: CacheLock<IdxModelCache<T>>,
 {
     async fn on_commit(&self) -> TransactionResult<()> {
-        let mut shared = self.shared_cache.write();
-        for item in self.local_additions.read().values() {
-            shared.add(item.clone());
-        }
-        for item in self.local_updates.read().values() {
-            shared.update(item.clone());
+        if self.completed.swap(true, Ordering::SeqCst) {
+            return Err(already_completed().into());
         }
-        for id in self.local_deletions.read().iter() {
-            shared.remove(id);
-        }
-        self.local_additions.write().clear();
-        self.local_updates.write().clear();
-        self.local_deletions.write().clear();
+        self.apply_staged().await?;
         Ok(())
     }
 
     async fn on_rollback(&self) -> TransactionResult<()> {
-        self.local_additions.write().clear();
-        self.local_updates.write().clear();
-        self.local_deletions.write().clear();
+        self.rollback_changes().await;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn already_completed() -> CacheError {
+    CacheError::OperationFailed("transaction already completed; nothing new to commit".to_string())
+}
+
+/// Calls `clear_staged` when dropped
+struct ClearStagedOnDrop<'a, T: IdxModel, L: CacheLock<IdxModelCache<T>>>(&'a TransactionAwareIdxModelCache<T, L>);
+
+impl<T: IdxModel, L: CacheLock<IdxModelCache<T>>> Drop for ClearStagedOnDrop<'_, T, L> {
+    fn drop(&mut self) {
+        self.0.clear_staged();
+    }
+}
+
+impl<T> DiscardChanges for TransactionAwareIdxModelCache<T>
+where
+    T: IdxModel,
+{
+    fn discard_changes(&self) {
+        block_on_parking_lot(self.rollback_changes());
+    }
+}
+
+impl<T, L> Generational for TransactionAwareIdxModelCache<T, L>
+where
+    T: IdxModel,
+    L: CacheLock<IdxModelCache<T>>,
+{
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
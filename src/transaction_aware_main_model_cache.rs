@@ -1,54 +1,374 @@
 use async_trait::async_trait;
-use parking_lot::RwLock;
+use parking_lot::{RwLock, RwLockWriteGuard};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use tracing::Instrument;
 use uuid::Uuid;
 
+use crate::error::{CacheError, CacheResult, CommitError};
+use crate::backend::ModelCacheBackend;
 use crate::main_model_cache::MainModelCache;
-use crate::traits::HasPrimaryKey;
+use crate::lifecycle::{GenerationParticipant, Generational};
+use crate::lock::{block_on_parking_lot, CacheLock};
+use crate::scope::{DiscardChanges, TransactionScope};
+use crate::transaction_group::TransactionParticipant;
+use crate::staging::{
+    write_through_ops, AfterCommitHook, AfterCommitHooks, StagedChanges, StagedOp, StagedSize, StagingLimit, StagingOrder,
+    UndoEntry,
+};
+use crate::traits::CacheKey;
 use postgres_unit_of_work::{TransactionAware, TransactionResult};
 
 /// A trait alias for types that can be used in the main model cache
-pub trait MainModel: Clone + HasPrimaryKey + Send + Sync + Debug {}
-impl<T> MainModel for T where T: Clone + HasPrimaryKey + Send + Sync + Debug {}
+///
+/// The transaction-aware caches, the registries and the notification handlers
+/// all require it. Check a model against it next to its definition with
+/// [`assert_main_model`](crate::assert_main_model).
+pub trait MainModel<K = Uuid>: Clone + CacheKey<K> + Send + Sync + Debug + 'static {}
+impl<T, K> MainModel<K> for T where T: Clone + CacheKey<K> + Send + Sync + Debug + 'static {}
+
+/// A trait alias for the key types of the main model cache
+pub trait MainModelKey: Eq + Hash + Clone + Send + Sync + Debug {}
+impl<K> MainModelKey for K where K: Eq + Hash + Clone + Send + Sync + Debug {}
+
+/// The previous state of each key written through, in write order
+type UndoLog<T, K> = RwLock<Vec<UndoEntry<Arc<T>, K>>>;
 
 /// A transaction-aware wrapper around MainModelCache that stages changes
 /// and applies them only on commit.
-pub struct TransactionAwareMainModelCache<T>
+///
+/// The shared cache can be any `ModelCacheBackend`; it defaults to `MainModelCache`.
+/// Caches keyed by something other than `Uuid` name the key type last, e.g.
+/// `TransactionAwareMainModelCache<Country, MainModelCache<Country, String>, String>`.
+///
+/// The shared cache sits behind any [`CacheLock`], a `parking_lot::RwLock` by
+/// default. The blocking methods need the default lock; each has an `_async`
+/// variant that takes any lock, e.g. a `tokio::sync::RwLock`.
+pub struct TransactionAwareMainModelCache<T, B = MainModelCache<T>, K = Uuid, L = RwLock<B>>
 where
-    T: MainModel,
+    T: MainModel<K>,
+    B: ModelCacheBackend<T, K>,
+    K: MainModelKey,
+    L: CacheLock<B>,
 {
-    shared_cache: Arc<RwLock<MainModelCache<T>>>,
-    local_additions: RwLock<HashMap<Uuid, T>>,
-    local_updates: RwLock<HashMap<Uuid, T>>,
-    local_deletions: RwLock<HashSet<Uuid>>,
+    shared_cache: Arc<L>,
+    local_additions: RwLock<HashMap<K, Arc<T>>>,
+    local_updates: RwLock<HashMap<K, Arc<T>>>,
+    local_deletions: RwLock<HashSet<K>>,
+    staging_order: RwLock<StagingOrder<K>>,
+    /// How to undo changes already applied, in write-through mode
+    undo_log: Option<UndoLog<T, K>>,
+    /// Set by commit and rollback, cleared when the next change is made
+    completed: AtomicBool,
+    /// Incremented by `begin` and `reset`
+    generation: AtomicU64,
+    /// Identifies the cache in logs; the shared cache's name by default
+    name: Option<String>,
+    after_commit: AfterCommitHooks<Arc<T>, K>,
+    staging_limit: Option<StagingLimit>,
+    backend: PhantomData<fn() -> B>,
 }
 
-impl<T> TransactionAwareMainModelCache<T>
+impl<T, B, K> TransactionAwareMainModelCache<T, B, K>
 where
-    T: MainModel,
+    T: MainModel<K>,
+    B: ModelCacheBackend<T, K>,
+    K: MainModelKey,
 {
     /// Creates a new transaction-aware cache wrapper
-    pub fn new(shared_cache: Arc<RwLock<MainModelCache<T>>>) -> Self {
+    pub fn new(shared_cache: Arc<RwLock<B>>) -> Self {
+        block_on_parking_lot(Self::new_async(shared_cache))
+    }
+
+    /// Creates a transaction-aware cache wrapper that writes through to the shared cache
+    ///
+    /// Changes are applied to the shared cache immediately, so other readers
+    /// see them before the transaction ends. Each change records the previous
+    /// state of its key; `on_commit` discards those records and `on_rollback`
+    /// restores them in reverse order. Entries evicted by a write-through
+    /// insert are not restored.
+    pub fn new_write_through(shared_cache: Arc<RwLock<B>>) -> Self {
+        block_on_parking_lot(Self::new_write_through_async(shared_cache))
+    }
+
+    /// Discards all pending changes, undoing write-through changes, and
+    /// starts a new generation
+    pub fn reset(&self) -> u64 {
+        block_on_parking_lot(self.reset_async())
+    }
+
+    /// Starts a transaction whose changes are rolled back if the returned
+    /// guard is dropped before `commit`
+    pub fn scoped(&self) -> TransactionScope<'_, Self> {
+        self.completed.store(false, Ordering::SeqCst);
+        TransactionScope::new(self)
+    }
+
+    /// Stages an item for addition to the cache
+    pub fn insert(&self, item: impl Into<Arc<T>>) {
+        block_on_parking_lot(self.insert_async(item))
+    }
+
+    /// Like `insert`, failing with `CacheError::CapacityExceeded` instead of
+    /// staging beyond the limit set by `with_staging_limit`
+    pub fn try_insert(&self, item: impl Into<Arc<T>>) -> CacheResult<()> {
+        block_on_parking_lot(self.try_insert_async(item))
+    }
+
+    /// Like `update`, failing with `CacheError::CapacityExceeded` instead of
+    /// staging beyond the limit set by `with_staging_limit`
+    pub fn try_update(&self, item: impl Into<Arc<T>>) -> CacheResult<()> {
+        block_on_parking_lot(self.try_update_async(item))
+    }
+
+    /// Stages an item for update in the cache
+    pub fn update(&self, item: impl Into<Arc<T>>) {
+        block_on_parking_lot(self.update_async(item))
+    }
+
+    /// Stages an item for removal from the cache
+    pub fn remove(&self, primary_key: &K) {
+        block_on_parking_lot(self.remove_async(primary_key))
+    }
+
+    /// Stages many items for addition, taking each lock once rather than per item
+    pub fn insert_all(&self, items: impl IntoIterator<Item = impl Into<Arc<T>>>) {
+        block_on_parking_lot(self.insert_all_async(items))
+    }
+
+    /// Stages many items for update, taking each lock once rather than per item
+    pub fn update_all(&self, items: impl IntoIterator<Item = impl Into<Arc<T>>>) {
+        block_on_parking_lot(self.update_all_async(items))
+    }
+
+    /// Stages many keys for removal, taking each lock once rather than per key
+    pub fn remove_all(&self, primary_keys: &[K]) {
+        block_on_parking_lot(self.remove_all_async(primary_keys))
+    }
+
+    /// Checks if the cache contains an item by primary key, considering staged changes
+    pub fn contains(&self, primary_key: &K) -> bool {
+        block_on_parking_lot(self.contains_async(primary_key))
+    }
+
+    /// Commits like `on_commit`, failing with each staged change that was not applied
+    ///
+    /// Additions of uncached items are applied up to the shared cache's
+    /// capacity, so a commit never evicts its own items. The additions beyond
+    /// it fail with `CacheError::CapacityExceeded` and stay staged, to be
+    /// committed again once there is room or rolled back; everything else is
+    /// applied and cleared. Unlike `on_commit`, committing again after
+    /// completion is no error.
+    pub fn try_commit(&self) -> Result<usize, CommitError<K>> {
+        block_on_parking_lot(self.try_commit_async())
+    }
+}
+
+impl<T, B, K, L> TransactionAwareMainModelCache<T, B, K, L>
+where
+    T: MainModel<K>,
+    B: ModelCacheBackend<T, K>,
+    K: MainModelKey,
+    L: CacheLock<B>,
+{
+    /// Like `new`, for a shared cache behind any lock
+    pub async fn new_async(shared_cache: Arc<L>) -> Self {
+        let name = shared_cache.read().await.name().map(str::to_string);
         Self {
             shared_cache,
             local_additions: RwLock::new(HashMap::new()),
             local_updates: RwLock::new(HashMap::new()),
             local_deletions: RwLock::new(HashSet::new()),
+            staging_order: RwLock::new(StagingOrder::default()),
+            undo_log: None,
+            completed: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            name,
+            after_commit: AfterCommitHooks::default(),
+            staging_limit: None,
+            backend: PhantomData,
         }
     }
 
-    /// Stages an item for addition to the cache
-    pub fn insert(&self, item: T) {
-        let primary_key = item.primary_key();
+    /// Like `new_write_through`, for a shared cache behind any lock
+    pub async fn new_write_through_async(shared_cache: Arc<L>) -> Self {
+        Self {
+            undo_log: Some(RwLock::new(Vec::new())),
+            ..Self::new_async(shared_cache).await
+        }
+    }
+
+    /// Names the wrapper in log lines instead of the shared cache's name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// The name logged in the `cache` field
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Calls `hook` with the changes each commit applied
+    ///
+    /// Hooks run after the shared cache is unlocked, and neither on rollback
+    /// nor for commits that applied nothing, unless `run_hooks_on_empty_commit`
+    /// is set. A panicking hook is logged and leaves the cache and the other
+    /// hooks unaffected.
+    pub fn on_after_commit(&mut self, hook: Arc<AfterCommitHook<Arc<T>, K>>) {
+        self.after_commit.add(hook);
+    }
+
+    /// Also run the after-commit hooks for commits that applied no change
+    pub fn run_hooks_on_empty_commit(&mut self) {
+        self.after_commit.on_empty = true;
+    }
+
+    /// Limits what `try_insert` and `try_update` stage, so a repository can
+    /// stop caching a transaction that grows too large
+    ///
+    /// `insert` and `update` stage regardless of the limit.
+    pub fn with_staging_limit(mut self, limit: StagingLimit) -> Self {
+        self.staging_limit = Some(limit);
+        self
+    }
+
+    /// The limit set by `with_staging_limit`
+    pub fn staging_limit(&self) -> Option<StagingLimit> {
+        self.staging_limit
+    }
+
+    /// Starts a new logical transaction on a reused wrapper
+    ///
+    /// Fails with `CacheError::OperationFailed` if changes from an earlier
+    /// transaction are still pending. Returns the new generation; participants
+    /// created by `participant()` for earlier generations can no longer commit.
+    pub fn begin(&self) -> CacheResult<u64> {
+        if self.is_dirty() {
+            return Err(CacheError::OperationFailed(
+                "cannot begin a transaction while changes are pending; commit, roll back or reset first".to_string(),
+            ));
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        Ok(self.generation.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    /// Like `reset`, for a shared cache behind any lock
+    pub async fn reset_async(&self) -> u64 {
+        self.rollback_changes().await;
+        self.completed.store(false, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Returns the current transaction generation
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns a participant to register with a unit of work for the current generation
+    ///
+    /// Unlike registering the wrapper itself, the participant refuses to
+    /// commit once `begin` or `reset` has started a later transaction.
+    pub fn participant(self: &Arc<Self>) -> TransactionParticipant
+    where
+        T: 'static,
+        B: 'static,
+        K: 'static,
+        L: 'static,
+    {
+        Arc::new(GenerationParticipant::new(self.clone()))
+    }
+
+    /// Returns true once the transaction was committed or rolled back and
+    /// no change has been made since
+    pub fn is_completed(&self) -> bool {
+        self.completed.load(Ordering::SeqCst)
+    }
+
+    /// Undoes write-through changes and discards staged ones
+    async fn rollback_changes(&self) {
+        if let Some(undo_log) = &self.undo_log {
+            let mut shared = self.shared_cache.write().await;
+            shared.batch(|cache| {
+                for entry in undo_log.write().drain(..).rev() {
+                    match entry.previous {
+                        Some(item) => cache.update(item),
+                        None => {
+                            cache.remove(&entry.primary_key);
+                        }
+                    }
+                }
+            });
+        }
+        self.clear_staged();
+        self.completed.store(true, Ordering::SeqCst);
+    }
+
+    /// In write-through mode, records how to undo a change to a key and
+    /// returns the locked shared cache to apply it to
+    async fn write_through(&self, primary_key: &K) -> Option<L::WriteGuard<'_>> {
+        let undo_log = self.undo_log.as_ref()?;
+        let shared = self.shared_cache.write().await;
+        undo_log.write().push(UndoEntry {
+            primary_key: primary_key.clone(),
+            previous: shared.peek(primary_key),
+        });
+        Some(shared)
+    }
+
+    /// In write-through mode, locks the shared cache and the undo log once
+    /// for a batch of changes
+    #[allow(clippy::type_complexity)]
+    async fn write_through_batch(&self) -> Option<(L::WriteGuard<'_>, RwLockWriteGuard<'_, Vec<UndoEntry<Arc<T>, K>>>)> {
+        let undo_log = self.undo_log.as_ref()?;
+        let shared = self.shared_cache.write().await;
+        Some((shared, undo_log.write()))
+    }
+
+    /// Like `insert`, for a shared cache behind any lock
+    pub async fn insert_async(&self, item: impl Into<Arc<T>>) {
+        let item = item.into();
+        let primary_key = CacheKey::<K>::cache_key(&*item);
+        self.completed.store(false, Ordering::SeqCst);
+        if let Some(mut shared) = self.write_through(&primary_key).await {
+            shared.insert(item);
+            return;
+        }
+        self.staging_order.write().touch(primary_key.clone());
         self.local_deletions.write().remove(&primary_key);
         self.local_additions.write().insert(primary_key, item);
     }
 
-    /// Stages an item for update in the cache
-    pub fn update(&self, item: T) {
-        let primary_key = item.primary_key();
+    /// Like `try_insert`, for a shared cache behind any lock
+    pub async fn try_insert_async(&self, item: impl Into<Arc<T>>) -> CacheResult<()> {
+        let item = item.into();
+        self.check_staging_limit(&CacheKey::<K>::cache_key(&*item))?;
+        self.insert_async(item).await;
+        Ok(())
+    }
+
+    /// Like `try_update`, for a shared cache behind any lock
+    pub async fn try_update_async(&self, item: impl Into<Arc<T>>) -> CacheResult<()> {
+        let item = item.into();
+        self.check_staging_limit(&CacheKey::<K>::cache_key(&*item))?;
+        self.update_async(item).await;
+        Ok(())
+    }
+
+    /// Like `update`, for a shared cache behind any lock
+    pub async fn update_async(&self, item: impl Into<Arc<T>>) {
+        let item = item.into();
+        let primary_key = CacheKey::<K>::cache_key(&*item);
+        self.completed.store(false, Ordering::SeqCst);
+        if let Some(mut shared) = self.write_through(&primary_key).await {
+            shared.update(item);
+            return;
+        }
+        self.staging_order.write().touch(primary_key.clone());
         self.local_deletions.write().remove(&primary_key);
         if let Some(local_item) = self.local_additions.write().get_mut(&primary_key) {
             *local_item = item;
@@ -57,18 +377,112 @@ where
         self.local_updates.write().insert(primary_key, item);
     }
 
-    /// Stages an item for removal from the cache
-    pub fn remove(&self, primary_key: &Uuid) {
+    /// Like `remove`, for a shared cache behind any lock
+    pub async fn remove_async(&self, primary_key: &K) {
+        self.completed.store(false, Ordering::SeqCst);
+        if let Some(mut shared) = self.write_through(primary_key).await {
+            shared.remove(primary_key);
+            return;
+        }
+        self.staging_order.write().touch(primary_key.clone());
         if self.local_additions.write().remove(primary_key).is_none() {
-            self.local_deletions.write().insert(*primary_key);
+            self.local_deletions.write().insert(primary_key.clone());
         }
         self.local_updates.write().remove(primary_key);
     }
 
+    /// Like `insert_all`, for a shared cache behind any lock
+    pub async fn insert_all_async(&self, items: impl IntoIterator<Item = impl Into<Arc<T>>>) {
+        let items: Vec<Arc<T>> = items.into_iter().map(Into::into).collect();
+        if items.is_empty() {
+            return;
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        if let Some((mut shared, mut undo_log)) = self.write_through_batch().await {
+            for item in items {
+                let primary_key = CacheKey::<K>::cache_key(&*item);
+                let previous = shared.peek(&primary_key);
+                undo_log.push(UndoEntry { primary_key, previous });
+                shared.insert(item);
+            }
+            return;
+        }
+        let mut staging_order = self.staging_order.write();
+        let mut additions = self.local_additions.write();
+        let mut deletions = self.local_deletions.write();
+        for item in items {
+            let primary_key = CacheKey::<K>::cache_key(&*item);
+            staging_order.touch(primary_key.clone());
+            deletions.remove(&primary_key);
+            additions.insert(primary_key, item);
+        }
+    }
+
+    /// Like `update_all`, for a shared cache behind any lock
+    pub async fn update_all_async(&self, items: impl IntoIterator<Item = impl Into<Arc<T>>>) {
+        let items: Vec<Arc<T>> = items.into_iter().map(Into::into).collect();
+        if items.is_empty() {
+            return;
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        if let Some((mut shared, mut undo_log)) = self.write_through_batch().await {
+            for item in items {
+                let primary_key = CacheKey::<K>::cache_key(&*item);
+                let previous = shared.peek(&primary_key);
+                undo_log.push(UndoEntry { primary_key, previous });
+                shared.update(item);
+            }
+            return;
+        }
+        let mut staging_order = self.staging_order.write();
+        let mut additions = self.local_additions.write();
+        let mut updates = self.local_updates.write();
+        let mut deletions = self.local_deletions.write();
+        for item in items {
+            let primary_key = CacheKey::<K>::cache_key(&*item);
+            staging_order.touch(primary_key.clone());
+            deletions.remove(&primary_key);
+            if let Some(local_item) = additions.get_mut(&primary_key) {
+                *local_item = item;
+            } else {
+                updates.insert(primary_key, item);
+            }
+        }
+    }
+
+    /// Like `remove_all`, for a shared cache behind any lock
+    pub async fn remove_all_async(&self, primary_keys: &[K]) {
+        if primary_keys.is_empty() {
+            return;
+        }
+        self.completed.store(false, Ordering::SeqCst);
+        if let Some((mut shared, mut undo_log)) = self.write_through_batch().await {
+            for primary_key in primary_keys {
+                undo_log.push(UndoEntry {
+                    primary_key: primary_key.clone(),
+                    previous: shared.peek(primary_key),
+                });
+                shared.remove(primary_key);
+            }
+            return;
+        }
+        let mut staging_order = self.staging_order.write();
+        let mut additions = self.local_additions.write();
+        let mut updates = self.local_updates.write();
+        let mut deletions = self.local_deletions.write();
+        for primary_key in primary_keys {
+            staging_order.touch(primary_key.clone());
+            updates.remove(primary_key);
+            if additions.remove(primary_key).is_none() {
+                deletions.insert(primary_key.clone());
+            }
+        }
+    }
+
     /// Gets an item by primary key, considering staged changes
     /// Note: This returns None for items in the cache since MainModelCache::get requires &mut self
     /// For transactional reads, check local changes first, then fall back to checking contains
-    pub fn get(&self, primary_key: &Uuid) -> Option<T> {
+    pub fn get(&self, primary_key: &K) -> Option<Arc<T>> {
         // Check if marked for deletion
         if self.local_deletions.read().contains(primary_key) {
             return None;
@@ -76,12 +490,12 @@ where
         
         // Check local additions first
         if let Some(item) = self.local_additions.read().get(primary_key) {
-            return Some(item.clone());
+            return Some(Arc::clone(item));
         }
         
         // Check local updates
         if let Some(item) = self.local_updates.read().get(primary_key) {
-            return Some(item.clone());
+            return Some(Arc::clone(item));
         }
         
         // For shared cache, we can't call get() as it requires &mut
@@ -90,8 +504,8 @@ where
         None
     }
 
-    /// Checks if the cache contains an item by primary key, considering staged changes
-    pub fn contains(&self, primary_key: &Uuid) -> bool {
+    /// Like `contains`, for a shared cache behind any lock
+    pub async fn contains_async(&self, primary_key: &K) -> bool {
         if self.local_deletions.read().contains(primary_key) {
             return false;
         }
@@ -101,7 +515,7 @@ where
         if self.local_updates.read().contains_key(primary_key) {
             return true;
         }
-        self.shared_cache.read().contains(primary_key)
+        self.shared_cache.read().await.contains(primary_key)
     }
 
     /// Clears all staged changes (useful for testing or manual rollback)
@@ -109,6 +523,7 @@ where
         self.local_additions.write().clear();
         self.local_updates.write().clear();
         self.local_deletions.write().clear();
+        self.staging_order.write().clear();
     }
 
     /// Returns the number of staged additions
@@ -125,51 +540,208 @@ where
     pub fn staged_deletions_count(&self) -> usize {
         self.local_deletions.read().len()
     }
+
+    /// Returns how much is staged or, in write-through mode, not yet committed
+    pub fn staged_size(&self) -> StagedSize {
+        let (items, deletions) = self.staged_counts();
+        StagedSize::new::<T, K>(items, deletions)
+    }
+
+    /// Items held by the staged changes or the undo log, and staged deletions
+    fn staged_counts(&self) -> (usize, usize) {
+        let written_through = self.undo_log.as_ref().map_or(0, |undo_log| undo_log.read().len());
+        (
+            self.local_additions.read().len() + self.local_updates.read().len() + written_through,
+            self.local_deletions.read().len(),
+        )
+    }
+
+    /// Fails if staging an item for `primary_key` would go beyond the staging limit
+    fn check_staging_limit(&self, primary_key: &K) -> CacheResult<()> {
+        let Some(limit) = self.staging_limit else {
+            return Ok(());
+        };
+        let (items, deletions) = self.staged_counts();
+        if self.undo_log.is_some() {
+            return limit.check(StagedSize::new::<T, K>(items + 1, deletions));
+        }
+        // Replacing a staged item does not grow the staged changes
+        if self.local_additions.read().contains_key(primary_key) || self.local_updates.read().contains_key(primary_key) {
+            return Ok(());
+        }
+        let deletions = deletions - usize::from(self.local_deletions.read().contains(primary_key));
+        limit.check(StagedSize::new::<T, K>(items + 1, deletions))
+    }
+
+    /// Returns true if any change is staged or, in write-through mode, not yet committed
+    pub fn is_dirty(&self) -> bool {
+        self.undo_log.as_ref().is_some_and(|undo_log| !undo_log.read().is_empty())
+            || !self.local_additions.read().is_empty()
+            || !self.local_updates.read().is_empty()
+            || !self.local_deletions.read().is_empty()
+    }
+
+    /// Returns a copy of the staged changes, each in staging order
+    pub fn staged_changes(&self) -> StagedChanges<Arc<T>, K> {
+        self.staged_ops().into()
+    }
+
+    /// Returns the staged changes in the order they are applied on commit
+    pub fn staged_ops(&self) -> Vec<StagedOp<Arc<T>, K>> {
+        self.staging_order.read().ops(
+            &self.local_additions.read(),
+            &self.local_updates.read(),
+            &self.local_deletions.read(),
+        )
+    }
+
+    /// Like `try_commit`, for a shared cache behind any lock
+    pub async fn try_commit_async(&self) -> Result<usize, CommitError<K>> {
+        self.completed.store(true, Ordering::SeqCst);
+        self.apply_staged().await
+    }
+
+    /// Applies the staged changes, keeping those that failed staged; the
+    /// caller marks the transaction completed
+    async fn apply_staged(&self) -> Result<usize, CommitError<K>> {
+        let span = tracing::info_span!(
+            "main_cache_commit",
+            cache = self.name.as_deref(),
+            additions = self.local_additions.read().len(),
+            updates = self.local_updates.read().len(),
+            deletions = self.local_deletions.read().len(),
+        );
+
+        async {
+            // Applied already; kept to report their net changes to the hooks
+            let written_through = self
+                .undo_log
+                .as_ref()
+                .map(|undo_log| std::mem::take(&mut *undo_log.write()))
+                .unwrap_or_default();
+
+            let mut shared = self.shared_cache.write().await;
+
+            // Additions beyond the capacity would evict other items of this commit;
+            // a disabled cache, with a capacity of 0, ignores them instead
+            let capacity = shared.capacity().filter(|limit| *limit > 0);
+            let mut new_items = 0;
+            let mut rejected = Vec::new();
+
+            let report = !self.after_commit.is_empty();
+            let mut applied = Vec::new();
+            if report {
+                applied = write_through_ops(written_through, |primary_key| shared.peek(primary_key));
+            }
+
+            // Apply changes in the order they were staged, as one change of the shared cache
+            let mut applied_count = 0;
+            shared.batch(|cache| {
+                for op in self.staged_ops() {
+                    if let (StagedOp::Add(item), Some(limit)) = (&op, capacity) {
+                        let primary_key = CacheKey::<K>::cache_key(&**item);
+                        if !cache.contains(&primary_key) {
+                            if new_items == limit {
+                                rejected.push((primary_key, item.clone(), limit));
+                                continue;
+                            }
+                            new_items += 1;
+                        }
+                    }
+                    applied_count += 1;
+                    if report {
+                        applied.push(op.clone());
+                    }
+                    match op {
+                        StagedOp::Add(item) => cache.insert(item),
+                        StagedOp::Update(item) => cache.update(item),
+                        StagedOp::Remove(id) => {
+                            cache.remove(&id);
+                        }
+                    }
+                }
+            });
+
+            // Clear staged changes, keeping the rejected ones for a retry
+            self.clear_staged();
+            let failures: Vec<(K, CacheError)> = rejected
+                .into_iter()
+                .map(|(primary_key, item, limit)| {
+                    self.staging_order.write().touch(primary_key.clone());
+                    self.local_additions.write().insert(primary_key.clone(), item);
+                    (primary_key, CacheError::CapacityExceeded { limit })
+                })
+                .collect();
+            drop(shared);
+
+            if report {
+                self.after_commit.run(applied.into(), self.name.as_deref());
+            }
+            if !failures.is_empty() {
+                tracing::warn!(failed = failures.len(), "staged additions beyond the cache capacity were not applied");
+                self.completed.store(false, Ordering::SeqCst);
+                return Err(CommitError { applied: applied_count, failures });
+            }
+            Ok(applied_count)
+        }
+        .instrument(span)
+        .await
+    }
 }
 
 #[async_trait]
-impl<T> TransactionAware for TransactionAwareMainModelCache<T>
+impl<T, B, K, L> TransactionAware for TransactionAwareMainModelCache<T, B, K, L>
 where
-    T: MainModel,
+    T: MainModel<K>,
+    B: ModelCacheBackend<T, K>,
+    K: MainModelKey,
+    L: CacheLock<B>,
 {
     async fn on_commit(&self) -> TransactionResult<()> {
-        let mut shared = self.shared_cache.write();
-        
-        // Apply additions
-        for item in self.local_additions.read().values() {
-            shared.insert(item.clone());
+        if self.completed.swap(true, Ordering::SeqCst) {
+            return Err(CacheError::OperationFailed(
+                "transaction already completed; nothing new to commit".to_string(),
+            )
+            .into());
         }
-        
-        // Apply updates
-        for item in self.local_updates.read().values() {
-            shared.update(item.clone());
-        }
-        
-        // Apply deletions
-        for id in self.local_deletions.read().iter() {
-            shared.remove(id);
-        }
-        
-        // Clear staged changes
-        self.local_additions.write().clear();
-        self.local_updates.write().clear();
-        self.local_deletions.write().clear();
-        
+        self.apply_staged().await?;
         Ok(())
     }
 
     async fn on_rollback(&self) -> TransactionResult<()> {
-        self.local_additions.write().clear();
-        self.local_updates.write().clear();
-        self.local_deletions.write().clear();
+        self.rollback_changes().await;
         Ok(())
     }
 }
 
+impl<T, B, K> DiscardChanges for TransactionAwareMainModelCache<T, B, K>
+where
+    T: MainModel<K>,
+    B: ModelCacheBackend<T, K>,
+    K: MainModelKey,
+{
+    fn discard_changes(&self) {
+        block_on_parking_lot(self.rollback_changes());
+    }
+}
+
+impl<T, B, K, L> Generational for TransactionAwareMainModelCache<T, B, K, L>
+where
+    T: MainModel<K>,
+    B: ModelCacheBackend<T, K>,
+    K: MainModelKey,
+    L: CacheLock<B>,
+{
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::main_model_cache::{CacheConfig, EvictionPolicy};
+    use crate::traits::HasPrimaryKey;
 
     #[derive(Debug, Clone)]
     struct TestEntity {
@@ -299,6 +871,252 @@ mod tests {
         assert!(!shared_cache.read().contains(&entity.id));
     }
 
+    #[tokio::test]
+    async fn test_staged_changes_snapshot() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let existing = TestEntity {
+            id: Uuid::new_v4(),
+            value: "existing".to_string(),
+        };
+        shared_cache.write().insert(existing.clone());
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+        assert!(!tx_cache.is_dirty());
+
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        };
+        tx_cache.insert(entity.clone());
+        tx_cache.remove(&existing.id);
+        assert!(tx_cache.is_dirty());
+
+        let changes = tx_cache.staged_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes.additions[0].id, entity.id);
+        assert!(changes.updates.is_empty());
+        assert_eq!(changes.deletions, vec![existing.id]);
+
+        tx_cache.on_commit().await.unwrap();
+        assert!(!tx_cache.is_dirty());
+        assert!(tx_cache.staged_changes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_commit_applies_changes_in_staging_order() {
+        // A full FIFO cache evicts its oldest entry when an item is inserted
+        let config = CacheConfig::new(2, EvictionPolicy::FIFO);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let oldest = TestEntity {
+            id: Uuid::new_v4(),
+            value: "oldest".to_string(),
+        };
+        let removed = TestEntity {
+            id: Uuid::new_v4(),
+            value: "removed".to_string(),
+        };
+        shared_cache.write().insert(oldest.clone());
+        shared_cache.write().insert(removed.clone());
+
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+        let added = TestEntity {
+            id: Uuid::new_v4(),
+            value: "added".to_string(),
+        };
+        tx_cache.remove(&removed.id);
+        tx_cache.insert(added.clone());
+
+        let ops = tx_cache.staged_ops();
+        assert!(matches!(&ops[..], [StagedOp::Remove(id), StagedOp::Add(item)] if *id == removed.id && item.id == added.id));
+
+        // Removing first makes room, so nothing is evicted
+        tx_cache.on_commit().await.unwrap();
+        let shared = shared_cache.read();
+        assert!(shared.contains(&oldest.id));
+        assert!(shared.contains(&added.id));
+        assert!(!shared.contains(&removed.id));
+    }
+
+    #[tokio::test]
+    async fn test_write_through_rollback_restores_previous_state() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let existing = TestEntity {
+            id: Uuid::new_v4(),
+            value: "original".to_string(),
+        };
+        let removed = TestEntity {
+            id: Uuid::new_v4(),
+            value: "removed".to_string(),
+        };
+        shared_cache.write().insert(existing.clone());
+        shared_cache.write().insert(removed.clone());
+
+        let tx_cache = TransactionAwareMainModelCache::new_write_through(shared_cache.clone());
+        let added = TestEntity {
+            id: Uuid::new_v4(),
+            value: "added".to_string(),
+        };
+        tx_cache.insert(added.clone());
+        tx_cache.update(TestEntity {
+            id: existing.id,
+            value: "first".to_string(),
+        });
+        tx_cache.update(TestEntity {
+            id: existing.id,
+            value: "second".to_string(),
+        });
+        tx_cache.remove(&removed.id);
+
+        // Changes are visible in the shared cache immediately
+        assert!(tx_cache.is_dirty());
+        assert_eq!(tx_cache.staged_additions_count(), 0);
+        assert!(shared_cache.read().contains(&added.id));
+        assert!(!shared_cache.read().contains(&removed.id));
+        assert_eq!(shared_cache.write().get(&existing.id).unwrap().value, "second");
+
+        tx_cache.on_rollback().await.unwrap();
+        assert!(!tx_cache.is_dirty());
+        assert!(!shared_cache.read().contains(&added.id));
+        assert!(shared_cache.read().contains(&removed.id));
+        assert_eq!(shared_cache.write().get(&existing.id).unwrap().value, "original");
+    }
+
+    #[test]
+    fn test_staging_limit() {
+        use crate::staging::StagingLimit;
+
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache).with_staging_limit(StagingLimit::Operations(1));
+
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "first".to_string(),
+        };
+        tx_cache.try_insert(entity.clone()).unwrap();
+        tx_cache.try_update(entity).unwrap();
+        let other = TestEntity {
+            id: Uuid::new_v4(),
+            value: "second".to_string(),
+        };
+        assert!(matches!(
+            tx_cache.try_insert(other),
+            Err(CacheError::CapacityExceeded { limit: 1 })
+        ));
+        assert_eq!(tx_cache.staged_size().operations, 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_staging() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let existing = TestEntity {
+            id: Uuid::new_v4(),
+            value: "existing".to_string(),
+        };
+        shared_cache.write().insert(existing.clone());
+
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+        let added: Vec<TestEntity> = (0..3)
+            .map(|i| TestEntity {
+                id: Uuid::new_v4(),
+                value: format!("added {i}"),
+            })
+            .collect();
+        tx_cache.insert_all(added.clone());
+        tx_cache.update_all(vec![TestEntity {
+            id: existing.id,
+            value: "changed".to_string(),
+        }]);
+        tx_cache.remove_all(&[added[1].id]);
+        assert_eq!(tx_cache.staged_additions_count(), 2);
+        assert_eq!(tx_cache.staged_updates_count(), 1);
+        assert_eq!(tx_cache.staged_deletions_count(), 0);
+
+        tx_cache.on_commit().await.unwrap();
+        assert!(shared_cache.read().contains(&added[0].id));
+        assert!(!shared_cache.read().contains(&added[1].id));
+        assert_eq!(shared_cache.write().get(&existing.id).unwrap().value, "changed");
+    }
+
+    #[tokio::test]
+    async fn test_after_commit_hooks_run_once_unlocked() {
+        use crate::staging::AppliedChanges;
+
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let seen = Arc::new(AtomicU64::new(0));
+
+        let mut tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+        tx_cache.on_after_commit(Arc::new(|_: &AppliedChanges<Arc<TestEntity>>| panic!("hook failed")));
+        let counter = seen.clone();
+        let cache = shared_cache.clone();
+        tx_cache.on_after_commit(Arc::new(move |changes: &AppliedChanges<Arc<TestEntity>>| {
+            assert!(cache.try_write().is_some());
+            counter.fetch_add(changes.len() as u64, Ordering::SeqCst);
+        }));
+
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        };
+        tx_cache.insert(entity.clone());
+        tx_cache.on_commit().await.unwrap();
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+        assert!(shared_cache.read().contains(&entity.id));
+
+        tx_cache.begin().unwrap();
+        tx_cache.remove(&entity.id);
+        tx_cache.on_rollback().await.unwrap();
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+        assert!(shared_cache.read().contains(&entity.id));
+    }
+
+    #[tokio::test]
+    async fn test_write_through_commit_keeps_changes() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let tx_cache = TransactionAwareMainModelCache::new_write_through(shared_cache.clone());
+
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        };
+        tx_cache.insert(entity.clone());
+        tx_cache.on_commit().await.unwrap();
+        assert!(!tx_cache.is_dirty());
+
+        // A later rollback has nothing left to undo
+        tx_cache.on_rollback().await.unwrap();
+        assert!(shared_cache.read().contains(&entity.id));
+    }
+
+    #[tokio::test]
+    async fn test_tokio_lock_wrapper_stages_and_writes_through() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(tokio::sync::RwLock::new(MainModelCache::new(config)));
+        let tx_cache: TransactionAwareMainModelCache<TestEntity, MainModelCache<TestEntity>, Uuid, _> =
+            TransactionAwareMainModelCache::new_async(shared_cache.clone()).await;
+
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        };
+        tx_cache.insert_async(entity.clone()).await;
+        assert!(tx_cache.contains_async(&entity.id).await);
+        assert!(!shared_cache.read().await.contains(&entity.id));
+        tx_cache.on_commit().await.unwrap();
+        assert!(shared_cache.read().await.contains(&entity.id));
+
+        let write_through: TransactionAwareMainModelCache<TestEntity, MainModelCache<TestEntity>, Uuid, _> =
+            TransactionAwareMainModelCache::new_write_through_async(shared_cache.clone()).await;
+        write_through.remove_async(&entity.id).await;
+        assert!(!shared_cache.read().await.contains(&entity.id));
+        write_through.on_rollback().await.unwrap();
+        assert!(shared_cache.read().await.contains(&entity.id));
+    }
+
     #[tokio::test]
     async fn test_update_replaces_addition() {
         let config = CacheConfig::new(10, EvictionPolicy::LRU);
@@ -350,4 +1168,146 @@ mod tests {
         tx_cache.on_commit().await.unwrap();
         assert!(!shared_cache.read().contains(&entity.id));
     }
+
+    #[tokio::test]
+    async fn test_scope_rolls_back_on_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let existing = TestEntity {
+            id: Uuid::new_v4(),
+            value: "original".to_string(),
+        };
+        shared_cache.write().insert(existing.clone());
+        let tx_cache = TransactionAwareMainModelCache::new_write_through(shared_cache.clone());
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let scope = tx_cache.scoped();
+            scope.update(TestEntity {
+                id: existing.id,
+                value: "changed".to_string(),
+            });
+            panic!("transaction closure failed");
+        }));
+        assert!(result.is_err());
+        assert!(tx_cache.is_completed());
+        assert!(!tx_cache.is_dirty());
+        assert_eq!(shared_cache.write().get(&existing.id).unwrap().value, "original");
+    }
+
+    #[tokio::test]
+    async fn test_commit_after_completion_is_an_error() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        };
+        let scope = tx_cache.scoped();
+        scope.insert(entity.clone());
+        scope.commit().await.unwrap();
+        assert!(shared_cache.read().contains(&entity.id));
+
+        assert!(tx_cache.on_commit().await.is_err());
+        tx_cache.on_rollback().await.unwrap();
+        assert!(shared_cache.read().contains(&entity.id));
+    }
+
+
+    #[tokio::test]
+    async fn test_begin_and_generations() {
+        let config = CacheConfig::new(10, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let tx_cache = Arc::new(TransactionAwareMainModelCache::new_write_through(shared_cache.clone()));
+
+        let stale = tx_cache.participant();
+        let entity = TestEntity {
+            id: Uuid::new_v4(),
+            value: "test".to_string(),
+        };
+        tx_cache.insert(entity.clone());
+        assert!(tx_cache.begin().is_err());
+
+        // Reset undoes the write-through insert and starts a new generation
+        assert_eq!(tx_cache.reset(), 1);
+        assert!(!shared_cache.read().contains(&entity.id));
+        assert_eq!(tx_cache.begin().unwrap(), 2);
+
+        let current = tx_cache.participant();
+        tx_cache.insert(entity.clone());
+        stale.on_rollback().await.unwrap();
+        assert!(stale.on_commit().await.is_err());
+        assert!(shared_cache.read().contains(&entity.id));
+
+        current.on_commit().await.unwrap();
+        assert_eq!(tx_cache.generation(), 2);
+        assert!(!tx_cache.is_dirty());
+    }
+
+
+    #[tokio::test]
+    async fn test_commit_beyond_capacity_keeps_rejected_additions_staged() {
+        let config = CacheConfig::new(2, EvictionPolicy::FIFO);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+
+        let entities: Vec<TestEntity> = ["a", "b", "c"]
+            .into_iter()
+            .map(|value| TestEntity { id: Uuid::new_v4(), value: value.to_string() })
+            .collect();
+        for entity in &entities {
+            tx_cache.insert(entity.clone());
+        }
+
+        // Committing all three would evict items added by the same commit
+        let err = tx_cache.try_commit().unwrap_err();
+        assert_eq!(err.applied, 2);
+        assert!(err.is_partial());
+        assert_eq!(err.failed_keys().collect::<Vec<_>>(), vec![&entities[2].id]);
+        assert!(matches!(err.failures[0].1, CacheError::CapacityExceeded { limit: 2 }));
+        assert!(shared_cache.read().contains(&entities[0].id));
+        assert!(!shared_cache.read().contains(&entities[2].id));
+
+        // The rejected addition stays staged; a retry commits it alone
+        assert!(!tx_cache.is_completed());
+        assert_eq!(tx_cache.staged_additions_count(), 1);
+        assert!(tx_cache.contains(&entities[2].id));
+        tx_cache.on_commit().await.unwrap();
+        assert!(!tx_cache.is_dirty());
+        assert!(shared_cache.read().contains(&entities[2].id));
+    }
+
+    #[tokio::test]
+    async fn test_on_commit_reports_rejected_additions() {
+        let config = CacheConfig::new(1, EvictionPolicy::LRU);
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(config)));
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+        tx_cache.insert(TestEntity { id: Uuid::new_v4(), value: "a".to_string() });
+        let rejected = TestEntity { id: Uuid::new_v4(), value: "b".to_string() };
+        tx_cache.insert(rejected.clone());
+
+        let err = tx_cache.on_commit().await.unwrap_err().to_string();
+        assert!(err.contains("applied 1 changes, 1 failed"), "{err}");
+        assert!(err.contains(&rejected.id.to_string()), "{err}");
+        tx_cache.on_rollback().await.unwrap();
+        assert!(!tx_cache.is_dirty());
+        assert_eq!(shared_cache.read().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_commit_to_disabled_cache_succeeds_without_caching() {
+        let shared_cache = Arc::new(RwLock::new(MainModelCache::new(CacheConfig::disabled())));
+        let tx_cache = TransactionAwareMainModelCache::new(shared_cache.clone());
+        let entity = TestEntity { id: Uuid::new_v4(), value: "a".to_string() };
+        tx_cache.insert(entity.clone());
+
+        // Staged reads still see the transaction's own changes
+        assert!(tx_cache.contains(&entity.id));
+        tx_cache.on_commit().await.unwrap();
+        assert!(shared_cache.read().is_empty());
+        assert!(tx_cache.get(&entity.id).is_none());
+    }
 }
\ No newline at end of file
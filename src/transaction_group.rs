@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+use crate::error::CacheError;
+use crate::index_cache::IdxModelCache;
+use crate::main_model_cache::MainModelCache;
+use crate::transaction_aware_index_cache::{IdxModel, TransactionAwareIdxModelCache};
+use crate::transaction_aware_main_model_cache::{MainModel, TransactionAwareMainModelCache};
+use postgres_unit_of_work::{TransactionAware, TransactionResult};
+
+/// A participant of a transaction group
+pub type TransactionParticipant = Arc<dyn TransactionAware + Send + Sync>;
+
+/// Commits and rolls back several transaction-aware participants together,
+/// so a unit of work only needs to register the group.
+///
+/// Participants are called in the order they were added. A failing
+/// participant does not stop the others: earlier ones have already
+/// committed, so every participant is called and the failures are reported
+/// together, as `CacheError::PartialCommit` if some participants committed.
+#[derive(Default)]
+pub struct CacheTransactionGroup {
+    participants: Vec<(String, TransactionParticipant)>,
+}
+
+impl CacheTransactionGroup {
+    /// Creates an empty group
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a participant under a name used in error reports
+    pub fn add_participant(&mut self, name: impl Into<String>, participant: TransactionParticipant) {
+        self.participants.push((name.into(), participant));
+    }
+
+    /// Wraps a shared index cache and adds the wrapper to the group
+    ///
+    /// Returns the wrapper to stage changes on.
+    pub fn add_index_cache<T>(
+        &mut self,
+        name: impl Into<String>,
+        shared_cache: Arc<RwLock<IdxModelCache<T>>>,
+    ) -> Arc<TransactionAwareIdxModelCache<T>>
+    where
+        T: IdxModel,
+    {
+        let cache = Arc::new(TransactionAwareIdxModelCache::new(shared_cache));
+        self.add_participant(name, cache.clone());
+        cache
+    }
+
+    /// Wraps a shared main model cache and adds the wrapper to the group
+    ///
+    /// Returns the wrapper to stage changes on.
+    pub fn add_main_model_cache<T>(
+        &mut self,
+        name: impl Into<String>,
+        shared_cache: Arc<RwLock<MainModelCache<T>>>,
+    ) -> Arc<TransactionAwareMainModelCache<T>>
+    where
+        T: MainModel,
+    {
+        let cache = Arc::new(TransactionAwareMainModelCache::new(shared_cache));
+        self.add_participant(name, cache.clone());
+        cache
+    }
+
+    /// Returns the names of the participants, in call order
+    pub fn participant_names(&self) -> Vec<&str> {
+        self.participants.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Returns the number of participants
+    pub fn len(&self) -> usize {
+        self.participants.len()
+    }
+
+    /// Returns true if the group has no participants
+    pub fn is_empty(&self) -> bool {
+        self.participants.is_empty()
+    }
+}
+
+#[async_trait]
+impl TransactionAware for CacheTransactionGroup {
+    async fn on_commit(&self) -> TransactionResult<()> {
+        let mut committed = Vec::new();
+        let mut failed = Vec::new();
+        for (name, participant) in &self.participants {
+            match participant.on_commit().await {
+                Ok(()) => committed.push(name.clone()),
+                Err(err) => {
+                    tracing::error!(participant = %name, error = %err, "cache group participant failed to commit");
+                    failed.push(format!("{name}: {err}"));
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else if committed.is_empty() {
+            Err(CacheError::CommitFailed(failed.join(", ")).into())
+        } else {
+            Err(CacheError::PartialCommit { committed, failed }.into())
+        }
+    }
+
+    async fn on_rollback(&self) -> TransactionResult<()> {
+        let mut failed = Vec::new();
+        for (name, participant) in &self.participants {
+            if let Err(err) = participant.on_rollback().await {
+                tracing::error!(participant = %name, error = %err, "cache group participant failed to roll back");
+                failed.push(format!("{name}: {err}"));
+            }
+        }
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(CacheError::RollbackFailed(failed.join(", ")).into())
+        }
+    }
+}
@@ -0,0 +1,885 @@
+//! SQL generation for the cache notification function and triggers
+//!
+//! [`TriggerSqlBuilder`] produces the statements installed by the `db_init`
+//! functions as plain strings, so they can also be embedded in migration
+//! files and reviewed before they are run.
+
+use crate::error::CacheError;
+use crate::listener::DEFAULT_CACHE_CHANNEL;
+
+/// The default name of the notification trigger function
+pub const DEFAULT_FUNCTION_NAME: &str = "notify_cache_change";
+
+/// The largest payload that can be sent with NOTIFY
+///
+/// PostgreSQL rejects NOTIFY payloads of 8000 bytes or more. Pass it to
+/// `TriggerSqlBuilder::payload_size_limit` to drop the row data of larger rows
+/// instead of failing the write.
+pub const DEFAULT_PAYLOAD_SIZE_LIMIT: usize = 7999;
+
+/// The setting the trigger function reads the hex-encoded payload secret from
+///
+/// Only read by functions built with `TriggerSqlBuilder::protect_data`.
+/// Custom settings are visible to every session they apply to, so set it for
+/// the writing role only, e.g. `ALTER ROLE app_writer SET
+/// postgres_index_cache.payload_key = '...'`.
+pub const PAYLOAD_KEY_SETTING: &str = "postgres_index_cache.payload_key";
+
+/// The version prefix of protected row data
+pub(crate) const PROTECTED_DATA_VERSION: &str = "v1";
+/// The label the encryption key is derived from the payload secret with
+pub(crate) const ENCRYPTION_KEY_LABEL: &str = "postgres-index-cache encryption";
+/// The label the MAC key is derived from the payload secret with
+pub(crate) const MAC_KEY_LABEL: &str = "postgres-index-cache mac";
+
+/// The default name of the table notifications are written to in outbox delivery
+pub const DEFAULT_OUTBOX_TABLE: &str = "cache_outbox";
+
+/// How the notification function delivers notifications
+///
+/// NOTIFY is best-effort: notifications sent while no listener is connected
+/// are lost. The outbox keeps each notification as a row of the outbox table
+/// until a `CacheOutboxPoller` has processed it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NotificationDelivery {
+    /// Send notifications with `pg_notify` only
+    #[default]
+    Notify,
+    /// Write notifications to the outbox table only
+    Outbox,
+    /// Send notifications and write them to the outbox table
+    NotifyAndOutbox,
+}
+
+impl NotificationDelivery {
+    /// Whether notifications are sent with `pg_notify`
+    pub fn notifies(&self) -> bool {
+        matches!(self, NotificationDelivery::Notify | NotificationDelivery::NotifyAndOutbox)
+    }
+
+    /// Whether notifications are written to the outbox table
+    pub fn uses_outbox(&self) -> bool {
+        matches!(self, NotificationDelivery::Outbox | NotificationDelivery::NotifyAndOutbox)
+    }
+}
+
+/// Events a cache notification trigger fires on
+///
+/// `Truncate` does not fire row-level triggers, so it is installed as a
+/// separate statement-level trigger and has to be requested explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+}
+
+impl TriggerEvent {
+    /// All row-level events, in the order they appear in generated SQL
+    pub const ALL: [TriggerEvent; 3] = [TriggerEvent::Insert, TriggerEvent::Update, TriggerEvent::Delete];
+
+    /// All row-level events and `Truncate`
+    pub const ALL_WITH_TRUNCATE: [TriggerEvent; 4] = [
+        TriggerEvent::Insert,
+        TriggerEvent::Update,
+        TriggerEvent::Delete,
+        TriggerEvent::Truncate,
+    ];
+
+    /// The SQL keyword for this event
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            TriggerEvent::Insert => "INSERT",
+            TriggerEvent::Update => "UPDATE",
+            TriggerEvent::Delete => "DELETE",
+            TriggerEvent::Truncate => "TRUNCATE",
+        }
+    }
+}
+
+/// Builder for the SQL statements of the cache notification infrastructure
+///
+/// # Example
+///
+/// ```rust
+/// use postgres_index_cache::{TriggerEvent, TriggerSqlBuilder};
+///
+/// let builder = TriggerSqlBuilder::new()
+///     .table("user_index_cache")
+///     .channel("users")
+///     .events(&[TriggerEvent::Insert, TriggerEvent::Delete]);
+///
+/// let function_sql = builder.build_function_sql().unwrap();
+/// let trigger_sql = builder.build_trigger_sql().unwrap();
+/// assert!(function_sql.contains("CREATE OR REPLACE FUNCTION"));
+/// assert!(trigger_sql.contains("AFTER INSERT OR DELETE"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerSqlBuilder {
+    table: Option<String>,
+    schema: Option<String>,
+    trigger_name: Option<String>,
+    notify_table: Option<String>,
+    channel: String,
+    events: Vec<TriggerEvent>,
+    include_data: bool,
+    data_columns: Vec<String>,
+    context_columns: Vec<String>,
+    changed_columns: Vec<String>,
+    payload_size_limit: Option<usize>,
+    protect_data: bool,
+    delivery: NotificationDelivery,
+    outbox_table: String,
+    function_name: String,
+    function_schema: Option<String>,
+}
+
+impl Default for TriggerSqlBuilder {
+    fn default() -> Self {
+        Self {
+            table: None,
+            schema: None,
+            trigger_name: None,
+            notify_table: None,
+            channel: DEFAULT_CACHE_CHANNEL.to_string(),
+            events: TriggerEvent::ALL.to_vec(),
+            include_data: true,
+            data_columns: Vec::new(),
+            context_columns: Vec::new(),
+            changed_columns: Vec::new(),
+            payload_size_limit: None,
+            protect_data: false,
+            delivery: NotificationDelivery::Notify,
+            outbox_table: DEFAULT_OUTBOX_TABLE.to_string(),
+            function_name: DEFAULT_FUNCTION_NAME.to_string(),
+            function_schema: None,
+        }
+    }
+}
+
+impl TriggerSqlBuilder {
+    /// Create a builder with the default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the table the trigger is attached to
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = Some(table.into());
+        self
+    }
+
+    /// Set the schema of the table
+    pub fn schema(mut self, schema: impl Into<String>) -> Self {
+        self.schema = Some(schema.into());
+        self
+    }
+
+    /// Set an explicit trigger name; defaults to `{table}_cache_notify`
+    pub fn trigger_name(mut self, trigger_name: impl Into<String>) -> Self {
+        self.trigger_name = Some(trigger_name.into());
+        self
+    }
+
+    /// Set the table name sent in notifications; defaults to the name of the table fired on
+    ///
+    /// Used for triggers on partitions, so notifications name the
+    /// partitioned table the caches are registered for.
+    pub fn notify_table(mut self, table: impl Into<String>) -> Self {
+        self.notify_table = Some(table.into());
+        self
+    }
+
+    /// Set the channel notifications are sent on
+    ///
+    /// This is both passed to the trigger and used as the function's default.
+    pub fn channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = channel.into();
+        self
+    }
+
+    /// Set the events the trigger fires on
+    ///
+    /// [`TriggerEvent::Truncate`] adds a statement-level trigger named
+    /// `{trigger_name}_truncate`.
+    pub fn events(mut self, events: &[TriggerEvent]) -> Self {
+        self.events = events.to_vec();
+        self
+    }
+
+    /// Set whether insert/update notifications carry the row data
+    pub fn include_data(mut self, include_data: bool) -> Self {
+        self.include_data = include_data;
+        self
+    }
+
+    /// Restrict the row data to the given columns; all columns are sent when empty
+    pub fn data_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.data_columns = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Always send the given columns in the notification's `context` object
+    ///
+    /// Unlike the row data, the context is sent for deletes and kept when the
+    /// payload is too large, so listeners can route on e.g. a tenant id.
+    pub fn context_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.context_columns = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only notify about updates that change at least one of the given columns
+    ///
+    /// PostgreSQL does not allow a `WHEN` condition referencing `OLD` on
+    /// insert or delete triggers, so updates then get their own trigger named
+    /// `{trigger_name}_update`. An empty list notifies about every update.
+    pub fn only_when_changed<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.changed_columns = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the payload size above which the row data is dropped; by default it is never dropped
+    ///
+    /// Without a limit, writing a row whose notification exceeds the NOTIFY
+    /// limit fails. With one, the notification is sent without data and the
+    /// handlers evict the item from their caches.
+    pub fn payload_size_limit(mut self, limit: Option<usize>) -> Self {
+        self.payload_size_limit = limit;
+        self
+    }
+
+    /// Set whether the function encrypts and signs the row data
+    ///
+    /// The function then needs the pgcrypto extension and reads the secret
+    /// from [`PAYLOAD_KEY_SETTING`]; sessions without the setting send no row
+    /// data. Listeners decrypt the data with the same secret as a `PayloadKey`.
+    pub fn protect_data(mut self, protect_data: bool) -> Self {
+        self.protect_data = protect_data;
+        self
+    }
+
+    /// Set how the notification function delivers notifications
+    pub fn delivery(mut self, delivery: NotificationDelivery) -> Self {
+        self.delivery = delivery;
+        self
+    }
+
+    /// Set the outbox table written to in outbox delivery; defaults to [`DEFAULT_OUTBOX_TABLE`]
+    ///
+    /// The table is looked up on the search path of the writing session.
+    pub fn outbox_table(mut self, table: impl Into<String>) -> Self {
+        self.outbox_table = table.into();
+        self
+    }
+
+    /// Set the name of the notification function
+    pub fn function_name(mut self, function_name: impl Into<String>) -> Self {
+        self.function_name = function_name.into();
+        self
+    }
+
+    /// Set the schema of the notification function; the search path is used when unset
+    pub fn function_schema(mut self, schema: impl Into<String>) -> Self {
+        self.function_schema = Some(schema.into());
+        self
+    }
+
+    /// The name of the trigger, if a table is set
+    pub fn resolved_trigger_name(&self) -> Option<String> {
+        self.trigger_name
+            .clone()
+            .or_else(|| self.table.as_ref().map(|table| format!("{table}_cache_notify")))
+    }
+
+    /// Build the `CREATE OR REPLACE FUNCTION` statement for the notification function
+    pub fn build_function_sql(&self) -> Result<String, CacheError> {
+        let payload_fallback = match self.payload_size_limit {
+            Some(limit) => format!(
+                r#"
+    -- NOTIFY payloads are limited in size: fall back to sending no row data
+    IF octet_length(payload) > {limit} THEN
+        payload = (notification::jsonb - 'data')::text;
+    END IF;"#
+            ),
+            None => String::new(),
+        };
+        let mut delivery = Vec::new();
+        if self.delivery.notifies() {
+            delivery.push("    PERFORM pg_notify(channel, payload);".to_string());
+        }
+        if self.delivery.uses_outbox() {
+            // The outbox has no size limit, so it keeps the row data
+            delivery.push(format!(
+                "    INSERT INTO {} (channel, payload) VALUES (channel, notification::text);",
+                quote_ident(&self.outbox_table)?
+            ));
+        }
+        let delivery = delivery.join("\n");
+        let protect_data = if self.protect_data {
+            format!(
+                r#"
+            -- Encrypt and sign the row data; sessions without a key send none
+            DECLARE
+                payload_key bytea := decode(nullif(current_setting({setting}, true), ''), 'hex');
+                iv bytea;
+                ciphertext bytea;
+            BEGIN
+                IF payload_key IS NOT NULL THEN
+                    iv = gen_random_bytes(16);
+                    ciphertext = encrypt_iv(
+                        convert_to(row_data::text, 'UTF8'),
+                        hmac(convert_to({encryption_label}, 'UTF8'), payload_key, 'sha256'),
+                        iv,
+                        'aes-cbc/pad:pkcs'
+                    );
+                    row_data = to_json({version} || '.' || encode(iv, 'hex')
+                        || '.' || encode(ciphertext, 'hex')
+                        || '.' || encode(hmac(
+                            convert_to(table_name, 'UTF8') || '\x00'::bytea
+                                || convert_to(lower(TG_OP), 'UTF8') || '\x00'::bytea
+                                || convert_to(NEW.id::text, 'UTF8') || '\x00'::bytea
+                                || iv || ciphertext,
+                            hmac(convert_to({mac_label}, 'UTF8'), payload_key, 'sha256'),
+                            'sha256'
+                        ), 'hex'));
+                ELSE
+                    row_data = NULL;
+                END IF;
+            END;"#,
+                setting = quote_literal(PAYLOAD_KEY_SETTING)?,
+                encryption_label = quote_literal(ENCRYPTION_KEY_LABEL)?,
+                mac_label = quote_literal(MAC_KEY_LABEL)?,
+                version = quote_literal(PROTECTED_DATA_VERSION)?,
+            )
+        } else {
+            String::new()
+        };
+
+        Ok(format!(
+            r#"-- Cache Notification Function
+--
+-- Generated by postgres-index-cache (TriggerSqlBuilder::build_function_sql).
+--
+-- Sends cache invalidation notifications via LISTEN/NOTIFY when data changes
+-- occur. Each notification carries the table name, the action and the primary
+-- key, plus the row data in JSON format for inserts and updates.
+--
+-- Triggers may pass optional arguments to override the defaults:
+--   TG_ARGV[0] - the channel to notify on (default {channel_literal})
+--   TG_ARGV[1] - 'true' or 'false', whether to include the row data
+--   TG_ARGV[2] - comma-separated list of columns to include in the row data
+--   TG_ARGV[3] - the table name sent in notifications (default TG_TABLE_NAME),
+--                so triggers on partitions report their partitioned table
+--   TG_ARGV[4] - comma-separated list of context columns, always sent in a
+--                top-level 'context' object even when the row data is dropped
+--
+-- Used as a statement-level AFTER TRUNCATE trigger it sends a 'truncate'
+-- notification with the nil UUID as id.
+
+CREATE OR REPLACE FUNCTION {function}()
+RETURNS TRIGGER AS $$
+DECLARE
+    notification json;
+    row_data json;
+    row_context json;
+    payload text;
+    channel text := {channel_literal};
+    include_data boolean := true;
+    table_name text := TG_TABLE_NAME;
+BEGIN
+    -- Read the optional trigger arguments
+    IF TG_NARGS > 0 THEN
+        channel := TG_ARGV[0];
+    END IF;
+    IF TG_NARGS > 1 THEN
+        include_data := TG_ARGV[1]::boolean;
+    END IF;
+    IF TG_NARGS > 3 AND TG_ARGV[3] <> '' THEN
+        table_name := TG_ARGV[3];
+    END IF;
+
+    -- Build the notification payload
+    IF (TG_OP = 'TRUNCATE') THEN
+        -- There is no row for a truncate, so the nil UUID is sent as the id
+        notification = json_build_object(
+            'table', table_name,
+            'action', 'truncate',
+            'id', '00000000-0000-0000-0000-000000000000'
+        );
+    ELSIF (TG_OP = 'DELETE') THEN
+        notification = json_build_object(
+            'table', table_name,
+            'action', 'delete',
+            'id', OLD.id
+        );
+    ELSE
+        -- For INSERT and UPDATE, include the row data
+        IF include_data THEN
+            row_data = row_to_json(NEW);
+            IF TG_NARGS > 2 AND TG_ARGV[2] <> '' THEN
+                SELECT json_object_agg(key, value) INTO row_data
+                FROM json_each(row_data)
+                WHERE key = ANY (string_to_array(TG_ARGV[2], ','));
+            END IF;{protect_data}
+        END IF;
+
+        IF row_data IS NULL THEN
+            notification = json_build_object(
+                'table', table_name,
+                'action', lower(TG_OP),
+                'id', NEW.id
+            );
+        ELSE
+            notification = json_build_object(
+                'table', table_name,
+                'action', lower(TG_OP),
+                'id', NEW.id,
+                'data', row_data
+            );
+        END IF;
+    END IF;
+
+    -- Copy the context columns of the row
+    IF TG_OP <> 'TRUNCATE' AND TG_NARGS > 4 AND TG_ARGV[4] <> '' THEN
+        IF (TG_OP = 'DELETE') THEN
+            row_context = row_to_json(OLD);
+        ELSE
+            row_context = row_to_json(NEW);
+        END IF;
+        SELECT json_object_agg(key, value) INTO row_context
+        FROM json_each(row_context)
+        WHERE key = ANY (string_to_array(TG_ARGV[4], ','));
+        -- json values cannot be concatenated, so the context is added as jsonb
+        notification = (notification::jsonb || jsonb_build_object('context', row_context))::json;
+    END IF;
+
+    -- Convert to text and send notification
+    payload = notification::text;{payload_fallback}
+{delivery}
+
+    -- Return the appropriate row
+    IF (TG_OP = 'TRUNCATE') THEN
+        RETURN NULL;
+    ELSIF (TG_OP = 'DELETE') THEN
+        RETURN OLD;
+    ELSE
+        RETURN NEW;
+    END IF;
+END;
+$$ LANGUAGE plpgsql;
+"#,
+            function = self.qualified_function()?,
+            channel_literal = quote_literal(&self.channel)?,
+        ))
+    }
+
+    /// Build the `CREATE TABLE IF NOT EXISTS` statement for the outbox table
+    ///
+    /// Rows are numbered by `seq`, which `CacheOutboxPoller` processes in order.
+    pub fn build_outbox_table_sql(&self) -> Result<String, CacheError> {
+        let table = quote_ident(&self.outbox_table)?;
+        Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {table} (
+    seq bigserial PRIMARY KEY,
+    channel text NOT NULL,
+    payload text NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS {index} ON {table} (channel, seq);",
+            index = quote_ident(&format!("{}_channel_seq", self.outbox_table))?,
+        ))
+    }
+
+    /// Build the statements that (re)create the trigger on the table
+    ///
+    /// The existing triggers with the same name are dropped first, so the
+    /// statements can be applied repeatedly.
+    pub fn build_trigger_sql(&self) -> Result<String, CacheError> {
+        let events: Vec<TriggerEvent> = TriggerEvent::ALL
+            .into_iter()
+            .filter(|event| self.events.contains(event))
+            .collect();
+        let truncate = self.events.contains(&TriggerEvent::Truncate);
+        if events.is_empty() && !truncate {
+            return Err(CacheError::InvalidArgument(
+                "trigger must fire on at least one event".to_string(),
+            ));
+        }
+
+        let mut arguments = vec![
+            quote_literal(&self.channel)?,
+            quote_literal(if self.include_data { "true" } else { "false" })?,
+        ];
+        // Optional positional arguments; empty ones keep the position of later ones
+        let mut optional = vec![
+            column_list(&self.data_columns)?,
+            self.notify_table.clone().unwrap_or_default(),
+            column_list(&self.context_columns)?,
+        ];
+        while optional.last().is_some_and(String::is_empty) {
+            optional.pop();
+        }
+        for argument in optional {
+            arguments.push(if argument.is_empty() {
+                "''".to_string()
+            } else {
+                quote_literal(&argument)?
+            });
+        }
+        let arguments = arguments.join(", ");
+
+        let trigger_name = self.required_trigger_name()?;
+        let mut statements = vec![self.build_drop_trigger_sql()?];
+
+        let split_updates = !self.changed_columns.is_empty() && events.contains(&TriggerEvent::Update);
+        let row_events: Vec<TriggerEvent> = events
+            .iter()
+            .copied()
+            .filter(|event| !(split_updates && *event == TriggerEvent::Update))
+            .collect();
+        if !row_events.is_empty() {
+            statements.push(self.create_trigger_statement(&trigger_name, &row_events, "ROW", None, &arguments)?);
+        }
+        if split_updates {
+            let conditions = self
+                .changed_columns
+                .iter()
+                .map(|column| -> Result<String, CacheError> {
+                    let column = quote_ident(column)?;
+                    Ok(format!("OLD.{column} IS DISTINCT FROM NEW.{column}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            statements.push(self.create_trigger_statement(
+                &format!("{trigger_name}_update"),
+                &[TriggerEvent::Update],
+                "ROW",
+                Some(conditions.join(" OR ")),
+                &arguments,
+            )?);
+        }
+        if truncate {
+            statements.push(self.create_trigger_statement(
+                &format!("{trigger_name}_truncate"),
+                &[TriggerEvent::Truncate],
+                "STATEMENT",
+                None,
+                &arguments,
+            )?);
+        }
+
+        Ok(statements.join("\n"))
+    }
+
+    /// Build the `DROP TRIGGER IF EXISTS` statements for the triggers on the table
+    pub fn build_drop_trigger_sql(&self) -> Result<String, CacheError> {
+        let trigger_name = self.required_trigger_name()?;
+        let table = self.qualified_table()?;
+        Ok(format!(
+            "DROP TRIGGER IF EXISTS {} ON {table};\n\
+             DROP TRIGGER IF EXISTS {} ON {table};\n\
+             DROP TRIGGER IF EXISTS {} ON {table};",
+            quote_ident(&trigger_name)?,
+            quote_ident(&format!("{trigger_name}_update"))?,
+            quote_ident(&format!("{trigger_name}_truncate"))?,
+        ))
+    }
+
+    fn create_trigger_statement(
+        &self,
+        trigger_name: &str,
+        events: &[TriggerEvent],
+        level: &str,
+        when: Option<String>,
+        arguments: &str,
+    ) -> Result<String, CacheError> {
+        let events: Vec<&str> = events.iter().map(TriggerEvent::as_sql).collect();
+        let when = when
+            .map(|condition| format!("\n    WHEN ({condition})"))
+            .unwrap_or_default();
+        Ok(format!(
+            "CREATE TRIGGER {}\n    AFTER {} ON {}\n    FOR EACH {}{}\n    EXECUTE FUNCTION {}({});",
+            quote_ident(trigger_name)?,
+            events.join(" OR "),
+            self.qualified_table()?,
+            level,
+            when,
+            self.qualified_function()?,
+            arguments,
+        ))
+    }
+
+    fn required_trigger_name(&self) -> Result<String, CacheError> {
+        self.resolved_trigger_name()
+            .ok_or_else(|| CacheError::InvalidArgument("no table set for trigger".to_string()))
+    }
+
+    /// The quoted, schema-qualified function name
+    fn qualified_function(&self) -> Result<String, CacheError> {
+        qualify(self.function_schema.as_deref(), &self.function_name)
+    }
+
+    /// The quoted, schema-qualified table name
+    pub(crate) fn qualified_table(&self) -> Result<String, CacheError> {
+        let table = self
+            .table
+            .as_deref()
+            .ok_or_else(|| CacheError::InvalidArgument("no table set for trigger".to_string()))?;
+        qualify(self.schema.as_deref(), table)
+    }
+}
+
+/// Join the columns passed to the trigger function as a comma-separated list
+fn column_list(columns: &[String]) -> Result<String, CacheError> {
+    for column in columns {
+        if column.is_empty() || column.contains(',') {
+            return Err(CacheError::InvalidArgument(format!(
+                "invalid column: {column:?}"
+            )));
+        }
+    }
+    Ok(columns.join(","))
+}
+
+/// Quote a name, qualified with its schema if one is given
+pub(crate) fn qualify(schema: Option<&str>, name: &str) -> Result<String, CacheError> {
+    match schema {
+        Some(schema) => Ok(format!("{}.{}", quote_ident(schema)?, quote_ident(name)?)),
+        None => quote_ident(name),
+    }
+}
+
+/// Quote a SQL identifier, rejecting values that can never be valid
+pub(crate) fn quote_ident(name: &str) -> Result<String, CacheError> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(CacheError::InvalidArgument(format!(
+            "invalid SQL identifier: {name:?}"
+        )));
+    }
+    Ok(format!("\"{}\"", name.replace('"', "\"\"")))
+}
+
+/// Quote a SQL string literal, rejecting empty values and control characters
+pub(crate) fn quote_literal(value: &str) -> Result<String, CacheError> {
+    if value.is_empty() || value.chars().any(char::is_control) {
+        return Err(CacheError::InvalidArgument(format!(
+            "invalid SQL literal: {value:?}"
+        )));
+    }
+    Ok(format!("'{}'", value.replace('\'', "''")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_function_sql_matches_shipped_script() {
+        let sql = TriggerSqlBuilder::new().build_function_sql().unwrap();
+        assert_eq!(sql, include_str!("../sql/cache_notification_triggers.sql"));
+    }
+
+    #[test]
+    fn test_function_sql_without_payload_fallback() {
+        let sql = TriggerSqlBuilder::new()
+            .function_name("notify_users")
+            .channel("users")
+            .build_function_sql()
+            .unwrap();
+
+        assert!(sql.contains("CREATE OR REPLACE FUNCTION \"notify_users\"()"));
+        assert!(sql.contains("channel text := 'users';"));
+        assert!(sql.contains("notification json;"));
+        assert!(!sql.contains("octet_length"));
+    }
+
+    #[test]
+    fn test_function_sql_with_payload_fallback() {
+        let sql = TriggerSqlBuilder::new()
+            .payload_size_limit(Some(DEFAULT_PAYLOAD_SIZE_LIMIT))
+            .build_function_sql()
+            .unwrap();
+
+        assert!(sql.contains("IF octet_length(payload) > 7999 THEN"));
+        assert!(sql.contains("payload = (notification::jsonb - 'data')::text;"));
+    }
+
+    #[test]
+    fn test_function_sql_with_protected_data() {
+        let sql = TriggerSqlBuilder::new()
+            .protect_data(true)
+            .payload_size_limit(Some(DEFAULT_PAYLOAD_SIZE_LIMIT))
+            .build_function_sql()
+            .unwrap();
+        assert!(sql.contains("current_setting('postgres_index_cache.payload_key', true)"));
+        assert!(sql.contains("encrypt_iv("));
+        assert!(sql.contains("'aes-cbc/pad:pkcs'"));
+        assert!(sql.contains("row_data = to_json('v1' || '.'"));
+        // The size fallback still applies to the protected data
+        assert!(sql.contains("octet_length(payload) > 7999"));
+    }
+
+    #[test]
+    fn test_function_sql_with_outbox_delivery() {
+        let sql = TriggerSqlBuilder::new()
+            .delivery(NotificationDelivery::Outbox)
+            .build_function_sql()
+            .unwrap();
+        assert!(sql.contains("INSERT INTO \"cache_outbox\" (channel, payload) VALUES (channel, notification::text);"));
+        assert!(!sql.contains("PERFORM pg_notify"));
+
+        let sql = TriggerSqlBuilder::new()
+            .delivery(NotificationDelivery::NotifyAndOutbox)
+            .outbox_table("billing_outbox")
+            .build_function_sql()
+            .unwrap();
+        assert!(sql.contains("PERFORM pg_notify(channel, payload);"));
+        assert!(sql.contains("INSERT INTO \"billing_outbox\""));
+
+        let table_sql = TriggerSqlBuilder::new().outbox_table("billing_outbox").build_outbox_table_sql().unwrap();
+        assert!(table_sql.starts_with("CREATE TABLE IF NOT EXISTS \"billing_outbox\" ("));
+        assert!(table_sql.contains("ON \"billing_outbox\" (channel, seq);"));
+    }
+
+    #[test]
+    fn test_schema_qualified_function() {
+        let builder = TriggerSqlBuilder::new()
+            .table("invoices")
+            .function_schema("billing")
+            .function_name("notify_cache_change_v2");
+
+        let function_sql = builder.build_function_sql().unwrap();
+        assert!(function_sql.contains("CREATE OR REPLACE FUNCTION \"billing\".\"notify_cache_change_v2\"()"));
+
+        let trigger_sql = builder.build_trigger_sql().unwrap();
+        assert!(trigger_sql.contains("EXECUTE FUNCTION \"billing\".\"notify_cache_change_v2\"('cache_invalidation', 'true');"));
+    }
+
+    #[test]
+    fn test_trigger_sql_snapshot() {
+        let sql = TriggerSqlBuilder::new()
+            .table("product_index_cache")
+            .schema("shop")
+            .channel("products")
+            .data_columns(["id", "user_id"])
+            .build_trigger_sql()
+            .unwrap();
+
+        assert_eq!(
+            sql,
+            "DROP TRIGGER IF EXISTS \"product_index_cache_cache_notify\" ON \"shop\".\"product_index_cache\";\n\
+             DROP TRIGGER IF EXISTS \"product_index_cache_cache_notify_update\" ON \"shop\".\"product_index_cache\";\n\
+             DROP TRIGGER IF EXISTS \"product_index_cache_cache_notify_truncate\" ON \"shop\".\"product_index_cache\";\n\
+             CREATE TRIGGER \"product_index_cache_cache_notify\"\n    \
+             AFTER INSERT OR UPDATE OR DELETE ON \"shop\".\"product_index_cache\"\n    \
+             FOR EACH ROW\n    \
+             EXECUTE FUNCTION \"notify_cache_change\"('products', 'true', 'id,user_id');"
+        );
+    }
+
+    #[test]
+    fn test_trigger_sql_only_when_changed() {
+        let sql = TriggerSqlBuilder::new()
+            .table("users")
+            .only_when_changed(["email_hash", "username_hash"])
+            .build_trigger_sql()
+            .unwrap();
+
+        assert_eq!(
+            sql,
+            "DROP TRIGGER IF EXISTS \"users_cache_notify\" ON \"users\";\n\
+             DROP TRIGGER IF EXISTS \"users_cache_notify_update\" ON \"users\";\n\
+             DROP TRIGGER IF EXISTS \"users_cache_notify_truncate\" ON \"users\";\n\
+             CREATE TRIGGER \"users_cache_notify\"\n    \
+             AFTER INSERT OR DELETE ON \"users\"\n    \
+             FOR EACH ROW\n    \
+             EXECUTE FUNCTION \"notify_cache_change\"('cache_invalidation', 'true');\n\
+             CREATE TRIGGER \"users_cache_notify_update\"\n    \
+             AFTER UPDATE ON \"users\"\n    \
+             FOR EACH ROW\n    \
+             WHEN (OLD.\"email_hash\" IS DISTINCT FROM NEW.\"email_hash\" \
+             OR OLD.\"username_hash\" IS DISTINCT FROM NEW.\"username_hash\")\n    \
+             EXECUTE FUNCTION \"notify_cache_change\"('cache_invalidation', 'true');"
+        );
+
+        // Without an update event there is nothing to filter
+        let sql = TriggerSqlBuilder::new()
+            .table("users")
+            .events(&[TriggerEvent::Insert])
+            .only_when_changed(["email_hash"])
+            .build_trigger_sql()
+            .unwrap();
+        assert!(!sql.contains("WHEN"));
+        assert!(!sql.contains("CREATE TRIGGER \"users_cache_notify_update\""));
+    }
+
+    #[test]
+    fn test_trigger_sql_truncate_and_notify_table() {
+        let sql = TriggerSqlBuilder::new()
+            .table("orders_2024")
+            .trigger_name("orders_cache_notify")
+            .notify_table("orders")
+            .events(&[TriggerEvent::Delete, TriggerEvent::Truncate])
+            .build_trigger_sql()
+            .unwrap();
+
+        assert!(sql.ends_with(
+            "CREATE TRIGGER \"orders_cache_notify\"\n    \
+             AFTER DELETE ON \"orders_2024\"\n    \
+             FOR EACH ROW\n    \
+             EXECUTE FUNCTION \"notify_cache_change\"('cache_invalidation', 'true', '', 'orders');\n\
+             CREATE TRIGGER \"orders_cache_notify_truncate\"\n    \
+             AFTER TRUNCATE ON \"orders_2024\"\n    \
+             FOR EACH STATEMENT\n    \
+             EXECUTE FUNCTION \"notify_cache_change\"('cache_invalidation', 'true', '', 'orders');"
+        ));
+
+        // A truncate-only trigger is valid on its own
+        let sql = TriggerSqlBuilder::new()
+            .table("users")
+            .events(&[TriggerEvent::Truncate])
+            .build_trigger_sql()
+            .unwrap();
+        assert!(!sql.contains("FOR EACH ROW"));
+        assert!(sql.contains("AFTER TRUNCATE ON \"users\""));
+    }
+
+    #[test]
+    fn test_trigger_sql_context_columns() {
+        let sql = TriggerSqlBuilder::new()
+            .table("orders")
+            .include_data(false)
+            .context_columns(["tenant_id", "region"])
+            .build_trigger_sql()
+            .unwrap();
+
+        // Unset arguments before the context columns are passed as empty strings
+        assert!(sql.contains(
+            "EXECUTE FUNCTION \"notify_cache_change\"('cache_invalidation', 'false', '', '', 'tenant_id,region');"
+        ));
+    }
+
+    #[test]
+    fn test_trigger_sql_rejects_invalid_input() {
+        assert!(TriggerSqlBuilder::new().build_trigger_sql().is_err());
+        assert!(TriggerSqlBuilder::new().table("users").events(&[]).build_trigger_sql().is_err());
+        assert!(TriggerSqlBuilder::new()
+            .table("users")
+            .data_columns(["a,b"])
+            .build_trigger_sql()
+            .is_err());
+        assert!(TriggerSqlBuilder::new().channel("a\nb").build_function_sql().is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use uuid::Uuid;
+
+use crate::traits::Versioned;
+
+/// What to do when a staged write was based on an outdated cached version
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail the commit with `CacheError::Conflict`; nothing is applied
+    #[default]
+    Fail,
+    /// Apply the staged write anyway, overwriting the newer cached item
+    LastWriteWins,
+    /// Keep the cached item and drop the conflicting staged write
+    Skip,
+}
+
+/// A staged change that no longer matched the shared cache at commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitAnomaly {
+    /// The cached version changed since the key was first staged; `None`
+    /// stands for an uncached key
+    VersionChanged {
+        key: Uuid,
+        expected: Option<u64>,
+        found: Option<u64>,
+    },
+    /// The key staged for update is no longer cached, e.g. deleted by a notification
+    MissingForUpdate(Uuid),
+    /// The key staged for deletion is no longer cached
+    MissingForDelete(Uuid),
+}
+
+impl CommitAnomaly {
+    /// The primary key of the staged change
+    pub fn key(&self) -> Uuid {
+        match self {
+            CommitAnomaly::VersionChanged { key, .. } => *key,
+            CommitAnomaly::MissingForUpdate(key) | CommitAnomaly::MissingForDelete(key) => *key,
+        }
+    }
+}
+
+/// What a commit found and did, returned by `commit_with_report`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitReport {
+    /// Number of staged changes applied to the shared cache
+    pub applied: usize,
+    /// Staged changes that no longer matched the shared cache, sorted by key
+    pub anomalies: Vec<CommitAnomaly>,
+    /// Keys whose staged change was dropped under `ConflictPolicy::Skip`, sorted
+    pub skipped: Vec<Uuid>,
+}
+
+impl CommitReport {
+    /// Returns true if every staged change matched the shared cache
+    pub fn is_clean(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+/// Reads the version of an item; set only by constructors bounded on `Versioned`
+pub(crate) type VersionOf<T> = fn(&T) -> u64;
+
+/// Version accessor for a `Versioned` type
+pub(crate) fn version_of<T: Versioned>() -> VersionOf<T> {
+    <T as Versioned>::version
+}
+
+/// Whether an incoming item is older than the cached one and must not replace it
+pub(crate) fn is_stale<T>(version_of: Option<VersionOf<T>>, incoming: &T, cached: Option<&T>) -> bool {
+    match (version_of, cached) {
+        (Some(version_of), Some(cached)) => version_of(incoming) < version_of(cached),
+        _ => false,
+    }
+}
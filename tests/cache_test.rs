@@ -1,7 +1,13 @@
 mod common;
 
-use common::{UserIndexCache, ProductIndexCache, User, Product};
-use postgres_index_cache::{IdxModelCache, TransactionAwareIdxModelCache};
+use common::{UserIndexCache, ProductIndexCache, User, Product, AccountIndexCache};
+use postgres_index_cache::{
+    CacheError, CommitAnomaly, ConflictPolicy, HasPrimaryKey, IdxModelCache, IndexKeys, Indexable, StagedOp,
+    StagingLimit, TransactionAwareIdxModelCache,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
 use parking_lot::RwLock;
 use std::sync::Arc;
 
@@ -109,6 +115,29 @@ fn test_uuid_index_queries() {
     assert_eq!(user2_products.len(), 1); // product3
 }
 
+#[test]
+fn test_remove_by_uuid_index_value() {
+    let alice = Uuid::new_v4();
+    let bob = Uuid::new_v4();
+    let laptop = ProductIndexCache::new(Uuid::new_v4(), alice, "Laptop");
+    let mouse = ProductIndexCache::new(Uuid::new_v4(), alice, "Mouse");
+    let keyboard = ProductIndexCache::new(Uuid::new_v4(), bob, "Keyboard");
+    let mut cache = IdxModelCache::new(vec![laptop.clone(), mouse.clone(), keyboard.clone()]).unwrap();
+
+    let mut removed = cache.remove_by_uuid_index_value("user_id", &alice);
+    removed.sort_by_key(|product| product.id);
+    let mut expected = vec![laptop.clone(), mouse.clone()];
+    expected.sort_by_key(|product| product.id);
+    assert_eq!(removed, expected);
+
+    // Gone from every index, the other user's product untouched
+    assert!(cache.get_by_uuid_index("user_id", &alice).is_none());
+    assert!(cache.get_by_i64_index("product_name_hash", &laptop.product_name_hash).is_none());
+    assert!(cache.contains_primary(&keyboard.id));
+    assert!(cache.remove_by_uuid_index_value("user_id", &alice).is_empty());
+    assert!(cache.remove_by_uuid_index_value("no_such_index", &bob).is_empty());
+}
+
 #[test]
 fn test_duplicate_primary_key_error() {
     let user1 = User::new("alice".to_string(), "alice@example.com".to_string());
@@ -221,6 +250,34 @@ async fn test_transaction_aware_cache_commit() {
     assert!(shared_cache.read().get_by_primary(&user2.id).is_some());
 }
 
+#[tokio::test]
+async fn test_commit_counts_as_one_generation() {
+    use postgres_index_cache::TransactionAware;
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let mut cache = IdxModelCache::new(vec![alice.clone()]).unwrap();
+    assert_eq!(cache.generation(), 0);
+
+    // An update and an add that replaces count once each
+    cache.update(alice.clone());
+    cache.add(alice.clone());
+    assert_eq!(cache.generation(), 2);
+    let shared_cache = Arc::new(RwLock::new(cache));
+
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    tx_cache.add(bob.clone());
+    tx_cache.add(UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com"));
+    tx_cache.remove(&alice.id);
+    tx_cache.on_commit().await.unwrap();
+    assert!(shared_cache.read().contains_primary(&bob.id));
+    assert!(!shared_cache.read().contains_primary(&alice.id));
+    assert_eq!(shared_cache.read().generation(), 3);
+
+    shared_cache.write().clear();
+    assert_eq!(shared_cache.read().generation(), 4);
+}
+
 #[tokio::test]
 async fn test_transaction_aware_cache_rollback() {
     // Create shared cache
@@ -288,7 +345,7 @@ async fn test_transaction_aware_cache_i64_index_with_staging() {
     
     // Query by username_hash within transaction
     let alice_hash = user_cache1.username_hash;
-    let results = tx_cache.get_by_i64_index("username_hash", &alice_hash);
+    let results = tx_cache.get_by_i64_index("username_hash", &alice_hash).unwrap();
     
     // Should get both alice users (user1 from shared, user3 from staging)
     assert_eq!(results.len(), 2);
@@ -299,7 +356,7 @@ async fn test_transaction_aware_cache_i64_index_with_staging() {
     tx_cache.update(updated_user_cache2.clone());
     
     // Query again - should now get 3 results
-    let results = tx_cache.get_by_i64_index("username_hash", &alice_hash);
+    let results = tx_cache.get_by_i64_index("username_hash", &alice_hash).unwrap();
     assert_eq!(results.len(), 3);
     
     // Rollback and verify shared cache is unchanged
@@ -337,7 +394,7 @@ async fn test_transaction_aware_cache_uuid_index_with_staging() {
     tx_cache.add(product_cache3.clone());
     
     // Query by user_id within transaction
-    let results = tx_cache.get_by_uuid_index("user_id", &user1.id);
+    let results = tx_cache.get_by_uuid_index("user_id", &user1.id).unwrap();
     assert_eq!(results.len(), 3); // All three products
     
     // Commit and verify
@@ -347,4 +404,1349 @@ async fn test_transaction_aware_cache_uuid_index_with_staging() {
     let shared_guard = shared_cache.read();
     let shared_results = shared_guard.get_by_uuid_index("user_id", &user1.id).unwrap();
     assert_eq!(shared_results.len(), 3);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_transaction_uuid_index_query_applies_staged_changes() {
+    let alice = User::new("alice".to_string(), "alice@example.com".to_string());
+    let bob = User::new("bob".to_string(), "bob@example.com".to_string());
+    let laptop = ProductIndexCache::from_product(&Product::new(alice.id, "Laptop".to_string()));
+    let mouse = ProductIndexCache::from_product(&Product::new(alice.id, "Mouse".to_string()));
+    let screen = ProductIndexCache::from_product(&Product::new(alice.id, "Screen".to_string()));
+    let shared_cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![laptop.clone(), mouse.clone(), screen.clone()]).unwrap(),
+    ));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache);
+    let ids = |items: Vec<ProductIndexCache>| {
+        let mut ids: Vec<Uuid> = items.iter().map(|item| item.id).collect();
+        ids.sort();
+        ids
+    };
+    let sorted = |mut ids: Vec<Uuid>| {
+        ids.sort();
+        ids
+    };
+
+    // Nothing staged: the shared items
+    let results = tx_cache.get_by_uuid_index("user_id", &alice.id).unwrap();
+    assert_eq!(ids(results), sorted(vec![laptop.id, mouse.id, screen.id]));
+
+    // A deletion, an update moving an item to another user and an addition
+    tx_cache.remove(&laptop.id);
+    let moved = ProductIndexCache { user_id: bob.id, ..mouse.clone() };
+    tx_cache.update(moved.clone());
+    let keyboard = ProductIndexCache::from_product(&Product::new(alice.id, "Keyboard".to_string()));
+    tx_cache.add(keyboard.clone());
+
+    let results = tx_cache.get_by_uuid_index("user_id", &alice.id).unwrap();
+    assert_eq!(ids(results), sorted(vec![screen.id, keyboard.id]));
+    assert_eq!(tx_cache.get_by_uuid_index("user_id", &bob.id).unwrap(), vec![moved]);
+}
+
+/// Stages an update of `account` in two transactions and commits both;
+/// the second commit is based on a version the first one replaced.
+/// Returns the shared cache, the account id and whether the second commit succeeded.
+async fn commit_concurrent_updates(
+    policy: ConflictPolicy,
+) -> (Arc<RwLock<IdxModelCache<AccountIndexCache>>>, Uuid, bool) {
+    use postgres_index_cache::TransactionAware;
+
+    let account = AccountIndexCache::new(Uuid::new_v4(), 100, 1);
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+
+    let first = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), policy);
+    let second = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), policy);
+    first.update(AccountIndexCache::new(account.id, 200, 2));
+    second.update(AccountIndexCache::new(account.id, 300, 2));
+
+    first.on_commit().await.unwrap();
+    let committed = second.on_commit().await.is_ok();
+    (shared_cache, account.id, committed)
+}
+
+#[tokio::test]
+async fn test_conflict_policy_fail() {
+    let (shared_cache, id, committed) = commit_concurrent_updates(ConflictPolicy::Fail).await;
+
+    assert!(!committed);
+    assert_eq!(shared_cache.read().get_by_primary(&id).unwrap().balance_hash, 200);
+}
+
+#[tokio::test]
+async fn test_conflict_policy_last_write_wins() {
+    let (shared_cache, id, committed) = commit_concurrent_updates(ConflictPolicy::LastWriteWins).await;
+
+    assert!(committed);
+    assert_eq!(shared_cache.read().get_by_primary(&id).unwrap().balance_hash, 300);
+}
+
+#[tokio::test]
+async fn test_conflict_policy_skip() {
+    use postgres_index_cache::TransactionAware;
+
+    let account = AccountIndexCache::new(Uuid::new_v4(), 100, 1);
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+
+    let first = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Skip);
+    let second = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Skip);
+    first.update(AccountIndexCache::new(account.id, 200, 2));
+    second.update(AccountIndexCache::new(account.id, 300, 2));
+    let other = AccountIndexCache::new(Uuid::new_v4(), 400, 1);
+    second.add(other.clone());
+
+    first.on_commit().await.unwrap();
+    second.on_commit().await.unwrap();
+
+    // The conflicting update is dropped, the unrelated addition is applied
+    let shared = shared_cache.read();
+    assert_eq!(shared.get_by_primary(&account.id).unwrap().balance_hash, 200);
+    assert!(shared.contains_primary(&other.id));
+}
+
+#[tokio::test]
+async fn test_conflict_detected_for_concurrent_addition() {
+    use postgres_index_cache::TransactionAware;
+
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::<AccountIndexCache>::new(vec![]).unwrap()));
+    let id = Uuid::new_v4();
+
+    let first = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Fail);
+    let second = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Fail);
+    first.add(AccountIndexCache::new(id, 100, 1));
+    second.add(AccountIndexCache::new(id, 200, 1));
+
+    first.on_commit().await.unwrap();
+    assert!(second.on_commit().await.is_err());
+    assert_eq!(shared_cache.read().get_by_primary(&id).unwrap().balance_hash, 100);
+}
+
+#[tokio::test]
+async fn test_no_conflict_for_sequential_transactions() {
+    use postgres_index_cache::TransactionAware;
+
+    let account = AccountIndexCache::new(Uuid::new_v4(), 100, 1);
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Fail);
+
+    tx_cache.update(AccountIndexCache::new(account.id, 200, 2));
+    tx_cache.on_commit().await.unwrap();
+    tx_cache.update(AccountIndexCache::new(account.id, 300, 3));
+    tx_cache.on_commit().await.unwrap();
+
+    assert_eq!(shared_cache.read().get_by_primary(&account.id).unwrap().version, 3);
+}
+
+/// Stages an update and a deletion of two users, then deletes both from the
+/// shared cache as notifications from another process would
+fn stage_against_deleted_rows(
+    policy: ConflictPolicy,
+) -> (Arc<RwLock<IdxModelCache<UserIndexCache>>>, TransactionAwareIdxModelCache<UserIndexCache>, UserIndexCache, Uuid) {
+    let updated = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let deleted = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![updated.clone(), deleted.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone()).with_commit_validation(policy);
+
+    let mut changed = updated.clone();
+    changed.email_hash = 515151;
+    tx_cache.update(changed.clone());
+    tx_cache.remove(&deleted.id);
+    shared_cache.write().remove(&updated.id);
+    shared_cache.write().remove(&deleted.id);
+    (shared_cache, tx_cache, changed, deleted.id)
+}
+
+#[test]
+fn test_commit_validation_skips_rows_deleted_meanwhile() {
+    let (shared_cache, tx_cache, changed, deleted_id) = stage_against_deleted_rows(ConflictPolicy::Skip);
+    let added = UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com");
+    tx_cache.add(added.clone());
+
+    let report = tx_cache.commit_with_report().unwrap();
+    let mut expected = vec![
+        CommitAnomaly::MissingForUpdate(changed.id),
+        CommitAnomaly::MissingForDelete(deleted_id),
+    ];
+    expected.sort_by_key(CommitAnomaly::key);
+    assert_eq!(report.anomalies, expected);
+    assert_eq!(report.applied, 1);
+    assert_eq!(report.skipped.len(), 2);
+
+    // The deleted row is not re-added
+    assert!(!shared_cache.read().contains_primary(&changed.id));
+    assert!(shared_cache.read().contains_primary(&added.id));
+}
+
+#[test]
+fn test_commit_validation_report_only_and_fail() {
+    let (shared_cache, tx_cache, changed, _) = stage_against_deleted_rows(ConflictPolicy::LastWriteWins);
+    let report = tx_cache.commit_with_report().unwrap();
+    assert!(!report.is_clean());
+    assert!(report.skipped.is_empty());
+    assert_eq!(shared_cache.read().get_by_primary(&changed.id), Some(changed));
+
+    let (shared_cache, tx_cache, changed, deleted_id) = stage_against_deleted_rows(ConflictPolicy::Fail);
+    match tx_cache.commit_with_report() {
+        Err(CacheError::Conflict { keys }) => {
+            let mut expected = vec![changed.id, deleted_id];
+            expected.sort();
+            assert_eq!(keys, expected);
+        }
+        other => panic!("expected a conflict, got {other:?}"),
+    }
+    assert!(!shared_cache.read().contains_primary(&changed.id));
+}
+
+#[test]
+fn test_try_commit_fails_each_conflicting_key() {
+    // Rows deleted meanwhile, staged for update and for deletion
+    let (shared_cache, tx_cache, changed, deleted_id) = stage_against_deleted_rows(ConflictPolicy::Fail);
+    let added = UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com");
+    tx_cache.add(added.clone());
+
+    let err = tx_cache.try_commit().unwrap_err();
+    assert_eq!(err.applied, 0);
+    let mut expected = vec![changed.id, deleted_id];
+    expected.sort();
+    assert_eq!(err.failed_keys().copied().collect::<Vec<_>>(), expected);
+    assert!(err
+        .failures
+        .iter()
+        .all(|(key, err)| matches!(err, CacheError::Conflict { keys } if keys.as_slice() == [*key])));
+    // Nothing is applied, and the conflicting changes are not kept for a retry
+    assert!(!shared_cache.read().contains_primary(&added.id));
+    assert!(!tx_cache.is_dirty());
+
+    // A version changed since the update was staged
+    let account = AccountIndexCache::new(Uuid::new_v4(), 100, 1);
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Fail);
+    tx_cache.update(AccountIndexCache::new(account.id, 300, 2));
+    shared_cache.write().update(AccountIndexCache::new(account.id, 200, 2));
+
+    let err = tx_cache.try_commit().unwrap_err();
+    assert_eq!(err.failed_keys().collect::<Vec<_>>(), vec![&account.id]);
+    assert!(err.to_string().contains(&account.id.to_string()), "{err}");
+    assert_eq!(shared_cache.read().get_by_primary(&account.id).unwrap().balance_hash, 200);
+}
+
+#[test]
+fn test_commit_validation_reports_version_changes() {
+    let account = AccountIndexCache::new(Uuid::new_v4(), 100, 1);
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::with_conflict_policy(shared_cache.clone(), ConflictPolicy::Fail)
+        .with_commit_validation(ConflictPolicy::LastWriteWins);
+
+    tx_cache.update(AccountIndexCache::new(account.id, 300, 2));
+    shared_cache.write().update(AccountIndexCache::new(account.id, 200, 2));
+
+    let report = tx_cache.commit_with_report().unwrap();
+    assert_eq!(
+        report.anomalies,
+        vec![CommitAnomaly::VersionChanged {
+            key: account.id,
+            expected: Some(1),
+            found: Some(2),
+        }]
+    );
+    assert_eq!(shared_cache.read().get_by_primary(&account.id).unwrap().balance_hash, 300);
+}
+
+#[test]
+fn test_snapshot_arc_is_unaffected_by_later_changes() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone()]).unwrap()));
+
+    let snapshot = shared_cache.read().snapshot_arc();
+    shared_cache.write().add(bob.clone());
+    shared_cache.write().remove(&alice.id);
+
+    // The snapshot keeps answering from the state it was taken in
+    assert!(snapshot.contains_primary(&alice.id));
+    assert!(!snapshot.contains_primary(&bob.id));
+    assert_eq!(snapshot.try_get_by_i64_index("email_hash", &alice.email_hash).unwrap(), &[alice.id]);
+    assert!(!shared_cache.read().contains_primary(&alice.id));
+    assert!(shared_cache.read().contains_primary(&bob.id));
+
+    // Snapshots can be cloned and read from other threads without locking
+    let clone = snapshot.clone();
+    let found = std::thread::spawn(move || clone.get_by_primary(&alice.id)).join().unwrap();
+    assert_eq!(found, Some(alice));
+}
+
+#[test]
+fn test_clear_keeps_snapshot_and_declared_indexes() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let mut cache = IdxModelCache::new(vec![alice.clone()]).unwrap().with_index_counts();
+
+    let snapshot = cache.snapshot_arc();
+    cache.clear();
+
+    assert!(snapshot.contains_primary(&alice.id));
+    assert!(!cache.contains_primary(&alice.id));
+    assert!(cache.try_get_by_i64_index("email_hash", &alice.email_hash).unwrap().is_empty());
+    assert!(cache.index_histogram("email_hash", 10).is_empty());
+
+    // Counts are still kept for items added after the clear
+    cache.add(alice.clone());
+    assert_eq!(cache.index_histogram("email_hash", 10).len(), 1);
+}
+
+#[test]
+fn test_evict_deleted() {
+    let live = AccountIndexCache::new(Uuid::new_v4(), 100, 1);
+    let mut deleted = AccountIndexCache::new(Uuid::new_v4(), 200, 1);
+    deleted.deleted_at = Some(chrono::Utc::now());
+    let mut cache = IdxModelCache::new(vec![live.clone(), deleted.clone()]).unwrap();
+
+    assert_eq!(cache.evict_deleted(), vec![deleted.id]);
+    assert!(cache.contains_primary(&live.id));
+    assert!(!cache.contains_primary(&deleted.id));
+    assert!(cache.get_by_i64_index("balance_hash", &200).is_none());
+}
+
+/// A model indexed by the date it becomes effective
+#[derive(Debug, Clone)]
+struct RateIndexCache {
+    id: Uuid,
+    effective_at: Option<DateTime<Utc>>,
+}
+
+impl RateIndexCache {
+    fn new(effective_at: DateTime<Utc>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            effective_at: Some(effective_at),
+        }
+    }
+}
+
+impl HasPrimaryKey for RateIndexCache {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for RateIndexCache {
+    fn i64_keys(&self) -> HashMap<String, Option<i64>> {
+        HashMap::new()
+    }
+
+    fn uuid_keys(&self) -> HashMap<String, Option<Uuid>> {
+        HashMap::new()
+    }
+
+    fn datetime_keys(&self) -> HashMap<String, Option<DateTime<Utc>>> {
+        HashMap::from([("effective_at".to_string(), self.effective_at)])
+    }
+}
+
+fn day(day: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap()
+}
+
+#[test]
+fn test_datetime_index_queries() {
+    let first = RateIndexCache::new(day(1));
+    let second = RateIndexCache::new(day(2));
+    let third = RateIndexCache::new(day(3));
+    let mut cache = IdxModelCache::new(vec![third.clone(), first.clone(), second.clone()]).unwrap();
+
+    assert_eq!(cache.get_by_datetime_index("effective_at", &day(2)), Some(&[second.id][..]));
+    assert_eq!(cache.get_by_datetime_range("effective_at", day(2)..), vec![second.id, third.id]);
+    assert_eq!(cache.get_by_datetime_range("effective_at", ..day(2)), vec![first.id]);
+    assert_eq!(cache.get_by_datetime_range("effective_at", ..=day(2)), vec![first.id, second.id]);
+    assert_eq!(cache.get_by_datetime_range("effective_at", ..).len(), 3);
+    assert!(cache.get_by_datetime_range("effective_at", day(3)..day(1)).is_empty());
+    assert!(cache.get_by_datetime_range("effective_at", day(2)..day(2)).is_empty());
+
+    // Moving an item updates its position in the index
+    let mut moved = first.clone();
+    moved.effective_at = Some(day(4));
+    cache.update(moved);
+    assert_eq!(cache.get_by_datetime_range("effective_at", day(3)..), vec![third.id, first.id]);
+    assert!(cache.get_by_datetime_index("effective_at", &day(1)).is_none());
+
+    cache.remove(&third.id);
+    assert_eq!(cache.get_by_datetime_range("effective_at", day(3)..), vec![first.id]);
+}
+
+#[test]
+fn test_datetime_index_microsecond_precision() {
+    let effective_at = day(1) + Duration::microseconds(123_456);
+    let rate = RateIndexCache::new(effective_at + Duration::nanoseconds(789));
+    let cache = IdxModelCache::new(vec![rate.clone()]).unwrap();
+
+    // Sub-microsecond digits are not significant, as in PostgreSQL timestamps
+    assert_eq!(cache.get_by_datetime_index("effective_at", &effective_at), Some(&[rate.id][..]));
+    assert!(cache
+        .get_by_datetime_index("effective_at", &(effective_at + Duration::microseconds(1)))
+        .is_none());
+}
+
+#[test]
+fn test_transaction_aware_cache_datetime_index_with_staging() {
+    let first = RateIndexCache::new(day(1));
+    let second = RateIndexCache::new(day(2));
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![first.clone(), second.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    let added = RateIndexCache::new(day(3));
+    tx_cache.add(added.clone());
+    let mut moved = first.clone();
+    moved.effective_at = Some(day(5));
+    tx_cache.update(moved);
+    tx_cache.remove(&second.id);
+
+    let ids = |items: Vec<RateIndexCache>| items.iter().map(|item| item.id).collect::<Vec<_>>();
+    assert_eq!(ids(tx_cache.get_by_datetime_range("effective_at", ..).unwrap()), vec![added.id, first.id]);
+    assert!(tx_cache.get_by_datetime_index("effective_at", &day(1)).unwrap().is_empty());
+    assert_eq!(ids(tx_cache.get_by_datetime_index("effective_at", &day(5)).unwrap()), vec![first.id]);
+
+    // The shared cache is unchanged until commit
+    assert_eq!(
+        shared_cache.read().get_by_datetime_range("effective_at", ..),
+        vec![first.id, second.id]
+    );
+}
+
+#[test]
+fn test_transaction_aware_cache_staged_changes() {
+    let user1 = User::new("alice".to_string(), "alice@example.com".to_string());
+    let user_cache1 = UserIndexCache::from_user(&user1);
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user_cache1.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    assert!(!tx_cache.is_dirty());
+
+    let user2 = User::new("bob".to_string(), "bob@example.com".to_string());
+    let user_cache2 = UserIndexCache::from_user(&user2);
+    tx_cache.add(user_cache2.clone());
+    let mut updated_user_cache1 = user_cache1.clone();
+    updated_user_cache1.email_hash = 888888;
+    tx_cache.update(updated_user_cache1.clone());
+
+    assert!(tx_cache.is_dirty());
+    assert_eq!(tx_cache.staged_additions_count(), 1);
+    assert_eq!(tx_cache.staged_updates_count(), 1);
+    assert_eq!(tx_cache.staged_deletions_count(), 0);
+
+    let changes = tx_cache.staged_changes();
+    assert_eq!(changes.additions, vec![user_cache2]);
+    assert_eq!(changes.updates, vec![updated_user_cache1]);
+    assert!(changes.deletions.is_empty());
+
+    tx_cache.remove(&user1.id);
+    assert_eq!(tx_cache.staged_changes().deletions, vec![user1.id]);
+    assert!(tx_cache.staged_changes().updates.is_empty());
+
+    tx_cache.clear_staged();
+    assert!(!tx_cache.is_dirty());
+    assert!(shared_cache.read().contains_primary(&user1.id));
+}
+
+#[tokio::test]
+async fn test_transaction_aware_cache_commit_in_staging_order() {
+    use postgres_index_cache::TransactionAware;
+
+    // The old user frees its username hash, which the new user then takes
+    let old_user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let new_user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.org");
+    let other_user = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let shared_cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![old_user.clone(), other_user.clone()]).unwrap()
+    ));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    let mut updated_other_user = other_user.clone();
+    updated_other_user.email_hash = 888888;
+    tx_cache.update(updated_other_user.clone());
+    tx_cache.remove(&old_user.id);
+    tx_cache.add(new_user.clone());
+    // Staging a key again moves it to the end
+    tx_cache.update(updated_other_user.clone());
+
+    assert_eq!(
+        tx_cache.staged_ops(),
+        vec![
+            StagedOp::Remove(old_user.id),
+            StagedOp::Add(new_user.clone()),
+            StagedOp::Update(updated_other_user.clone()),
+        ]
+    );
+
+    tx_cache.on_commit().await.unwrap();
+    let shared = shared_cache.read();
+    assert_eq!(
+        shared.get_by_i64_index("username_hash", &new_user.username_hash),
+        Some(&[new_user.id][..])
+    );
+    assert!(!shared.contains_primary(&old_user.id));
+    assert_eq!(shared.get_by_primary(&other_user.id).unwrap().email_hash, 888888);
+}
+
+#[test]
+fn test_staged_ops_skip_cancelled_changes() {
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache);
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    tx_cache.add(user.clone());
+    tx_cache.remove(&user.id);
+
+    assert!(tx_cache.staged_ops().is_empty());
+    assert!(!tx_cache.is_dirty());
+}
+
+#[tokio::test]
+async fn test_transaction_aware_cache_snapshot_reads() {
+    use postgres_index_cache::TransactionAware;
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new_with_snapshot(shared_cache.clone());
+    let live_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    // A concurrent writer changes the shared cache during the transaction
+    let mut changed = user.clone();
+    changed.email_hash = 888888;
+    let added = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    shared_cache.write().update(changed.clone());
+    shared_cache.write().add(added.clone());
+
+    // Snapshot reads still see the state at construction
+    assert_eq!(tx_cache.get_by_primary(&user.id), Some(user.clone()));
+    assert!(!tx_cache.contains_primary(&added.id));
+    assert!(tx_cache.get_by_i64_index("email_hash", &888888).unwrap().is_empty());
+    assert_eq!(live_cache.get_by_primary(&user.id), Some(changed.clone()));
+
+    // Commits apply to the live shared cache and refresh the snapshot
+    let third = UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com");
+    tx_cache.add(third.clone());
+    tx_cache.on_commit().await.unwrap();
+    assert!(shared_cache.read().contains_primary(&third.id));
+    assert_eq!(shared_cache.read().get_by_primary(&user.id), Some(changed.clone()));
+    assert_eq!(tx_cache.get_by_primary(&user.id), Some(changed));
+    assert!(tx_cache.contains_primary(&added.id));
+}
+
+#[tokio::test]
+async fn test_transaction_aware_cache_write_through() {
+    use postgres_index_cache::TransactionAware;
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new_write_through(shared_cache.clone());
+
+    let added = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let mut changed = user.clone();
+    changed.email_hash = 888888;
+    tx_cache.add(added.clone());
+    tx_cache.update(changed.clone());
+
+    // Other readers see the changes before commit
+    assert!(tx_cache.is_dirty());
+    assert!(tx_cache.staged_changes().is_empty());
+    assert!(shared_cache.read().contains_primary(&added.id));
+    assert_eq!(shared_cache.read().get_by_i64_index("email_hash", &888888), Some(&[user.id][..]));
+
+    // Rollback restores the previous state, indexes included
+    tx_cache.on_rollback().await.unwrap();
+    assert!(!shared_cache.read().contains_primary(&added.id));
+    assert_eq!(shared_cache.read().get_by_primary(&user.id), Some(user.clone()));
+    assert!(shared_cache.read().get_by_i64_index("email_hash", &888888).is_none());
+
+    // Committed changes stay
+    tx_cache.remove(&user.id);
+    tx_cache.on_commit().await.unwrap();
+    tx_cache.on_rollback().await.unwrap();
+    assert!(!shared_cache.read().contains_primary(&user.id));
+}
+
+#[tokio::test]
+async fn test_transaction_aware_cache_batch_staging() {
+    use postgres_index_cache::TransactionAware;
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    let added: Vec<UserIndexCache> = (0..3)
+        .map(|i| UserIndexCache::new(Uuid::new_v4(), &format!("user{i}"), &format!("user{i}@example.com")))
+        .collect();
+    tx_cache.add_all(added.clone());
+    let mut changed = added[0].clone();
+    changed.email_hash = 424242;
+    let mut changed_user = user.clone();
+    changed_user.email_hash = 434343;
+    tx_cache.update_all(vec![changed.clone(), changed_user.clone()]);
+    tx_cache.remove_all(&[added[1].id, user.id]);
+
+    // Batches stage the same changes as their single-item counterparts
+    let changes = tx_cache.staged_changes();
+    assert_eq!(changes.additions, vec![added[2].clone(), changed.clone()]);
+    assert!(changes.updates.is_empty());
+    assert_eq!(changes.deletions, vec![user.id]);
+    assert!(!shared_cache.read().contains_primary(&added[0].id));
+
+    tx_cache.on_commit().await.unwrap();
+    {
+        let shared = shared_cache.read();
+        assert_eq!(shared.get_by_primary(&added[0].id), Some(changed));
+        assert!(!shared.contains_primary(&added[1].id));
+        assert!(shared.contains_primary(&added[2].id));
+        assert!(!shared.contains_primary(&user.id));
+    }
+
+    // Write-through batches are undone on rollback
+    let write_through = TransactionAwareIdxModelCache::new_write_through(shared_cache.clone());
+    write_through.remove_all(&[added[0].id, added[2].id]);
+    write_through.add_all(vec![user.clone()]);
+    assert!(shared_cache.read().contains_primary(&user.id));
+    assert!(!shared_cache.read().contains_primary(&added[2].id));
+    write_through.on_rollback().await.unwrap();
+    assert!(!shared_cache.read().contains_primary(&user.id));
+    assert!(shared_cache.read().contains_primary(&added[0].id));
+    assert!(shared_cache.read().contains_primary(&added[2].id));
+}
+
+#[test]
+fn test_staging_limit() {
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone()).with_staging_limit(StagingLimit::Operations(2));
+    assert_eq!(tx_cache.staging_limit(), Some(StagingLimit::Operations(2)));
+
+    let first = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let second = UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com");
+    tx_cache.try_add(first.clone()).unwrap();
+    tx_cache.remove(&user.id);
+    assert_eq!(tx_cache.staged_size().operations, 2);
+    assert!(matches!(
+        tx_cache.try_add(second.clone()),
+        Err(CacheError::CapacityExceeded { limit: 2 })
+    ));
+
+    // Replacing a staged change does not count against the limit
+    let mut changed = first.clone();
+    changed.email_hash = 616161;
+    tx_cache.try_update(changed).unwrap();
+    tx_cache.try_update(user.clone()).unwrap();
+    assert_eq!(tx_cache.staged_size().operations, 2);
+
+    // The infallible methods stage regardless
+    tx_cache.add(second);
+    assert_eq!(tx_cache.staged_size().operations, 3);
+    assert_eq!(
+        tx_cache.staged_size().estimated_bytes,
+        3 * std::mem::size_of::<UserIndexCache>()
+    );
+
+    let bytes = TransactionAwareIdxModelCache::new_write_through(shared_cache.clone())
+        .with_staging_limit(StagingLimit::Bytes(std::mem::size_of::<UserIndexCache>()));
+    bytes.try_update(user.clone()).unwrap();
+    assert!(bytes.try_update(user).is_err());
+}
+
+#[tokio::test]
+async fn test_after_commit_hooks_receive_applied_changes() {
+    use postgres_index_cache::{AppliedChanges, TransactionAware};
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let seen: Arc<RwLock<Vec<AppliedChanges<UserIndexCache>>>> = Arc::new(RwLock::new(Vec::new()));
+
+    let mut tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    tx_cache.on_after_commit(Arc::new(|_: &AppliedChanges<UserIndexCache>| panic!("hook failed")));
+    let recorder = seen.clone();
+    let cache = shared_cache.clone();
+    tx_cache.on_after_commit(Arc::new(move |changes: &AppliedChanges<UserIndexCache>| {
+        // The shared cache is unlocked while hooks run
+        assert!(cache.try_write().is_some());
+        recorder.write().push(changes.clone());
+    }));
+
+    let added = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    tx_cache.add(added.clone());
+    tx_cache.remove(&user.id);
+    tx_cache.on_commit().await.unwrap();
+
+    // The panicking hook neither poisons the cache nor stops the others
+    assert_eq!(seen.read().len(), 1);
+    assert_eq!(seen.read()[0].additions, vec![added.clone()]);
+    assert_eq!(seen.read()[0].deletions, vec![user.id]);
+    assert!(shared_cache.read().contains_primary(&added.id));
+
+    // Rollbacks and empty commits run no hooks
+    tx_cache.begin().unwrap();
+    tx_cache.remove(&added.id);
+    tx_cache.on_rollback().await.unwrap();
+    tx_cache.begin().unwrap();
+    tx_cache.on_commit().await.unwrap();
+    assert_eq!(seen.read().len(), 1);
+
+    tx_cache.run_hooks_on_empty_commit();
+    tx_cache.begin().unwrap();
+    tx_cache.on_commit().await.unwrap();
+    assert_eq!(seen.read().len(), 2);
+    assert!(seen.read()[1].is_empty());
+
+    // Write-through commits report each key's net change
+    let mut write_through = TransactionAwareIdxModelCache::new_write_through(shared_cache.clone());
+    let recorder = seen.clone();
+    write_through.on_after_commit(Arc::new(move |changes: &AppliedChanges<UserIndexCache>| {
+        recorder.write().push(changes.clone());
+    }));
+    let mut changed = added.clone();
+    changed.email_hash = 777777;
+    write_through.update(changed.clone());
+    let temporary = UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com");
+    write_through.add(temporary.clone());
+    write_through.remove(&temporary.id);
+    write_through.on_commit().await.unwrap();
+    assert_eq!(seen.read().len(), 3);
+    assert_eq!(seen.read()[2].updates, vec![changed]);
+    assert!(seen.read()[2].additions.is_empty());
+    assert!(seen.read()[2].deletions.is_empty());
+}
+
+#[tokio::test]
+async fn test_transaction_scope_rolls_back_on_panic() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let staged = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    let write_through = TransactionAwareIdxModelCache::new_write_through(shared_cache.clone());
+
+    let added = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let scope = staged.scoped();
+        scope.add(added.clone());
+        scope.remove(&user.id);
+        panic!("transaction closure failed");
+    }));
+    assert!(result.is_err());
+    assert!(!staged.is_dirty());
+    assert!(staged.is_completed());
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let scope = write_through.scoped();
+        scope.add(added.clone());
+        scope.remove(&user.id);
+        panic!("transaction closure failed");
+    }));
+    assert!(result.is_err());
+    assert!(!write_through.is_dirty());
+
+    // Neither wrapper left its changes in the shared cache
+    assert!(shared_cache.read().contains_primary(&user.id));
+    assert!(!shared_cache.read().contains_primary(&added.id));
+}
+
+#[tokio::test]
+async fn test_transaction_scope_commit() {
+    use postgres_index_cache::TransactionAware;
+
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let scope = tx_cache.scoped();
+    scope.add(user.clone());
+    scope.commit().await.unwrap();
+    assert!(shared_cache.read().contains_primary(&user.id));
+    assert!(tx_cache.is_completed());
+
+    // A second commit with nothing new staged is rejected, not re-applied
+    assert!(tx_cache.on_commit().await.is_err());
+
+    // Staging a new change starts the next transaction
+    tx_cache.remove(&user.id);
+    assert!(!tx_cache.is_completed());
+    tx_cache.on_commit().await.unwrap();
+    assert!(!shared_cache.read().contains_primary(&user.id));
+}
+
+#[test]
+fn test_transaction_aware_cache_returns_previous_values() {
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    // The first update sees the shared item, the next one the staged update
+    let mut first = user.clone();
+    first.email_hash = 111111;
+    let mut second = user.clone();
+    second.email_hash = 222222;
+    assert_eq!(tx_cache.update(first.clone()), Some(user.clone()));
+    assert_eq!(tx_cache.update(second.clone()), Some(first));
+    assert_eq!(tx_cache.remove(&user.id), Some(second));
+
+    // Already staged for removal
+    assert_eq!(tx_cache.remove(&user.id), None);
+    assert_eq!(tx_cache.update(user.clone()), None);
+
+    // Staged additions and unknown keys
+    let added = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    tx_cache.add(added.clone());
+    assert_eq!(tx_cache.remove(&added.id), Some(added));
+    assert_eq!(tx_cache.remove(&Uuid::new_v4()), None);
+
+    // Write-through mode returns the shared value it replaced
+    let write_through = TransactionAwareIdxModelCache::new_write_through(shared_cache.clone());
+    assert_eq!(write_through.remove(&user.id), Some(user));
+}
+
+#[tokio::test]
+async fn test_transaction_aware_cache_over_tokio_lock() {
+    use postgres_index_cache::TransactionAware;
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(tokio::sync::RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new_async(shared_cache.clone()).await;
+
+    let added = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    tx_cache.add_async(added.clone()).await;
+    assert_eq!(tx_cache.remove_async(&user.id).await, Some(user.clone()));
+    assert_eq!(tx_cache.get_by_primary_async(&added.id).await, Some(added.clone()));
+    assert!(!tx_cache.contains_primary_async(&user.id).await);
+    assert_eq!(
+        tx_cache.get_by_i64_index_async("username_hash", &added.username_hash).await.unwrap(),
+        vec![added.clone()]
+    );
+
+    // The commit waits for a guard held across an await instead of blocking the thread
+    let guard = shared_cache.write().await;
+    let commit = tokio::spawn({
+        let tx_cache = Arc::new(tx_cache);
+        async move { tx_cache.on_commit().await }
+    });
+    tokio::task::yield_now().await;
+    assert!(!commit.is_finished());
+    drop(guard);
+    commit.await.unwrap().unwrap();
+    assert!(shared_cache.read().await.contains_primary(&added.id));
+    assert!(!shared_cache.read().await.contains_primary(&user.id));
+
+    // Write-through changes are undone on rollback
+    let write_through = TransactionAwareIdxModelCache::new_write_through_async(shared_cache.clone()).await;
+    write_through.add_async(user.clone()).await;
+    assert!(shared_cache.read().await.contains_primary(&user.id));
+    write_through.on_rollback().await.unwrap();
+    assert!(!shared_cache.read().await.contains_primary(&user.id));
+}
+
+#[tokio::test]
+async fn test_transaction_aware_cache_begin_and_reset() {
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let tx_cache = Arc::new(TransactionAwareIdxModelCache::new(shared_cache.clone()));
+
+    assert_eq!(tx_cache.begin().unwrap(), 1);
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    tx_cache.add(user.clone());
+
+    // Pending changes must be committed, rolled back or reset first
+    assert!(matches!(tx_cache.begin(), Err(CacheError::OperationFailed(_))));
+    assert_eq!(tx_cache.reset(), 2);
+    assert!(!tx_cache.is_dirty());
+    assert_eq!(tx_cache.begin().unwrap(), 3);
+
+    tx_cache.add(user.clone());
+    tx_cache.participant().on_commit().await.unwrap();
+    assert!(shared_cache.read().contains_primary(&user.id));
+}
+
+#[tokio::test]
+async fn test_participant_from_previous_generation_cannot_commit() {
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let tx_cache = Arc::new(TransactionAwareIdxModelCache::new(shared_cache.clone()));
+
+    tx_cache.begin().unwrap();
+    let stale = tx_cache.participant();
+    stale.on_rollback().await.unwrap();
+
+    // The pooled wrapper is reused for the next request
+    tx_cache.begin().unwrap();
+    let current = tx_cache.participant();
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    tx_cache.add(user.clone());
+
+    // A late completion of the earlier unit of work leaves the new changes alone
+    assert!(stale.on_commit().await.is_err());
+    stale.on_rollback().await.unwrap();
+    assert!(!shared_cache.read().contains_primary(&user.id));
+    assert!(tx_cache.is_dirty());
+
+    current.on_commit().await.unwrap();
+    assert!(shared_cache.read().contains_primary(&user.id));
+}
+
+#[test]
+fn test_transaction_aware_cache_misspelled_index() {
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![user.clone()]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    // The shared cache has no such index, so it reports None like a missing key
+    assert!(shared_cache.read().get_by_i64_index("user_name_hash", &user.username_hash).is_none());
+
+    // The wrapper tells the two apart
+    assert!(matches!(
+        tx_cache.get_by_i64_index("user_name_hash", &user.username_hash),
+        Err(CacheError::IndexNotFound(name)) if name == "user_name_hash"
+    ));
+    assert!(tx_cache.get_by_i64_index("username_hash", &0).unwrap().is_empty());
+    assert!(matches!(tx_cache.get_by_uuid_index("owner", &user.id), Err(CacheError::IndexNotFound(_))));
+    assert!(matches!(tx_cache.get_by_datetime_range("created", ..), Err(CacheError::IndexNotFound(_))));
+
+    // An index declared only by a staged item is known
+    let products = TransactionAwareIdxModelCache::new(Arc::new(RwLock::new(
+        IdxModelCache::<ProductIndexCache>::new(vec![]).unwrap(),
+    )));
+    assert!(products.get_by_uuid_index("user_id", &user.id).is_err());
+    let product = ProductIndexCache::from_product(&Product::new(user.id, "Laptop".to_string()));
+    products.add(product.clone());
+    assert_eq!(products.get_by_uuid_index("user_id", &user.id).unwrap(), vec![product]);
+}
+
+#[test]
+fn test_try_get_distinguishes_unknown_index_from_no_matches() {
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let mut cache = IdxModelCache::new(vec![user.clone()]).unwrap();
+
+    assert_eq!(cache.try_get_by_i64_index("username_hash", &user.username_hash).unwrap(), &[user.id]);
+    assert!(cache.try_get_by_i64_index("username_hash", &0).unwrap().is_empty());
+    assert!(matches!(
+        cache.try_get_by_i64_index("user_name_hash", &user.username_hash),
+        Err(CacheError::IndexNotFound(name)) if name == "user_name_hash"
+    ));
+    assert!(matches!(cache.try_get_by_uuid_index("username_hash", &user.id), Err(CacheError::IndexNotFound(_))));
+
+    // An index emptied by removals or a clear is still known
+    cache.remove(&user.id);
+    assert!(cache.try_get_by_i64_index("email_hash", &user.email_hash).unwrap().is_empty());
+    cache.clear();
+    assert!(cache.try_get_by_i64_index("email_hash", &user.email_hash).unwrap().is_empty());
+
+    // A cache created empty only knows explicitly declared indexes
+    let products = IdxModelCache::<ProductIndexCache>::new(vec![]).unwrap().with_uuid_index("user_id");
+    assert!(products.try_get_by_uuid_index("user_id", &user.id).unwrap().is_empty());
+    assert!(products.try_get_by_datetime_range("created_at", ..).is_err());
+
+    // The transaction-aware wrapper uses the same declarations
+    let tx_cache = TransactionAwareIdxModelCache::new(Arc::new(RwLock::new(products)));
+    assert!(tx_cache.get_by_uuid_index("user_id", &user.id).unwrap().is_empty());
+}
+
+#[test]
+fn test_new_lenient_keeps_last_occurrence_of_duplicates() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let mut renamed = alice.clone();
+    renamed.username_hash = 424242;
+
+    let items = vec![alice.clone(), bob.clone(), alice.clone(), renamed.clone()];
+    assert!(matches!(IdxModelCache::new(items.clone()), Err(CacheError::DuplicatePrimaryKey(_))));
+
+    let (cache, duplicates) = IdxModelCache::new_lenient(items);
+    assert_eq!(duplicates, vec![alice.id, alice.id]);
+    assert_eq!(cache.iter().count(), 2);
+    assert_eq!(cache.get_by_primary(&alice.id), Some(renamed));
+
+    // The replaced occurrences left no postings behind
+    assert!(cache.get_by_i64_index("username_hash", &alice.username_hash).is_none());
+    assert_eq!(cache.get_by_i64_index("username_hash", &424242), Some(&[alice.id][..]));
+    assert_eq!(cache.get_by_i64_index("email_hash", &alice.email_hash), Some(&[alice.id][..]));
+}
+
+/// A model whose indexing panics, to interrupt a commit halfway
+#[derive(Debug, Clone)]
+struct FragileIndexCache {
+    id: Uuid,
+    poisoned: bool,
+}
+
+impl HasPrimaryKey for FragileIndexCache {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for FragileIndexCache {
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        assert!(!self.poisoned, "poisoned item");
+        IndexKeys::new()
+    }
+
+    fn uuid_index_keys(&self) -> IndexKeys<Uuid> {
+        IndexKeys::new()
+    }
+}
+
+#[tokio::test]
+async fn test_commit_leaves_wrapper_clean_when_applying_panics() {
+    use futures::FutureExt;
+    use postgres_index_cache::TransactionAware;
+    use std::panic::AssertUnwindSafe;
+
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    let healthy = FragileIndexCache { id: Uuid::new_v4(), poisoned: false };
+    tx_cache.add(healthy.clone());
+    tx_cache.add(FragileIndexCache { id: Uuid::new_v4(), poisoned: true });
+
+    let result = AssertUnwindSafe(tx_cache.on_commit()).catch_unwind().await;
+    assert!(result.is_err());
+
+    // Nothing stays staged and the shared cache is still usable
+    assert!(!tx_cache.is_dirty());
+    assert!(shared_cache.read().contains_primary(&healthy.id));
+    tx_cache.add(FragileIndexCache { id: Uuid::new_v4(), poisoned: false });
+    tx_cache.on_commit().await.unwrap();
+    assert_eq!(shared_cache.read().iter().count(), 2);
+}
+
+#[test]
+fn test_index_histogram_follows_changes() {
+    use postgres_index_cache::IndexValue;
+
+    let alice = Uuid::new_v4();
+    let bob = Uuid::new_v4();
+    let first = ProductIndexCache::new(Uuid::new_v4(), alice, "first");
+    let second = ProductIndexCache::new(Uuid::new_v4(), alice, "second");
+    let third = ProductIndexCache::new(Uuid::new_v4(), bob, "third");
+
+    // Counts are not kept by default
+    let cache = IdxModelCache::new(vec![first.clone(), second.clone()]).unwrap();
+    assert!(cache.index_histogram("user_id", 10).is_empty());
+
+    // Enabling counts takes the items already in the cache into account
+    let mut cache = cache.with_index_counts();
+    cache.add(third.clone());
+    assert_eq!(
+        cache.index_histogram("user_id", 10),
+        vec![(IndexValue::Uuid(alice), 2), (IndexValue::Uuid(bob), 1)]
+    );
+    assert_eq!(cache.index_histogram("user_id", 1), vec![(IndexValue::Uuid(alice), 2)]);
+    assert_eq!(cache.index_histogram("product_name_hash", 10).len(), 3);
+
+    // Moving a product to another user moves its count
+    cache.update(ProductIndexCache::new(second.id, bob, "second"));
+    assert_eq!(
+        cache.index_histogram("user_id", 10),
+        vec![(IndexValue::Uuid(bob), 2), (IndexValue::Uuid(alice), 1)]
+    );
+
+    cache.remove(&first.id);
+    assert_eq!(cache.index_histogram("user_id", 10), vec![(IndexValue::Uuid(bob), 2)]);
+
+    cache.clear();
+    assert!(cache.index_histogram("user_id", 10).is_empty());
+    cache.add(first);
+    assert_eq!(cache.index_histogram("user_id", 10), vec![(IndexValue::Uuid(alice), 1)]);
+}
+
+/// A model with a String index on the domain of its email address
+#[derive(Debug, Clone)]
+struct EmailIndexCache {
+    id: Uuid,
+    email_domain: String,
+}
+
+impl EmailIndexCache {
+    fn new(email_domain: &str) -> Self {
+        Self { id: Uuid::new_v4(), email_domain: email_domain.to_string() }
+    }
+}
+
+impl HasPrimaryKey for EmailIndexCache {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for EmailIndexCache {
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        IndexKeys::new()
+    }
+
+    fn uuid_index_keys(&self) -> IndexKeys<Uuid> {
+        IndexKeys::new()
+    }
+
+    fn string_index_keys(&self) -> IndexKeys<String> {
+        let mut keys = IndexKeys::new();
+        keys.push((std::borrow::Cow::Borrowed("email_domain"), Some(self.email_domain.clone())));
+        keys
+    }
+}
+
+#[test]
+fn test_string_prefix_queries() {
+    let acme_corp = EmailIndexCache::new("acme.corp");
+    let acme_io = EmailIndexCache::new("acme.io");
+    let acmex = EmailIndexCache::new("acmex.com");
+    let other = EmailIndexCache::new("example.com");
+    let mut cache =
+        IdxModelCache::new(vec![acme_io.clone(), other.clone(), acmex.clone(), acme_corp.clone()]).unwrap();
+
+    // Ordered by value and capped
+    assert_eq!(cache.get_by_string_prefix("email_domain", "acme.", 10), vec![acme_corp.id, acme_io.id]);
+    assert_eq!(cache.get_by_string_prefix("email_domain", "acme", 2), vec![acme_corp.id, acme_io.id]);
+    assert_eq!(cache.get_by_string_prefix("email_domain", "acme", 10).len(), 3);
+    assert!(cache.get_by_string_prefix("email_domain", "zzz", 10).is_empty());
+    assert_eq!(cache.get_by_string_index("email_domain", "example.com"), Some(&[other.id][..]));
+
+    // Unknown indexes
+    assert!(cache.get_by_string_prefix("unknown", "acme", 10).is_empty());
+    assert!(matches!(
+        cache.try_get_by_string_prefix("unknown", "acme", 10),
+        Err(CacheError::IndexNotFound(_))
+    ));
+
+    cache.remove(&acme_io.id);
+    assert_eq!(cache.get_by_string_prefix("email_domain", "acme.", 10), vec![acme_corp.id]);
+}
+
+#[test]
+fn test_transaction_aware_string_prefix_merges_staged_changes() {
+    let acme_corp = EmailIndexCache::new("acme.corp");
+    let acme_io = EmailIndexCache::new("acme.io");
+    let acme_net = EmailIndexCache::new("acme.net");
+    let shared_cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![acme_corp.clone(), acme_io.clone(), acme_net.clone()]).unwrap(),
+    ));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    // A staged deletion, an update moving an item out of the prefix and a staged addition
+    tx_cache.remove(&acme_corp.id);
+    tx_cache.update(EmailIndexCache { id: acme_io.id, email_domain: "example.com".to_string() });
+    let acme_app = EmailIndexCache::new("acme.app");
+    tx_cache.add(acme_app.clone());
+
+    let ids = |items: Vec<EmailIndexCache>| items.into_iter().map(|item| item.id).collect::<Vec<_>>();
+    assert_eq!(ids(tx_cache.get_by_string_prefix("email_domain", "acme.", 10).unwrap()), vec![acme_app.id, acme_net.id]);
+    // The cap applies after the staged changes, which removed two of the first shared matches
+    assert_eq!(ids(tx_cache.get_by_string_prefix("email_domain", "acme.", 1).unwrap()), vec![acme_app.id]);
+    assert_eq!(ids(tx_cache.get_by_string_index("email_domain", "example.com").unwrap()), vec![acme_io.id]);
+
+    // The shared cache is unchanged until commit
+    assert_eq!(shared_cache.read().get_by_string_prefix("email_domain", "acme.", 10).len(), 3);
+}
+
+#[test]
+fn test_string_normalizer_applies_to_indexing_and_lookup() {
+    use common::entities::normalized_hash;
+
+    let mixed = EmailIndexCache::new("Alice@Example.com ");
+    let cache = IdxModelCache::new(vec![mixed.clone()])
+        .unwrap()
+        .with_string_normalizer("email_domain", Arc::new(|value: &str| value.trim().to_lowercase()));
+
+    // Items cached before the normalizer was set are re-indexed
+    assert_eq!(cache.get_by_string_index("email_domain", "alice@example.com"), Some(&[mixed.id][..]));
+    assert_eq!(cache.get_by_string_index("email_domain", "  ALICE@example.COM"), Some(&[mixed.id][..]));
+    assert_eq!(cache.get_by_string_prefix("email_domain", "Alice@", 10), vec![mixed.id]);
+    assert_eq!(cache.normalize_string("email_domain", "Alice@Example.com "), "alice@example.com");
+
+    // Both spellings resolve to the same posting list
+    let mut cache = cache;
+    let lower = EmailIndexCache::new("alice@example.com");
+    cache.add(lower.clone());
+    let mut both = vec![mixed.id, lower.id];
+    both.sort();
+    let mut found = cache.get_by_string_index("email_domain", "Alice@Example.com ").unwrap().to_vec();
+    found.sort();
+    assert_eq!(found, both);
+
+    // Removing an item drops its normalized posting
+    cache.remove(&mixed.id);
+    assert_eq!(cache.get_by_string_index("email_domain", "alice@example.com"), Some(&[lower.id][..]));
+
+    // Staged items are compared in normalized form too
+    let shared_cache = Arc::new(RwLock::new(cache));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache);
+    let staged = EmailIndexCache::new(" ALICE@EXAMPLE.COM");
+    tx_cache.add(staged.clone());
+    assert_eq!(tx_cache.get_by_string_index("email_domain", "alice@example.com").unwrap().len(), 2);
+
+    // i64 hash indexes agree when they hash the normalized form
+    assert_eq!(normalized_hash("Alice@Example.com "), normalized_hash("alice@example.com"));
+}
+
+#[test]
+fn test_traced_reads_report_their_source() {
+    use postgres_index_cache::ReadSource;
+
+    let owner = Uuid::new_v4();
+    let shared = ProductIndexCache::new(Uuid::new_v4(), owner, "shared");
+    let updated = ProductIndexCache::new(Uuid::new_v4(), owner, "updated");
+    let deleted = ProductIndexCache::new(Uuid::new_v4(), owner, "deleted");
+    let shared_cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![shared.clone(), updated.clone(), deleted.clone()]).unwrap(),
+    ));
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+
+    let added = ProductIndexCache::new(Uuid::new_v4(), owner, "added");
+    tx_cache.add(added.clone());
+    tx_cache.update(ProductIndexCache::new(updated.id, owner, "renamed"));
+    tx_cache.remove(&deleted.id);
+
+    assert_eq!(tx_cache.get_by_primary_traced(&shared.id).map(|(_, source)| source), Some(ReadSource::Shared));
+    assert_eq!(tx_cache.get_by_primary_traced(&added.id).map(|(_, source)| source), Some(ReadSource::StagedAddition));
+    let (item, source) = tx_cache.get_by_primary_traced(&updated.id).unwrap();
+    assert_eq!(source, ReadSource::StagedUpdate);
+    assert_eq!(item.product_name_hash, ProductIndexCache::new(updated.id, owner, "renamed").product_name_hash);
+    assert!(tx_cache.get_by_primary_traced(&deleted.id).is_none());
+    assert_eq!(tx_cache.read_source(&deleted.id), None);
+
+    let sources: HashMap<Uuid, ReadSource> = tx_cache
+        .get_by_uuid_index_traced("user_id", &owner)
+        .unwrap()
+        .into_iter()
+        .map(|(item, source)| (item.id, source))
+        .collect();
+    assert_eq!(
+        sources,
+        HashMap::from([
+            (shared.id, ReadSource::Shared),
+            (updated.id, ReadSource::StagedUpdate),
+            (added.id, ReadSource::StagedAddition),
+        ])
+    );
+}
+
+#[test]
+fn test_cache_names_carry_over_to_wrappers() {
+    let shared_cache = Arc::new(RwLock::new(
+        IdxModelCache::<ProductIndexCache>::new(vec![]).unwrap().with_name("products_by_owner"),
+    ));
+    assert_eq!(shared_cache.read().name(), Some("products_by_owner"));
+
+    let tx_cache = TransactionAwareIdxModelCache::new(shared_cache.clone());
+    assert_eq!(tx_cache.name(), Some("products_by_owner"));
+    let tx_cache = TransactionAwareIdxModelCache::new_write_through(shared_cache).with_name("checkout");
+    assert_eq!(tx_cache.name(), Some("checkout"));
+
+    let unnamed = Arc::new(RwLock::new(IdxModelCache::<ProductIndexCache>::new(vec![]).unwrap()));
+    assert_eq!(TransactionAwareIdxModelCache::new(unnamed).name(), None);
+}
+
+#[test]
+fn test_dump_json_includes_index_keys() {
+    let owner = Uuid::new_v4();
+    let laptop = ProductIndexCache::new(Uuid::new_v4(), owner, "Laptop");
+    let mouse = ProductIndexCache::new(Uuid::new_v4(), owner, "Mouse");
+    let cache = IdxModelCache::new(vec![laptop.clone(), mouse.clone()]).unwrap();
+
+    let entry = cache.dump_entry_json(&laptop.id).unwrap();
+    assert_eq!(entry["primary_key"], serde_json::json!(laptop.id));
+    assert_eq!(entry["item"], serde_json::to_value(&laptop).unwrap());
+    assert_eq!(entry["index_keys"]["uuid"]["user_id"], serde_json::json!(owner));
+    assert_eq!(entry["index_keys"]["i64"]["product_name_hash"], serde_json::json!(laptop.product_name_hash));
+    assert!(cache.dump_entry_json(&Uuid::new_v4()).is_none());
+
+    let dump = cache.dump_json_limited(1);
+    assert!(dump["name"].is_null());
+    assert_eq!(dump["entries"], 2);
+    assert_eq!(dump["truncated"], true);
+    assert_eq!(dump["items"].as_array().unwrap().len(), 1);
+
+    let dump = cache.dump_json();
+    assert_eq!(dump["truncated"], false);
+    assert_eq!(dump["items"].as_array().unwrap().len(), 2);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_sample_is_reproducible_with_a_seed() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let owner = Uuid::new_v4();
+    let items: Vec<ProductIndexCache> = (0..100)
+        .map(|i| ProductIndexCache::new(Uuid::new_v4(), owner, &format!("product{i}")))
+        .collect();
+    let cache = IdxModelCache::new(items).unwrap();
+
+    let sample: Vec<Uuid> = cache.sample(10, &mut StdRng::seed_from_u64(9)).iter().map(|item| item.id).collect();
+    assert_eq!(sample.len(), 10);
+    assert_eq!(sample.iter().collect::<std::collections::HashSet<_>>().len(), 10);
+    assert_eq!(cache.sample_keys(10, &mut StdRng::seed_from_u64(9)), sample);
+    assert!(cache.sample(0, &mut StdRng::seed_from_u64(9)).is_empty());
+}
+
+#[tokio::test]
+async fn test_wait_for_wakes_on_add_and_times_out() {
+    let user = UserIndexCache::from_user(&User::new("alice".to_string(), "alice@example.com".to_string()));
+    let shared_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+
+    let writer = shared_cache.clone();
+    let added = user.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        writer.write().add(added);
+    });
+
+    let timeout = std::time::Duration::from_secs(5);
+    assert!(IdxModelCache::wait_for(&*shared_cache, user.id, timeout).await);
+    assert!(!IdxModelCache::wait_for(&*shared_cache, Uuid::new_v4(), std::time::Duration::from_millis(20)).await);
+
+    let writer = shared_cache.clone();
+    tokio::spawn(async move {
+        writer.write().remove(&user.id);
+    });
+    assert!(IdxModelCache::wait_until(&*shared_cache, timeout, |cache| !cache.contains_primary(&user.id)).await);
+}
+
+fn users(count: usize) -> Vec<UserIndexCache> {
+    (0..count)
+        .map(|i| UserIndexCache::new(Uuid::new_v4(), &format!("user{i}"), &format!("user{i}@example.com")))
+        .collect()
+}
+
+#[test]
+fn test_iter_sorted_and_page_order_by_primary_key() {
+    let items = users(10);
+    let cache = IdxModelCache::new(items.clone()).unwrap();
+    let mut sorted: Vec<Uuid> = items.iter().map(|item| item.id).collect();
+    sorted.sort();
+
+    assert_eq!(cache.iter_sorted().map(|item| item.id).collect::<Vec<_>>(), sorted);
+    let page: Vec<Uuid> = cache.page(3, 4).iter().map(|item| item.id).collect();
+    assert_eq!(page, sorted[3..7]);
+    assert_eq!(cache.page(8, 4).len(), 2);
+    assert!(cache.page(10, 4).is_empty());
+}
+
+#[test]
+fn test_page_after_is_stable_while_the_cache_changes() {
+    let items = users(20);
+    let mut cache = IdxModelCache::new(items.clone()).unwrap();
+    let mut listed: Vec<Uuid> = Vec::new();
+    let mut removed: Vec<Uuid> = Vec::new();
+
+    let mut page = cache.page(0, 3);
+    while let Some(last) = page.last().map(|item| item.id) {
+        listed.extend(page.iter().map(|item| item.id));
+        // Another writer removes a listed and an unlisted item and adds one
+        let unlisted = cache.iter_sorted().map(|item| item.id).find(|id| !listed.contains(id));
+        for id in [listed[0], last].into_iter().chain(unlisted) {
+            if cache.remove(&id).is_some() {
+                removed.push(id);
+            }
+        }
+        cache.add(users(1).remove(0));
+        page = cache.page_after(&last, 3);
+    }
+
+    let mut deduped = listed.clone();
+    deduped.sort();
+    deduped.dedup();
+    assert_eq!(deduped.len(), listed.len(), "an item was listed twice");
+    assert!(listed.windows(2).all(|pair| pair[0] < pair[1]));
+    // Every item cached throughout was listed
+    for item in &items {
+        assert!(listed.contains(&item.id) || removed.contains(&item.id), "{} was skipped", item.id);
+    }
+}
@@ -1,7 +1,10 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
-use postgres_index_cache::{HasPrimaryKey, Indexable};
+use chrono::{DateTime, Utc};
+use postgres_index_cache::{HasPrimaryKey, HasTableName, IndexKeys, Indexable, IsDeleted, Versioned};
+use smallvec::smallvec;
 
 // Hash function to compute i64 hash values
 pub fn hash_as_i64<T: Serialize>(data: &T) -> i64 {
@@ -16,7 +19,7 @@ pub fn hash_as_i64<T: Serialize>(data: &T) -> i64 {
 }
 
 /// Sample User entity for testing
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct User {
     pub id: Uuid,
     pub username: String,
@@ -34,6 +37,23 @@ impl User {
     }
 }
 
+impl HasPrimaryKey for User {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl HasTableName for User {
+    fn table_name() -> &'static str {
+        "users"
+    }
+}
+
+/// Hashes a username or email as it should be matched: trimmed and lowercase
+pub fn normalized_hash(value: &str) -> i64 {
+    hash_as_i64(&value.trim().to_lowercase())
+}
+
 /// UserIndexCache - the cache model for User with hash fields
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UserIndexCache {
@@ -46,8 +66,8 @@ impl UserIndexCache {
     pub fn new(id: Uuid, username: &str, email: &str) -> Self {
         Self {
             id,
-            username_hash: hash_as_i64(&username),
-            email_hash: hash_as_i64(&email),
+            username_hash: normalized_hash(username),
+            email_hash: normalized_hash(email),
         }
     }
     
@@ -56,6 +76,12 @@ impl UserIndexCache {
     }
 }
 
+impl HasTableName for UserIndexCache {
+    fn table_name() -> &'static str {
+        "user_index_cache"
+    }
+}
+
 impl HasPrimaryKey for UserIndexCache {
     fn primary_key(&self) -> Uuid {
         self.id
@@ -116,6 +142,12 @@ impl ProductIndexCache {
     }
 }
 
+impl HasTableName for ProductIndexCache {
+    fn table_name() -> &'static str {
+        "product_index_cache"
+    }
+}
+
 impl HasPrimaryKey for ProductIndexCache {
     fn primary_key(&self) -> Uuid {
         self.id
@@ -123,15 +155,69 @@ impl HasPrimaryKey for ProductIndexCache {
 }
 
 impl Indexable for ProductIndexCache {
+    fn i64_index_keys(&self) -> IndexKeys<i64> {
+        smallvec![(Cow::Borrowed("product_name_hash"), Some(self.product_name_hash))]
+    }
+
+    fn uuid_index_keys(&self) -> IndexKeys<Uuid> {
+        smallvec![(Cow::Borrowed("user_id"), Some(self.user_id))]
+    }
+}
+
+/// AccountIndexCache - a versioned, soft-deletable cache model
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountIndexCache {
+    pub id: Uuid,
+    pub balance_hash: i64,
+    pub version: u64,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl AccountIndexCache {
+    #[allow(dead_code)]
+    pub fn new(id: Uuid, balance_hash: i64, version: u64) -> Self {
+        Self {
+            id,
+            balance_hash,
+            version,
+            deleted_at: None,
+        }
+    }
+}
+
+impl HasTableName for AccountIndexCache {
+    fn table_name() -> &'static str {
+        "account_index_cache"
+    }
+}
+
+impl HasPrimaryKey for AccountIndexCache {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for AccountIndexCache {
     fn i64_keys(&self) -> HashMap<String, Option<i64>> {
         let mut map = HashMap::new();
-        map.insert("product_name_hash".to_string(), Some(self.product_name_hash));
+        map.insert("balance_hash".to_string(), Some(self.balance_hash));
         map
     }
 
     fn uuid_keys(&self) -> HashMap<String, Option<Uuid>> {
-        let mut map = HashMap::new();
-        map.insert("user_id".to_string(), Some(self.user_id));
-        map
+        HashMap::new()
     }
-}
\ No newline at end of file
+}
+
+impl Versioned for AccountIndexCache {
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl IsDeleted for AccountIndexCache {
+    fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
@@ -2,6 +2,6 @@ pub mod entities;
 pub mod repositories;
 
 #[allow(unused_imports)]
-pub use entities::{User, Product, UserIndexCache, ProductIndexCache};
+pub use entities::{User, Product, UserIndexCache, ProductIndexCache, AccountIndexCache};
 #[allow(unused_imports)]
 pub use repositories::{UserRepository, ProductRepository};
\ No newline at end of file
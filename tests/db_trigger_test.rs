@@ -5,9 +5,20 @@ use std::time::Duration;
 use parking_lot::RwLock;
 use uuid::Uuid;
 use postgres_index_cache::{
-    CacheNotificationListener, IdxModelCache, IndexCacheHandler,
-    init_cache_triggers, cleanup_cache_triggers,
+    CacheNotification, CacheNotificationHandler, CacheNotificationListener, IdxModelCache, IndexCacheHandler,
+    init_cache_triggers, init_cache_triggers_with_channel, installed_version, cleanup_cache_triggers,
+    init_table_trigger, drop_table_trigger, verify_cache_triggers, assert_cache_triggers,
+    init_cache_triggers_with_function, installed_version_with_function,
+    cleanup_cache_triggers_with_function, verify_cache_triggers_with_function,
+    cleanup_cache_triggers_for_table, cleanup_cache_triggers_for_table_with_function, list_cache_triggers, flush_table_cache,
+    CacheConfig, CachedRepository, CacheNotifier, DriftChecker, CacheRuntimeBuilder, CacheStartupError,
+    CacheTableSpec, ListenerState, TriggerVerificationError, CacheOutboxPoller, EvictionPolicy, MainModelCache,
+    NotificationDelivery, OutboxAck, ReadPolicy, cleanup_cache_outbox, DEFAULT_OUTBOX_TABLE,
+    FunctionOptions, TableTriggerSpec, TriggerEvent, TriggerOptions, TriggerSqlBuilder, TRIGGER_SCRIPT_VERSION,
+    DEFAULT_PAYLOAD_SIZE_LIMIT,
+    validate_handlers_against_db, assert_handlers_match_db, FieldMismatch, CacheKey,
 };
+use async_trait::async_trait;
 use sqlx::PgPool;
 use tokio::time::sleep;
 
@@ -16,6 +27,9 @@ use common::{
     UserRepository, ProductRepository,
 };
 
+/// How long to wait for a notification to reach a cache
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Helper function to get database URL from environment or use default
 fn get_database_url() -> String {
     std::env::var("DATABASE_URL")
@@ -36,7 +50,7 @@ async fn setup_database() -> PgPool {
         .await
         .expect("Failed to initialize cache triggers");
 
-    // Read and execute the examples SQL script (creates tables and triggers)
+    // Read and execute the examples SQL script (creates tables)
     let examples_sql = include_str!("migrations/cache_notification_triggers_examples.sql");
     
     // Execute the entire examples script using raw_sql (supports multiple statements)
@@ -45,12 +59,27 @@ async fn setup_database() -> PgPool {
         .await
         .expect("Failed to execute the examples script");
 
+    // Attach the notification triggers to the cache tables
+    for options in [
+        TriggerOptions::for_type::<UserIndexCache>(),
+        TriggerOptions::for_type::<ProductIndexCache>(),
+    ] {
+        init_table_trigger(&pool, &options)
+            .await
+            .expect("Failed to install table trigger");
+    }
+
     pool
 }
 
 /// Clean up database after tests
 async fn cleanup_database(pool: &PgPool) {
-    // Execute cleanup script for examples (tables and triggers)
+    // Remove the notification triggers of the example tables
+    for table in ["user_index_cache", "product_index_cache", "partitioned_user_index_cache"] {
+        cleanup_cache_triggers_for_table(pool, table, None).await.ok();
+    }
+
+    // Execute cleanup script for examples (tables)
     let cleanup_examples_sql = include_str!("cleanup/cleanup_cache_notification_triggers_examples.sql");
     sqlx::raw_sql(cleanup_examples_sql)
         .execute(pool)
@@ -105,8 +134,8 @@ async fn test_user_insert_triggers_cache_notification() {
     .await
     .expect("Failed to insert user");
 
-    // Give time for notification to be processed
-    sleep(Duration::from_millis(500)).await;
+    // Wait for the notification to be processed
+    IdxModelCache::wait_for(&*user_cache, user_cache_instance.id, NOTIFICATION_TIMEOUT).await;
 
     // Verify the cache was updated via the trigger
     let cache = user_cache.read();
@@ -167,8 +196,8 @@ async fn test_product_insert_triggers_cache_notification() {
     
     product_repo.create(&product).await.expect("Failed to create product");
     
-    // Give time for notification to be processed
-    sleep(Duration::from_millis(500)).await;
+    // Wait for the notification to be processed
+    IdxModelCache::wait_for(&*product_cache, product.id, NOTIFICATION_TIMEOUT).await;
     
     // Verify the cache was updated via the trigger
     let cache = product_cache.read();
@@ -224,8 +253,11 @@ async fn test_user_update_triggers_cache_notification() {
     updated_user.email = "charlie.updated@example.com".to_string();
     user_repo.update(&updated_user).await.expect("Failed to update user");
     
-    // Give time for notification to be processed
-    sleep(Duration::from_millis(500)).await;
+    // Wait for the notification to be processed
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| {
+        cache.get_by_primary(&user.id).is_some_and(|cached| cached.email_hash != initial_cache.email_hash)
+    })
+    .await;
     
     // Verify the cache was updated
     let cache = user_cache.read();
@@ -243,6 +275,63 @@ async fn test_user_update_triggers_cache_notification() {
     pool.close().await;
 }
 
+#[tokio::test]
+#[serial_test::serial]
+async fn test_oversized_row_fails_or_is_evicted_with_payload_limit() {
+    // Setup database
+    let pool = setup_database().await;
+    sqlx::raw_sql("ALTER TABLE user_index_cache ADD COLUMN note TEXT")
+        .execute(&pool)
+        .await
+        .expect("Failed to add column");
+
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("oscar".to_string(), "oscar@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+    let set_note = || {
+        sqlx::query("UPDATE user_index_cache SET note = $1 WHERE id = $2")
+            .bind("x".repeat(2 * DEFAULT_PAYLOAD_SIZE_LIMIT))
+            .bind(user.id)
+            .execute(&pool)
+    };
+
+    // By default the oversized notification fails the write
+    assert!(set_note().await.is_err());
+
+    // With a payload limit the row is notified without its data
+    let sql = TriggerSqlBuilder::new()
+        .payload_size_limit(Some(DEFAULT_PAYLOAD_SIZE_LIMIT))
+        .build_function_sql()
+        .expect("Failed to build function");
+    sqlx::raw_sql(&sql).execute(&pool).await.expect("Failed to install function");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![UserIndexCache::from_user(&user)]).unwrap()));
+    let handler = Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    ));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    set_note().await.expect("Failed to update user");
+
+    // The stale item is evicted instead of kept
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| !cache.contains_primary(&user.id)).await;
+    assert!(!user_cache.read().contains_primary(&user.id));
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_product_update_triggers_cache_notification() {
@@ -284,8 +373,13 @@ async fn test_product_update_triggers_cache_notification() {
     updated_product.product_name = "Wireless Mouse".to_string();
     product_repo.update(&updated_product).await.expect("Failed to update product");
     
-    // Give time for notification to be processed
-    sleep(Duration::from_millis(500)).await;
+    // Wait for the notification to be processed
+    IdxModelCache::wait_until(&*product_cache, NOTIFICATION_TIMEOUT, |cache| {
+        cache
+            .get_by_primary(&product.id)
+            .is_some_and(|cached| cached.product_name_hash != initial_cache.product_name_hash)
+    })
+    .await;
     
     // Verify the cache was updated
     let cache = product_cache.read();
@@ -349,8 +443,8 @@ async fn test_user_delete_triggers_cache_notification() {
         .await
         .expect("Failed to delete user");
     
-    // Give time for notification to be processed
-    sleep(Duration::from_millis(500)).await;
+    // Wait for the notification to be processed
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| !cache.contains_primary(&user.id)).await;
     
     // Verify the cache entry was removed
     let cache = user_cache.read();
@@ -412,8 +506,8 @@ async fn test_product_delete_triggers_cache_notification() {
         .await
         .expect("Failed to delete product");
     
-    // Give time for notification to be processed
-    sleep(Duration::from_millis(500)).await;
+    // Wait for the notification to be processed
+    IdxModelCache::wait_until(&*product_cache, NOTIFICATION_TIMEOUT, |cache| !cache.contains_primary(&product.id)).await;
     
     // Verify the cache entry was removed
     let cache = product_cache.read();
@@ -470,7 +564,7 @@ async fn test_multi_table_operations_with_cache() {
     let user = User::new("grace".to_string(), "grace@example.com".to_string());
     user_repo.create(&user).await.expect("Failed to create user");
     
-    sleep(Duration::from_millis(500)).await;
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
     
     // Verify user is in cache
     assert!(
@@ -482,7 +576,7 @@ async fn test_multi_table_operations_with_cache() {
     let product = Product::new(user.id, "Monitor".to_string());
     product_repo.create(&product).await.expect("Failed to create product");
     
-    sleep(Duration::from_millis(500)).await;
+    IdxModelCache::wait_for(&*product_cache, product.id, NOTIFICATION_TIMEOUT).await;
     
     // Verify product is in cache
     assert!(
@@ -499,4 +593,1285 @@ async fn test_multi_table_operations_with_cache() {
     // Cleanup
     cleanup_database(&pool).await;
     pool.close().await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_drop_table_trigger_stops_notifications() {
+    // Setup database
+    let pool = setup_database().await;
+
+    // Remove the trigger from the user cache table
+    drop_table_trigger(&pool, &TriggerOptions::new("user_index_cache"))
+        .await
+        .expect("Failed to drop table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let handler = Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    ));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("heidi".to_string(), "heidi@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+
+    sleep(Duration::from_millis(500)).await;
+
+    assert!(
+        !user_cache.read().contains_primary(&user.id),
+        "No notification should be sent once the trigger is dropped"
+    );
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_table_trigger_notifies_on_custom_channel() {
+    // Setup database
+    let pool = setup_database().await;
+
+    // Route the user cache table to its own channel
+    init_table_trigger(
+        &pool,
+        &TriggerOptions::new("user_index_cache").with_channel("user_cache_channel"),
+    )
+    .await
+    .expect("Failed to install table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let default_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+
+    // One listener on the custom channel, one on the default channel
+    let mut listener = CacheNotificationListener::with_channel("user_cache_channel".to_string());
+    listener.register_handler(Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    )));
+    let mut default_listener = CacheNotificationListener::new();
+    default_listener.register_handler(Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        default_cache.clone(),
+    )));
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+    let pool_clone = pool.clone();
+    let _default_listen_handle = tokio::spawn(async move {
+        default_listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("ivan".to_string(), "ivan@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
+
+    assert!(
+        user_cache.read().contains_primary(&user.id),
+        "Notification should arrive on the custom channel"
+    );
+    assert!(
+        !default_cache.read().contains_primary(&user.id),
+        "Notification should not arrive on the default channel"
+    );
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_one_listener_routes_tables_on_their_own_channels() {
+    let pool = setup_database().await;
+
+    // The product cache table notifies on its own channel
+    init_table_trigger(&pool, &TriggerOptions::for_type::<ProductIndexCache>().with_channel("product_cache_channel"))
+        .await
+        .expect("Failed to install table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let product_cache: Arc<RwLock<IdxModelCache<ProductIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    listener
+        .register_handler_on_channel("product_cache_channel", Arc::new(IndexCacheHandler::for_type(product_cache.clone())))
+        .expect("Failed to register product handler");
+    let listener = Arc::new(listener);
+    let _listen_handle = tokio::spawn({
+        let listener = listener.clone();
+        let pool = pool.clone();
+        async move { listener.listen(&pool).await.ok() }
+    });
+    listener.wait_until_listening().await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let product_repo = ProductRepository::new(pool.clone());
+    let user = User::new("judy".to_string(), "judy@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+    let product = Product::new(user.id, "Tablet".to_string());
+    product_repo.create(&product).await.expect("Failed to create product");
+
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
+    IdxModelCache::wait_for(&*product_cache, product.id, NOTIFICATION_TIMEOUT).await;
+    assert!(user_cache.read().contains_primary(&user.id));
+    assert!(product_cache.read().contains_primary(&product.id));
+
+    // A user notification arriving on the product channel is not applied
+    sqlx::query("SELECT pg_notify('product_cache_channel', $1)")
+        .bind(CacheNotification::delete("user_index_cache", user.id).to_payload())
+        .execute(&pool)
+        .await
+        .expect("Failed to notify");
+    sleep(Duration::from_millis(300)).await;
+    assert!(user_cache.read().contains_primary(&user.id), "User notifications should only be taken from the default channel");
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_function_default_channel_is_templated() {
+    // Setup database with the function defaulting to a custom channel
+    let pool = setup_database().await;
+    init_cache_triggers_with_channel(&pool, "custom_default_channel")
+        .await
+        .expect("Failed to initialize cache triggers");
+
+    // A hand-written trigger passing no arguments uses the function's default
+    sqlx::raw_sql(
+        "DROP TRIGGER IF EXISTS user_index_cache_cache_notify ON user_index_cache;
+         CREATE TRIGGER user_index_cache_cache_notify
+             AFTER INSERT OR UPDATE OR DELETE ON user_index_cache
+             FOR EACH ROW
+             EXECUTE FUNCTION notify_cache_change();",
+    )
+    .execute(&pool)
+    .await
+    .expect("Failed to create trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::with_channel("custom_default_channel".to_string());
+    listener.register_handler(Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    )));
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("judy".to_string(), "judy@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
+
+    assert!(
+        user_cache.read().contains_primary(&user.id),
+        "Notification should arrive on the templated default channel"
+    );
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_update_of_unlisted_column_sends_no_notification() {
+    // Setup database, only notifying about email changes on the user cache table
+    let pool = setup_database().await;
+    init_table_trigger(
+        &pool,
+        &TriggerOptions::new("user_index_cache").only_when_changed(&["email_hash"]),
+    )
+    .await
+    .expect("Failed to install table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    )));
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    // Inserts are still notified
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("karl".to_string(), "karl@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
+    let initial = user_cache
+        .read()
+        .get_by_primary(&user.id)
+        .expect("User should be in cache after insert");
+
+    // Changing a column that is not listed is not notified
+    sqlx::query("UPDATE user_index_cache SET username_hash = 42 WHERE id = $1")
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .expect("Failed to update user");
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(
+        user_cache.read().get_by_primary(&user.id).unwrap().username_hash,
+        initial.username_hash,
+        "Update of an unlisted column should not reach the cache"
+    );
+
+    // Changing a listed column is notified with the full row
+    sqlx::query("UPDATE user_index_cache SET email_hash = 43 WHERE id = $1")
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .expect("Failed to update user");
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| {
+        cache.get_by_primary(&user.id).is_some_and(|cached| cached.email_hash == 43)
+    })
+    .await;
+    let cached = user_cache.read().get_by_primary(&user.id).unwrap();
+    assert_eq!(cached.email_hash, 43);
+    assert_eq!(cached.username_hash, 42);
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_verify_cache_triggers_reports_discrepancies() {
+    // Setup database
+    let pool = setup_database().await;
+
+    let expected = [
+        TableTriggerSpec::new("user_index_cache"),
+        TableTriggerSpec::new("product_index_cache"),
+    ];
+
+    // Everything installed by setup is as expected
+    let report = verify_cache_triggers(&pool, &expected)
+        .await
+        .expect("Failed to verify triggers");
+    assert!(report.is_ok(), "Unexpected report: {report}");
+    assert_cache_triggers(&pool, &expected)
+        .await
+        .expect("Triggers should be verified");
+
+    // Drop one trigger and narrow the events of the other
+    drop_table_trigger(&pool, &TriggerOptions::new("user_index_cache"))
+        .await
+        .expect("Failed to drop table trigger");
+    init_table_trigger(
+        &pool,
+        &TriggerOptions::new("product_index_cache").with_events(&[TriggerEvent::Insert]),
+    )
+    .await
+    .expect("Failed to install table trigger");
+
+    let report = verify_cache_triggers(&pool, &expected[..1])
+        .await
+        .expect("Failed to verify triggers");
+    assert!(report.function_exists);
+    assert_eq!(report.missing, vec![TableTriggerSpec::new("user_index_cache")]);
+    assert_eq!(report.extra.len(), 1);
+    assert_eq!(report.extra[0].table, "product_index_cache");
+
+    let report = verify_cache_triggers(&pool, &expected[1..])
+        .await
+        .expect("Failed to verify triggers");
+    assert_eq!(report.mismatched.len(), 1);
+    assert_eq!(report.mismatched[0].actual_events, vec![TriggerEvent::Insert]);
+    assert!(assert_cache_triggers(&pool, &expected).await.is_err());
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_validate_handlers_reports_fields_missing_from_their_table() {
+    let pool = setup_database().await;
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let report = validate_handlers_against_db(&pool, &listener).await.expect("Failed to validate handlers");
+    assert!(report.is_ok(), "Unexpected report: {report}");
+
+    // A user handler registered for the products table expects the user columns
+    listener.register_handler(Arc::new(IndexCacheHandler::new("product_index_cache".to_string(), user_cache.clone())));
+    listener.register_handler(Arc::new(IndexCacheHandler::new("no_such_table".to_string(), user_cache.clone())));
+    let report = validate_handlers_against_db(&pool, &listener).await.expect("Failed to validate handlers");
+    assert_eq!(
+        report.mismatched,
+        vec![FieldMismatch {
+            table: "product_index_cache".to_string(),
+            missing: vec!["username_hash".to_string(), "email_hash".to_string()],
+        }]
+    );
+    assert_eq!(report.missing_tables, vec!["no_such_table".to_string()]);
+    assert!(assert_handlers_match_db(&pool, &listener).await.is_err());
+
+    // Declared fields replace the ones derived from the model
+    listener.register_handler(Arc::new(
+        IndexCacheHandler::new("product_index_cache".to_string(), user_cache.clone()).with_expected_fields(&["id"]),
+    ));
+    let report = validate_handlers_against_db(&pool, &listener).await.expect("Failed to validate handlers");
+    assert!(report.mismatched.is_empty());
+
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_trigger_installation_is_versioned() {
+    // Setup database
+    let pool = setup_database().await;
+    assert_eq!(
+        installed_version(&pool).await.expect("Failed to read version"),
+        Some(TRIGGER_SCRIPT_VERSION)
+    );
+
+    // Re-running the installer keeps the triggers attached to the function
+    init_cache_triggers(&pool).await.expect("Re-init should be a no-op");
+    let report = verify_cache_triggers(&pool, &[TableTriggerSpec::new("user_index_cache")])
+        .await
+        .expect("Failed to verify triggers");
+    assert!(report.missing.is_empty());
+
+    // Older versions are upgraded in place
+    sqlx::query("UPDATE postgres_index_cache_meta SET version = 0")
+        .execute(&pool)
+        .await
+        .expect("Failed to update version");
+    init_cache_triggers(&pool).await.expect("Upgrade should succeed");
+    assert_eq!(
+        installed_version(&pool).await.expect("Failed to read version"),
+        Some(TRIGGER_SCRIPT_VERSION)
+    );
+
+    // Newer versions are never downgraded
+    sqlx::query("UPDATE postgres_index_cache_meta SET version = $1")
+        .bind(TRIGGER_SCRIPT_VERSION + 1)
+        .execute(&pool)
+        .await
+        .expect("Failed to update version");
+    assert!(init_cache_triggers(&pool).await.is_err());
+
+    // Cleanup removes the version record
+    cleanup_database(&pool).await;
+    assert_eq!(installed_version(&pool).await.expect("Failed to read version"), None);
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_truncate_clears_cache() {
+    // Setup database
+    let pool = setup_database().await;
+
+    // Reinstall the user cache trigger including truncates
+    let events = [
+        TriggerEvent::Insert,
+        TriggerEvent::Update,
+        TriggerEvent::Delete,
+        TriggerEvent::Truncate,
+    ];
+    init_table_trigger(&pool, &TriggerOptions::new("user_index_cache").with_events(&events))
+        .await
+        .expect("Failed to install table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let handler = Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    ));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("ivan".to_string(), "ivan@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
+    assert!(user_cache.read().contains_primary(&user.id));
+
+    sqlx::query("TRUNCATE user_index_cache")
+        .execute(&pool)
+        .await
+        .expect("Failed to truncate table");
+
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| cache.iter().next().is_none()).await;
+    assert_eq!(
+        user_cache.read().iter().count(),
+        0,
+        "Cache should be emptied by the truncate notification"
+    );
+
+    let report = verify_cache_triggers(
+        &pool,
+        &[
+            TableTriggerSpec::new("user_index_cache").with_events(&events),
+            TableTriggerSpec::new("product_index_cache"),
+        ],
+    )
+    .await
+    .expect("Failed to verify triggers");
+    assert!(report.is_ok(), "{report}");
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_partitioned_table_insert_notifies_parent_table() {
+    // Setup database
+    let pool = setup_database().await;
+
+    init_table_trigger(&pool, &TriggerOptions::new("partitioned_user_index_cache"))
+        .await
+        .expect("Failed to install table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let handler = Arc::new(IndexCacheHandler::new(
+        "partitioned_user_index_cache".to_string(),
+        user_cache.clone(),
+    ));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    // Rows land in different partitions but are reported for the parent table
+    let entries: Vec<UserIndexCache> = (0..4)
+        .map(|i| UserIndexCache::new(Uuid::new_v4(), &format!("user{i}"), &format!("user{i}@example.com")))
+        .collect();
+    for entry in &entries {
+        sqlx::query("INSERT INTO partitioned_user_index_cache (id, username_hash, email_hash) VALUES ($1, $2, $3)")
+            .bind(entry.id)
+            .bind(entry.username_hash)
+            .bind(entry.email_hash)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert into partitioned table");
+    }
+
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| {
+        entries.iter().all(|entry| cache.contains_primary(&entry.id))
+    })
+    .await;
+
+    {
+        let cache = user_cache.read();
+        for entry in &entries {
+            assert!(
+                cache.contains_primary(&entry.id),
+                "Rows inserted into any partition should reach the cache"
+            );
+        }
+    }
+
+    // Dropping the trigger removes it from the partitions as well
+    drop_table_trigger(&pool, &TriggerOptions::new("partitioned_user_index_cache"))
+        .await
+        .expect("Failed to drop table trigger");
+    let report = verify_cache_triggers(
+        &pool,
+        &[
+            TableTriggerSpec::new("user_index_cache"),
+            TableTriggerSpec::new("product_index_cache"),
+        ],
+    )
+    .await
+    .expect("Failed to verify triggers");
+    assert!(report.extra.is_empty(), "{report}");
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_installers_run_inside_transaction() {
+    // Setup database
+    let pool = setup_database().await;
+    cleanup_cache_triggers(&pool).await.expect("Failed to cleanup triggers");
+
+    // A rolled back installation leaves nothing behind
+    let mut tx = pool.begin().await.expect("Failed to begin transaction");
+    init_cache_triggers(&mut *tx).await.expect("Failed to init in transaction");
+    init_table_trigger(&mut *tx, &TriggerOptions::new("user_index_cache"))
+        .await
+        .expect("Failed to install table trigger in transaction");
+    assert_eq!(
+        installed_version(&mut *tx).await.expect("Failed to read version"),
+        Some(TRIGGER_SCRIPT_VERSION)
+    );
+    tx.rollback().await.expect("Failed to rollback");
+    assert_eq!(installed_version(&pool).await.expect("Failed to read version"), None);
+
+    // A committed one is visible afterwards
+    let mut tx = pool.begin().await.expect("Failed to begin transaction");
+    init_cache_triggers(&mut *tx).await.expect("Failed to init in transaction");
+    init_table_trigger(&mut *tx, &TriggerOptions::new("user_index_cache"))
+        .await
+        .expect("Failed to install table trigger in transaction");
+    tx.commit().await.expect("Failed to commit");
+
+    // Plain connections work as well
+    let mut conn = pool.acquire().await.expect("Failed to acquire connection");
+    assert_cache_triggers(&mut *conn, &[TableTriggerSpec::new("user_index_cache")])
+        .await
+        .expect("Triggers should be installed");
+    drop(conn);
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_custom_function_name_and_schema() {
+    // Setup database
+    let pool = setup_database().await;
+    sqlx::raw_sql("CREATE SCHEMA IF NOT EXISTS billing")
+        .execute(&pool)
+        .await
+        .expect("Failed to create schema");
+
+    let function = FunctionOptions::new("notify_cache_change_v2")
+        .with_schema("billing")
+        .with_channel("billing_cache");
+    init_cache_triggers_with_function(&pool, &function)
+        .await
+        .expect("Failed to install custom function");
+    assert_eq!(
+        installed_version_with_function(&pool, &function)
+            .await
+            .expect("Failed to read version"),
+        Some(TRIGGER_SCRIPT_VERSION)
+    );
+
+    // Route the user cache table through the custom function
+    let options = TriggerOptions::new("user_index_cache")
+        .with_channel("billing_cache")
+        .with_function(&function);
+    init_table_trigger(&pool, &options)
+        .await
+        .expect("Failed to install table trigger");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let handler = Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    ));
+
+    let mut listener = CacheNotificationListener::with_channel("billing_cache".to_string());
+    listener.register_handler(handler);
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let user = User::new("judy".to_string(), "judy@example.com".to_string());
+    user_repo.create(&user).await.expect("Failed to create user");
+
+    IdxModelCache::wait_for(&*user_cache, user.id, NOTIFICATION_TIMEOUT).await;
+    assert!(user_cache.read().contains_primary(&user.id));
+
+    // Each function only reports its own triggers
+    let report = verify_cache_triggers_with_function(&pool, &function, &[TableTriggerSpec::new("user_index_cache")])
+        .await
+        .expect("Failed to verify triggers");
+    assert!(report.is_ok(), "{report}");
+    let report = verify_cache_triggers(&pool, &[TableTriggerSpec::new("product_index_cache")])
+        .await
+        .expect("Failed to verify triggers");
+    assert!(report.is_ok(), "{report}");
+
+    // Per-table cleanup only drops the triggers of the function it is given
+    cleanup_cache_triggers_for_table(&pool, "user_index_cache", Some("public"))
+        .await
+        .expect("Failed to cleanup table triggers");
+    let report = verify_cache_triggers_with_function(&pool, &function, &[TableTriggerSpec::new("user_index_cache")])
+        .await
+        .expect("Failed to verify triggers");
+    assert!(report.is_ok(), "{report}");
+    cleanup_cache_triggers_for_table_with_function(&pool, &function, "user_index_cache", Some("public"))
+        .await
+        .expect("Failed to cleanup table triggers");
+    let report = verify_cache_triggers_with_function(&pool, &function, &[TableTriggerSpec::new("user_index_cache")])
+        .await
+        .expect("Failed to verify triggers");
+    assert!(!report.is_ok(), "{report}");
+
+    // Removing the custom function leaves the default one installed
+    cleanup_cache_triggers_with_function(&pool, &function)
+        .await
+        .expect("Failed to cleanup custom function");
+    assert_eq!(
+        installed_version_with_function(&pool, &function)
+            .await
+            .expect("Failed to read version"),
+        None
+    );
+    assert_eq!(
+        installed_version(&pool).await.expect("Failed to read version"),
+        Some(TRIGGER_SCRIPT_VERSION)
+    );
+    assert_cache_triggers(&pool, &[TableTriggerSpec::new("product_index_cache")])
+        .await
+        .expect("Default function triggers should be untouched");
+
+    // Cleanup
+    sqlx::raw_sql("DROP SCHEMA IF EXISTS billing CASCADE")
+        .execute(&pool)
+        .await
+        .expect("Failed to drop schema");
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[cfg(feature = "payload-protection")]
+#[tokio::test]
+#[serial_test::serial]
+async fn test_trigger_protected_data_is_decrypted_by_listener() {
+    use postgres_index_cache::{PayloadKey, PAYLOAD_KEY_SETTING};
+
+    let pool = setup_database().await;
+    let function = FunctionOptions::new("notify_cache_change_protected")
+        .with_channel("protected_cache")
+        .with_protected_data();
+    init_cache_triggers_with_function(&pool, &function)
+        .await
+        .expect("Failed to install protecting function");
+    let options = TriggerOptions::new("user_index_cache")
+        .with_channel("protected_cache")
+        .with_function(&function);
+    init_table_trigger(&pool, &options)
+        .await
+        .expect("Failed to install table trigger");
+
+    let secret = "5e".repeat(32);
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::with_channel("protected_cache".to_string());
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    listener.set_payload_key(PayloadKey::from_hex(&secret).unwrap());
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    let insert = |user: UserIndexCache, secret: Option<String>| {
+        let pool = pool.clone();
+        async move {
+            let mut tx = pool.begin().await.expect("Failed to begin transaction");
+            if let Some(secret) = secret {
+                sqlx::query("SELECT set_config($1, $2, true)")
+                    .bind(PAYLOAD_KEY_SETTING)
+                    .bind(secret)
+                    .execute(&mut *tx)
+                    .await
+                    .expect("Failed to set the payload key");
+            }
+            sqlx::query("INSERT INTO user_index_cache (id, username_hash, email_hash) VALUES ($1, $2, $3)")
+                .bind(user.id)
+                .bind(user.username_hash)
+                .bind(user.email_hash)
+                .execute(&mut *tx)
+                .await
+                .expect("Failed to insert user");
+            tx.commit().await.expect("Failed to commit");
+        }
+    };
+
+    // Encrypted by pgcrypto, decrypted by the listener
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    insert(alice.clone(), Some(secret.clone())).await;
+    assert!(IdxModelCache::wait_for(&*user_cache, alice.id, NOTIFICATION_TIMEOUT).await);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice));
+
+    // Sessions without the key send no row data
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    insert(bob.clone(), None).await;
+    sleep(Duration::from_millis(500)).await;
+    assert!(!user_cache.read().contains_primary(&bob.id));
+
+    cleanup_cache_triggers_with_function(&pool, &function)
+        .await
+        .expect("Failed to cleanup protecting function");
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+/// A notification recorded by a `RecordingHandler`, keyed by the order it was received in
+#[derive(Debug, Clone)]
+struct Received(usize, CacheNotification);
+
+impl CacheKey<usize> for Received {
+    fn cache_key(&self) -> usize {
+        self.0
+    }
+}
+
+/// Handler recording the notifications it receives
+///
+/// They are kept in a cache, so tests can wait for them with `wait_until`.
+struct RecordingHandler {
+    table_name: String,
+    received: Arc<RwLock<MainModelCache<Received, usize>>>,
+}
+
+#[async_trait]
+impl CacheNotificationHandler for RecordingHandler {
+    async fn handle_notification(&self, notification: CacheNotification) {
+        let mut received = self.received.write();
+        let index = received.len();
+        received.insert(Received(index, notification));
+    }
+
+    fn table_name(&self) -> &str {
+        &self.table_name
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_context_columns_reach_filter_without_data() {
+    // Setup database
+    let pool = setup_database().await;
+
+    // Send only the user id as context, without any row data
+    let options = TriggerOptions::new("product_index_cache")
+        .with_include_data(false)
+        .with_context_columns(&["user_id"]);
+    init_table_trigger(&pool, &options)
+        .await
+        .expect("Failed to install table trigger");
+
+    let user_repo = UserRepository::new(pool.clone());
+    let product_repo = ProductRepository::new(pool.clone());
+    let alice = User::new("kate".to_string(), "kate@example.com".to_string());
+    let bob = User::new("leo".to_string(), "leo@example.com".to_string());
+    user_repo.create(&alice).await.expect("Failed to create user");
+    user_repo.create(&bob).await.expect("Failed to create user");
+
+    let received = Arc::new(RwLock::new(MainModelCache::keyed(CacheConfig::new(10, EvictionPolicy::LRU))));
+    let handler = Arc::new(RecordingHandler {
+        table_name: "product_index_cache".to_string(),
+        received: received.clone(),
+    });
+
+    // Route on the context: only products of alice are dispatched
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+    let alice_id = serde_json::json!(alice.id);
+    listener.set_filter(move |notification| notification.context_value("user_id") == Some(&alice_id));
+
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+
+    sleep(Duration::from_millis(100)).await;
+
+    let alice_product = Product::new(alice.id, "Keyboard".to_string());
+    let bob_product = Product::new(bob.id, "Mouse".to_string());
+    product_repo.create(&alice_product).await.expect("Failed to create product");
+    product_repo.create(&bob_product).await.expect("Failed to create product");
+    product_repo.delete(alice_product.id).await.expect("Failed to delete product");
+
+    // The notification of bob's product arrives before the delete, so none is left to come
+    assert!(MainModelCache::wait_until(&*received, NOTIFICATION_TIMEOUT, |received| received.len() == 2).await);
+
+    {
+        let mut received = received.write();
+        let received: Vec<CacheNotification> =
+            (0..received.len()).map(|index| received.get(&index).unwrap().1.clone()).collect();
+        let actions: Vec<&str> = received.iter().map(|n| n.action.as_str()).collect();
+        assert_eq!(actions, vec!["insert", "delete"]);
+        for notification in received.iter() {
+            assert_eq!(notification.id, alice_product.id);
+            assert!(notification.data.is_none());
+            assert_eq!(notification.context, Some(serde_json::json!({ "user_id": alice.id })));
+        }
+    }
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_list_and_cleanup_triggers_for_table() {
+    // Setup database
+    let pool = setup_database().await;
+
+    let triggers = list_cache_triggers(&pool).await.expect("Failed to list triggers");
+    let names: Vec<(&str, &str)> = triggers
+        .iter()
+        .map(|trigger| (trigger.table.as_str(), trigger.trigger_name.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("product_index_cache", "product_index_cache_cache_notify"),
+            ("user_index_cache", "user_index_cache_cache_notify"),
+        ]
+    );
+    for trigger in &triggers {
+        assert_eq!(trigger.schema, "public");
+        assert_eq!(trigger.events, TriggerEvent::ALL.to_vec());
+        assert!(trigger.enabled);
+    }
+
+    // Only the triggers of the given table are removed
+    cleanup_cache_triggers_for_table(&pool, "user_index_cache", Some("public"))
+        .await
+        .expect("Failed to cleanup table triggers");
+    let triggers = list_cache_triggers(&pool).await.expect("Failed to list triggers");
+    assert_eq!(triggers.len(), 1);
+    assert_eq!(triggers[0].table, "product_index_cache");
+    assert_eq!(
+        installed_version(&pool).await.expect("Failed to read version"),
+        Some(TRIGGER_SCRIPT_VERSION)
+    );
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_cached_repository_reads_through_cache() {
+    // Setup database
+    let pool = setup_database().await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let alice = User::new("alice".to_string(), "alice@example.com".to_string());
+    user_repo.create(&alice).await.expect("Failed to create user");
+
+    let cache = Arc::new(RwLock::new(MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU))));
+    let repository = CachedRepository::new(cache.clone(), pool.clone(), |pool: PgPool, id: Uuid| async move {
+        UserRepository::new(pool).find_by_id(id).await
+    })
+    .with_single_flight();
+
+    // First read misses and loads from the database, second read hits
+    let loaded = repository.get(alice.id).await.expect("Failed to get user");
+    assert_eq!(loaded.as_deref(), Some(&alice));
+    let cached = repository.get(alice.id).await.expect("Failed to get user").unwrap();
+    assert!(Arc::ptr_eq(&cached, loaded.as_ref().unwrap()));
+    assert_eq!(cache.read().statistics().misses(), 1);
+    assert_eq!(cache.read().statistics().hits(), 1);
+
+    // Unknown rows are not cached
+    assert!(repository.get(Uuid::new_v4()).await.expect("Failed to get user").is_none());
+    assert_eq!(cache.read().len(), 1);
+
+    // Refresh picks up the new row; invalidate forces the next read to load it again
+    let renamed = User { username: "alice2".to_string(), ..alice.clone() };
+    user_repo.update(&renamed).await.expect("Failed to update user");
+    assert_eq!(repository.get(alice.id).await.unwrap().as_deref(), Some(&alice));
+    assert_eq!(repository.refresh(alice.id).await.unwrap().as_deref(), Some(&renamed));
+    assert!(repository.invalidate(alice.id));
+    assert!(!repository.invalidate(alice.id));
+    assert_eq!(repository.get(alice.id).await.unwrap().as_deref(), Some(&renamed));
+
+    // Refreshing a deleted row drops it from the cache
+    user_repo.delete(alice.id).await.expect("Failed to delete user");
+    assert!(repository.refresh(alice.id).await.unwrap().is_none());
+    assert!(!cache.read().contains(&alice.id));
+
+    // Concurrent misses for the same key load it once
+    let bob = User::new("bob".to_string(), "bob@example.com".to_string());
+    user_repo.create(&bob).await.expect("Failed to create user");
+    let misses = cache.read().statistics().misses();
+    let (first, second) = tokio::join!(repository.get(bob.id), repository.get(bob.id));
+    assert!(Arc::ptr_eq(&first.unwrap().unwrap(), &second.unwrap().unwrap()));
+    assert_eq!(cache.read().statistics().misses(), misses + 2);
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_cached_repository_bypasses_cache_until_ready() {
+    let pool = setup_database().await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let alice = User::new("alice".to_string(), "alice@example.com".to_string());
+    user_repo.create(&alice).await.expect("Failed to create user");
+
+    let cache = Arc::new(RwLock::new(MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU))));
+    let fetch = |pool: PgPool, id: Uuid| async move { UserRepository::new(pool).find_by_id(id).await };
+    let repository = CachedRepository::new(cache.clone(), pool.clone(), fetch).with_read_policy(ReadPolicy::Bypass);
+
+    // Reads go to the database without touching the cache
+    assert_eq!(repository.get(alice.id).await.unwrap().as_deref(), Some(&alice));
+    assert!(cache.read().is_empty());
+    assert_eq!(cache.read().statistics().misses(), 0);
+
+    cache.write().mark_ready();
+    assert_eq!(repository.get(alice.id).await.unwrap().as_deref(), Some(&alice));
+    assert!(cache.read().contains(&alice.id));
+    assert_eq!(cache.read().statistics().misses(), 1);
+
+    // Waiting reads go through the cache once it becomes ready
+    let cache = Arc::new(RwLock::new(MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU))));
+    let repository = CachedRepository::new(cache.clone(), pool.clone(), fetch)
+        .with_read_policy(ReadPolicy::WaitForReady(Duration::from_secs(5)));
+    let loader = cache.clone();
+    tokio::spawn(async move {
+        sleep(Duration::from_millis(20)).await;
+        loader.write().mark_ready();
+    });
+    assert_eq!(repository.get(alice.id).await.unwrap().as_deref(), Some(&alice));
+    assert!(cache.read().contains(&alice.id));
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_notifier_notifies_on_commit() {
+    let pool = setup_database().await;
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    let notifier = CacheNotifier::new();
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+
+    // Nothing is delivered while the transaction is open
+    let mut tx = pool.begin().await.expect("Failed to begin transaction");
+    notifier.notify_insert(&mut *tx, "user_index_cache", &alice).await.expect("Failed to notify");
+    notifier.notify_insert(&mut *tx, "user_index_cache", &bob).await.expect("Failed to notify");
+    sleep(Duration::from_millis(300)).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+
+    tx.commit().await.expect("Failed to commit");
+    IdxModelCache::wait_for(&*user_cache, bob.id, NOTIFICATION_TIMEOUT).await;
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice.clone()));
+    assert!(user_cache.read().contains_primary(&bob.id));
+
+    // A rolled back transaction sends nothing
+    let mut tx = pool.begin().await.expect("Failed to begin transaction");
+    notifier.notify_delete(&mut *tx, "user_index_cache", alice.id).await.expect("Failed to notify");
+    tx.rollback().await.expect("Failed to roll back");
+    notifier.notify_delete(&pool, "user_index_cache", bob.id).await.expect("Failed to notify");
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| !cache.contains_primary(&bob.id)).await;
+    assert!(user_cache.read().contains_primary(&alice.id));
+    assert!(!user_cache.read().contains_primary(&bob.id));
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_flush_table_cache_clears_listening_caches() {
+    let pool = setup_database().await;
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone()]).unwrap()));
+    let product_cache: Arc<RwLock<IdxModelCache<ProductIndexCache>>> = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![ProductIndexCache::new(Uuid::new_v4(), alice.id, "Laptop")]).unwrap(),
+    ));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(product_cache.clone())));
+    let pool_clone = pool.clone();
+    let _listen_handle = tokio::spawn(async move {
+        listener.listen(&pool_clone).await.ok();
+    });
+    sleep(Duration::from_millis(100)).await;
+
+    flush_table_cache(&pool, "user_index_cache").await.expect("Failed to send flush");
+    IdxModelCache::wait_until(&*user_cache, NOTIFICATION_TIMEOUT, |cache| !cache.contains_primary(&alice.id)).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+    assert_eq!(product_cache.read().iter().count(), 1);
+
+    CacheNotifier::new().notify_flush(&pool, "product_index_cache").await.expect("Failed to notify");
+    IdxModelCache::wait_until(&*product_cache, NOTIFICATION_TIMEOUT, |cache| cache.iter().next().is_none()).await;
+    assert_eq!(product_cache.read().iter().count(), 0);
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_outbox_poller_applies_notifications_written_without_listener() {
+    let pool = setup_database().await;
+
+    // Deliver the user notifications through the outbox only
+    let function = FunctionOptions::new("notify_cache_change_outbox").with_delivery(NotificationDelivery::Outbox);
+    init_cache_triggers_with_function(&pool, &function).await.expect("Failed to install outbox function");
+    init_table_trigger(&pool, &TriggerOptions::for_type::<UserIndexCache>().with_function(&function))
+        .await
+        .expect("Failed to install table trigger");
+
+    // Written while nobody listens
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    for user in [&alice, &bob] {
+        sqlx::query("INSERT INTO user_index_cache (id, username_hash, email_hash) VALUES ($1, $2, $3)")
+            .bind(user.id)
+            .bind(user.username_hash)
+            .bind(user.email_hash)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert user");
+    }
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let listener = Arc::new(listener);
+
+    // A cursor poller keeps the rows for other replicas
+    let cursor_poller = CacheOutboxPoller::new(listener.clone()).with_ack(OutboxAck::Cursor).with_batch_size(1);
+    assert_eq!(cursor_poller.poll_once(&pool).await.expect("Failed to poll"), 1);
+    assert!(user_cache.read().contains_primary(&alice.id));
+    assert!(!user_cache.read().contains_primary(&bob.id));
+    assert_eq!(cursor_poller.poll_once(&pool).await.expect("Failed to poll"), 1);
+    assert_eq!(cursor_poller.poll_once(&pool).await.expect("Failed to poll"), 0);
+    assert!(user_cache.read().contains_primary(&bob.id));
+
+    // Deleting processed rows drains the outbox; processing them again is harmless
+    let poller = CacheOutboxPoller::new(listener.clone());
+    assert_eq!(poller.poll_once(&pool).await.expect("Failed to poll"), 2);
+    assert_eq!(poller.last_seq(), cursor_poller.last_seq());
+    let remaining: i64 = sqlx::query_scalar(&format!("SELECT count(*) FROM {DEFAULT_OUTBOX_TABLE}"))
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to count outbox rows");
+    assert_eq!(remaining, 0);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice.clone()));
+
+    // Cleanup
+    cleanup_cache_triggers_with_function(&pool, &function).await.expect("Failed to remove outbox function");
+    cleanup_cache_outbox(&pool, DEFAULT_OUTBOX_TABLE).await.expect("Failed to drop outbox");
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_drift_checker_reports_and_repairs_drift() {
+    let pool = setup_database().await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let alice = User::new("alice".to_string(), "alice@example.com".to_string());
+    let bob = User::new("bob".to_string(), "bob@example.com".to_string());
+    let carol = User::new("carol".to_string(), "carol@example.com".to_string());
+    for user in [&alice, &bob, &carol] {
+        user_repo.create(user).await.expect("Failed to create user");
+    }
+
+    // Bob is stale, Dave was deleted in the database and Carol was never cached
+    let stale_bob = UserIndexCache::new(bob.id, "bobby", "bob@example.com");
+    let dave = UserIndexCache::new(Uuid::new_v4(), "dave", "dave@example.com");
+    let cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![UserIndexCache::from_user(&alice), stale_bob, dave.clone()]).unwrap(),
+    ));
+    let fetch = |pool: PgPool, ids: Vec<Uuid>| async move {
+        let rows = sqlx::query_as::<_, (Uuid, i64, i64)>(
+            "SELECT id, username_hash, email_hash FROM user_index_cache WHERE id = ANY($1)",
+        )
+        .bind(ids)
+        .fetch_all(&pool)
+        .await?;
+        Ok::<Vec<UserIndexCache>, sqlx::Error>(rows
+            .into_iter()
+            .map(|(id, username_hash, email_hash)| UserIndexCache { id, username_hash, email_hash })
+            .collect())
+    };
+
+    let checker = DriftChecker::for_index_cache(cache.clone(), fetch);
+    let report = checker.run_once(&pool).await.expect("Failed to check drift");
+    assert_eq!(report.checked, 3);
+    assert_eq!(report.mismatched, vec![bob.id]);
+    assert_eq!(report.missing_in_db, vec![dave.id]);
+    assert!(report.missing_in_cache.is_empty());
+    assert_eq!(report.repaired, 0);
+
+    let report = checker.check_keys(&pool, vec![carol.id]).await.expect("Failed to check drift");
+    assert_eq!(report.missing_in_cache, vec![carol.id]);
+
+    // Auto-repair applies the database state
+    let checker = DriftChecker::for_index_cache(cache.clone(), fetch).with_auto_repair();
+    let report = checker
+        .check_keys(&pool, vec![alice.id, bob.id, carol.id, dave.id])
+        .await
+        .expect("Failed to check drift");
+    assert_eq!(report.drifted(), 3);
+    assert_eq!(report.repaired, 3);
+    assert_eq!(cache.read().get_by_primary(&bob.id), Some(UserIndexCache::from_user(&bob)));
+    assert!(cache.read().contains_primary(&carol.id));
+    assert!(!cache.read().contains_primary(&dave.id));
+    assert!(checker.run_once(&pool).await.expect("Failed to check drift").is_clean());
+
+    // A sample rate of zero checks nothing
+    let checker = DriftChecker::for_index_cache(cache.clone(), fetch).with_sample_rate(0.0);
+    assert_eq!(checker.run_once(&pool).await.expect("Failed to check drift").checked, 0);
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+/// Loads all rows of `user_index_cache`
+async fn load_user_index_cache(pool: PgPool) -> Result<Vec<UserIndexCache>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (Uuid, i64, i64)>("SELECT id, username_hash, email_hash FROM user_index_cache")
+        .fetch_all(&pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, username_hash, email_hash)| UserIndexCache { id, username_hash, email_hash })
+        .collect())
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_runtime_preloads_and_keeps_caches_up_to_date() {
+    let pool = setup_database().await;
+
+    let user_repo = UserRepository::new(pool.clone());
+    let alice = User::new("alice".to_string(), "alice@example.com".to_string());
+    user_repo.create(&alice).await.expect("Failed to create user");
+
+    let cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let runtime = CacheRuntimeBuilder::new(pool.clone())
+        .with_table(CacheTableSpec::index_cache(cache.clone(), load_user_index_cache))
+        .build()
+        .start()
+        .await
+        .expect("Failed to start the runtime");
+
+    let status = runtime.status();
+    assert_eq!(status.listener, ListenerState::Listening);
+    assert_eq!(status.preloaded, vec![("user_index_cache".to_string(), 1)]);
+    assert!(cache.read().contains_primary(&alice.id));
+
+    // Changes after the start arrive through the listener
+    let bob = User::new("bob".to_string(), "bob@example.com".to_string());
+    user_repo.create(&bob).await.expect("Failed to create user");
+    IdxModelCache::wait_for(&*cache, bob.id, NOTIFICATION_TIMEOUT).await;
+    assert!(cache.read().contains_primary(&bob.id));
+
+    runtime.shutdown().await.expect("Failed to shut down the runtime");
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_runtime_fails_verification_for_tables_without_trigger() {
+    let pool = setup_database().await;
+
+    let cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let spec = CacheTableSpec::index_cache(cache.clone(), load_user_index_cache)
+        .with_trigger(TableTriggerSpec::new("table_without_trigger"));
+    let result = CacheRuntimeBuilder::new(pool.clone()).with_table(spec).build().start().await;
+
+    match result {
+        Err(CacheStartupError::TriggerVerification(TriggerVerificationError::Mismatch(report))) => {
+            assert_eq!(report.missing, vec![TableTriggerSpec::new("table_without_trigger")]);
+        }
+        Err(err) => panic!("unexpected error: {err}"),
+        Ok(_) => panic!("the runtime started without its trigger"),
+    }
+
+    // Cleanup
+    cleanup_database(&pool).await;
+    pool.close().await;
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use chrono::{DateTime, TimeZone, Utc};
+use postgres_index_cache::{HasPrimaryKey, HasTableName, IdxModelCache, IndexKeys, Indexable};
+use uuid::Uuid;
+
+use common::{Product, ProductIndexCache, User, UserIndexCache};
+
+/// Same model as `UserIndexCache`, with derived trait impls
+#[derive(Debug, Clone, HasPrimaryKey, Indexable, HasTableName)]
+#[cache(table = "user_index_cache")]
+struct DerivedUserIndexCache {
+    #[cache(primary_key)]
+    id: Uuid,
+    #[cache(i64_index)]
+    username_hash: i64,
+    #[cache(i64_index)]
+    email_hash: i64,
+}
+
+#[derive(Debug, Clone, HasPrimaryKey, Indexable, HasTableName)]
+struct DerivedProductIndexCache {
+    #[cache(primary_key)]
+    id: Uuid,
+    #[cache(uuid_index = "owner")]
+    user_id: Uuid,
+    #[cache(uuid_index)]
+    category_id: Option<Uuid>,
+    #[cache(i64_index = "name_hash")]
+    product_name_hash: i64,
+    #[cache(i64_index)]
+    position: Option<i32>,
+    #[allow(dead_code)]
+    description: String,
+}
+
+#[derive(Debug, Clone, HasPrimaryKey, Indexable)]
+struct DerivedEventIndexCache {
+    #[cache(primary_key)]
+    id: Uuid,
+    #[cache(datetime_index = "starts")]
+    starts_at: DateTime<Utc>,
+    #[cache(datetime_index)]
+    cancelled_at: Option<DateTime<Utc>>,
+}
+
+impl DerivedProductIndexCache {
+    fn from_index_cache(cache: &ProductIndexCache, category_id: Option<Uuid>, position: Option<i32>) -> Self {
+        Self {
+            id: cache.id,
+            user_id: cache.user_id,
+            category_id,
+            product_name_hash: cache.product_name_hash,
+            position,
+            description: String::new(),
+        }
+    }
+}
+
+/// Index keys by name, ignoring their order
+fn by_name<V>(keys: IndexKeys<V>) -> HashMap<Cow<'static, str>, Option<V>> {
+    keys.into_iter().collect()
+}
+
+#[test]
+fn test_derived_impls_match_hand_written() {
+    let user = User::new("alice".to_string(), "alice@example.com".to_string());
+    let manual = UserIndexCache::from_user(&user);
+    let derived = DerivedUserIndexCache {
+        id: manual.id,
+        username_hash: manual.username_hash,
+        email_hash: manual.email_hash,
+    };
+
+    assert_eq!(DerivedUserIndexCache::table_name(), UserIndexCache::table_name());
+    assert_eq!(derived.primary_key(), manual.primary_key());
+    assert_eq!(by_name(derived.i64_index_keys()), by_name(manual.i64_index_keys()));
+    assert_eq!(by_name(derived.uuid_index_keys()), by_name(manual.uuid_index_keys()));
+    assert!(derived.datetime_index_keys().is_empty());
+}
+
+#[test]
+fn test_derived_index_names_and_options() {
+    let user_id = Uuid::new_v4();
+    let cache_entry = ProductIndexCache::from_product(&Product::new(user_id, "Keyboard".to_string()));
+    let product = DerivedProductIndexCache::from_index_cache(&cache_entry, None, Some(7));
+
+    assert_eq!(DerivedProductIndexCache::table_name(), "derived_product_index_cache");
+    assert_eq!(product.primary_key(), cache_entry.id);
+    assert_eq!(
+        product.uuid_index_keys().as_slice(),
+        [
+            (Cow::Borrowed("owner"), Some(user_id)),
+            (Cow::Borrowed("category_id"), None),
+        ]
+    );
+    assert_eq!(
+        product.i64_index_keys().as_slice(),
+        [
+            (Cow::Borrowed("name_hash"), Some(cache_entry.product_name_hash)),
+            (Cow::Borrowed("position"), Some(7)),
+        ]
+    );
+
+    // The derived impls work with the cache
+    let cache = IdxModelCache::new(vec![product.clone()]).unwrap();
+    assert_eq!(cache.get_by_uuid_index("owner", &user_id), Some(&[product.id][..]));
+    assert_eq!(
+        cache.get_by_i64_index("name_hash", &cache_entry.product_name_hash),
+        Some(&[product.id][..])
+    );
+}
+
+#[test]
+fn test_derived_datetime_index() {
+    let starts_at = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+    let event = DerivedEventIndexCache {
+        id: Uuid::new_v4(),
+        starts_at,
+        cancelled_at: None,
+    };
+
+    assert_eq!(
+        event.datetime_index_keys().as_slice(),
+        [
+            (Cow::Borrowed("starts"), Some(starts_at)),
+            (Cow::Borrowed("cancelled_at"), None),
+        ]
+    );
+
+    let cache = IdxModelCache::new(vec![event.clone()]).unwrap();
+    assert_eq!(cache.get_by_datetime_index("starts", &starts_at), Some(&[event.id][..]));
+}
+
+#[test]
+fn test_derive_compile_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
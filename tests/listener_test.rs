@@ -3,11 +3,15 @@ mod common;
 use std::sync::Arc;
 use parking_lot::RwLock;
 use postgres_index_cache::{
-    CacheNotification, CacheNotificationListener, IdxModelCache, IndexCacheHandler,
+    AggregatingCacheHandler, CacheAction, CascadeHandler, CacheBootstrapper, CacheConfig, CacheError, CacheManager, CacheNotification, CacheNotificationHandler, CacheNotificationListener,
+    DuplicateInsert, EvictionPolicy, HandlerOptions, HandlerRegistry, HandlerStats, HasTableName, IdxModelCache, IndexCacheHandler, LinkedCacheHandler, MainModelCache,
+    ItemLoader, NotificationOutcome, NotificationSink, OnDeserError, PauseMode, ResyncMode, RetryPolicy, SinkError, SinkHandler,
+    ALL_TABLES, DEFAULT_CACHE_CHANNEL, SharedCacheCoordinator, TransactionAwareIdxModelCache,
 };
+use futures::future::BoxFuture;
 use uuid::Uuid;
 
-use common::entities::{User, UserIndexCache, Product, ProductIndexCache};
+use common::entities::{User, UserIndexCache, Product, ProductIndexCache, AccountIndexCache};
 
 #[tokio::test]
 async fn test_user_cache_notification_insert() {
@@ -51,6 +55,8 @@ async fn test_user_cache_notification_insert() {
         action: "insert".to_string(),
         id: user_id,
         data: Some(serde_json::to_value(&user_cache_entry).unwrap()),
+        key: None,
+        context: None,
     };
     
     let payload = serde_json::to_string(&notification_with_cache).unwrap();
@@ -99,14 +105,7 @@ async fn test_user_cache_notification_update() {
     let updated_cache_entry = UserIndexCache::from_user(&updated_user);
     
     // Create notification for update
-    let notification = CacheNotification {
-        table: "user_index_cache".to_string(),
-        action: "update".to_string(),
-        id: user_id,
-        data: Some(serde_json::to_value(&updated_cache_entry).unwrap()),
-    };
-    
-    let payload = serde_json::to_string(&notification).unwrap();
+    let payload = CacheNotification::update("user_index_cache", &updated_cache_entry).unwrap().to_payload();
     
     // Process the notification
     listener.process_notification(&payload).await;
@@ -145,21 +144,303 @@ async fn test_user_cache_notification_delete() {
     assert!(user_cache.read().contains_primary(&user_id));
     
     // Create notification for delete
+    let payload = CacheNotification::delete("user_index_cache", user_id).to_payload();
+    
+    // Process the notification
+    listener.process_notification(&payload).await;
+    
+    // Verify the cache entry was removed
+    let cache = user_cache.read();
+    assert!(!cache.contains_primary(&user_id));
+}
+
+#[tokio::test]
+async fn test_handler_for_type_reads_table_name() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+
+    // The handler is registered for the table of the cached type
+    let handler = Arc::new(IndexCacheHandler::for_type(user_cache.clone()));
+    assert_eq!(handler.table_name(), "user_index_cache");
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+
+    let entry = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let notification = CacheNotification::insert(UserIndexCache::table_name(), &entry).unwrap();
+
+    listener.process_notification(&notification.to_payload()).await;
+
+    assert!(user_cache.read().contains_primary(&entry.id));
+}
+
+#[tokio::test]
+async fn test_user_cache_notification_truncate() {
+    // Create user cache with initial data
+    let entries = vec![
+        UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com"),
+        UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com"),
+    ];
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(entries).unwrap()));
+
+    // Create handler and listener
+    let handler = Arc::new(IndexCacheHandler::new(
+        "user_index_cache".to_string(),
+        user_cache.clone(),
+    ));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(handler);
+
+    // A truncate carries the nil UUID and no data
     let notification = CacheNotification {
         table: "user_index_cache".to_string(),
-        action: "delete".to_string(),
-        id: user_id,
+        action: "truncate".to_string(),
+        id: Uuid::nil(),
         data: None,
+        key: None,
+        context: None,
     };
-    
+
     let payload = serde_json::to_string(&notification).unwrap();
-    
+
     // Process the notification
     listener.process_notification(&payload).await;
-    
-    // Verify the cache entry was removed
+
+    // Verify the cache was emptied
     let cache = user_cache.read();
-    assert!(!cache.contains_primary(&user_id));
+    assert_eq!(cache.iter().count(), 0);
+    assert!(cache.get_by_i64_index("username_hash", &common::entities::hash_as_i64(&"alice")).is_none());
+}
+
+#[tokio::test]
+async fn test_user_cache_notification_flush() {
+    let entries = vec![
+        UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com"),
+        UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com"),
+    ];
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(entries).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+
+    // A flush needs no data and any id is ignored
+    listener
+        .process_notification(r#"{"table": "user_index_cache", "action": "flush", "id": 42}"#)
+        .await;
+
+    let cache = user_cache.read();
+    assert_eq!(cache.iter().count(), 0);
+    assert!(cache.get_by_i64_index("username_hash", &common::entities::hash_as_i64(&"alice")).is_none());
+}
+
+#[tokio::test]
+async fn test_process_notification_reports_outcome() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let payload = user_notification("insert", &user);
+
+    let table = "user_index_cache".to_string();
+    assert_eq!(listener.process_notification(&payload).await, NotificationOutcome::Applied { table });
+    let unknown = r#"{"table": "unknown_table", "action": "delete", "id": "00000000-0000-0000-0000-000000000000"}"#;
+    let outcome = listener.process_notification(unknown).await;
+    assert_eq!(outcome, NotificationOutcome::NoHandler { table: "unknown_table".to_string() });
+    assert_eq!(outcome.table(), Some("unknown_table"));
+    assert!(matches!(listener.process_notification("not json").await, NotificationOutcome::ParseError(_)));
+
+    // Held back or dropped while paused; only dropped payloads need delivering again
+    listener.pause(PauseMode::Buffer { capacity: 1 });
+    let buffered = listener.process_notification(&payload).await;
+    assert_eq!(buffered, NotificationOutcome::Buffered);
+    assert!(buffered.is_consumed());
+    let discarded = listener.process_notification(&payload).await;
+    assert_eq!(discarded, NotificationOutcome::Discarded);
+    assert!(!discarded.is_consumed());
+}
+
+#[tokio::test]
+async fn test_handler_skips_stale_versions() {
+    let account = AccountIndexCache::new(Uuid::new_v4(), 200, 2);
+    let account_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+    let handler = IndexCacheHandler::for_type(account_cache.clone()).skip_stale_versions();
+
+    let notification = |version: u64, balance_hash: i64| CacheNotification {
+        table: "account_index_cache".to_string(),
+        action: "update".to_string(),
+        id: account.id,
+        data: Some(serde_json::to_value(AccountIndexCache::new(account.id, balance_hash, version)).unwrap()),
+        key: None,
+        context: None,
+    };
+
+    // A late notification for an older version leaves the cache untouched
+    handler.handle_notification(notification(1, 100)).await;
+    assert_eq!(account_cache.read().get_by_primary(&account.id).unwrap().balance_hash, 200);
+
+    handler.handle_notification(notification(3, 300)).await;
+    assert_eq!(account_cache.read().get_by_primary(&account.id).unwrap().version, 3);
+}
+
+#[tokio::test]
+async fn test_handler_removes_soft_deleted_items() {
+    let account = AccountIndexCache::new(Uuid::new_v4(), 200, 1);
+    let account_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+    let handler = IndexCacheHandler::for_type(account_cache.clone()).remove_deleted();
+
+    let mut deleted = account.clone();
+    deleted.deleted_at = Some(chrono::Utc::now());
+    handler
+        .handle_notification(CacheNotification {
+            table: "account_index_cache".to_string(),
+            action: "update".to_string(),
+            id: account.id,
+            data: Some(serde_json::to_value(&deleted).unwrap()),
+            key: None,
+            context: None,
+        })
+        .await;
+
+    assert!(!account_cache.read().contains_primary(&account.id));
+}
+
+#[tokio::test]
+async fn test_handler_migrates_legacy_payloads() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let migrator = |mut data: serde_json::Value| -> Result<serde_json::Value, String> {
+        let object = data.as_object_mut().ok_or("data is not an object")?;
+        if let Some(email_hash) = object.remove("email_h") {
+            object.insert("email_hash".to_string(), email_hash);
+        }
+        Ok(data)
+    };
+    let handler = IndexCacheHandler::for_type(user_cache.clone()).with_payload_migrator(Arc::new(migrator));
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let notification = |data: serde_json::Value| CacheNotification {
+        table: "user_index_cache".to_string(),
+        action: "insert".to_string(),
+        id: user.id,
+        data: Some(data),
+        key: None,
+        context: None,
+    };
+
+    // An old trigger still sends the legacy field name
+    handler
+        .handle_notification(notification(serde_json::json!({
+            "id": user.id,
+            "username_hash": user.username_hash,
+            "email_h": user.email_hash,
+        })))
+        .await;
+    assert_eq!(user_cache.read().get_by_primary(&user.id), Some(user.clone()));
+
+    // A rejected payload is dropped
+    user_cache.write().remove(&user.id);
+    handler.handle_notification(notification(serde_json::json!([user.id]))).await;
+    assert!(!user_cache.read().contains_primary(&user.id));
+}
+
+#[tokio::test]
+async fn test_handler_defers_notifications_for_keys_staged_in_open_transactions() {
+    use postgres_index_cache::TransactionAware;
+
+    let account = AccountIndexCache::new(Uuid::new_v4(), 200, 1);
+    let other = AccountIndexCache::new(Uuid::new_v4(), 500, 1);
+    let account_cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![account.clone(), other.clone()]).unwrap(),
+    ));
+    let coordinator = Arc::new(SharedCacheCoordinator::new(account_cache.clone()));
+    let handler = IndexCacheHandler::for_type(account_cache.clone()).with_coordinator(coordinator.clone());
+    let tx_cache = TransactionAwareIdxModelCache::new_coordinated(coordinator.clone());
+
+    let notification = |item: &AccountIndexCache| CacheNotification {
+        table: "account_index_cache".to_string(),
+        action: "update".to_string(),
+        id: item.id,
+        data: Some(serde_json::to_value(item).unwrap()),
+        key: None,
+        context: None,
+    };
+
+    // The local transaction stages a change based on the old value
+    tx_cache.update(AccountIndexCache::new(account.id, 250, 1));
+    assert!(coordinator.is_open(&account.id));
+
+    // Another service changes both rows; only the staged key is deferred
+    handler.handle_notification(notification(&AccountIndexCache::new(account.id, 300, 2))).await;
+    handler.handle_notification(notification(&AccountIndexCache::new(other.id, 600, 2))).await;
+    assert_eq!(coordinator.deferred_count(), 1);
+    assert_eq!(account_cache.read().get_by_primary(&account.id).unwrap().balance_hash, 200);
+    assert_eq!(account_cache.read().get_by_primary(&other.id).unwrap().balance_hash, 600);
+
+    // The fresher notification is applied after the commit, not overwritten by it
+    tx_cache.on_commit().await.unwrap();
+    assert!(!coordinator.is_open(&account.id));
+    assert_eq!(coordinator.deferred_count(), 0);
+    assert_eq!(account_cache.read().get_by_primary(&account.id).unwrap().balance_hash, 300);
+}
+
+#[tokio::test]
+async fn test_coordinated_batch_applies_as_one_generation() {
+    let account = AccountIndexCache::new(Uuid::new_v4(), 200, 1);
+    let other = AccountIndexCache::new(Uuid::new_v4(), 500, 1);
+    let account_cache = Arc::new(RwLock::new(
+        IdxModelCache::new(vec![account.clone(), other.clone()]).unwrap(),
+    ));
+    let coordinator = Arc::new(SharedCacheCoordinator::new(account_cache.clone()));
+    let handler = IndexCacheHandler::for_type(account_cache.clone()).with_coordinator(coordinator.clone());
+    let tx_cache = TransactionAwareIdxModelCache::new_coordinated(coordinator.clone());
+    tx_cache.update(AccountIndexCache::new(account.id, 250, 1));
+
+    let notification = |item: &AccountIndexCache| CacheNotification {
+        table: "account_index_cache".to_string(),
+        action: "update".to_string(),
+        id: item.id,
+        data: Some(serde_json::to_value(item).unwrap()),
+        key: None,
+        context: None,
+    };
+    let generation = account_cache.read().generation();
+    handler
+        .handle_notifications(vec![
+            notification(&AccountIndexCache::new(account.id, 300, 2)),
+            notification(&AccountIndexCache::new(other.id, 600, 2)),
+        ])
+        .await;
+
+    let cache = account_cache.read();
+    assert_eq!(cache.generation(), generation + 1);
+    assert_eq!(cache.get_by_primary(&account.id).unwrap().balance_hash, 200);
+    assert_eq!(cache.get_by_primary(&other.id).unwrap().balance_hash, 600);
+    assert_eq!(coordinator.deferred_count(), 1);
+}
+
+#[tokio::test]
+async fn test_deferred_notifications_apply_after_rollback() {
+    use postgres_index_cache::TransactionAware;
+
+    let account = AccountIndexCache::new(Uuid::new_v4(), 200, 1);
+    let account_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![account.clone()]).unwrap()));
+    let coordinator = Arc::new(SharedCacheCoordinator::new(account_cache.clone()));
+    let handler = IndexCacheHandler::for_type(account_cache.clone()).with_coordinator(coordinator.clone());
+    let tx_cache = TransactionAwareIdxModelCache::new_coordinated(coordinator.clone());
+
+    tx_cache.update(AccountIndexCache::new(account.id, 250, 1));
+    handler
+        .handle_notification(CacheNotification {
+            table: "account_index_cache".to_string(),
+            action: "delete".to_string(),
+            id: account.id,
+            data: None,
+            key: None,
+            context: None,
+        })
+        .await;
+    assert!(account_cache.read().contains_primary(&account.id));
+
+    tx_cache.on_rollback().await.unwrap();
+    assert!(!account_cache.read().contains_primary(&account.id));
 }
 
 #[tokio::test]
@@ -194,6 +475,8 @@ async fn test_product_cache_notification_insert() {
         action: "insert".to_string(),
         id: product_id,
         data: Some(serde_json::to_value(&product_cache_entry).unwrap()),
+        key: None,
+        context: None,
     };
     
     let payload = serde_json::to_string(&notification).unwrap();
@@ -255,6 +538,8 @@ async fn test_multi_table_listener() {
         action: "insert".to_string(),
         id: user_id,
         data: Some(serde_json::to_value(&user_cache_entry).unwrap()),
+        key: None,
+        context: None,
     };
     listener.process_notification(&serde_json::to_string(&user_notification).unwrap()).await;
     
@@ -264,6 +549,8 @@ async fn test_multi_table_listener() {
         action: "insert".to_string(),
         id: product_id,
         data: Some(serde_json::to_value(&product_cache_entry).unwrap()),
+        key: None,
+        context: None,
     };
     listener.process_notification(&serde_json::to_string(&product_notification).unwrap()).await;
     
@@ -289,6 +576,8 @@ async fn test_notification_with_unknown_table() {
         action: "insert".to_string(),
         id: Uuid::new_v4(),
         data: None,
+        key: None,
+        context: None,
     };
     
     let payload = serde_json::to_string(&notification).unwrap();
@@ -302,4 +591,1019 @@ async fn test_notification_with_unknown_table() {
 fn test_custom_channel_name() {
     let listener = CacheNotificationListener::with_channel("my_custom_channel".to_string());
     assert_eq!(listener.channel(), "my_custom_channel");
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_cache_manager_holds_caches_by_name() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let product_cache = Arc::new(RwLock::new(MainModelCache::<ProductIndexCache>::new(CacheConfig::new(
+        10,
+        EvictionPolicy::LRU,
+    ))));
+
+    let mut manager = CacheManager::new();
+    manager.register_index_cache("user_index_cache", user_cache.clone()).unwrap();
+    manager.register_main_model_cache("product_cache", product_cache.clone()).unwrap();
+    assert!(matches!(
+        manager.register_index_cache("user_index_cache", user_cache.clone()),
+        Err(CacheError::InvalidArgument(_))
+    ));
+    assert_eq!(manager.names(), vec!["user_index_cache", "product_cache"]);
+
+    // Lookups check the name, the kind of cache and the item type
+    let found = manager.index_cache::<UserIndexCache>("user_index_cache").unwrap();
+    assert!(Arc::ptr_eq(&found, &user_cache));
+    assert!(manager.main_model_cache::<ProductIndexCache>("product_cache").is_some());
+    assert!(manager.index_cache::<ProductIndexCache>("user_index_cache").is_none());
+    assert!(manager.index_cache::<ProductIndexCache>("product_cache").is_none());
+    assert!(manager.index_cache::<UserIndexCache>("missing").is_none());
+
+    // One listener keeps every cache in sync
+    let listener = manager.listener();
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let product = ProductIndexCache::new(Uuid::new_v4(), user.id, "Widget");
+    for (table, id, data) in [
+        ("user_index_cache", user.id, serde_json::to_value(&user).unwrap()),
+        ("product_index_cache", product.id, serde_json::to_value(&product).unwrap()),
+    ] {
+        let notification = CacheNotification {
+            table: table.to_string(),
+            action: "insert".to_string(),
+            id,
+            data: Some(data),
+            key: None,
+            context: None,
+        };
+        listener.process_notification(&serde_json::to_string(&notification).unwrap()).await;
+    }
+    assert!(user_cache.read().contains_primary(&user.id));
+    assert!(product_cache.read().contains(&product.id));
+
+    product_cache.write().get(&product.id);
+    product_cache.write().get(&Uuid::new_v4());
+    let statistics = manager.statistics();
+    assert_eq!(statistics.caches, 2);
+    assert_eq!(statistics.entries, 2);
+    assert_eq!((statistics.hits, statistics.misses), (1, 1));
+    assert_eq!(statistics.hit_rate(), 0.5);
+
+    manager.clear_all();
+    assert_eq!(user_cache.read().iter().count(), 0);
+    assert!(product_cache.read().is_empty());
+    assert_eq!(manager.statistics().entries, 0);
+}
+
+#[tokio::test]
+async fn test_linked_handler_updates_both_caches() {
+    let index_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let main_cache = Arc::new(RwLock::new(MainModelCache::<User>::new(CacheConfig::new(10, EvictionPolicy::LRU))));
+    let handler = LinkedCacheHandler::for_type(index_cache.clone(), main_cache.clone(), UserIndexCache::from_user);
+    assert_eq!(handler.table_name(), "users");
+
+    let notification = |action: &str, user: &User, with_data: bool| CacheNotification {
+        table: "users".to_string(),
+        action: action.to_string(),
+        id: user.id,
+        data: with_data.then(|| serde_json::to_value(user).unwrap()),
+        key: None,
+        context: None,
+    };
+
+    let user = User::new("alice".to_string(), "alice@example.com".to_string());
+    handler.handle_notification(notification("insert", &user, true)).await;
+    assert_eq!(index_cache.read().get_by_primary(&user.id), Some(UserIndexCache::from_user(&user)));
+    assert_eq!(main_cache.write().get(&user.id).as_deref(), Some(&user));
+
+    let renamed = User { username: "alicia".to_string(), ..user.clone() };
+    handler.handle_notification(notification("update", &renamed, true)).await;
+    assert_eq!(index_cache.read().get_by_primary(&user.id), Some(UserIndexCache::from_user(&renamed)));
+    assert_eq!(main_cache.write().get(&user.id).unwrap().username, "alicia");
+
+    // A delete removes the item from both caches even if one lost it already
+    main_cache.write().remove(&user.id);
+    handler.handle_notification(notification("delete", &user, false)).await;
+    assert!(!index_cache.read().contains_primary(&user.id));
+    assert!(main_cache.read().is_empty());
+}
+
+#[tokio::test]
+async fn test_linked_handler_waits_for_tokio_guards_held_across_await() {
+    let index_cache = Arc::new(tokio::sync::RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let main_cache = Arc::new(tokio::sync::RwLock::new(MainModelCache::<User>::new(CacheConfig::new(10, EvictionPolicy::LRU))));
+    let handler = Arc::new(LinkedCacheHandler::for_type(index_cache.clone(), main_cache.clone(), UserIndexCache::from_user));
+
+    let user = User::new("alice".to_string(), "alice@example.com".to_string());
+    let notification = CacheNotification {
+        table: "users".to_string(),
+        action: "insert".to_string(),
+        id: user.id,
+        data: Some(serde_json::to_value(&user).unwrap()),
+        key: None,
+        context: None,
+    };
+
+    // Hold both guards across an await, as code refetching a row would
+    let index_guard = index_cache.write().await;
+    let main_guard = main_cache.write().await;
+    let applying = tokio::spawn({
+        let handler = handler.clone();
+        async move { handler.handle_notification(notification).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    assert!(!applying.is_finished());
+    assert!(!index_guard.contains_primary(&user.id));
+    drop(main_guard);
+    drop(index_guard);
+
+    applying.await.unwrap();
+    assert_eq!(index_cache.read().await.get_by_primary(&user.id), Some(UserIndexCache::from_user(&user)));
+    assert_eq!(main_cache.write().await.get(&user.id).as_deref(), Some(&user));
+
+    // The index handler takes the same kind of lock
+    let index_handler = IndexCacheHandler::for_type(index_cache.clone());
+    index_handler.handle_notification(CacheNotification::delete("user_index_cache", user.id)).await;
+    assert!(!index_cache.read().await.contains_primary(&user.id));
+}
+
+fn user_notification(action: &str, user: &UserIndexCache) -> String {
+    let notification = match action {
+        "delete" => CacheNotification::delete("user_index_cache", user.id),
+        _ => CacheNotification {
+            action: action.to_string(),
+            ..CacheNotification::insert("user_index_cache", user).unwrap()
+        },
+    };
+    notification.to_payload()
+}
+
+#[tokio::test]
+async fn test_bootstrap_replays_changes_made_during_load() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let carol = UserIndexCache::new(Uuid::new_v4(), "carol", "carol@example.com");
+    let alicia = UserIndexCache::new(alice.id, "alicia", "alice@example.com");
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let listener = Arc::new(listener);
+    let bootstrapper = CacheBootstrapper::new(listener.clone()).without_waiting_for_listen();
+
+    let (snapshot_taken, snapshot_taken_rx) = tokio::sync::oneshot::channel::<()>();
+    let (writes_done, writes_done_rx) = tokio::sync::oneshot::channel::<()>();
+
+    // The snapshot is read before the concurrent writes and applied after them
+    let load = || async {
+        let snapshot = vec![alice.clone(), carol.clone()];
+        snapshot_taken.send(()).unwrap();
+        writes_done_rx.await.unwrap();
+        *user_cache.write() = IdxModelCache::new(snapshot)?;
+        Ok::<_, CacheError>(())
+    };
+    let writes = async {
+        snapshot_taken_rx.await.unwrap();
+        listener.process_notification(&user_notification("update", &alicia)).await;
+        listener.process_notification(&user_notification("insert", &bob)).await;
+        listener.process_notification(&user_notification("delete", &carol)).await;
+        assert!(listener.is_buffering());
+        assert!(user_cache.read().get_by_primary(&alice.id).is_none());
+        assert!(!user_cache.read().is_ready());
+        writes_done.send(()).unwrap();
+    };
+    let (replayed, ()) = tokio::join!(bootstrapper.bootstrap(load), writes);
+
+    assert_eq!(replayed.unwrap(), 3);
+    assert!(!listener.is_buffering());
+    {
+        let cache = user_cache.read();
+        assert!(cache.is_ready(), "The cache is ready once the buffered changes are replayed");
+        assert_eq!(cache.get_by_primary(&alice.id), Some(alicia.clone()));
+        assert!(cache.contains_primary(&bob.id));
+        assert!(!cache.contains_primary(&carol.id));
+    }
+
+    // Live dispatch afterwards
+    listener.process_notification(&user_notification("delete", &bob)).await;
+    assert!(!user_cache.read().contains_primary(&bob.id));
+}
+
+#[tokio::test]
+async fn test_bootstrap_stops_buffering_when_load_fails() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let listener = Arc::new(listener);
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+
+    let result = CacheBootstrapper::new(listener.clone())
+        .without_waiting_for_listen()
+        .bootstrap(|| async {
+            listener.process_notification(&user_notification("insert", &user)).await;
+            Err::<(), _>(CacheError::OperationFailed("snapshot failed".to_string()))
+        })
+        .await;
+
+    assert!(matches!(result, Err(CacheError::OperationFailed(_))));
+    assert!(!listener.is_buffering());
+    assert!(user_cache.read().contains_primary(&user.id));
+    assert!(!user_cache.read().is_ready());
+}
+
+/// Waits before passing each notification on, tracking how many run at once
+struct SlowHandler {
+    inner: IndexCacheHandler<UserIndexCache>,
+    delay: std::time::Duration,
+    running: std::sync::atomic::AtomicUsize,
+    max_running: std::sync::atomic::AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl CacheNotificationHandler for SlowHandler {
+    async fn handle_notification(&self, notification: CacheNotification) {
+        use std::sync::atomic::Ordering;
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_running.fetch_max(running, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        // The cache is changed last, after the only await
+        self.inner.handle_notification(notification).await;
+    }
+
+    fn table_name(&self) -> &str {
+        self.inner.table_name()
+    }
+}
+
+fn slow_handler(cache: Arc<RwLock<IdxModelCache<UserIndexCache>>>, delay_ms: u64) -> Arc<SlowHandler> {
+    Arc::new(SlowHandler {
+        inner: IndexCacheHandler::for_type(cache),
+        delay: std::time::Duration::from_millis(delay_ms),
+        running: Default::default(),
+        max_running: Default::default(),
+    })
+}
+
+#[tokio::test]
+async fn test_handler_timeout_abandons_notification() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler_with_options(
+        slow_handler(user_cache.clone(), 500),
+        HandlerOptions::default().with_timeout(std::time::Duration::from_millis(20)),
+    );
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    listener.process_notification(&user_notification("insert", &user)).await;
+
+    assert_eq!(listener.handler_timeouts("user_index_cache"), 1);
+    assert!(!user_cache.read().contains_primary(&user.id));
+    assert_eq!(listener.handler_timeouts("product_index_cache"), 0);
+}
+
+#[tokio::test]
+async fn test_process_notifications_applies_each_table_in_one_batch() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let alicia = UserIndexCache::new(alice.id, "alicia", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone()]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone()).skip_unchanged()));
+    let generation = user_cache.read().generation();
+
+    let unknown = CacheNotification::delete("unknown_table", Uuid::new_v4()).to_payload();
+    let outcomes = listener
+        .process_notifications(vec![
+            user_notification("insert", &bob),
+            user_notification("update", &alicia),
+            unknown,
+            "not a notification".to_string(),
+            user_notification("delete", &bob),
+            // Equal to the cached alice, but applied after the update to alicia
+            user_notification("update", &alice),
+        ])
+        .await;
+
+    let applied = NotificationOutcome::Applied { table: "user_index_cache".to_string() };
+    assert_eq!(outcomes.len(), 6);
+    assert_eq!(outcomes[..2], [applied.clone(), applied.clone()]);
+    assert_eq!(outcomes[2], NotificationOutcome::NoHandler { table: "unknown_table".to_string() });
+    assert!(matches!(outcomes[3], NotificationOutcome::ParseError(_)));
+    assert_eq!(outcomes[4..], [applied.clone(), applied]);
+
+    let cache = user_cache.read();
+    assert_eq!(cache.generation(), generation + 1);
+    assert_eq!(cache.get_by_primary(&alice.id), Some(alice));
+    assert!(!cache.contains_primary(&bob.id));
+}
+
+#[tokio::test]
+async fn test_batch_timeout_fails_every_notification_in_it() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler_with_options(
+        slow_handler(user_cache.clone(), 500),
+        HandlerOptions::default().with_timeout(std::time::Duration::from_millis(20)),
+    );
+
+    let users = [
+        UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com"),
+        UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com"),
+    ];
+    let outcomes = listener.process_notifications(users.iter().map(|user| user_notification("insert", user))).await;
+
+    assert!(outcomes.iter().all(|outcome| matches!(outcome, NotificationOutcome::HandlerError { .. })));
+    let stats = listener.handler_stats("user_index_cache").unwrap();
+    assert_eq!((stats.count, stats.timeouts, stats.failures), (1, 1, 2));
+    assert!(users.iter().all(|user| !user_cache.read().contains_primary(&user.id)));
+}
+
+#[tokio::test]
+async fn test_handler_concurrency_limit() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let handler = slow_handler(user_cache.clone(), 20);
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler_with_options(handler.clone(), HandlerOptions::default().with_max_concurrency(2));
+
+    let users: Vec<UserIndexCache> = (0..5)
+        .map(|i| UserIndexCache::new(Uuid::new_v4(), &format!("user{i}"), &format!("user{i}@example.com")))
+        .collect();
+    let payloads: Vec<String> = users.iter().map(|user| user_notification("insert", user)).collect();
+    futures::future::join_all(payloads.iter().map(|payload| listener.process_notification(payload))).await;
+
+    assert_eq!(handler.max_running.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert!(users.iter().all(|user| user_cache.read().contains_primary(&user.id)));
+}
+
+#[tokio::test]
+async fn test_handler_stats_record_latency() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(slow_handler(user_cache.clone(), 30));
+    listener.set_slow_handler_threshold(std::time::Duration::from_millis(10));
+    assert_eq!(listener.handler_stats("user_index_cache"), Some(HandlerStats::default()));
+    assert_eq!(listener.handler_stats("product_index_cache"), None);
+
+    for i in 0..3 {
+        let user = UserIndexCache::new(Uuid::new_v4(), &format!("user{i}"), &format!("user{i}@example.com"));
+        listener.process_notification(&user_notification("insert", &user)).await;
+    }
+
+    let stats = listener.handler_stats("user_index_cache").unwrap();
+    assert_eq!(stats.count, 3);
+    assert!(stats.mean >= std::time::Duration::from_millis(30));
+    assert!(stats.p95 >= std::time::Duration::from_millis(30));
+    assert!(stats.max >= stats.p95);
+    assert_eq!(stats.timeouts, 0);
+    assert_eq!(listener.all_handler_stats(), vec![("user_index_cache".to_string(), stats)]);
+}
+
+#[tokio::test]
+async fn test_replay_is_idempotent_with_stale_version_skipping() {
+    let account_cache: Arc<RwLock<IdxModelCache<AccountIndexCache>>> =
+        Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(account_cache.clone()).skip_stale_versions()));
+
+    let first = Uuid::new_v4();
+    let second = Uuid::new_v4();
+    let third = Uuid::new_v4();
+    let notification = |action: &str, account: AccountIndexCache| {
+        serde_json::to_string(&CacheNotification {
+            table: "account_index_cache".to_string(),
+            action: action.to_string(),
+            id: account.id,
+            data: (action != "delete").then(|| serde_json::to_value(&account).unwrap()),
+            key: None,
+            context: None,
+        })
+        .unwrap()
+    };
+    let stream = vec![
+        notification("insert", AccountIndexCache::new(first, 100, 1)),
+        notification("insert", AccountIndexCache::new(second, 200, 1)),
+        notification("update", AccountIndexCache::new(first, 110, 2)),
+        notification("delete", AccountIndexCache::new(second, 200, 1)),
+        notification("insert", AccountIndexCache::new(third, 300, 1)),
+        notification("update", AccountIndexCache::new(first, 120, 3)),
+        r#"{"table": "unknown_table", "action": "delete", "id": "00000000-0000-0000-0000-000000000000"}"#.to_string(),
+        "not json".to_string(),
+    ];
+    let contents = || {
+        let mut accounts: Vec<AccountIndexCache> = account_cache.read().iter().cloned().collect();
+        accounts.sort_by_key(|account| account.id);
+        accounts
+    };
+
+    let report = listener.replay(stream.clone()).await;
+    assert_eq!(report.total(), 8);
+    assert_eq!(report.applied(), 6);
+    assert_eq!(report.no_handler(), 1);
+    assert_eq!(report.parse_errors(), 1);
+    assert_eq!(report.handler_errors(), 0);
+    assert!(matches!(report.outcomes[7], NotificationOutcome::ParseError(_)));
+    let once = contents();
+    assert_eq!(once.len(), 2);
+
+    // Replaying the same window again, or an overlapping one, changes nothing
+    listener.replay(stream.clone()).await;
+    assert_eq!(contents(), once);
+    listener.replay(stream[2..].to_vec()).await;
+    assert_eq!(contents(), once);
+    assert_eq!(account_cache.read().get_by_primary(&first).unwrap().version, 3);
+}
+
+#[tokio::test]
+async fn test_pause_and_resume() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let resyncs = Arc::new(AtomicUsize::new(0));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let counter = resyncs.clone();
+    listener.on_resync(move || {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    let users: Vec<UserIndexCache> = (0..3)
+        .map(|i| UserIndexCache::new(Uuid::new_v4(), &format!("user{i}"), &format!("user{i}@example.com")))
+        .collect();
+
+    // Buffered notifications are applied on replay
+    listener.pause(PauseMode::Buffer { capacity: 10 });
+    assert!(listener.is_paused());
+    listener.process_notification(&user_notification("insert", &users[0])).await;
+    listener.process_notification(&user_notification("insert", &users[1])).await;
+    assert_eq!(user_cache.read().iter().count(), 0);
+    assert_eq!(listener.resume(ResyncMode::Replay).await, 2);
+    assert!(!listener.is_paused());
+    assert_eq!(user_cache.read().iter().count(), 2);
+    assert_eq!(resyncs.load(Ordering::SeqCst), 0);
+
+    // Dropping the buffer runs the resync callbacks instead
+    listener.pause(PauseMode::Buffer { capacity: 10 });
+    listener.process_notification(&user_notification("delete", &users[0])).await;
+    assert_eq!(listener.resume(ResyncMode::DropAndResync).await, 0);
+    assert!(user_cache.read().contains_primary(&users[0].id));
+    assert_eq!(resyncs.load(Ordering::SeqCst), 1);
+
+    // A replay after notifications were discarded resyncs as well
+    listener.pause(PauseMode::Buffer { capacity: 1 });
+    listener.process_notification(&user_notification("insert", &users[2])).await;
+    listener.process_notification(&user_notification("delete", &users[1])).await;
+    assert_eq!(listener.paused_discarded(), 1);
+    assert_eq!(listener.resume(ResyncMode::Replay).await, 0);
+    assert!(!user_cache.read().contains_primary(&users[2].id));
+    assert_eq!(resyncs.load(Ordering::SeqCst), 2);
+
+    listener.pause(PauseMode::Discard);
+    listener.process_notification(&user_notification("insert", &users[2])).await;
+    assert_eq!(listener.paused_discarded(), 2);
+    listener.resume(ResyncMode::DropAndResync).await;
+    assert_eq!(resyncs.load(Ordering::SeqCst), 3);
+
+    // Live again
+    listener.process_notification(&user_notification("insert", &users[2])).await;
+    assert!(user_cache.read().contains_primary(&users[2].id));
+    assert_eq!(listener.resume(ResyncMode::Replay).await, 0);
+}
+
+#[tokio::test]
+async fn test_aggregating_handler_folds_notifications() {
+    let seen: Arc<RwLock<Vec<(CacheAction, Uuid, bool)>>> = Arc::new(RwLock::new(Vec::new()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(AggregatingCacheHandler::new(
+        "user_index_cache".to_string(),
+        seen.clone(),
+        |seen: &mut Vec<(CacheAction, Uuid, bool)>, action, id, data| seen.push((action, id, data.is_some())),
+    )));
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    listener.process_notification(&user_notification("insert", &user)).await;
+    listener.process_notification(&user_notification("delete", &user)).await;
+    // Dropped: unknown action, and an update without data
+    listener.process_notification(&user_notification("upsert", &user)).await;
+    let mut update = serde_json::from_str::<CacheNotification>(&user_notification("update", &user)).unwrap();
+    update.data = None;
+    listener.process_notification(&serde_json::to_string(&update).unwrap()).await;
+
+    assert_eq!(
+        *seen.read(),
+        vec![(CacheAction::Insert, user.id, true), (CacheAction::Delete, user.id, false)]
+    );
+}
+
+#[tokio::test]
+async fn test_handler_registry_is_shared_between_listeners() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let product_cache: Arc<RwLock<IdxModelCache<ProductIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let registry = HandlerRegistry::new();
+    let live = CacheNotificationListener::with_registry("cache_changes".to_string(), registry.clone());
+    let mut outbox = CacheNotificationListener::with_registry("cache_changes".to_string(), registry.clone());
+
+    // Registered after both listeners were created, through the registry itself
+    registry.register(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    assert!(live.registry().contains("user_index_cache"));
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let (alice_payload, bob_payload) = (user_notification("insert", &alice), user_notification("insert", &bob));
+    tokio::join!(live.process_notification(&alice_payload), outbox.process_notification(&bob_payload));
+    assert!(user_cache.read().contains_primary(&alice.id));
+    assert!(user_cache.read().contains_primary(&bob.id));
+
+    // Registered through one listener, visible to the other
+    outbox.register_handler(Arc::new(IndexCacheHandler::for_type(product_cache)));
+    assert_eq!(live.registry().tables(), vec!["product_index_cache".to_string(), "user_index_cache".to_string()]);
+    assert_eq!(live.handler_stats("user_index_cache").unwrap().count, 2);
+}
+
+#[tokio::test]
+async fn test_index_handler_counts_duplicate_inserts_and_missing_deletes() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let alicia = UserIndexCache::new(alice.id, "alicia", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone()]).unwrap()));
+
+    // Counted and kept
+    let handler = IndexCacheHandler::for_type(user_cache.clone()).on_duplicate_insert(DuplicateInsert::Count { apply: false });
+    handler.handle_notification(CacheNotification::insert("user_index_cache", &alicia).unwrap()).await;
+    handler.handle_notification(CacheNotification::insert("user_index_cache", &bob).unwrap()).await;
+    assert_eq!(handler.duplicate_inserts(), 1);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice.clone()));
+
+    // Logged, counted and applied
+    let handler = IndexCacheHandler::for_type(user_cache.clone()).on_duplicate_insert(DuplicateInsert::Warn { apply: true });
+    handler.handle_notification(CacheNotification::insert("user_index_cache", &alicia).unwrap()).await;
+    assert_eq!(handler.duplicate_inserts(), 1);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alicia.clone()));
+
+    // Not recorded by default
+    let handler = IndexCacheHandler::for_type(user_cache.clone());
+    handler.handle_notification(CacheNotification::insert("user_index_cache", &alice).unwrap()).await;
+    assert_eq!(handler.duplicate_inserts(), 0);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice.clone()));
+
+    handler.handle_notification(CacheNotification::delete("user_index_cache", bob.id)).await;
+    handler.handle_notification(CacheNotification::delete("user_index_cache", bob.id)).await;
+    assert_eq!(handler.missing_deletes(), 1);
+}
+
+#[cfg(feature = "payload-protection")]
+#[tokio::test]
+async fn test_listener_decrypts_protected_data_and_rejects_the_rest() {
+    use postgres_index_cache::PayloadKey;
+
+    let key = PayloadKey::new(&[7; 32]).unwrap();
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let dead_letters = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    listener.set_payload_key(key.clone());
+    let hook_dead_letters = dead_letters.clone();
+    listener.set_dead_letter_hook(move |notification, reason| {
+        hook_dead_letters.lock().push((notification.id, reason.to_string()));
+    });
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let protected_for = |table: &str, user: &UserIndexCache| {
+        let notification = CacheNotification::insert("user_index_cache", user).unwrap();
+        let data = key.protect(table, "insert", &user.id.to_string(), notification.data.as_ref().unwrap()).unwrap();
+        CacheNotification { data: Some(data), ..notification }.to_payload()
+    };
+
+    let outcome = listener.process_notification(&protected_for("user_index_cache", &alice)).await;
+    assert!(matches!(outcome, NotificationOutcome::Applied { .. }));
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice.clone()));
+
+    // Unprotected data, and data protected for another table, never reach the handler
+    let outcome = listener.process_notification(&user_notification("insert", &bob)).await;
+    assert!(matches!(outcome, NotificationOutcome::Rejected { .. }));
+    assert!(outcome.is_consumed());
+    let outcome = listener.process_notification(&protected_for("product_index_cache", &bob)).await;
+    assert!(matches!(outcome, NotificationOutcome::Rejected { .. }));
+    assert!(!user_cache.read().contains_primary(&bob.id));
+    assert_eq!(dead_letters.lock().iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![bob.id, bob.id]);
+
+    // Protected data replayed for another row or action is rejected as well
+    let replayed = |id: Uuid, action: &str| {
+        let mut notification: CacheNotification = serde_json::from_str(&protected_for("user_index_cache", &alice)).unwrap();
+        notification.id = id;
+        notification.action = action.to_string();
+        notification.to_payload()
+    };
+    let outcome = listener.process_notification(&replayed(bob.id, "insert")).await;
+    assert!(matches!(outcome, NotificationOutcome::Rejected { .. }));
+    let outcome = listener.process_notification(&replayed(alice.id, "update")).await;
+    assert!(matches!(outcome, NotificationOutcome::Rejected { .. }));
+    assert!(!user_cache.read().contains_primary(&bob.id));
+    assert_eq!(dead_letters.lock().len(), 4);
+
+    // Deletes carry no data and are dispatched as they are
+    listener.process_notification(&user_notification("delete", &alice)).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+}
+
+#[tokio::test]
+async fn test_cascade_handler_removes_children_of_deleted_parent() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    let laptop = ProductIndexCache::new(Uuid::new_v4(), alice.id, "Laptop");
+    let mouse = ProductIndexCache::new(Uuid::new_v4(), alice.id, "Mouse");
+    let keyboard = ProductIndexCache::new(Uuid::new_v4(), bob.id, "Keyboard");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone(), bob.clone()]).unwrap()));
+    let product_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![laptop.clone(), mouse.clone(), keyboard.clone()]).unwrap()));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(
+        CascadeHandler::wrapping(Arc::new(IndexCacheHandler::for_type(user_cache.clone())))
+            .cascade_to(product_cache.clone(), "user_id"),
+    ));
+
+    // Other actions only reach the parent's handler
+    let alicia = UserIndexCache::new(alice.id, "alicia", "alice@example.com");
+    listener.process_notification(&user_notification("update", &alicia)).await;
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alicia.clone()));
+    assert_eq!(product_cache.read().iter().count(), 3);
+
+    listener.process_notification(&user_notification("delete", &alicia)).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+    assert!(!product_cache.read().contains_primary(&laptop.id));
+    assert!(!product_cache.read().contains_primary(&mouse.id));
+    assert!(product_cache.read().contains_primary(&keyboard.id));
+
+    // Without a parent handler, only the children are touched
+    let handler = CascadeHandler::new("user_index_cache".to_string()).cascade_to(product_cache.clone(), "user_id");
+    handler.handle_notification(CacheNotification::delete("user_index_cache", bob.id)).await;
+    assert!(product_cache.read().iter().next().is_none());
+    assert!(user_cache.read().contains_primary(&bob.id));
+}
+
+/// Records what it publishes and whether the user cache held the item by then;
+/// fails while `unavailable` is above zero, counting it down
+struct RecordingSink {
+    user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>>,
+    published: parking_lot::Mutex<Vec<(String, Uuid, bool)>>,
+    unavailable: std::sync::atomic::AtomicU32,
+    reject: bool,
+}
+
+impl RecordingSink {
+    fn new(user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>>) -> Self {
+        Self { user_cache, published: Default::default(), unavailable: Default::default(), reject: false }
+    }
+}
+
+#[async_trait::async_trait]
+impl NotificationSink for RecordingSink {
+    async fn publish(&self, notification: &CacheNotification) -> Result<(), SinkError> {
+        use std::sync::atomic::Ordering;
+        if self.reject {
+            return Err(SinkError::Rejected("not for this bus".to_string()));
+        }
+        if self.unavailable.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)).is_ok() {
+            return Err(SinkError::Unavailable("reconnecting".to_string()));
+        }
+        let cached = self.user_cache.read().contains_primary(&notification.id);
+        self.published.lock().push((notification.table.clone(), notification.id, cached));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_sink_handler_publishes_after_the_cache_handler() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let laptop = ProductIndexCache::new(Uuid::new_v4(), alice.id, "Laptop");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let users_sink = Arc::new(RecordingSink::new(user_cache.clone()));
+    let all_sink = Arc::new(RecordingSink::new(user_cache.clone()));
+
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(SinkHandler::wrapping(
+        Arc::new(IndexCacheHandler::for_type(user_cache.clone())),
+        users_sink.clone(),
+    )));
+    listener.register_handler(Arc::new(SinkHandler::for_all_tables(all_sink.clone())));
+    assert!(listener.has_handler(ALL_TABLES));
+
+    listener.process_notification(&user_notification("insert", &alice)).await;
+    assert!(user_cache.read().contains_primary(&alice.id));
+    assert_eq!(*users_sink.published.lock(), vec![("user_index_cache".to_string(), alice.id, true)]);
+
+    // The handler of all tables runs after the table's own, and alone for tables without one
+    let product_payload = CacheNotification::insert("product_index_cache", &laptop).unwrap().to_payload();
+    let outcome = listener.process_notification(&product_payload).await;
+    assert!(matches!(outcome, NotificationOutcome::Applied { .. }));
+    assert_eq!(
+        *all_sink.published.lock(),
+        vec![("user_index_cache".to_string(), alice.id, true), ("product_index_cache".to_string(), laptop.id, false)]
+    );
+    assert_eq!(users_sink.published.lock().len(), 1);
+}
+
+#[tokio::test]
+async fn test_sink_handler_retries_and_dead_letters_independently() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let sink = Arc::new(RecordingSink::new(user_cache.clone()));
+    sink.unavailable.store(2, std::sync::atomic::Ordering::SeqCst);
+    let dead_letters = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+    let retry = RetryPolicy::new(3).with_backoff(std::time::Duration::from_millis(1), std::time::Duration::from_millis(5));
+    let recorded = dead_letters.clone();
+    let handler = Arc::new(
+        SinkHandler::wrapping(Arc::new(IndexCacheHandler::for_type(user_cache.clone())), sink.clone())
+            .with_retry(retry)
+            .with_dead_letter(Arc::new(move |notification: &CacheNotification, reason: &str| {
+                recorded.lock().push((notification.id, reason.to_string()));
+            })),
+    );
+
+    // The cache is updated at once, the sink once it is available again
+    handler.handle_notification(CacheNotification::insert("user_index_cache", &alice).unwrap()).await;
+    assert!(user_cache.read().contains_primary(&alice.id));
+    for _ in 0..100 {
+        if handler.published() == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+    assert_eq!(handler.published(), 1);
+    assert_eq!(sink.published.lock().len(), 1);
+
+    assert!(dead_letters.lock().is_empty());
+
+    // Rejections are not retried
+    let recorded = dead_letters.clone();
+    let rejecting = SinkHandler::new(
+        "user_index_cache".to_string(),
+        Arc::new(RecordingSink { reject: true, ..RecordingSink::new(user_cache.clone()) }),
+    )
+    .with_retry(retry)
+    .with_dead_letter(Arc::new(move |notification: &CacheNotification, reason: &str| {
+        recorded.lock().push((notification.id, reason.to_string()));
+    }));
+    rejecting.handle_notification(CacheNotification::delete("user_index_cache", alice.id)).await;
+    assert_eq!(rejecting.failures(), 1);
+    assert_eq!(rejecting.published(), 0);
+    let dead_letters = dead_letters.lock();
+    assert_eq!(dead_letters.len(), 1);
+    assert!(dead_letters[0].1.contains("not for this bus"));
+    assert!(user_cache.read().contains_primary(&alice.id));
+}
+
+#[tokio::test]
+async fn test_index_handler_on_deser_error() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let alicia = UserIndexCache::new(alice.id, "alicia", "alice@example.com");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone()]).unwrap()));
+    let malformed = || CacheNotification {
+        action: "update".to_string(),
+        data: Some(serde_json::json!({ "id": alice.id, "username_hash": "not a hash" })),
+        ..CacheNotification::insert("user_index_cache", &alice).unwrap()
+    };
+
+    // Kept by default, but counted
+    let handler = IndexCacheHandler::for_type(user_cache.clone());
+    handler.handle_notification(malformed()).await;
+    assert_eq!(handler.deserialization_failures(), 1);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice.clone()));
+
+    // Refetched through the loader
+    let reloaded = alicia.clone();
+    let loader: Arc<ItemLoader<UserIndexCache>> = Arc::new(move |_| -> BoxFuture<'static, Option<UserIndexCache>> {
+        let reloaded = reloaded.clone();
+        Box::pin(async move { Some(reloaded) })
+    });
+    let handler = IndexCacheHandler::for_type(user_cache.clone()).on_deser_error(OnDeserError::Refetch).with_loader(loader);
+    handler.handle_notification(malformed()).await;
+    assert_eq!(handler.deserialization_failures(), 1);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alicia.clone()));
+
+    // Without a loader, refetching invalidates
+    let handler = IndexCacheHandler::for_type(user_cache.clone()).on_deser_error(OnDeserError::Refetch);
+    handler.handle_notification(malformed()).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+
+    // Invalidated, including when the payload migrator fails
+    user_cache.write().add(alice.clone());
+    let handler = IndexCacheHandler::for_type(user_cache.clone())
+        .on_deser_error(OnDeserError::Invalidate)
+        .with_payload_migrator(Arc::new(|_: serde_json::Value| -> Result<serde_json::Value, String> {
+            Err("unsupported schema".to_string())
+        }));
+    let update = CacheNotification { action: "update".to_string(), ..CacheNotification::insert("user_index_cache", &alicia).unwrap() };
+    handler.handle_notification(update).await;
+    assert_eq!(handler.deserialization_failures(), 1);
+    assert!(!user_cache.read().contains_primary(&alice.id));
+}
+
+#[tokio::test]
+async fn test_index_handler_skips_unchanged_updates() {
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let alicia = UserIndexCache::new(alice.id, "alicia", "alice@example.com");
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::new(vec![alice.clone()]).unwrap()));
+    let handler = IndexCacheHandler::for_type(user_cache.clone()).skip_unchanged();
+
+    handler.handle_notification(CacheNotification::update("user_index_cache", &alice).unwrap()).await;
+    assert_eq!(handler.unchanged_updates(), 1);
+
+    handler.handle_notification(CacheNotification::update("user_index_cache", &alicia).unwrap()).await;
+    assert_eq!(handler.unchanged_updates(), 1);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alicia));
+
+    // Inserts are never skipped
+    handler.handle_notification(CacheNotification::insert("user_index_cache", &alice).unwrap()).await;
+    assert_eq!(handler.unchanged_updates(), 1);
+    assert_eq!(user_cache.read().get_by_primary(&alice.id), Some(alice));
+}
+
+#[tokio::test]
+async fn test_last_applied_is_set_by_bootstrap_and_notifications() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    let listener = Arc::new(listener);
+    assert!(listener.last_applied("user_index_cache").is_none());
+
+    let started = chrono::Utc::now();
+    CacheBootstrapper::new(listener.clone())
+        .without_waiting_for_listen()
+        .bootstrap(|| async { Ok::<(), CacheError>(()) })
+        .await
+        .unwrap();
+    let loaded = listener.last_applied("user_index_cache").unwrap();
+    assert_eq!((loaded.action.as_str(), loaded.key.as_deref()), ("load", None));
+    assert!(loaded.at >= started);
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    listener.process_notification(&user_notification("insert", &alice)).await;
+    let applied = listener.last_applied("user_index_cache").unwrap();
+    assert_eq!(applied.action, "insert");
+    assert_eq!(applied.key, Some(alice.id.to_string()));
+    assert!(applied.at >= loaded.at);
+    assert_eq!(listener.all_last_applied(), vec![("user_index_cache".to_string(), applied)]);
+
+    // Notifications that are not applied leave it as it is
+    listener.process_notification(&user_notification("upsert", &alice)).await;
+    assert_eq!(listener.last_applied("user_index_cache").unwrap().action, "insert");
+}
+
+#[tokio::test]
+async fn test_handlers_on_channels_only_receive_their_channel() {
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let product_cache: Arc<RwLock<IdxModelCache<ProductIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(user_cache.clone())));
+    listener
+        .register_handler_on_channel("product_cache", Arc::new(IndexCacheHandler::for_type(product_cache.clone())))
+        .unwrap();
+    assert_eq!(listener.channels(), vec![listener.channel().to_string(), "product_cache".to_string()]);
+
+    // A table is handled on one channel only
+    let err = listener
+        .register_handler_on_channel("user_cache", Arc::new(IndexCacheHandler::for_type(user_cache.clone())))
+        .unwrap_err();
+    assert!(matches!(err, CacheError::InvalidArgument(_)));
+    let err = listener
+        .register_handler_on_channel("other_cache", Arc::new(IndexCacheHandler::for_type(product_cache.clone())))
+        .unwrap_err();
+    assert!(err.to_string().contains("channel 'product_cache'"));
+    listener
+        .register_handler_on_channel("product_cache", Arc::new(IndexCacheHandler::for_type(product_cache.clone())))
+        .unwrap();
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let laptop = ProductIndexCache::new(Uuid::new_v4(), alice.id, "Laptop");
+    let product_payload = CacheNotification::insert("product_index_cache", &laptop).unwrap().to_payload();
+
+    // Wrong channels are dropped
+    listener.process_notification_on_channel("product_cache", &user_notification("insert", &alice)).await;
+    listener.process_notification_on_channel(DEFAULT_CACHE_CHANNEL, &product_payload).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+    assert!(!product_cache.read().contains_primary(&laptop.id));
+
+    listener.process_notification_on_channel(DEFAULT_CACHE_CHANNEL, &user_notification("insert", &alice)).await;
+    listener.process_notification_on_channel("product_cache", &product_payload).await;
+    assert!(user_cache.read().contains_primary(&alice.id));
+    assert!(product_cache.read().contains_primary(&laptop.id));
+
+    assert_eq!(listener.registered_tables(), vec!["product_index_cache".to_string(), "user_index_cache".to_string()]);
+    assert!(listener.has_handler("user_index_cache"));
+    assert!(!listener.has_handler("orders"));
+    let handlers = listener.registered_handlers();
+    assert_eq!(handlers[0].channel.as_deref(), Some("product_cache"));
+    assert_eq!(handlers[1].channel, None);
+    assert!(handlers[1].handler_type.contains("IndexCacheHandler"));
+    assert!(handlers[1].handler_type.contains("UserIndexCache"));
+}
+
+#[tokio::test]
+async fn test_register_handler_keeps_handler_on_its_channel() {
+    let product_cache: Arc<RwLock<IdxModelCache<ProductIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let other_cache: Arc<RwLock<IdxModelCache<ProductIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener
+        .register_handler_on_channel("product_cache", Arc::new(IndexCacheHandler::for_type(product_cache.clone())))
+        .unwrap();
+
+    // Registering the table again without a channel is refused
+    listener.register_handler(Arc::new(IndexCacheHandler::for_type(other_cache.clone())));
+    assert_eq!(listener.registered_handlers()[0].channel.as_deref(), Some("product_cache"));
+    assert_eq!(listener.channels(), vec![listener.channel().to_string(), "product_cache".to_string()]);
+
+    let alice_id = Uuid::new_v4();
+    let laptop = ProductIndexCache::new(Uuid::new_v4(), alice_id, "Laptop");
+    let payload = CacheNotification::insert("product_index_cache", &laptop).unwrap().to_payload();
+    listener.process_notification_on_channel("product_cache", &payload).await;
+    assert!(product_cache.read().contains_primary(&laptop.id));
+    assert!(!other_cache.read().contains_primary(&laptop.id));
+}
+
+/// Times out on its first invocations, then passes notifications on
+struct FlakyHandler {
+    inner: IndexCacheHandler<UserIndexCache>,
+    slow_calls: std::sync::atomic::AtomicU32,
+}
+
+#[async_trait::async_trait]
+impl CacheNotificationHandler for FlakyHandler {
+    async fn handle_notification(&self, notification: CacheNotification) {
+        use std::sync::atomic::Ordering;
+        if self.slow_calls.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |calls| calls.checked_sub(1)).is_ok() {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        self.inner.handle_notification(notification).await;
+    }
+
+    fn table_name(&self) -> &str {
+        self.inner.table_name()
+    }
+}
+
+#[tokio::test]
+async fn test_retry_policy_retries_timed_out_handlers_in_background() {
+    use std::time::Duration;
+    let options = HandlerOptions::default()
+        .with_timeout(Duration::from_millis(20))
+        .with_retry(RetryPolicy::new(2).with_backoff(Duration::from_millis(5), Duration::from_millis(5)));
+    let flaky_listener = |cache: Arc<RwLock<IdxModelCache<UserIndexCache>>>, slow_calls: u32| {
+        let mut listener = CacheNotificationListener::new();
+        listener.register_handler_with_options(
+            Arc::new(FlakyHandler { inner: IndexCacheHandler::for_type(cache), slow_calls: slow_calls.into() }),
+            options,
+        );
+        listener
+    };
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+
+    // The first retry completes
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let listener = flaky_listener(user_cache.clone(), 1);
+    listener.process_notification(&user_notification("insert", &alice)).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(user_cache.read().contains_primary(&alice.id));
+    let stats = listener.handler_stats("user_index_cache").unwrap();
+    assert_eq!((stats.timeouts, stats.retries, stats.failures), (1, 1, 0));
+
+    // Every retry times out; the notification goes to the dead-letter hook
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let dead_letters: Arc<RwLock<Vec<(Uuid, String)>>> = Arc::new(RwLock::new(Vec::new()));
+    let mut listener = flaky_listener(user_cache.clone(), 10);
+    listener.set_dead_letter_hook({
+        let dead_letters = dead_letters.clone();
+        move |notification, reason| dead_letters.write().push((notification.id, reason.to_string()))
+    });
+    listener.process_notification(&user_notification("insert", &alice)).await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!user_cache.read().contains_primary(&alice.id));
+    let stats = listener.handler_stats("user_index_cache").unwrap();
+    assert_eq!((stats.timeouts, stats.retries, stats.failures), (3, 2, 1));
+    assert_eq!(*dead_letters.read(), vec![(alice.id, "handler timed out 3 times".to_string())]);
+}
+
+#[tokio::test]
+async fn test_newer_notification_ends_retry_of_the_same_item() {
+    use std::time::Duration;
+    let user_cache: Arc<RwLock<IdxModelCache<UserIndexCache>>> = Arc::new(RwLock::new(IdxModelCache::new(vec![]).unwrap()));
+    let mut listener = CacheNotificationListener::new();
+    listener.register_handler_with_options(
+        Arc::new(FlakyHandler { inner: IndexCacheHandler::for_type(user_cache.clone()), slow_calls: 1.into() }),
+        HandlerOptions::default()
+            .with_timeout(Duration::from_millis(20))
+            .with_retry(RetryPolicy::new(2).with_backoff(Duration::from_millis(50), Duration::from_millis(50))),
+    );
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+
+    // The insert times out; the delete applies before its retry is due
+    listener.process_notification(&user_notification("insert", &alice)).await;
+    listener.process_notification(&user_notification("delete", &alice)).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(!user_cache.read().contains_primary(&alice.id));
+    let stats = listener.handler_stats("user_index_cache").unwrap();
+    assert_eq!((stats.timeouts, stats.retries, stats.superseded, stats.failures), (1, 0, 1, 0));
+}
@@ -3,7 +3,8 @@
 -- This file contains example PostgreSQL triggers and table schemas to demonstrate
 -- cache invalidation notifications via LISTEN/NOTIFY when data changes occur.
 --
--- These examples use the notify_cache_change() function defined in sql/cache_notification_triggers.sql
+-- These examples use the notify_cache_change() function defined in sql/cache_notification_triggers.sql.
+-- The triggers themselves are installed by the tests through init_table_trigger().
 
 -- =====================================================================
 -- Example: Users Table
@@ -25,13 +26,6 @@ CREATE TABLE IF NOT EXISTS user_index_cache (
     email_hash BIGINT NOT NULL
 );
 
--- Create trigger for user_index_cache table
-DROP TRIGGER IF EXISTS user_index_cache_notify ON user_index_cache;
-CREATE TRIGGER user_index_cache_notify
-    AFTER INSERT OR UPDATE OR DELETE ON user_index_cache
-    FOR EACH ROW
-    EXECUTE FUNCTION notify_cache_change();
-
 -- =====================================================================
 -- Example: Products Table
 -- =====================================================================
@@ -52,12 +46,21 @@ CREATE TABLE IF NOT EXISTS product_index_cache (
     product_name_hash BIGINT NOT NULL
 );
 
--- Create trigger for product_index_cache table
-DROP TRIGGER IF EXISTS product_index_cache_notify ON product_index_cache;
-CREATE TRIGGER product_index_cache_notify
-    AFTER INSERT OR UPDATE OR DELETE ON product_index_cache
-    FOR EACH ROW
-    EXECUTE FUNCTION notify_cache_change();
+-- =====================================================================
+-- Example: Partitioned Table
+-- =====================================================================
+
+-- A hash-partitioned index cache table; its trigger reports the parent table
+CREATE TABLE IF NOT EXISTS partitioned_user_index_cache (
+    id UUID PRIMARY KEY,
+    username_hash BIGINT NOT NULL,
+    email_hash BIGINT NOT NULL
+) PARTITION BY HASH (id);
+
+CREATE TABLE IF NOT EXISTS partitioned_user_index_cache_p0
+    PARTITION OF partitioned_user_index_cache FOR VALUES WITH (MODULUS 2, REMAINDER 0);
+CREATE TABLE IF NOT EXISTS partitioned_user_index_cache_p1
+    PARTITION OF partitioned_user_index_cache FOR VALUES WITH (MODULUS 2, REMAINDER 1);
 
 -- =====================================================================
 -- Test the Notification System
@@ -120,8 +123,8 @@ CREATE TRIGGER product_index_cache_notify
 -- Cleanup (if needed)
 -- =====================================================================
 
--- DROP TRIGGER IF EXISTS user_index_cache_notify ON user_index_cache;
--- DROP TRIGGER IF EXISTS product_index_cache_notify ON product_index_cache;
+-- DROP TRIGGER IF EXISTS user_index_cache_cache_notify ON user_index_cache;
+-- DROP TRIGGER IF EXISTS product_index_cache_cache_notify ON product_index_cache;
 -- DROP FUNCTION IF EXISTS notify_cache_change();
 -- DROP TABLE IF EXISTS product_index_cache;
 -- DROP TABLE IF EXISTS user_index_cache;
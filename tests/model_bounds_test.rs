@@ -0,0 +1,18 @@
+mod common;
+
+use postgres_index_cache::{assert_idx_model, assert_main_model};
+use uuid::Uuid;
+
+use common::entities::{AccountIndexCache, ProductIndexCache, User, UserIndexCache};
+
+// The test entities are cacheable, or this file does not compile
+const _: () = assert_idx_model::<UserIndexCache>();
+const _: () = assert_idx_model::<ProductIndexCache>();
+const _: () = assert_idx_model::<AccountIndexCache>();
+const _: () = assert_main_model::<User, Uuid>();
+
+#[test]
+fn test_model_bound_errors_point_at_the_assertion() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/bounds/*.rs");
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::sync::Arc;
+use parking_lot::RwLock;
+use postgres_index_cache::{
+    CacheConfig, CacheNotification, CacheNotificationHandler, EvictionPolicy, MainModelCache,
+    RedisTierConfig, TieredModelCache, TieredModelCacheHandler,
+};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use common::entities::UserIndexCache;
+
+/// Helper function to get Redis URL from environment or use default
+fn get_redis_url() -> String {
+    std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6380".to_string())
+}
+
+async fn connect() -> ConnectionManager {
+    let client = redis::Client::open(get_redis_url()).expect("Invalid Redis URL");
+    ConnectionManager::new(client).await.expect("Failed to connect to Redis")
+}
+
+fn memory_cache() -> Arc<RwLock<MainModelCache<UserIndexCache>>> {
+    Arc::new(RwLock::new(MainModelCache::new(CacheConfig::new(10, EvictionPolicy::LRU))))
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_tiered_cache_falls_back_to_redis() {
+    let prefix = format!("test_users_{}", Uuid::new_v4());
+    let replica_a = TieredModelCache::new(memory_cache(), connect().await, RedisTierConfig::new(prefix.clone()));
+    let replica_b = TieredModelCache::new(memory_cache(), connect().await, RedisTierConfig::new(prefix.clone()));
+
+    let user = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    replica_a.insert(user.clone()).await;
+
+    // Replica B misses in memory, finds the item in Redis and keeps a copy
+    assert!(!replica_b.memory().read().contains(&user.id));
+    assert_eq!(replica_b.get(&user.id).await.as_deref(), Some(&user));
+    assert!(replica_b.memory().read().contains(&user.id));
+
+    // Removal propagates to Redis, but not to the other replica's memory
+    assert!(replica_a.remove(&user.id).await.is_some());
+    let mut redis = connect().await;
+    let stored: Option<String> = redis.get(replica_a.redis_key(&user.id)).await.unwrap();
+    assert!(stored.is_none());
+
+    // A miss in both tiers
+    assert!(replica_a.get(&Uuid::new_v4()).await.is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_tiered_handler_deletes_redis_keys() {
+    let prefix = format!("test_users_{}", Uuid::new_v4());
+    let cache = Arc::new(TieredModelCache::new(memory_cache(), connect().await, RedisTierConfig::new(prefix)));
+    let handler = TieredModelCacheHandler::new("user_index_cache".to_string(), cache.clone());
+
+    let alice = UserIndexCache::new(Uuid::new_v4(), "alice", "alice@example.com");
+    let bob = UserIndexCache::new(Uuid::new_v4(), "bob", "bob@example.com");
+    cache.insert(alice.clone()).await;
+    cache.insert(bob.clone()).await;
+
+    handler
+        .handle_notification(CacheNotification {
+            table: "user_index_cache".to_string(),
+            action: "delete".to_string(),
+            id: alice.id,
+            data: None,
+            key: None,
+            context: None,
+        })
+        .await;
+
+    let mut redis = connect().await;
+    let stored: Option<String> = redis.get(cache.redis_key(&alice.id)).await.unwrap();
+    assert!(stored.is_none());
+    assert!(!cache.memory().read().contains(&alice.id));
+
+    handler
+        .handle_notification(CacheNotification {
+            table: "user_index_cache".to_string(),
+            action: "truncate".to_string(),
+            id: Uuid::nil(),
+            data: None,
+            key: None,
+            context: None,
+        })
+        .await;
+
+    let stored: Option<String> = redis.get(cache.redis_key(&bob.id)).await.unwrap();
+    assert!(stored.is_none());
+    assert!(cache.memory().read().is_empty());
+}
@@ -0,0 +1,77 @@
+//! Writes to an index cache while a snapshot of it is alive
+//!
+//! Counts the bytes each write allocates, which would grow with the size of
+//! the cache if a write copied the items or indexes the snapshot shares. The
+//! counting allocator is global, so this file holds a single test.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use postgres_index_cache::IdxModelCache;
+use uuid::Uuid;
+
+use common::UserIndexCache;
+
+const ITEMS: usize = 50_000;
+const WRITES: usize = 100;
+
+/// The system allocator, counting the bytes allocated
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocated_by(f: impl FnOnce()) -> usize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    f();
+    ALLOCATED.load(Ordering::Relaxed) - before
+}
+
+fn user(n: usize) -> UserIndexCache {
+    UserIndexCache::new(Uuid::new_v4(), &format!("user{n}"), &format!("user{n}@example.com"))
+}
+
+#[test]
+fn test_writes_with_a_live_snapshot_copy_only_what_they_touch() {
+    let users: Vec<UserIndexCache> = (0..ITEMS).map(user).collect();
+    let removed: Vec<Uuid> = users.iter().take(WRITES).map(|user| user.id).collect();
+    let mut cache = IdxModelCache::new(users).unwrap();
+    let snapshot = cache.snapshot_arc();
+
+    let mut most = 0;
+    for (n, primary_key) in removed.iter().enumerate() {
+        let added = user(ITEMS + n);
+        most = most.max(allocated_by(|| cache.add(added)));
+        most = most.max(allocated_by(|| {
+            cache.remove(primary_key);
+        }));
+    }
+
+    // Copying the items alone, without their maps, would take this much
+    let items_copy = ITEMS * std::mem::size_of::<UserIndexCache>();
+    assert!(most < items_copy / 20, "a write allocated {most} bytes, copying the items takes {items_copy}");
+
+    assert_eq!(snapshot.iter().count(), ITEMS);
+    assert!(removed.iter().all(|primary_key| snapshot.contains_primary(primary_key)));
+    assert_eq!(cache.iter().count(), ITEMS);
+    assert!(!removed.iter().any(|primary_key| cache.contains_primary(primary_key)));
+}
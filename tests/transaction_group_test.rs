@@ -0,0 +1,134 @@
+mod common;
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use postgres_index_cache::{
+    CacheConfig, CacheError, CacheRegistry, CacheTransactionGroup, EvictionPolicy, IdxModelCache, MainModelCache,
+    TransactionAware, TransactionParticipant,
+};
+use postgres_unit_of_work::{TransactionError, TransactionResult};
+
+use common::{Product, ProductIndexCache, User, UserIndexCache};
+
+/// A participant whose commit always fails
+struct FailingParticipant;
+
+#[async_trait]
+impl TransactionAware for FailingParticipant {
+    async fn on_commit(&self) -> TransactionResult<()> {
+        Err(TransactionError::CommitFailed("disk full".to_string()))
+    }
+
+    async fn on_rollback(&self) -> TransactionResult<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_group_commits_all_participants() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let product_cache = Arc::new(RwLock::new(IdxModelCache::<ProductIndexCache>::new(vec![]).unwrap()));
+    let main_cache = Arc::new(RwLock::new(MainModelCache::<UserIndexCache>::new(
+        CacheConfig::new(10, EvictionPolicy::LRU),
+    )));
+
+    let mut group = CacheTransactionGroup::new();
+    let tx_users = group.add_index_cache("users", user_cache.clone());
+    let tx_products = group.add_index_cache("products", product_cache.clone());
+    let tx_main = group.add_main_model_cache("user_models", main_cache.clone());
+    assert_eq!(group.participant_names(), vec!["users", "products", "user_models"]);
+
+    let user = User::new("alice".to_string(), "alice@example.com".to_string());
+    let user_entry = UserIndexCache::from_user(&user);
+    let product_entry = ProductIndexCache::from_product(&Product::new(user.id, "Keyboard".to_string()));
+    tx_users.add(user_entry.clone());
+    tx_products.add(product_entry.clone());
+    tx_main.insert(user_entry.clone());
+
+    group.on_commit().await.unwrap();
+    assert!(user_cache.read().contains_primary(&user.id));
+    assert!(product_cache.read().contains_primary(&product_entry.id));
+    assert!(main_cache.read().contains(&user.id));
+}
+
+#[tokio::test]
+async fn test_group_rolls_back_all_participants() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let mut group = CacheTransactionGroup::new();
+    let tx_users = group.add_index_cache("users", user_cache.clone());
+
+    let user_entry = UserIndexCache::from_user(&User::new("alice".to_string(), "alice@example.com".to_string()));
+    tx_users.add(user_entry.clone());
+
+    group.on_rollback().await.unwrap();
+    assert!(!tx_users.is_dirty());
+    assert!(!user_cache.read().contains_primary(&user_entry.id));
+}
+
+#[tokio::test]
+async fn test_group_reports_partial_commit() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let product_cache = Arc::new(RwLock::new(IdxModelCache::<ProductIndexCache>::new(vec![]).unwrap()));
+
+    let mut group = CacheTransactionGroup::new();
+    let tx_users = group.add_index_cache("users", user_cache.clone());
+    group.add_participant("search_index", Arc::new(FailingParticipant));
+    let tx_products = group.add_index_cache("products", product_cache.clone());
+
+    let user = User::new("alice".to_string(), "alice@example.com".to_string());
+    let product_entry = ProductIndexCache::from_product(&Product::new(user.id, "Keyboard".to_string()));
+    tx_users.add(UserIndexCache::from_user(&user));
+    tx_products.add(product_entry.clone());
+
+    let err = group.on_commit().await.unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("committed [users, products]"), "{message}");
+    assert!(message.contains("search_index"), "{message}");
+
+    // Participants after the failing one are still committed
+    assert!(user_cache.read().contains_primary(&user.id));
+    assert!(product_cache.read().contains_primary(&product_entry.id));
+}
+
+#[tokio::test]
+async fn test_registry_registers_wrappers_for_each_transaction() {
+    let user_cache = Arc::new(RwLock::new(IdxModelCache::<UserIndexCache>::new(vec![]).unwrap()));
+    let main_cache = Arc::new(RwLock::new(MainModelCache::<UserIndexCache>::new(
+        CacheConfig::new(10, EvictionPolicy::LRU),
+    )));
+
+    let mut registry = CacheRegistry::new();
+    registry.register_index_cache("users", user_cache.clone()).unwrap();
+    registry.register_main_model_cache("user_models", main_cache.clone()).unwrap();
+    assert!(matches!(
+        registry.register_index_cache("users", user_cache.clone()),
+        Err(CacheError::InvalidArgument(_))
+    ));
+    assert_eq!(registry.names(), vec!["users", "user_models"]);
+
+    let mut registered = Vec::new();
+    let tx = registry.begin_transaction(&mut |participant: TransactionParticipant| registered.push(participant));
+    assert_eq!(registered.len(), 1);
+
+    // Handles are typed; a wrong name or type gives None
+    let tx_users = tx.index_cache::<UserIndexCache>("users").unwrap();
+    let tx_main = tx.main_model_cache::<UserIndexCache>("user_models").unwrap();
+    assert!(tx.index_cache::<ProductIndexCache>("users").is_none());
+    assert!(tx.index_cache::<UserIndexCache>("user_models").is_none());
+    assert!(tx.index_cache::<UserIndexCache>("products").is_none());
+
+    let user_entry = UserIndexCache::from_user(&User::new("alice".to_string(), "alice@example.com".to_string()));
+    tx_users.add(user_entry.clone());
+    tx_main.insert(user_entry.clone());
+
+    // Committing the registered participant applies every wrapper
+    registered[0].on_commit().await.unwrap();
+    assert!(user_cache.read().contains_primary(&user_entry.id));
+    assert!(main_cache.read().contains(&user_entry.id));
+
+    // The next transaction gets fresh wrappers
+    let next = registry.begin_transaction(&mut |participant: TransactionParticipant| registered.push(participant));
+    assert!(!next.index_cache::<UserIndexCache>("users").unwrap().is_dirty());
+    assert_eq!(registered.len(), 2);
+}
@@ -0,0 +1,23 @@
+use std::rc::Rc;
+use postgres_index_cache::{assert_idx_model, HasPrimaryKey, Indexable};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+struct SessionIdx {
+    id: Uuid,
+    owner: Rc<str>,
+}
+
+impl HasPrimaryKey for SessionIdx {
+    fn primary_key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Indexable for SessionIdx {}
+
+const _: () = assert_idx_model::<SessionIdx>();
+
+fn main() {
+    let _ = SessionIdx { id: Uuid::nil(), owner: Rc::from("alice") }.owner;
+}
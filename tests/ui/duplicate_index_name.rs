@@ -0,0 +1,13 @@
+use postgres_index_cache::Indexable;
+use uuid::Uuid;
+
+#[derive(Indexable)]
+struct UserIndexCache {
+    id: Uuid,
+    #[cache(i64_index = "name_hash")]
+    username_hash: i64,
+    #[cache(i64_index = "name_hash")]
+    display_name_hash: i64,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use postgres_index_cache::HasPrimaryKey;
+use uuid::Uuid;
+
+#[derive(HasPrimaryKey)]
+struct UserIndexCache {
+    id: Uuid,
+    username_hash: i64,
+}
+
+fn main() {}
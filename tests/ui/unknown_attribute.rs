@@ -0,0 +1,10 @@
+use postgres_index_cache::Indexable;
+use uuid::Uuid;
+
+#[derive(Indexable)]
+struct UserIndexCache {
+    #[cache(index)]
+    id: Uuid,
+}
+
+fn main() {}